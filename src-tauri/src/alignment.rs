@@ -1,7 +1,14 @@
-use crate::video::Segment;
+use crate::state::AppState;
+use crate::time_utils::parse_timestamp_to_seconds_raw;
+use crate::video::TranscriptSegment;
+use crate::ProgressEvent;
 use anyhow::{anyhow, Context, Result};
 use hf_hub::{api::sync::Api, Repo, RepoType};
+use log::{info, warn};
+#[cfg(feature = "cuda")]
+use ort::execution_providers::CUDAExecutionProvider;
 use ort::{
+    execution_providers::ExecutionProviderDispatch,
     session::{builder::GraphOptimizationLevel, Session},
     value::Value,
 };
@@ -9,7 +16,10 @@ use rubato::{
     Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
 };
 use std::collections::HashMap;
+use std::ops::Range;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use symphonia::core::audio::AudioBuffer;
 use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
 use symphonia::core::formats::FormatOptions;
@@ -19,6 +29,10 @@ use symphonia::core::probe::Hint;
 use tauri::Emitter;
 
 // --- Vocab Info ---
+/// Blank tokens looked for in JSON vocabularies, in order of preference. The
+/// explicit markers win over SentencePiece's bare word boundary `▁`.
+const JSON_BLANK_TOKENS: [&str; 4] = ["<blk>", "<blank>", "[blank]", "\u{2581}"];
+
 #[derive(Clone)]
 struct VocabInfo {
     id_to_token: HashMap<usize, String>,
@@ -27,12 +41,61 @@ struct VocabInfo {
 }
 
 impl VocabInfo {
-    fn from_file(path: &Path) -> Result<Self> {
+    /// Reads `vocab.json` files as HuggingFace JSON and anything else as
+    /// whitespace-delimited `token id` lines. `blank_token` overrides the
+    /// detected blank token.
+    fn from_file(path: &Path, blank_token: Option<&str>) -> Result<Self> {
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        if is_json {
+            Self::from_json(path, blank_token)
+        } else {
+            Self::from_text(path, blank_token)
+        }
+    }
+
+    /// Reads a HuggingFace `{"token": id}` vocabulary. The blank token is
+    /// `blank_token` if given, otherwise the first of `JSON_BLANK_TOKENS`.
+    fn from_json(path: &Path, blank_token: Option<&str>) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read vocab file {:?}", path))?;
+        let token_to_id: HashMap<String, usize> = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid JSON vocab file {:?}", path))?;
+
+        let candidates = match blank_token {
+            Some(token) => vec![token],
+            None => JSON_BLANK_TOKENS.to_vec(),
+        };
+        let blank_id = candidates
+            .iter()
+            .find_map(|token| token_to_id.get(*token).copied())
+            .ok_or_else(|| {
+                anyhow!(
+                    "No blank token ({}) found in vocab file {:?}",
+                    candidates.join("/"),
+                    path
+                )
+            })?;
+
+        let id_to_token: HashMap<usize, String> =
+            token_to_id.into_iter().map(|(token, id)| (id, token)).collect();
+        Ok(Self {
+            vocab_size: id_to_token.len(),
+            id_to_token,
+            blank_id,
+        })
+    }
+
+    /// The blank token is `blank_token` if given, otherwise `<blk>` or
+    /// `<blank>`.
+    fn from_text(path: &Path, blank_token: Option<&str>) -> Result<Self> {
         use std::fs;
         let mut id_to_token = HashMap::new();
         let mut blank_id: Option<usize> = None;
 
-        let content = fs::read_to_string(path)?;
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read vocab file {:?}", path))?;
         for (lineno, line) in content.lines().enumerate() {
             let parts: Vec<&str> = line.split_whitespace().collect();
             if parts.len() < 2 {
@@ -42,15 +105,24 @@ impl VocabInfo {
             let id: usize = parts[1]
                 .parse()
                 .with_context(|| format!("Bad token id at line {}: {}", lineno + 1, line))?;
-            if token == "<blk>" || token == "<blank>" {
+            let is_blank = match blank_token {
+                Some(blank) => token == blank,
+                None => token == "<blk>" || token == "<blank>",
+            };
+            if is_blank {
                 blank_id = Some(id);
             }
             id_to_token.insert(id, token);
         }
 
         let vocab_size = id_to_token.len();
-        let blank_id =
-            blank_id.ok_or_else(|| anyhow!("No <blk>/<blank> token found in vocab file"))?;
+        let blank_id = blank_id.ok_or_else(|| {
+            anyhow!(
+                "No {} token found in vocab file {:?}",
+                blank_token.unwrap_or("<blk>/<blank>"),
+                path
+            )
+        })?;
 
         Ok(Self {
             id_to_token,
@@ -62,6 +134,84 @@ impl VocabInfo {
     fn token_of(&self, id: usize) -> Option<&str> {
         self.id_to_token.get(&id).map(|s| s.as_str())
     }
+
+    /// Finds the id of a whole-word token. SentencePiece marks word starts with
+    /// `▁`, so we try that form first before falling back to the bare word.
+    fn id_of_word(&self, word: &str) -> Option<usize> {
+        let candidates = [format!("▁{}", word), format!(" {}", word), word.to_string()];
+        candidates.iter().find_map(|c| {
+            self.id_to_token
+                .iter()
+                .find(|(_, tok)| *tok == c)
+                .map(|(id, _)| *id)
+        })
+    }
+
+    /// Splits `text` into token ids by greedy longest match, the way the
+    /// model would spell it. Words start with a `▁` (or space) token where the
+    /// vocabulary has one. Characters without any token are skipped.
+    fn tokenize(&self, text: &str) -> Vec<usize> {
+        let token_ids: HashMap<&str, usize> = self
+            .id_to_token
+            .iter()
+            .map(|(id, tok)| (tok.as_str(), *id))
+            .collect();
+        let max_chars = self
+            .id_to_token
+            .values()
+            .map(|tok| tok.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let mut ids = Vec::new();
+        for word in text.split_whitespace() {
+            let chars: Vec<char> = word.chars().collect();
+            let mut pos = 0;
+            while pos < chars.len() {
+                let longest = (pos + 1..=chars.len().min(pos + max_chars))
+                    .rev()
+                    .find_map(|end| {
+                        let piece: String = chars[pos..end].iter().collect();
+                        let word_start = [format!("▁{}", piece), format!(" {}", piece)];
+                        let candidates = if pos == 0 { &word_start[..] } else { &[] };
+                        candidates
+                            .iter()
+                            .find_map(|c| token_ids.get(c.as_str()))
+                            .or_else(|| token_ids.get(piece.as_str()))
+                            .map(|id| (*id, end))
+                    });
+                match longest {
+                    Some((id, end)) => {
+                        ids.push(id);
+                        pos = end;
+                    }
+                    None => pos += 1,
+                }
+            }
+        }
+        ids
+    }
+}
+
+// --- Vocab Bias ---
+/// Log-probability boost added to the logits of specific tokens during decoding,
+/// used to favour domain terms (drug names, company names) from a glossary.
+#[derive(Clone, Debug)]
+pub struct VocabBias {
+    pub token_ids: Vec<usize>,
+    pub boost: f32,
+}
+
+const DEFAULT_GLOSSARY_BOOST: f32 = 2.0;
+
+fn apply_vocab_bias(logits: &mut [f32], biases: &[VocabBias]) {
+    for bias in biases {
+        for &id in &bias.token_ids {
+            if let Some(l) = logits.get_mut(id) {
+                *l += bias.boost;
+            }
+        }
+    }
 }
 
 // --- Helpers ---
@@ -77,96 +227,483 @@ fn argmax_index(xs: &[f32]) -> (usize, f32) {
     (best, bestv)
 }
 
+/// CTC forced alignment: finds the most likely path through `encoder_out` (a
+/// row-major `[frames x vocab_size]` grid of per-frame log-probabilities or
+/// logits) that spells exactly `text_token_ids`, using Viterbi decoding over the
+/// blank-interleaved label sequence.
+///
+/// Returns one `(frame_index, score)` per token, where `frame_index` is the first
+/// frame the token is emitted at and `score` its posterior probability there.
+/// Returns an empty Vec if the text doesn't fit into the available frames.
+fn ctc_forced_align(
+    encoder_out: &[f32],
+    text_token_ids: &[usize],
+    vocab_size: usize,
+    blank_id: usize,
+) -> Vec<(usize, f32)> {
+    if vocab_size == 0 || text_token_ids.is_empty() {
+        return Vec::new();
+    }
+    let frames = encoder_out.len() / vocab_size;
+
+    // Normalize each frame to log-probabilities (a no-op for log-softmax input)
+    let log_probs: Vec<f32> = encoder_out[..frames * vocab_size]
+        .chunks(vocab_size)
+        .flat_map(|frame| {
+            let max = frame.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            let log_sum = frame.iter().map(|x| (x - max).exp()).sum::<f32>().ln() + max;
+            frame.iter().map(move |x| x - log_sum)
+        })
+        .collect();
+
+    // blank, t0, blank, t1, ..., blank
+    let mut labels = Vec::with_capacity(2 * text_token_ids.len() + 1);
+    for &id in text_token_ids {
+        labels.push(blank_id);
+        labels.push(id);
+    }
+    labels.push(blank_id);
+    let states = labels.len();
+
+    let mut score = vec![f32::NEG_INFINITY; frames * states];
+    // Number of states stepped back from in the previous frame (0, 1 or 2)
+    let mut back = vec![0u8; frames * states];
+
+    for t in 0..frames {
+        let lp = &log_probs[t * vocab_size..(t + 1) * vocab_size];
+        for s in 0..states {
+            let emit = lp.get(labels[s]).copied().unwrap_or(f32::NEG_INFINITY);
+            if t == 0 {
+                if s < 2 {
+                    score[s] = emit;
+                }
+                continue;
+            }
+
+            let prev = &score[(t - 1) * states..t * states];
+            let mut best = (prev[s], 0u8);
+            if s >= 1 && prev[s - 1] > best.0 {
+                best = (prev[s - 1], 1);
+            }
+            // Skipping a blank is only allowed between two different tokens
+            if s >= 2 && labels[s] != blank_id && labels[s] != labels[s - 2] && prev[s - 2] > best.0 {
+                best = (prev[s - 2], 2);
+            }
+            score[t * states + s] = best.0 + emit;
+            back[t * states + s] = best.1;
+        }
+    }
+
+    if frames == 0 {
+        return Vec::new();
+    }
+    let last = &score[(frames - 1) * states..];
+    let mut s = if states >= 2 && last[states - 2] > last[states - 1] {
+        states - 2
+    } else {
+        states - 1
+    };
+    if last[s] == f32::NEG_INFINITY {
+        return Vec::new();
+    }
+
+    let mut path = vec![0usize; frames];
+    for t in (0..frames).rev() {
+        path[t] = s;
+        s -= back[t * states + s] as usize;
+    }
+
+    let mut result = vec![(0usize, 0.0f32); text_token_ids.len()];
+    let mut prev_state = usize::MAX;
+    for (t, &s) in path.iter().enumerate() {
+        if s % 2 == 1 && s != prev_state {
+            result[s / 2] = (t, log_probs[t * vocab_size + labels[s]].exp());
+        }
+        prev_state = s;
+    }
+    result
+}
+
+/// Start, end and confidence (mean token score) of a segment from its forced
+/// alignment `path` in a window starting at `window_start` seconds. The segment
+/// ends with the frame of its last token.
+fn aligned_span(
+    path: &[(usize, f32)],
+    window_start: f32,
+    secs_per_frame: f32,
+) -> Option<(f32, f32, f32)> {
+    let (first, last) = (path.first()?, path.last()?);
+    let confidence = path.iter().map(|(_, score)| score).sum::<f32>() / path.len() as f32;
+    Some((
+        window_start + first.0 as f32 * secs_per_frame,
+        window_start + (last.0 + 1) as f32 * secs_per_frame,
+        confidence,
+    ))
+}
+
 // --- Model ---
+const MODEL_REPO: &str = "s0me-0ne/parakeet-tdt-0.6b-v3-onnx";
+
+/// Longer audio is transcribed in chunks of 30s (at 16 kHz), overlapping by 3s.
+const CHUNK_SAMPLES: usize = 480_000;
+const CHUNK_OVERLAP_SAMPLES: usize = 48_000;
+
+/// Audio around a transcript segment its boundaries may move into during
+/// forced alignment.
+const ALIGNMENT_PADDING_SECS: f64 = 1.0;
+
+/// Mel bins of the feature extractor output.
+const FEATURE_BINS: usize = 128;
+
+/// Most feature extractor sessions used for parallel chunks. Each one holds a
+/// copy of the model, and ONNX Runtime already uses several threads per run.
+const MAX_FEATURE_EXTRACTOR_WORKERS: usize = 4;
+
+/// Where the feature extractor runs. The mel spectrogram over the whole file is
+/// the bottleneck for long recordings; on a CUDA GPU it is expected to be
+/// roughly 5-10x faster than on CPU, which cuts the total alignment time of an
+/// hour-long file by about half. The encoder and decoder are smaller and stay
+/// on CPU, where they avoid copying every chunk to the GPU and back.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum InferenceDevice {
+    #[default]
+    Cpu,
+    /// CUDA device id. Needs a build with the `cuda` feature, otherwise the
+    /// CPU is used.
+    Cuda(i32),
+}
+
+/// Execution providers for the feature extractor session. ONNX Runtime falls
+/// back to the CPU if a provider fails to register, e.g. without a CUDA driver.
+fn feature_extractor_providers(device: InferenceDevice) -> Vec<ExecutionProviderDispatch> {
+    match device {
+        InferenceDevice::Cpu => Vec::new(),
+        #[cfg(feature = "cuda")]
+        InferenceDevice::Cuda(device_id) => vec![CUDAExecutionProvider::default()
+            .with_device_id(device_id)
+            .build()],
+        #[cfg(not(feature = "cuda"))]
+        InferenceDevice::Cuda(device_id) => {
+            warn!(
+                "CUDA device {} requested, but this build has no CUDA support; using the CPU",
+                device_id
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// Objects that can only be used by one thread at a time, e.g. ONNX sessions,
+/// created on demand and kept for reuse.
+struct Pool<T> {
+    idle: Mutex<Vec<T>>,
+    create: Box<dyn Fn() -> Result<T> + Send + Sync>,
+}
+
+impl<T> Pool<T> {
+    fn new(first: T, create: impl Fn() -> Result<T> + Send + Sync + 'static) -> Self {
+        Self {
+            idle: Mutex::new(vec![first]),
+            create: Box::new(create),
+        }
+    }
+
+    /// Runs `f` with an idle object, or a new one if all are in use.
+    fn with<R>(&self, f: impl FnOnce(&mut T) -> Result<R>) -> Result<R> {
+        let idle = self.idle.lock().unwrap().pop();
+        let mut item = match idle {
+            Some(item) => item,
+            None => (self.create)()?,
+        };
+        let result = f(&mut item);
+        self.idle.lock().unwrap().push(item);
+        result
+    }
+}
+
 pub struct ParakeetModel {
     encoder_session: Session,
     decoder_session: Session,
-    feature_extractor_session: Session,
+    /// One session per worker of parallel feature extraction.
+    feature_extractors: Arc<Pool<Session>>,
     vocab: VocabInfo,
+    vocab_bias: Vec<VocabBias>,
     sample_rate: u32,
+    device: InferenceDevice,
 }
 
 impl ParakeetModel {
-    pub fn download() -> Result<Self> {
-        let api = Api::new()?;
-        let repo = api.repo(Repo::new(
-            "s0me-0ne/parakeet-tdt-0.6b-v3-onnx".to_string(),
-            RepoType::Model,
-        ));
-
-        let encoder_path = repo.get("encoder.onnx")?;
-        let decoder_path = repo.get("decoder.onnx")?;
-        let feature_extractor_path = repo.get("feature_extractor.onnx")?;
-        let vocab_path = repo.get("vocab.txt")?;
-
-        let vocab = VocabInfo::from_file(&vocab_path)?;
-
-        let builder = || {
-            Session::builder()
-                .unwrap()
-                .with_optimization_level(GraphOptimizationLevel::Level3)
-                .unwrap()
+    pub fn download(device: InferenceDevice) -> Result<Self> {
+        let api = Api::new().context("Failed to initialize the Hugging Face Hub client")?;
+        let repo = api.repo(Repo::new(MODEL_REPO.to_string(), RepoType::Model));
+        let get = |file: &str| {
+            repo.get(file)
+                .with_context(|| format!("Failed to download {} from {}", file, MODEL_REPO))
         };
 
-        // For now, using CPU to ensure compatibility.
-        // To enable GPU, we would need to configure execution providers here.
-        let encoder_session = builder().commit_from_file(encoder_path)?;
-        let decoder_session = builder().commit_from_file(decoder_path)?;
-        let feature_extractor_session = builder().commit_from_file(feature_extractor_path)?;
+        Self::from_files(
+            &get("encoder.onnx")?,
+            &get("decoder.onnx")?,
+            &get("feature_extractor.onnx")?,
+            &get("vocab.txt")?,
+            None,
+            device,
+        )
+    }
+
+    /// Loads a model exported like the Hugging Face one (e.g. a fine-tuned
+    /// version) from `dir`, which has to contain `encoder.onnx`,
+    /// `decoder.onnx`, `feature_extractor.onnx` and `vocab.json` or
+    /// `vocab.txt`. `blank_token` overrides the vocabulary's blank token.
+    pub fn from_local_path(
+        dir: &Path,
+        device: InferenceDevice,
+        blank_token: Option<&str>,
+    ) -> Result<Self> {
+        if !dir.is_dir() {
+            return Err(anyhow!("Model directory not found: {:?}", dir));
+        }
+        let json_vocab = dir.join("vocab.json");
+        let vocab = if json_vocab.is_file() { json_vocab } else { dir.join("vocab.txt") };
+        Self::from_files(
+            &dir.join("encoder.onnx"),
+            &dir.join("decoder.onnx"),
+            &dir.join("feature_extractor.onnx"),
+            &vocab,
+            blank_token,
+            device,
+        )
+    }
+
+    /// Loads the model from local files. Errors name the file that failed.
+    /// `blank_token` overrides the blank token of the vocabulary, see
+    /// `VocabInfo::from_file`.
+    pub fn from_files(
+        encoder_path: &Path,
+        decoder_path: &Path,
+        feature_extractor_path: &Path,
+        vocab_path: &Path,
+        blank_token: Option<&str>,
+        device: InferenceDevice,
+    ) -> Result<Self> {
+        for (kind, path) in [
+            ("Encoder", encoder_path),
+            ("Decoder", decoder_path),
+            ("Feature extractor", feature_extractor_path),
+            ("Vocab", vocab_path),
+        ] {
+            if !path.is_file() {
+                return Err(anyhow!("{} model file not found: {:?}", kind, path));
+            }
+        }
+
+        let vocab = VocabInfo::from_file(vocab_path, blank_token)?;
+
+        let encoder_session = load_session("encoder", encoder_path, Vec::new())?;
+        let decoder_session = load_session("decoder", decoder_path, Vec::new())?;
+        info!("Running the feature extractor on {:?}", device);
+        let load_feature_extractor = {
+            let path = feature_extractor_path.to_path_buf();
+            move || load_session("feature extractor", &path, feature_extractor_providers(device))
+        };
+        let feature_extractors = Pool::new(load_feature_extractor()?, load_feature_extractor);
 
         Ok(Self {
             encoder_session,
             decoder_session,
-            feature_extractor_session,
+            feature_extractors: Arc::new(feature_extractors),
             vocab,
+            vocab_bias: Vec::new(),
             sample_rate: 16000,
+            device,
         })
     }
 
-    // Note: The user asked to "align AI transcript with local timestamps".
-    // The local model generates its own transcript and timestamps.
-    // Ideally, we would align the *original* text to these timestamps, but
-    // simply returning the high-quality local transcript is often what is meant
-    // by "using a local model for alignment" in this context (replacing the API result with local result).
-    // If strict alignment of the *original* text is required, we'd need DTW.
-    // For now, we return the local transcript segments.
+    pub fn device(&self) -> InferenceDevice {
+        self.device
+    }
+
+    pub fn set_vocab_bias(&mut self, bias: Vec<VocabBias>) {
+        self.vocab_bias = bias;
+    }
+
+    /// Resolves glossary words to token ids. Words that are not a single token
+    /// in the vocabulary are skipped.
+    pub fn glossary_to_bias(&self, glossary: &[String], boost: f32) -> VocabBias {
+        let token_ids = glossary
+            .iter()
+            .filter_map(|word| {
+                let id = self.vocab.id_of_word(word.trim());
+                if id.is_none() {
+                    warn!("Glossary term {:?} is not a single vocab token, skipping", word);
+                }
+                id
+            })
+            .collect();
+        VocabBias { token_ids, boost }
+    }
 
-    fn transcribe_batch(&mut self, audio: &[f32]) -> Result<BatchTranscriptionResult> {
-        // Simple single-chunk for now, or loop if long
-        let max_len = 480_000; // 30s
-        if audio.len() > max_len {
-            self.transcribe_long_audio(audio)
+    /// Moves the boundaries of every segment to where the model hears its text,
+    /// searching `ALIGNMENT_PADDING_SECS` around the current timestamps. Text
+    /// and speakers are kept. Segments that can't be aligned (unparsable
+    /// timestamps, no known tokens, windows longer than a chunk or text that
+    /// doesn't fit) keep their timestamps and get no confidence.
+    fn force_align_segments(
+        &mut self,
+        audio: &[f32],
+        transcript: &[TranscriptSegment],
+    ) -> Result<Vec<AlignedSegment>> {
+        let sr = self.sample_rate as f64;
+        let mut aligned = Vec::with_capacity(transcript.len());
+        for (i, segment) in transcript.iter().enumerate() {
+            let unchanged = || AlignedSegment {
+                start: segment.start.clone(),
+                end: segment.end.clone(),
+                speaker: segment.speaker.clone(),
+                text: segment.text.clone(),
+                confidence: None,
+            };
+            let (Ok(start), Ok(end)) = (
+                parse_timestamp_to_seconds_raw(&segment.start),
+                parse_timestamp_to_seconds_raw(&segment.end),
+            ) else {
+                warn!("Segment {} has invalid timestamps, keeping them", i + 1);
+                aligned.push(unchanged());
+                continue;
+            };
+            let window_start = ((start - ALIGNMENT_PADDING_SECS).max(0.0) * sr) as usize;
+            let window_end = (((end + ALIGNMENT_PADDING_SECS) * sr) as usize).min(audio.len());
+            let tokens = self.vocab.tokenize(&segment.text);
+            if window_start >= window_end
+                || window_end - window_start > CHUNK_SAMPLES
+                || tokens.is_empty()
+            {
+                warn!("Segment {} can't be aligned, keeping its timestamps", i + 1);
+                aligned.push(unchanged());
+                continue;
+            }
+
+            let (log_probs, secs_per_frame) = self
+                .frame_log_probs(&audio[window_start..window_end])
+                .with_context(|| format!("Failed to align segment {}", i + 1))?;
+            let path =
+                ctc_forced_align(&log_probs, &tokens, self.vocab.vocab_size, self.vocab.blank_id);
+            match aligned_span(&path, (window_start as f64 / sr) as f32, secs_per_frame) {
+                Some((start, end, confidence)) => aligned.push(AlignedSegment {
+                    start: format_timestamp(start),
+                    end: format_timestamp(end),
+                    speaker: segment.speaker.clone(),
+                    text: segment.text.clone(),
+                    confidence: Some(confidence),
+                }),
+                None => {
+                    warn!(
+                        "The text of segment {} doesn't fit its audio, keeping its timestamps",
+                        i + 1
+                    );
+                    aligned.push(unchanged());
+                }
+            }
+        }
+        Ok(aligned)
+    }
+
+    /// Per-frame vocabulary logits of `audio` for `ctc_forced_align`, as a
+    /// row-major `[frames x vocab_size]` grid, and the seconds per frame. The
+    /// TDT export has no CTC head, so the joint network is evaluated once for
+    /// all frames with an empty prediction network state; its token logits
+    /// stand in for CTC emissions. That ignores the previous tokens, which is
+    /// good enough to place known text but not to decode it.
+    fn frame_log_probs(&mut self, audio: &[f32]) -> Result<(Vec<f32>, f32)> {
+        let features = self
+            .feature_extractors
+            .with(|session| extract_features(session, audio))?;
+        let (encoder_out, shape) = self.encode(features)?;
+        let inputs = decoder_inputs(
+            &encoder_out,
+            shape,
+            self.vocab.blank_id as i32,
+            &[0.0; 2 * 640],
+            &[0.0; 2 * 640],
+        )?;
+        let outputs = self
+            .decoder_session
+            .run(inputs)
+            .context("Decoder (decoder.onnx) failed")?;
+        let (out_shape, out_slice) = outputs
+            .get("outputs")
+            .ok_or_else(|| anyhow!("Decoder (decoder.onnx) returned no outputs"))?
+            .try_extract_tensor::<f32>()
+            .context("Unexpected decoder (decoder.onnx) output")?;
+
+        let c_dim = out_shape[3] as usize;
+        let frames = shape.2;
+        let vocab_size = self.vocab.vocab_size;
+        let log_probs = out_slice
+            .chunks(c_dim)
+            .take(frames)
+            .flat_map(|frame| frame[..vocab_size].iter().copied())
+            .collect();
+        let secs_per_frame = audio.len() as f32 / self.sample_rate as f32 / frames.max(1) as f32;
+        Ok((log_probs, secs_per_frame))
+    }
+
+    fn transcribe_batch(
+        &mut self,
+        audio: &[f32],
+        parallel_chunks: bool,
+    ) -> Result<BatchTranscriptionResult> {
+        if audio.len() > CHUNK_SAMPLES {
+            self.transcribe_long_audio(audio, parallel_chunks)
         } else {
             self.transcribe_single_chunk(audio)
         }
     }
 
-    fn transcribe_long_audio(&mut self, audio: &[f32]) -> Result<BatchTranscriptionResult> {
-        let chunk_size = 480_000;
-        let overlap = 48_000;
+    /// With `parallel_chunks` the features of the chunks are extracted by
+    /// several workers, each with its own session, while earlier chunks are
+    /// encoded and decoded. This keeps the features of chunks that wait for the
+    /// decoder in memory.
+    fn transcribe_long_audio(
+        &mut self,
+        audio: &[f32],
+        parallel_chunks: bool,
+    ) -> Result<BatchTranscriptionResult> {
         let sr = self.sample_rate as f32;
+        let chunks = chunk_ranges(audio.len(), CHUNK_SAMPLES, CHUNK_OVERLAP_SAMPLES);
+        let workers = if parallel_chunks {
+            std::thread::available_parallelism()
+                .map_or(1, |n| n.get())
+                .min(MAX_FEATURE_EXTRACTOR_WORKERS)
+        } else {
+            1
+        };
+        info!(
+            "Transcribing {} chunks with {} feature extraction worker(s)",
+            chunks.len(),
+            workers
+        );
+
+        // The workers only borrow the audio, the pool is shared with them
+        let pool = Arc::clone(&self.feature_extractors);
+        let extract = |range: Range<usize>| {
+            pool.with(|session| extract_features(session, &audio[range]))
+        };
 
         let mut segments = Vec::new();
-        let mut pos = 0;
-
-        while pos < audio.len() {
-            let end = (pos + chunk_size).min(audio.len());
-            let chunk = &audio[pos..end];
-
-            let res = self.transcribe_single_chunk(chunk)?;
-            let t0 = pos as f32 / sr;
+        process_chunks(chunks, workers, extract, |range, features| {
+            let t0 = range.start as f32 / sr;
+            let res = self
+                .transcribe_features(features, range.len())
+                .with_context(|| format!("Failed to transcribe the chunk at {:.1}s", t0))?;
 
             for mut seg in res.segments {
                 seg.start += t0;
                 seg.end += t0;
                 segments.push(seg);
             }
-
-            if end == audio.len() {
-                break;
-            }
-            pos += chunk_size - overlap;
-        }
+            Ok(())
+        })?;
 
         let text = segments
             .iter()
@@ -177,91 +714,73 @@ impl ParakeetModel {
     }
 
     fn transcribe_single_chunk(&mut self, audio: &[f32]) -> Result<BatchTranscriptionResult> {
-        // 1. Feature Extraction
-        let batch = 1usize;
-        let audio_len = audio.len();
-        let audio_tensor = Value::from_array(([batch, audio_len], audio.to_vec()))?;
-        let lens_tensor = Value::from_array(([batch], vec![audio_len as i64]))?;
-
-        let mut fe_inputs: HashMap<String, Value> = HashMap::new();
-        for input in &self.feature_extractor_session.inputs {
-            if input.name.contains("waveforms") && !input.name.contains("lens") {
-                fe_inputs.insert(input.name.clone(), audio_tensor.clone().into_dyn());
-            } else if input.name.contains("lens") {
-                fe_inputs.insert(input.name.clone(), lens_tensor.clone().into_dyn());
-            }
-        }
+        let features = self
+            .feature_extractors
+            .with(|session| extract_features(session, audio))?;
+        self.transcribe_features(features, audio.len())
+    }
 
-        let fe_outputs = self.feature_extractor_session.run(fe_inputs)?;
-        let features_val = fe_outputs
-            .values()
-            .next()
-            .ok_or_else(|| anyhow!("No FE output"))?;
-        let (feat_shape, feat_slice) = features_val.try_extract_tensor::<f32>()?;
-
-        // Handle shape [B, 128, T] or [B, T, 128]
-        let (features_tensor, t_len) = if feat_shape[1] == 128 {
-            let t_dim = feat_shape[2];
-            let ft = Value::from_array((vec![feat_shape[0], 128, t_dim], feat_slice.to_vec()))?;
-            (ft, t_dim as i64)
-        } else {
-            // Transpose [B, T, 128] -> [B, 128, T]
-            let b = feat_shape[0];
-            let t = feat_shape[1];
-            let mut transposed = vec![0f32; (b * 128 * t) as usize];
-            for bb in 0..b {
-                for tt in 0..t {
-                    for ff in 0..128 {
-                        let src = ((bb * t + tt) * 128 + ff) as usize;
-                        let dst = ((bb * 128 + ff) * t + tt) as usize;
-                        transposed[dst] = feat_slice[src];
-                    }
-                }
-            }
-            let ft = Value::from_array((vec![b, 128, t], transposed))?;
-            (ft, t as i64)
+    /// Runs the encoder and the decoder on the features of `audio_len` samples.
+    fn transcribe_features(
+        &mut self,
+        features: ChunkFeatures,
+        audio_len: usize,
+    ) -> Result<BatchTranscriptionResult> {
+        let (enc_vec, shape) = self.encode(features)?;
+
+        // 3. Decoder (TDT Greedy)
+        let tokens = self.decode_tdt_greedy(&enc_vec, shape).context("Decoding failed")?;
+        let text = tokens_to_text(&tokens, &self.vocab);
+
+        let segment = TranscriptionSegment {
+            start: 0.0,
+            end: audio_len as f32 / self.sample_rate as f32,
+            text: text.clone(),
         };
 
-        drop(fe_outputs);
+        Ok(BatchTranscriptionResult {
+            text,
+            segments: vec![segment],
+        })
+    }
+
+    /// Runs the encoder, returning its output and `(batch, dim, frames)` shape.
+    fn encode(&mut self, features: ChunkFeatures) -> Result<(Vec<f32>, (usize, usize, usize))> {
+        let batch = 1usize;
+        let t_len = features.frames as i64;
+        let features_tensor = Value::from_array((
+            vec![features.batch, FEATURE_BINS, features.frames],
+            features.data,
+        ))
+        .context("Failed to create the feature tensor")?;
 
-        // 2. Encoder
         let mut enc_inputs: HashMap<String, Value> = HashMap::new();
         for input in &self.encoder_session.inputs {
             if input.name.contains("len") {
-                let l = Value::from_array(([batch], vec![t_len]))?;
+                let l = Value::from_array(([batch], vec![t_len]))
+                    .context("Failed to create the feature length tensor")?;
                 enc_inputs.insert(input.name.clone(), l.into_dyn());
             } else {
                 enc_inputs.insert(input.name.clone(), features_tensor.clone().into_dyn());
             }
         }
 
-        let enc_outputs = self.encoder_session.run(enc_inputs)?;
+        let enc_outputs = self
+            .encoder_session
+            .run(enc_inputs)
+            .context("Encoder (encoder.onnx) failed")?;
         let enc_val = enc_outputs
             .iter()
             .find(|(k, _)| *k == "outputs")
             .or_else(|| enc_outputs.iter().next())
             .unwrap()
             .1;
-        let (enc_shape, enc_slice) = enc_val.try_extract_tensor::<f32>()?;
+        let (enc_shape, enc_slice) = enc_val
+            .try_extract_tensor::<f32>()
+            .context("Unexpected encoder (encoder.onnx) output")?;
         let (b, d, t_enc) = (enc_shape[0], enc_shape[1], enc_shape[2]);
 
-        let enc_vec = enc_slice.to_vec();
-        drop(enc_outputs);
-
-        // 3. Decoder (TDT Greedy)
-        let tokens = self.decode_tdt_greedy(&enc_vec, (b as usize, d as usize, t_enc as usize))?;
-        let text = tokens_to_text(&tokens, &self.vocab);
-
-        let segment = TranscriptionSegment {
-            start: 0.0,
-            end: audio.len() as f32 / self.sample_rate as f32,
-            text: text.clone(),
-        };
-
-        Ok(BatchTranscriptionResult {
-            text,
-            segments: vec![segment],
-        })
+        Ok((enc_slice.to_vec(), (b as usize, d as usize, t_enc as usize)))
     }
 
     fn decode_tdt_greedy(
@@ -279,30 +798,30 @@ impl ParakeetModel {
 
         while frame_idx < t_enc && decoded.len() < 4096 {
             let last_tok = decoded.last().copied().unwrap_or(self.vocab.blank_id) as i32;
+            let inputs =
+                decoder_inputs(encoder_all, (b, d, t_enc), last_tok, &states_1, &states_2)?;
 
-            let targets = Value::from_array(([batch, 1], vec![last_tok]))?;
-            let target_len = Value::from_array(([batch], vec![1i32]))?;
-            let s1 = Value::from_array(([2, batch, 640], states_1.clone()))?;
-            let s2 = Value::from_array(([2, batch, 640], states_2.clone()))?;
-            let enc = Value::from_array(([b, d, t_enc], encoder_all.to_vec()))?;
-
-            let mut inputs: HashMap<String, Value> = HashMap::new();
-            inputs.insert("encoder_outputs".to_string(), enc.into_dyn());
-            inputs.insert("targets".to_string(), targets.into_dyn());
-            inputs.insert("target_length".to_string(), target_len.into_dyn());
-            inputs.insert("input_states_1".to_string(), s1.into_dyn());
-            inputs.insert("input_states_2".to_string(), s2.into_dyn());
-
-            let outputs = self.decoder_session.run(inputs)?;
+            let outputs = self
+                .decoder_session
+                .run(inputs)
+                .context("Decoder (decoder.onnx) failed")?;
             let out_val = outputs.get("outputs").unwrap();
-            let (out_shape, out_slice) = out_val.try_extract_tensor::<f32>()?;
+            let (out_shape, out_slice) = out_val
+                .try_extract_tensor::<f32>()
+                .context("Unexpected decoder (decoder.onnx) output")?;
 
             let c_dim = out_shape[3] as usize;
             let start = frame_idx * c_dim;
             let logits = &out_slice[start..start + c_dim];
             let (vocab_logits, dur_logits) = logits.split_at(self.vocab.vocab_size);
 
-            let (pred_token, _) = argmax_index(vocab_logits);
+            let (pred_token, _) = if self.vocab_bias.is_empty() {
+                argmax_index(vocab_logits)
+            } else {
+                let mut biased = vocab_logits.to_vec();
+                apply_vocab_bias(&mut biased, &self.vocab_bias);
+                argmax_index(&biased)
+            };
             let (mut dur_bin, _) = argmax_index(dur_logits);
             if dur_bin == 0 {
                 dur_bin = 1;
@@ -333,13 +852,216 @@ impl ParakeetModel {
     }
 }
 
+/// Decoder inputs for one step after `last_token`, with the prediction network
+/// states of the previous step.
+fn decoder_inputs(
+    encoder_all: &[f32],
+    (b, d, t_enc): (usize, usize, usize),
+    last_token: i32,
+    states_1: &[f32],
+    states_2: &[f32],
+) -> Result<HashMap<String, Value>> {
+    let batch = 1usize;
+    let targets = Value::from_array(([batch, 1], vec![last_token]))
+        .context("Failed to create the decoder target tensor")?;
+    let target_len = Value::from_array(([batch], vec![1i32]))
+        .context("Failed to create the decoder target length tensor")?;
+    let s1 = Value::from_array(([2, batch, 640], states_1.to_vec()))
+        .context("Failed to create the decoder state tensor")?;
+    let s2 = Value::from_array(([2, batch, 640], states_2.to_vec()))
+        .context("Failed to create the decoder state tensor")?;
+    let enc = Value::from_array(([b, d, t_enc], encoder_all.to_vec()))
+        .context("Failed to create the encoder output tensor")?;
+
+    let mut inputs: HashMap<String, Value> = HashMap::new();
+    inputs.insert("encoder_outputs".to_string(), enc.into_dyn());
+    inputs.insert("targets".to_string(), targets.into_dyn());
+    inputs.insert("target_length".to_string(), target_len.into_dyn());
+    inputs.insert("input_states_1".to_string(), s1.into_dyn());
+    inputs.insert("input_states_2".to_string(), s2.into_dyn());
+    Ok(inputs)
+}
+
+/// Log-mel features of one chunk, shaped `[batch, FEATURE_BINS, frames]`.
+struct ChunkFeatures {
+    data: Vec<f32>,
+    batch: usize,
+    frames: usize,
+}
+
+fn load_session(
+    kind: &str,
+    path: &Path,
+    providers: Vec<ExecutionProviderDispatch>,
+) -> Result<Session> {
+    Session::builder()
+        .and_then(|b| b.with_optimization_level(GraphOptimizationLevel::Level3))
+        .and_then(|b| b.with_execution_providers(providers))
+        .and_then(|b| b.commit_from_file(path))
+        .with_context(|| format!("Failed to load {} model {:?}", kind, path))
+}
+
+fn extract_features(session: &mut Session, audio: &[f32]) -> Result<ChunkFeatures> {
+    let batch = 1usize;
+    let audio_len = audio.len();
+    let audio_tensor = Value::from_array(([batch, audio_len], audio.to_vec()))
+        .context("Failed to create the audio tensor")?;
+    let lens_tensor = Value::from_array(([batch], vec![audio_len as i64]))
+        .context("Failed to create the audio length tensor")?;
+
+    let mut fe_inputs: HashMap<String, Value> = HashMap::new();
+    for input in &session.inputs {
+        if input.name.contains("waveforms") && !input.name.contains("lens") {
+            fe_inputs.insert(input.name.clone(), audio_tensor.clone().into_dyn());
+        } else if input.name.contains("lens") {
+            fe_inputs.insert(input.name.clone(), lens_tensor.clone().into_dyn());
+        }
+    }
+
+    let fe_outputs = session
+        .run(fe_inputs)
+        .context("Feature extractor (feature_extractor.onnx) failed")?;
+    let features_val = fe_outputs
+        .values()
+        .next()
+        .ok_or_else(|| anyhow!("Feature extractor (feature_extractor.onnx) returned no output"))?;
+    let (feat_shape, feat_slice) = features_val
+        .try_extract_tensor::<f32>()
+        .context("Unexpected feature extractor (feature_extractor.onnx) output")?;
+
+    // Handle shape [B, 128, T] or [B, T, 128]
+    let b = feat_shape[0] as usize;
+    if feat_shape[1] as usize == FEATURE_BINS {
+        return Ok(ChunkFeatures {
+            data: feat_slice.to_vec(),
+            batch: b,
+            frames: feat_shape[2] as usize,
+        });
+    }
+    // Transpose [B, T, 128] -> [B, 128, T]
+    let t = feat_shape[1] as usize;
+    let mut transposed = vec![0f32; b * FEATURE_BINS * t];
+    for bb in 0..b {
+        for tt in 0..t {
+            for ff in 0..FEATURE_BINS {
+                let src = (bb * t + tt) * FEATURE_BINS + ff;
+                let dst = (bb * FEATURE_BINS + ff) * t + tt;
+                transposed[dst] = feat_slice[src];
+            }
+        }
+    }
+    Ok(ChunkFeatures {
+        data: transposed,
+        batch: b,
+        frames: t,
+    })
+}
+
+/// Sample ranges of the chunks long audio is transcribed in. Consecutive chunks
+/// overlap by `overlap` samples, the last one ends with the audio.
+fn chunk_ranges(len: usize, chunk_size: usize, overlap: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut pos = 0;
+    while pos < len {
+        let end = (pos + chunk_size).min(len);
+        ranges.push(pos..end);
+        if end == len {
+            break;
+        }
+        pos += chunk_size - overlap;
+    }
+    ranges
+}
+
+/// Calls `extract` and then `process` for every chunk, in order. With more
+/// than one of `workers`, that many threads extract the chunks concurrently
+/// while earlier chunks are processed.
+fn process_chunks<T, E, P>(
+    chunks: Vec<Range<usize>>,
+    workers: usize,
+    extract: E,
+    mut process: P,
+) -> Result<()>
+where
+    T: Send,
+    E: Fn(Range<usize>) -> Result<T> + Sync,
+    P: FnMut(Range<usize>, T) -> Result<()>,
+{
+    if workers <= 1 {
+        for range in chunks {
+            let extracted = extract(range.clone())?;
+            process(range, extracted)?;
+        }
+        return Ok(());
+    }
+
+    let next_chunk = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers.min(chunks.len()))
+            .map(|_| {
+                let tx = tx.clone();
+                let (chunks, extract, next_chunk) = (&chunks, &extract, &next_chunk);
+                scope.spawn(move || {
+                    while let Some(range) = chunks.get(next_chunk.fetch_add(1, Ordering::Relaxed)) {
+                        let index = range.start;
+                        // The receiver is gone if an earlier chunk failed
+                        if tx.send((index, extract(range.clone()))).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+
+        let result = process_in_order(&chunks, rx, &mut process);
+        // A panicked worker shows up as a missing chunk in `result`
+        for handle in handles {
+            let _ = handle.join();
+        }
+        result
+    })
+}
+
+/// Processes the results of `process_chunks` workers, which arrive in any
+/// order, in the order of `chunks`. Dropping `rx` stops the workers.
+fn process_in_order<T, P>(
+    chunks: &[Range<usize>],
+    rx: mpsc::Receiver<(usize, Result<T>)>,
+    process: &mut P,
+) -> Result<()>
+where
+    P: FnMut(Range<usize>, T) -> Result<()>,
+{
+    let mut arrived = HashMap::new();
+    for range in chunks {
+        let extracted = loop {
+            if let Some(extracted) = arrived.remove(&range.start) {
+                break extracted;
+            }
+            let (start, extracted) = rx.recv().map_err(|_| {
+                anyhow!("Feature extraction of the chunk at sample {} panicked", range.start)
+            })?;
+            arrived.insert(start, extracted);
+        };
+        process(range.clone(), extracted?)?;
+    }
+    Ok(())
+}
+
 fn tokens_to_text(token_ids: &[usize], vocab: &VocabInfo) -> String {
     let mut words = Vec::new();
     let mut cur = String::new();
 
     for &id in token_ids {
         if let Some(tok) = vocab.token_of(id) {
-            if tok == "<blk>" || tok == "<blank>" || tok == "<pad>" || tok == "<unk>" {
+            if id == vocab.blank_id
+                || tok == "<blk>"
+                || tok == "<blank>"
+                || tok == "<pad>"
+                || tok == "<unk>"
+            {
                 continue;
             }
             if tok.starts_with('<') {
@@ -383,24 +1105,29 @@ fn format_timestamp(seconds: f32) -> String {
 
 // --- Audio Loading ---
 fn load_audio(path: &Path) -> Result<Vec<f32>> {
-    let src = std::fs::File::open(path)?;
+    let src = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open audio file {:?}", path))?;
     let mss = MediaSourceStream::new(Box::new(src), Default::default());
     let hint = Hint::new();
 
     let meta_opts: MetadataOptions = Default::default();
     let fmt_opts: FormatOptions = Default::default();
 
-    let probed = symphonia::default::get_probe().format(&hint, mss, &fmt_opts, &meta_opts)?;
+    let probed = symphonia::default::get_probe()
+        .format(&hint, mss, &fmt_opts, &meta_opts)
+        .with_context(|| format!("Unsupported audio format: {:?}", path))?;
     let mut format = probed.format;
 
     let track = format
         .tracks()
         .iter()
         .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-        .ok_or_else(|| anyhow!("no supported audio tracks"))?;
+        .ok_or_else(|| anyhow!("No supported audio track in {:?}", path))?;
 
     let dec_opts: DecoderOptions = Default::default();
-    let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &dec_opts)?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &dec_opts)
+        .with_context(|| format!("Unsupported audio codec in {:?}", path))?;
 
     let track_id = track.id;
     let mut samples: Vec<f32> = Vec::new();
@@ -428,7 +1155,7 @@ fn load_audio(path: &Path) -> Result<Vec<f32>> {
                 }
             }
             Err(symphonia::core::errors::Error::IoError(_)) => break,
-            Err(e) => return Err(anyhow!("Decode error: {}", e)),
+            Err(e) => return Err(anyhow!("Decode error in {:?}: {}", path, e)),
         }
     }
 
@@ -441,9 +1168,12 @@ fn load_audio(path: &Path) -> Result<Vec<f32>> {
             oversampling_factor: 256,
             window: WindowFunction::BlackmanHarris2,
         };
-        let mut resampler = SincFixedIn::<f32>::new(ratio, ratio, params, samples.len(), 1)?;
+        let mut resampler = SincFixedIn::<f32>::new(ratio, ratio, params, samples.len(), 1)
+            .with_context(|| format!("Failed to create a resampler for {} Hz", sample_rate))?;
         let waves_in = vec![samples];
-        let waves_out = resampler.process(&waves_in, None)?;
+        let waves_out = resampler
+            .process(&waves_in, None)
+            .with_context(|| format!("Failed to resample {:?} to 16 kHz", path))?;
         Ok(waves_out[0].clone())
     } else {
         Ok(samples)
@@ -457,39 +1187,185 @@ pub struct AlignedSegment {
     end: String,
     speaker: String,
     text: String,
+    /// Mean posterior of the segment's tokens at their aligned frames, `None`
+    /// if the timestamps were kept or the text was transcribed.
+    confidence: Option<f32>,
+}
+
+/// The model in `slot`, loaded with `load` if the slot is empty or holds a
+/// model that doesn't `fit` the request (e.g. one for another device).
+fn cached_model<M>(
+    slot: &mut Option<M>,
+    fits: impl Fn(&M) -> bool,
+    load: impl FnOnce() -> Result<M>,
+) -> Result<&mut M> {
+    if !slot.as_ref().is_some_and(fits) {
+        // Free the old model before loading the new one
+        *slot = None;
+        *slot = Some(load()?);
+    }
+    Ok(slot.as_mut().expect("model was just loaded"))
+}
+
+/// Runs `f` with the cached alignment model on a blocking thread. Downloads,
+/// loading and inference take seconds to minutes and must not stall the async
+/// runtime; calls wait for each other, as they share the model.
+async fn with_model_slot<T, F>(state: &AppState, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Option<ParakeetModel>) -> Result<T, String> + Send + 'static,
+{
+    let slot = Arc::clone(&state.parakeet);
+    tokio::task::spawn_blocking(move || f(&mut slot.lock().unwrap()))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Downloads (on first use) and loads the alignment model ahead of the first
+/// `align_transcript` call.
+#[tauri::command]
+pub async fn preload_alignment_model(
+    state: tauri::State<'_, AppState>,
+    device: Option<InferenceDevice>,
+) -> Result<(), String> {
+    let device = device.unwrap_or_default();
+    with_model_slot(&state, move |slot| {
+        cached_model(slot, |m| m.device() == device, || ParakeetModel::download(device))
+            // `{:#}` keeps the whole context chain, e.g. which model file failed
+            .map_err(|e| format!("{:#}", e))?;
+        Ok(())
+    })
+    .await
 }
 
+/// Replaces the alignment model with one from `model_dir`, see
+/// `ParakeetModel::from_local_path`. It is used by `align_transcript` until it
+/// is unloaded or another device is requested.
+#[tauri::command]
+pub async fn load_local_alignment_model(
+    state: tauri::State<'_, AppState>,
+    model_dir: String,
+    device: Option<InferenceDevice>,
+    blank_token: Option<String>,
+) -> Result<(), String> {
+    with_model_slot(&state, move |slot| {
+        // Free the old model before loading the new one
+        *slot = None;
+        let model = ParakeetModel::from_local_path(
+            Path::new(&model_dir),
+            device.unwrap_or_default(),
+            blank_token.as_deref(),
+        )
+        .map_err(|e| format!("{:#}", e))?;
+        info!("Loaded alignment model from {}", model_dir);
+        *slot = Some(model);
+        Ok(())
+    })
+    .await
+}
+
+/// Frees the memory of the loaded alignment model, after a running
+/// `align_transcript` call has finished.
+#[tauri::command]
+pub async fn unload_alignment_model(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    with_model_slot(&state, |slot| {
+        if slot.take().is_some() {
+            info!("Alignment model unloaded");
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Force-aligns the text of `transcript` to the audio, see
+/// `ParakeetModel::force_align_segments`. Without a transcript the audio is
+/// transcribed by the local model instead, which is the only case that uses
+/// `glossary_boost` and `parallel_chunks`.
+///
+/// The model stays loaded in `AppState` for later calls, see
+/// `unload_alignment_model`.
 #[tauri::command]
 pub async fn align_transcript(
+    state: tauri::State<'_, AppState>,
     window: tauri::Window,
     audio_path: String,
-    _transcript: Vec<Segment>,
+    transcript: Vec<TranscriptSegment>,
+    glossary_boost: Option<Vec<String>>,
+    device: Option<InferenceDevice>,
+    parallel_chunks: Option<bool>,
 ) -> Result<Vec<AlignedSegment>, String> {
-    window
-        .emit("progress", "Downloading alignment model...")
-        .map_err(|e| e.to_string())?;
+    let device = device.unwrap_or_default();
+    with_model_slot(&state, move |slot| {
+        align_with_cached_model(
+            slot,
+            &window,
+            &audio_path,
+            &transcript,
+            glossary_boost,
+            device,
+            parallel_chunks.unwrap_or(false),
+        )
+    })
+    .await
+}
 
-    let mut model =
-        ParakeetModel::download().map_err(|e| format!("Failed to download model: {}", e))?;
+fn align_with_cached_model(
+    slot: &mut Option<ParakeetModel>,
+    window: &tauri::Window,
+    audio_path: &str,
+    transcript: &[TranscriptSegment],
+    glossary_boost: Option<Vec<String>>,
+    device: InferenceDevice,
+    parallel_chunks: bool,
+) -> Result<Vec<AlignedSegment>, String> {
+    let model = cached_model(slot, |m| m.device() == device, || {
+        let _ = window.emit(
+            "progress",
+            ProgressEvent::message("align_transcript", 0.0, "Downloading alignment model..."),
+        );
+        ParakeetModel::download(device)
+    })
+    .map_err(|e| format!("{:#}", e))?;
+
+    // The glossary of an earlier call must not stick to the cached model
+    let bias = glossary_boost
+        .map(|glossary| vec![model.glossary_to_bias(&glossary, DEFAULT_GLOSSARY_BOOST)])
+        .unwrap_or_default();
+    model.set_vocab_bias(bias);
 
     window
-        .emit("progress", "Aligning...")
+        .emit(
+            "progress",
+            ProgressEvent::message("align_transcript", 20.0, "Aligning..."),
+        )
         .map_err(|e| e.to_string())?;
 
-    let audio = load_audio(Path::new(&audio_path)).map_err(|e| e.to_string())?;
-    let result = model.transcribe_batch(&audio).map_err(|e| e.to_string())?;
-
-    let aligned: Vec<AlignedSegment> = result
-        .segments
-        .into_iter()
-        .map(|s| AlignedSegment {
-            start: format_timestamp(s.start),
-            end: format_timestamp(s.end),
-            speaker: "Local".to_string(),
-            text: s.text,
-        })
-        .collect();
-
+    let audio = load_audio(Path::new(audio_path)).map_err(|e| format!("{:#}", e))?;
+    let aligned = if transcript.is_empty() {
+        let result = model
+            .transcribe_batch(&audio, parallel_chunks)
+            .map_err(|e| format!("{:#}", e))?;
+        result
+            .segments
+            .into_iter()
+            .map(|s| AlignedSegment {
+                start: format_timestamp(s.start),
+                end: format_timestamp(s.end),
+                speaker: "Local".to_string(),
+                text: s.text,
+                confidence: None,
+            })
+            .collect()
+    } else {
+        model
+            .force_align_segments(&audio, transcript)
+            .map_err(|e| format!("{:#}", e))?
+    };
+
+    let _ = window.emit(
+        "progress",
+        ProgressEvent::message("align_transcript", 100.0, "Alignment done"),
+    );
     Ok(aligned)
 }
 
@@ -499,6 +1375,211 @@ mod tests {
     use std::io::Write;
     use tempfile::NamedTempFile;
 
+    #[test]
+    fn test_local_model_missing_file_is_named() {
+        let files = ["encoder.onnx", "decoder.onnx", "feature_extractor.onnx", "vocab.txt"];
+        for missing in files {
+            let dir = tempfile::tempdir().unwrap();
+            for name in files.iter().filter(|n| **n != missing) {
+                std::fs::write(dir.path().join(name), b"").unwrap();
+            }
+
+            let err = ParakeetModel::from_local_path(dir.path(), InferenceDevice::Cpu, None)
+                .err()
+                .unwrap();
+            assert!(format!("{:#}", err).contains(missing), "{:#}", err);
+        }
+
+        // vocab.json is preferred over vocab.txt, with the configured blank token
+        let dir = tempfile::tempdir().unwrap();
+        for name in files {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        std::fs::write(dir.path().join("vocab.json"), r#"{"a": 0}"#).unwrap();
+        let err = ParakeetModel::from_local_path(dir.path(), InferenceDevice::Cpu, Some("<ctc>"))
+            .err()
+            .unwrap();
+        let message = format!("{:#}", err);
+        assert!(message.contains("<ctc>") && message.contains("vocab.json"), "{}", message);
+
+        let missing_dir = Path::new("/nonexistent/model");
+        let err = ParakeetModel::from_local_path(missing_dir, InferenceDevice::Cpu, None)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("/nonexistent/model"), "{}", err);
+    }
+
+    #[test]
+    fn test_missing_model_file_is_named() {
+        let dir = tempfile::tempdir().unwrap();
+        let vocab = dir.path().join("vocab.txt");
+        std::fs::write(&vocab, "<blk> 0\n").unwrap();
+        for name in ["encoder.onnx", "decoder.onnx", "feature_extractor.onnx"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let err = ParakeetModel::from_files(
+            &dir.path().join("wrong/encoder.onnx"),
+            &dir.path().join("decoder.onnx"),
+            &dir.path().join("feature_extractor.onnx"),
+            &vocab,
+            None,
+            InferenceDevice::Cpu,
+        )
+        .err()
+        .unwrap();
+        assert!(format!("{:#}", err).contains("encoder.onnx"), "{:#}", err);
+
+        let err = ParakeetModel::from_files(
+            &dir.path().join("encoder.onnx"),
+            &dir.path().join("decoder.onnx"),
+            &dir.path().join("feature_extractor.onnx"),
+            &dir.path().join("missing_vocab.txt"),
+            None,
+            InferenceDevice::Cpu,
+        )
+        .err()
+        .unwrap();
+        assert!(format!("{:#}", err).contains("missing_vocab.txt"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_cached_model_loads_once() {
+        let loads = std::cell::Cell::new(0);
+        let load = |device: InferenceDevice| {
+            loads.set(loads.get() + 1);
+            Ok(device)
+        };
+        let mut slot = None;
+
+        for _ in 0..2 {
+            let model = cached_model(&mut slot, |m| *m == InferenceDevice::Cpu, || {
+                load(InferenceDevice::Cpu)
+            })
+            .unwrap();
+            assert_eq!(*model, InferenceDevice::Cpu);
+        }
+        assert_eq!(loads.get(), 1);
+
+        // Another device replaces the cached model
+        cached_model(&mut slot, |m| *m == InferenceDevice::Cuda(0), || {
+            load(InferenceDevice::Cuda(0))
+        })
+        .unwrap();
+        assert_eq!(loads.get(), 2);
+        assert_eq!(slot, Some(InferenceDevice::Cuda(0)));
+
+        // A failed load leaves the slot empty
+        let failed = cached_model(&mut slot, |_| false, || Err(anyhow!("offline")));
+        assert!(failed.is_err());
+        assert_eq!(slot, None);
+    }
+
+    #[test]
+    fn test_chunk_ranges() {
+        // 100s at 16 kHz: chunks start every 27s
+        let ranges = chunk_ranges(1_600_000, CHUNK_SAMPLES, CHUNK_OVERLAP_SAMPLES);
+        assert_eq!(
+            ranges,
+            [0..480_000, 432_000..912_000, 864_000..1_344_000, 1_296_000..1_600_000]
+        );
+        let ranges = chunk_ranges(480_000, CHUNK_SAMPLES, CHUNK_OVERLAP_SAMPLES);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0], 0..480_000);
+        assert!(chunk_ranges(0, CHUNK_SAMPLES, CHUNK_OVERLAP_SAMPLES).is_empty());
+    }
+
+    #[test]
+    fn test_parallel_chunks_match_sequential() {
+        let chunks = chunk_ranges(1_600_000, CHUNK_SAMPLES, CHUNK_OVERLAP_SAMPLES);
+        let run = |workers: usize| {
+            let mut processed = Vec::new();
+            process_chunks(
+                chunks.clone(),
+                workers,
+                |range: Range<usize>| Ok(range.len()),
+                |range, len| {
+                    processed.push((range.start, len));
+                    Ok(())
+                },
+            )
+            .unwrap();
+            processed
+        };
+
+        let sequential = run(1);
+        assert_eq!(sequential.len(), 4);
+        assert_eq!(run(3), sequential);
+
+        let failing = process_chunks(
+            chunks,
+            3,
+            |range: Range<usize>| match range.start {
+                0 => Ok(()),
+                start => Err(anyhow!("Chunk at {} failed", start)),
+            },
+            |_, _| Ok(()),
+        );
+        assert_eq!(failing.unwrap_err().to_string(), "Chunk at 432000 failed");
+    }
+
+    #[test]
+    fn test_parallel_chunks_overlap() {
+        let chunks = chunk_ranges(1_600_000, CHUNK_SAMPLES, CHUNK_OVERLAP_SAMPLES);
+        // Each extraction uses its own object from the pool, like the sessions
+        let pool = Pool::new(0, || Ok(0));
+        let (running, max_running) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        process_chunks(
+            chunks,
+            2,
+            |_| {
+                pool.with(|_| {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(())
+                })
+            },
+            |_, _| Ok(()),
+        )
+        .unwrap();
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        // The first object and one more for the second worker
+        assert_eq!(pool.idle.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_feature_extractor_providers() {
+        assert!(feature_extractor_providers(InferenceDevice::Cpu).is_empty());
+        let device: InferenceDevice = serde_json::from_str(r#"{"Cuda":1}"#).unwrap();
+        assert_eq!(device, InferenceDevice::Cuda(1));
+        #[cfg(not(feature = "cuda"))]
+        assert!(feature_extractor_providers(device).is_empty());
+    }
+
+    #[cfg(feature = "cuda")]
+    #[test]
+    fn test_cuda_provider_selected() {
+        let providers = feature_extractor_providers(InferenceDevice::Cuda(0));
+        assert_eq!(providers.len(), 1);
+        // Registering must not fail without a GPU, ONNX Runtime falls back to the CPU
+        Session::builder()
+            .and_then(|b| b.with_execution_providers(providers))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_load_audio_error_names_file() {
+        let err = load_audio(Path::new("/nonexistent/interview.wav")).unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains("interview.wav"), "{}", message);
+        // The io error is kept in the chain
+        let io_error = err.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(io_error.kind(), std::io::ErrorKind::NotFound);
+    }
+
     #[test]
     fn test_vocab_info() {
         let mut file = NamedTempFile::new().unwrap();
@@ -506,7 +1587,7 @@ mod tests {
         writeln!(file, "world 1").unwrap();
         writeln!(file, "<blk> 2").unwrap();
 
-        let vocab = VocabInfo::from_file(file.path()).unwrap();
+        let vocab = VocabInfo::from_file(file.path(), None).unwrap();
 
         assert_eq!(vocab.vocab_size, 3);
         assert_eq!(vocab.blank_id, 2);
@@ -514,6 +1595,165 @@ mod tests {
         assert_eq!(vocab.token_of(1), Some("world"));
         assert_eq!(vocab.token_of(2), Some("<blk>"));
         assert_eq!(vocab.token_of(3), None);
+
+        // Configured blank token
+        let vocab = VocabInfo::from_file(file.path(), Some("world")).unwrap();
+        assert_eq!(vocab.blank_id, 1);
+        let err = VocabInfo::from_file(file.path(), Some("<eps>")).err().unwrap();
+        assert!(err.to_string().contains("<eps>"), "{}", err);
+    }
+
+    #[test]
+    fn test_vocab_info_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, json: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, json).unwrap();
+            path
+        };
+
+        let path = write("vocab.json", r#"{"hello": 0, "world": 1, "<blank>": 2}"#);
+        let vocab = VocabInfo::from_file(&path, None).unwrap();
+        assert_eq!(vocab.vocab_size, 3);
+        assert_eq!(vocab.blank_id, 2);
+        assert_eq!(vocab.token_of(0), Some("hello"));
+        assert_eq!(vocab.token_of(2), Some("<blank>"));
+
+        let path = write("brackets.json", r#"{"[blank]": 0, "a": 1}"#);
+        assert_eq!(VocabInfo::from_json(&path, None).unwrap().blank_id, 0);
+
+        // The word boundary is only the blank without an explicit marker
+        let path = write("sentencepiece.json", r#"{"\u2581": 0, "\u2581a": 1}"#);
+        assert_eq!(VocabInfo::from_json(&path, None).unwrap().blank_id, 0);
+        let path = write("both.json", r#"{"\u2581": 0, "<blank>": 1}"#);
+        assert_eq!(VocabInfo::from_json(&path, None).unwrap().blank_id, 1);
+
+        // Configured blank token
+        let path = write("custom.json", r#"{"<ctc>": 0, "<blank>": 1}"#);
+        let vocab = VocabInfo::from_file(&path, Some("<ctc>")).unwrap();
+        assert_eq!(vocab.blank_id, 0);
+        assert!(VocabInfo::from_file(&path, Some("<eps>")).is_err());
+
+        let path = write("no_blank.json", r#"{"a": 0, "b": 1}"#);
+        assert!(VocabInfo::from_file(&path, None).is_err());
+        let path = write("invalid.json", "a 0\nb 1");
+        assert!(VocabInfo::from_file(&path, None).is_err());
+    }
+
+    #[test]
+    fn test_vocab_bias_boosts_token() {
+        let mut logits = vec![1.0, 3.0, 2.5, 0.5];
+        let softmax = |xs: &[f32]| {
+            let sum: f32 = xs.iter().map(|x| x.exp()).sum();
+            xs.iter().map(|x| x.exp() / sum).collect::<Vec<_>>()
+        };
+        let before = softmax(&logits)[2];
+        assert_eq!(argmax_index(&logits).0, 1);
+
+        apply_vocab_bias(
+            &mut logits,
+            &[VocabBias {
+                token_ids: vec![2, 99], // out-of-range ids are ignored
+                boost: 1.0,
+            }],
+        );
+
+        assert!(softmax(&logits)[2] > before);
+        assert_eq!(argmax_index(&logits).0, 2);
+    }
+
+    #[test]
+    fn test_vocab_id_of_word() {
+        let mut id_to_token = HashMap::new();
+        id_to_token.insert(0, "▁aspirin".to_string());
+        id_to_token.insert(1, "acme".to_string());
+        id_to_token.insert(2, "<blk>".to_string());
+        let vocab = VocabInfo {
+            id_to_token,
+            vocab_size: 3,
+            blank_id: 2,
+        };
+
+        assert_eq!(vocab.id_of_word("aspirin"), Some(0));
+        assert_eq!(vocab.id_of_word("acme"), Some(1));
+        assert_eq!(vocab.id_of_word("ibuprofen"), None);
+    }
+
+    /// Builds a `[frames x vocab]` log-prob grid from per-frame probabilities.
+    fn ctc_grid(probs: &[&[f32]]) -> Vec<f32> {
+        probs.iter().flat_map(|f| f.iter().map(|p| p.ln())).collect()
+    }
+
+    #[test]
+    fn test_ctc_forced_align() {
+        // vocab: 0 = blank, 1 = "a", 2 = "b"
+        let grid = ctc_grid(&[
+            &[0.8, 0.1, 0.1],
+            &[0.1, 0.8, 0.1],
+            &[0.6, 0.3, 0.1],
+            &[0.2, 0.1, 0.7],
+            &[0.9, 0.05, 0.05],
+        ]);
+
+        let aligned = ctc_forced_align(&grid, &[1, 2], 3, 0);
+        assert_eq!(aligned.len(), 2);
+        assert_eq!(aligned[0].0, 1);
+        assert!((aligned[0].1 - 0.8).abs() < 1e-5);
+        assert_eq!(aligned[1].0, 3);
+        assert!((aligned[1].1 - 0.7).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_ctc_forced_align_follows_text_not_argmax() {
+        // Frame 1 prefers "b", but the text is "a a": the path has to emit "a",
+        // then a blank separating the repeated token, then "a" again.
+        let grid = ctc_grid(&[
+            &[0.1, 0.8, 0.1],
+            &[0.2, 0.2, 0.6],
+            &[0.1, 0.7, 0.2],
+        ]);
+
+        let frames = |aligned: Vec<(usize, f32)>| aligned.iter().map(|a| a.0).collect::<Vec<_>>();
+        assert_eq!(frames(ctc_forced_align(&grid, &[1, 1], 3, 0)), vec![0, 2]);
+
+        // Logits are normalized per frame, so shifting them doesn't change the path
+        let logits: Vec<f32> = grid.iter().map(|x| x + 5.0).collect();
+        assert_eq!(frames(ctc_forced_align(&logits, &[1, 1], 3, 0)), vec![0, 2]);
+    }
+
+    #[test]
+    fn test_ctc_forced_align_too_few_frames() {
+        let grid = ctc_grid(&[&[0.1, 0.8, 0.1], &[0.1, 0.8, 0.1]]);
+        // Repeated tokens need a blank in between: 3 frames minimum
+        assert!(ctc_forced_align(&grid, &[1, 1], 3, 0).is_empty());
+        assert!(ctc_forced_align(&grid, &[], 3, 0).is_empty());
+    }
+
+    #[test]
+    fn test_aligned_span() {
+        // Window from 10s, 80 ms frames
+        let (start, end, confidence) =
+            aligned_span(&[(2, 0.8), (5, 0.6), (6, 0.4)], 10.0, 0.08).unwrap();
+        assert!((start - 10.16).abs() < 1e-4);
+        assert!((end - 10.56).abs() < 1e-4);
+        assert!((confidence - 0.6).abs() < 1e-6);
+        assert!(aligned_span(&[], 10.0, 0.08).is_none());
+    }
+
+    #[test]
+    fn test_vocab_tokenize() {
+        let tokens = ["<blk>", "▁hel", "lo", "▁hello", "▁wor", "ld", "▁a", "!"];
+        let vocab = VocabInfo {
+            id_to_token: tokens.iter().enumerate().map(|(id, t)| (id, t.to_string())).collect(),
+            vocab_size: tokens.len(),
+            blank_id: 0,
+        };
+
+        // Longest match first, word starts only match `▁` tokens
+        assert_eq!(vocab.tokenize("hello world!"), vec![3, 4, 5, 7]);
+        // Unknown characters are skipped
+        assert_eq!(vocab.tokenize("a  ölo"), vec![6, 2]);
+        assert!(vocab.tokenize("").is_empty());
     }
 
     #[test]
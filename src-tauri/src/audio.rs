@@ -0,0 +1,1230 @@
+use crate::probe::{probe_media, MediaInfo, MediaType};
+use crate::time_utils::parse_timestamp_to_seconds_raw;
+use crate::video::{base_ffmpeg_command, base_ffmpeg_command_with_log_level, run_ffmpeg};
+use anyhow::{anyhow, Result};
+use ffmpeg_sidecar::event::FfmpegEvent;
+use log::{debug, info};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use symphonia::core::audio::AudioBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Longest preview we extract, regardless of the requested range.
+const MAX_PREVIEW_SECS: f64 = 30.0;
+
+fn preview_dir() -> PathBuf {
+    std::env::temp_dir().join("ai-media-cutter-previews")
+}
+
+#[tauri::command]
+pub async fn extract_clip_preview_audio(
+    input_path: String,
+    start: String,
+    end: String,
+) -> Result<String, String> {
+    let output = extract_preview_audio(Path::new(&input_path), &start, &end, &preview_dir())
+        .map_err(|e| e.to_string())?;
+    Ok(output.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn cleanup_temp_previews() -> Result<(), String> {
+    let dir = preview_dir();
+    if dir.exists() {
+        info!("Removing preview directory {:?}", dir);
+        std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Extracts a small mono OGG (22050 Hz, at most 30 s) for scrubbing in the frontend.
+/// Repeated requests for the same range reuse the same file name.
+fn extract_preview_audio(input: &Path, start: &str, end: &str, out_dir: &Path) -> Result<PathBuf> {
+    if !input.exists() {
+        return Err(anyhow!("Input file does not exist"));
+    }
+    std::fs::create_dir_all(out_dir)?;
+
+    let mut hasher = DefaultHasher::new();
+    (input, start, end).hash(&mut hasher);
+    let output_path = out_dir.join(format!("preview_{:x}.ogg", hasher.finish()));
+
+    // ffmpeg -ss start -t duration -i input -vn -ac 1 -ar 22050 -c:a libvorbis -q:a 2 preview.ogg
+    base_ffmpeg_command()
+        .args(build_preview_input_args(start, end)?)
+        .input(input.to_str().unwrap())
+        .args(["-y", "-vn", "-ac", "1", "-ar", "22050", "-c:a", "libvorbis", "-q:a", "2"])
+        .output(output_path.to_str().unwrap())
+        .spawn()?
+        .iter()?
+        .for_each(|event| {
+            if let FfmpegEvent::Log(_, msg) = event {
+                debug!("[FFmpeg Preview] {}", msg);
+            }
+        });
+
+    if !output_path.exists() {
+        return Err(anyhow!("FFmpeg failed to create preview {:?}", output_path));
+    }
+    Ok(output_path)
+}
+
+/// Input seek arguments for the preview, clamping the duration to `MAX_PREVIEW_SECS`.
+fn build_preview_input_args(start: &str, end: &str) -> Result<Vec<String>> {
+    let start_secs = parse_timestamp_to_seconds_raw(start)?;
+    let end_secs = parse_timestamp_to_seconds_raw(end)?;
+    if end_secs <= start_secs {
+        return Err(anyhow!("Preview end {} must be after start {}", end, start));
+    }
+    let duration = (end_secs - start_secs).min(MAX_PREVIEW_SECS);
+
+    Ok(vec![
+        "-ss".to_string(),
+        format!("{:.3}", start_secs),
+        "-t".to_string(),
+        format!("{:.3}", duration),
+    ])
+}
+
+/// Audio encoders we produce. Their quality settings use different flags and
+/// scales, see `codec_quality_args`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AudioFmt {
+    Vorbis,
+    Mp3,
+    Aac,
+    Opus,
+    Flac,
+}
+
+impl AudioFmt {
+    pub fn codec(&self) -> &'static str {
+        match self {
+            Self::Vorbis => "libvorbis",
+            Self::Mp3 => "libmp3lame",
+            Self::Aac => "aac",
+            Self::Opus => "libopus",
+            Self::Flac => "flac",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Vorbis => "audio/ogg",
+            Self::Mp3 => "audio/mpeg",
+            Self::Aac => "audio/aac",
+            Self::Opus => "audio/opus",
+            Self::Flac => "audio/flac",
+        }
+    }
+
+    /// Extension of the files we write in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Vorbis => "ogg",
+            Self::Mp3 => "mp3",
+            Self::Aac => "m4a",
+            Self::Opus => "opus",
+            Self::Flac => "flac",
+        }
+    }
+
+    /// Format of files with `extension`, if we can encode it.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "ogg" | "oga" => Some(Self::Vorbis),
+            "mp3" => Some(Self::Mp3),
+            "m4a" | "aac" => Some(Self::Aac),
+            "opus" => Some(Self::Opus),
+            "flac" => Some(Self::Flac),
+            _ => None,
+        }
+    }
+}
+
+/// Output path and format of `prepare_audio_for_ai`. The audio of a video is
+/// extracted to OGG next to it. Audio files keep their format and extension
+/// (OGG if we can't encode the format), as `<name>.ai.<ext>` so the input isn't
+/// overwritten.
+pub fn ai_audio_output(input_path: &Path, media_type: MediaType) -> (PathBuf, AudioFmt) {
+    if media_type == MediaType::Video {
+        return (input_path.with_extension("ogg"), AudioFmt::Vorbis);
+    }
+    let extension = input_path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match AudioFmt::from_extension(&extension) {
+        Some(format) => (input_path.with_extension(format!("ai.{}", extension)), format),
+        None => (input_path.with_extension("ai.ogg"), AudioFmt::Vorbis),
+    }
+}
+
+/// One output of `convert_audio_formats`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct AudioConvertFormat {
+    pub format: AudioFmt,
+    /// 0 (smallest) to 10 (best), see `codec_quality_args`.
+    pub quality: Option<i32>,
+}
+
+/// Output path of `convert_audio_formats` for `format`, named like the output
+/// of `ai_audio_output` so audio inputs aren't overwritten.
+fn converted_audio_output(input_path: &Path, media_type: MediaType, format: AudioFmt) -> PathBuf {
+    if media_type == MediaType::Video {
+        input_path.with_extension(format.extension())
+    } else {
+        input_path.with_extension(format!("ai.{}", format.extension()))
+    }
+}
+
+/// Encodes the first audio stream of `input_path` into every format of
+/// `formats` in a single FFmpeg pass, so the input is only decoded once.
+/// `info` is the probed input. Returns the output paths in the order of
+/// `formats`.
+pub fn convert_audio_formats<F>(
+    input_path: &Path,
+    info: &MediaInfo,
+    formats: &[AudioConvertFormat],
+    on_progress: F,
+) -> Result<Vec<PathBuf>>
+where
+    F: Fn(String),
+{
+    if !input_path.exists() {
+        return Err(anyhow!("Input file does not exist"));
+    }
+    if formats.is_empty() {
+        return Err(anyhow!("No output format given"));
+    }
+    if info.audio_streams().next().is_none() {
+        return Err(anyhow!("No audio stream found in {:?}", input_path));
+    }
+
+    let mut outputs: Vec<(PathBuf, AudioConvertFormat)> = Vec::new();
+    for format in formats {
+        let path = converted_audio_output(input_path, info.media_type(), format.format);
+        if outputs.iter().any(|(existing, _)| *existing == path) {
+            return Err(anyhow!("{:?} is requested more than once", format.format));
+        }
+        // e.g. Vorbis from clip.ogg, the input would be deleted below. Ignoring
+        // case, as clip.OGG is the same file on case-insensitive filesystems.
+        if path.to_string_lossy().eq_ignore_ascii_case(&input_path.to_string_lossy()) {
+            return Err(anyhow!(
+                "Converting {:?} to {:?} would overwrite the input",
+                input_path,
+                format.format
+            ));
+        }
+        outputs.push((path, *format));
+    }
+    // Overwritten by a new run, so stale files must not count as success
+    for (path, _) in &outputs {
+        let _ = std::fs::remove_file(path);
+    }
+
+    info!(
+        "Converting {:?} to {} formats in one pass",
+        input_path,
+        outputs.len()
+    );
+    // ffmpeg -i input -y -map 0:a:0 -c:a libvorbis -q:a 4 out.ogg -map 0:a:0 -c:a libmp3lame ... out.mp3
+    let mut command = base_ffmpeg_command();
+    command
+        .input(input_path.to_str().unwrap())
+        .args(build_multi_format_args(&outputs));
+    run_ffmpeg(&mut command, &outputs[0].0, on_progress)?;
+
+    if let Some((missing, _)) = outputs.iter().find(|(path, _)| !path.exists()) {
+        return Err(anyhow!("FFmpeg failed to create {:?}", missing));
+    }
+    Ok(outputs.into_iter().map(|(path, _)| path).collect())
+}
+
+/// Output arguments writing the first audio stream to each of `outputs`.
+fn build_multi_format_args(outputs: &[(PathBuf, AudioConvertFormat)]) -> Vec<String> {
+    let mut args = vec!["-y".to_string()];
+    for (path, format) in outputs {
+        args.extend([
+            "-map".to_string(),
+            "0:a:0".to_string(),
+            "-c:a".to_string(),
+            format.format.codec().to_string(),
+        ]);
+        args.extend(codec_quality_args(&format.format, format.quality));
+        args.push(path.to_string_lossy().to_string());
+    }
+    args
+}
+
+/// Quality on the common 0 (smallest) to 10 (best) scale, the former fixed `-q:a 4`.
+pub const DEFAULT_AUDIO_QUALITY: i32 = 4;
+
+/// Encoder quality arguments for a quality from 0 (smallest) to 10 (best);
+/// values outside are clamped. Vorbis and MP3 use VBR `-q:a` (LAME's scale is
+/// inverted, 0 is best), AAC and Opus a bitrate from 32 to 192 kbit/s, and
+/// FLAC, which is lossless, the compression level.
+pub fn codec_quality_args(format: &AudioFmt, quality: Option<i32>) -> Vec<String> {
+    let quality = quality.unwrap_or(DEFAULT_AUDIO_QUALITY).clamp(0, 10);
+    let (flag, value) = match format {
+        AudioFmt::Vorbis => ("-q:a", quality.to_string()),
+        AudioFmt::Mp3 => ("-q:a", ((10 - quality) * 9 / 10).to_string()),
+        AudioFmt::Aac | AudioFmt::Opus => ("-b:a", format!("{}k", 32 + quality * 16)),
+        AudioFmt::Flac => ("-compression_level", quality.to_string()),
+    };
+    vec![flag.to_string(), value]
+}
+
+/// Expected upload size above which `prepare_audio_for_ai` downmixes to mono,
+/// just below Gemini's 20 MB inline request limit.
+pub const DEFAULT_MONO_DOWNMIX_THRESHOLD_MB: f64 = 19.0;
+
+/// Nominal stereo Vorbis bitrates (kbit/s) for `-q:a 0` to `10`.
+const VORBIS_STEREO_KBPS: [f64; 11] = [
+    64.0, 80.0, 96.0, 112.0, 128.0, 160.0, 192.0, 224.0, 256.0, 320.0, 500.0,
+];
+
+/// Estimated size of the OGG `prepare_audio_for_ai` produces at the given
+/// Vorbis quality (e.g. ~64 kbit/s per channel at `-q:a 4`).
+pub fn estimate_ai_audio_size(duration: f64, channels: u32, quality: Option<i32>) -> u64 {
+    let quality = quality.unwrap_or(DEFAULT_AUDIO_QUALITY).clamp(0, 10) as usize;
+    let bytes_per_channel_sec = VORBIS_STEREO_KBPS[quality] * 1000.0 / 8.0 / 2.0;
+    (duration.max(0.0) * channels as f64 * bytes_per_channel_sec) as u64
+}
+
+/// Encoder arguments for the AI upload, see `ai_audio_output` for the format.
+/// Multi-channel audio whose expected size (estimated as Vorbis) exceeds
+/// `mono_threshold_bytes` is downmixed to mono, halving the size (stereo).
+/// `audio_stream_index` selects one of the audio streams (e.g. the speech track
+/// of a file with music on the first one) instead of FFmpeg's default choice,
+/// and must exist in `info`.
+pub fn build_ai_audio_args(
+    info: &MediaInfo,
+    format: AudioFmt,
+    mono_threshold_bytes: u64,
+    quality: Option<i32>,
+    audio_stream_index: Option<u32>,
+) -> Result<Vec<String>> {
+    let mut args = vec!["-vn".to_string()];
+    let stream = match audio_stream_index {
+        Some(index) => {
+            let stream_count = info.audio_streams().count();
+            let stream = info.audio_streams().nth(index as usize).ok_or_else(|| {
+                anyhow!(
+                    "Audio stream {} does not exist, the file has {} audio stream(s)",
+                    index,
+                    stream_count
+                )
+            })?;
+            args.extend(["-map".to_string(), format!("0:a:{}", index)]);
+            Some(stream)
+        }
+        None => info.audio_streams().next(),
+    };
+    args.extend(["-c:a".to_string(), format.codec().to_string()]);
+    args.extend(codec_quality_args(&format, quality));
+
+    let channels = stream.and_then(|s| s.channels).unwrap_or(1);
+    let expected_size = estimate_ai_audio_size(info.duration, channels, quality);
+    if channels > 1 && expected_size > mono_threshold_bytes {
+        info!(
+            "Expected audio size {} bytes exceeds {} bytes, downmixing {} channels to mono (~{} bytes)",
+            expected_size,
+            mono_threshold_bytes,
+            channels,
+            expected_size / channels as u64
+        );
+        args.extend(["-ac".to_string(), "1".to_string()]);
+    }
+    Ok(args)
+}
+
+/// Noise gate used to tell speech from silence, same as `detect_silence`.
+const QUALITY_SILENCE_THRESHOLD: &str = "-30dB";
+/// Used instead of an infinite SNR for digitally silent noise floors (16-bit range).
+const MAX_SNR_DB: f64 = 96.0;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AudioQualityReport {
+    /// RMS level above the noise floor.
+    pub snr_db: f64,
+    /// Fraction of samples at full scale.
+    pub clipping_fraction: f64,
+    pub silence_fraction: f64,
+    pub speech_fraction: f64,
+    /// Half the zero-crossing rate times the sample rate, averaged over the
+    /// channels and the whole file. This is exact only for a single tone:
+    /// harmonics and broadband noise push it up, silent parts pull it towards
+    /// the noise, so for speech it is only good for telling a muffled from a
+    /// hissy recording, not for pitch. No spectral analysis (e.g. an extra
+    /// `aeval` pass) is done.
+    pub dominant_frequency_hz: f64,
+    /// 0.0 (unusable) to 1.0 (studio quality).
+    pub quality_score: f64,
+}
+
+/// The parts of the astats "Overall" summary we use.
+#[derive(Debug, Default, PartialEq)]
+struct AstatsSummary {
+    peak_level_db: f64,
+    rms_level_db: f64,
+    noise_floor_db: f64,
+    peak_count: f64,
+    /// Per channel.
+    samples: f64,
+    channels: u32,
+    zero_crossings_rate: f64,
+}
+
+#[tauri::command]
+pub async fn analyze_audio_quality(path: String) -> Result<AudioQualityReport, String> {
+    assess_audio_quality(Path::new(&path)).map_err(|e| e.to_string())
+}
+
+/// Runs astats and silencedetect over the whole file in one ffmpeg pass and
+/// combines the results with the probed duration and sample rate. The
+/// dominant frequency comes from astats' zero-crossing rate, see
+/// `AudioQualityReport::dominant_frequency_hz` for its limits.
+fn assess_audio_quality(input: &Path) -> Result<AudioQualityReport> {
+    if !input.exists() {
+        return Err(anyhow!("Input file does not exist"));
+    }
+    let info = probe_media(input)?;
+    let sample_rate = info
+        .audio_streams()
+        .next()
+        .and_then(|s| s.sample_rate)
+        .ok_or_else(|| anyhow!("No audio stream found in {:?}", input))?;
+
+    // ffmpeg -i input -vn -af astats=metadata=0,silencedetect=noise=-30dB:d=0.5 -f null -
+    let filter = format!(
+        "astats=metadata=0,silencedetect=noise={}:d=0.5",
+        QUALITY_SILENCE_THRESHOLD
+    );
+    let mut lines = Vec::new();
+    base_ffmpeg_command_with_log_level("level+info")
+        .input(input.to_str().unwrap())
+        .args(["-vn", "-af", &filter, "-f", "null", "-"])
+        .spawn()?
+        .iter()?
+        .for_each(|event| {
+            if let FfmpegEvent::Log(_, line) = event {
+                lines.push(line);
+            }
+        });
+
+    let astats = parse_astats(&lines).ok_or_else(|| anyhow!("No astats output from ffmpeg"))?;
+    let silence = total_silence(&lines, info.duration);
+    debug!("astats: {:?}, silence: {:.2}s of {:.2}s", astats, silence, info.duration);
+
+    Ok(build_quality_report(&astats, silence, info.duration, sample_rate))
+}
+
+fn parse_astats(lines: &[String]) -> Option<AstatsSummary> {
+    // With `level+info` the lines carry a `[info]` tag after the filter name
+    let re = Regex::new(r"\[Parsed_astats_\d+ @ [^\]]+\] (?:\[\w+\] )?(.+)$").unwrap();
+
+    let mut summary = AstatsSummary::default();
+    let mut in_overall = false;
+    let mut found = false;
+    let mut channel_zcr = Vec::new();
+
+    for line in lines {
+        let Some(caps) = re.captures(line.trim_end()) else {
+            continue;
+        };
+        let content = caps[1].trim();
+        if content == "Overall" {
+            in_overall = true;
+            found = true;
+            continue;
+        }
+        if content.starts_with("Channel:") {
+            in_overall = false;
+            summary.channels += 1;
+            continue;
+        }
+        let Some((key, value)) = content.split_once(':') else {
+            continue;
+        };
+        let Ok(value) = value.trim().parse::<f64>() else {
+            continue;
+        };
+
+        // Zero crossings are only reported per channel
+        if !in_overall {
+            if key == "Zero crossings rate" {
+                channel_zcr.push(value);
+            }
+            continue;
+        }
+        match key {
+            "Peak level dB" => summary.peak_level_db = value,
+            "RMS level dB" => summary.rms_level_db = value,
+            "Noise floor dB" => summary.noise_floor_db = value,
+            "Peak count" => summary.peak_count = value,
+            "Number of samples" => summary.samples = value,
+            _ => {}
+        }
+    }
+
+    if !channel_zcr.is_empty() {
+        summary.zero_crossings_rate = channel_zcr.iter().sum::<f64>() / channel_zcr.len() as f64;
+    }
+    summary.channels = summary.channels.max(1);
+    found.then_some(summary)
+}
+
+/// Sums the silencedetect intervals. A silence still open at the end of the
+/// log lasts until the end of the file.
+fn total_silence(lines: &[String], duration: f64) -> f64 {
+    let re_start = Regex::new(r"silence_start: (-?\d+(\.\d+)?)").unwrap();
+    let re_end = Regex::new(r"silence_end: (\d+(\.\d+)?)").unwrap();
+
+    let mut total = 0.0;
+    let mut current_start: Option<f64> = None;
+    for line in lines {
+        if let Some(caps) = re_start.captures(line) {
+            current_start = caps[1].parse::<f64>().ok().map(|s| s.max(0.0));
+        } else if let Some(caps) = re_end.captures(line) {
+            if let (Some(start), Ok(end)) = (current_start.take(), caps[1].parse::<f64>()) {
+                total += end - start;
+            }
+        }
+    }
+    if let Some(start) = current_start {
+        total += (duration - start).max(0.0);
+    }
+    total
+}
+
+fn build_quality_report(
+    astats: &AstatsSummary,
+    silence_secs: f64,
+    duration: f64,
+    sample_rate: u32,
+) -> AudioQualityReport {
+    let snr_db = (astats.rms_level_db - astats.noise_floor_db).clamp(0.0, MAX_SNR_DB);
+
+    // Only count peaks as clipping if they actually reach full scale
+    let clipping_fraction = if astats.peak_level_db >= -0.1 && astats.samples > 0.0 {
+        (astats.peak_count / (astats.samples * astats.channels as f64)).min(1.0)
+    } else {
+        0.0
+    };
+
+    let silence_fraction = if duration > 0.0 {
+        (silence_secs / duration).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let speech_fraction = 1.0 - silence_fraction;
+
+    // Two zero crossings per period
+    let dominant_frequency_hz = astats.zero_crossings_rate * sample_rate as f64 / 2.0;
+
+    let snr_score = ((snr_db - 10.0) / 30.0).clamp(0.0, 1.0);
+    // 0.1% clipped samples is already clearly audible
+    let clipping_score = 1.0 - (clipping_fraction * 1000.0).clamp(0.0, 1.0);
+    let speech_score = (speech_fraction / 0.5).clamp(0.0, 1.0);
+    let frequency_score = if (80.0..=4000.0).contains(&dominant_frequency_hz) {
+        1.0
+    } else {
+        0.5
+    };
+    let quality_score =
+        0.4 * snr_score + 0.25 * clipping_score + 0.25 * speech_score + 0.1 * frequency_score;
+
+    AudioQualityReport {
+        snr_db,
+        clipping_fraction,
+        silence_fraction,
+        speech_fraction,
+        dominant_frequency_hz,
+        quality_score,
+    }
+}
+
+/// Peak targets accepted by `peak_normalize`, in dBFS.
+pub const PEAK_TARGET_RANGE_DBFS: std::ops::RangeInclusive<f64> = -60.0..=0.0;
+
+/// volumedetect reports digital silence as -91 dB; nothing to normalize there.
+const SILENT_PEAK_DB: f64 = -90.0;
+
+/// Gain that moves the measured peak to the target.
+pub fn peak_gain_db(current_peak_db: f64, target_peak_dbfs: f64) -> f64 {
+    target_peak_dbfs - current_peak_db
+}
+
+/// First pass: `ffmpeg -i input -vn -af volumedetect -f null -`
+fn build_volumedetect_args(input: &Path) -> Vec<String> {
+    vec![
+        "-i".to_string(),
+        input.to_string_lossy().to_string(),
+        "-vn".to_string(),
+        "-af".to_string(),
+        "volumedetect".to_string(),
+        "-f".to_string(),
+        "null".to_string(),
+        "-".to_string(),
+    ]
+}
+
+/// Second pass: applies the gain to the audio and copies the video, if any.
+fn build_peak_gain_args(input: &Path, output: &Path, gain_db: f64) -> Vec<String> {
+    vec![
+        "-y".to_string(),
+        "-i".to_string(),
+        input.to_string_lossy().to_string(),
+        "-af".to_string(),
+        format!("volume={:.2}dB", gain_db),
+        "-c:v".to_string(),
+        "copy".to_string(),
+        output.to_string_lossy().to_string(),
+    ]
+}
+
+/// `max_volume` of the volumedetect summary.
+fn parse_max_volume(lines: &[String]) -> Option<f64> {
+    let re =
+        Regex::new(r"\[Parsed_volumedetect_\d+ @ [^\]]+\] max_volume: (-?\d+(\.\d+)?) dB").unwrap();
+    lines
+        .iter()
+        .find_map(|line| re.captures(line))
+        .and_then(|caps| caps[1].parse().ok())
+}
+
+/// Scales the audio so that its loudest sample reaches `target_peak_dbfs`.
+/// Unlike loudness normalization, this only guarantees the headroom, e.g. for
+/// broadcast deliveries. Returns the applied gain in dB.
+pub fn peak_normalize<F>(
+    input: &Path,
+    output: &Path,
+    target_peak_dbfs: f64,
+    on_progress: F,
+) -> Result<f64>
+where
+    F: Fn(String),
+{
+    peak_normalize_with(input, output, target_peak_dbfs, |args| {
+        let mut lines = Vec::new();
+        base_ffmpeg_command_with_log_level("level+info")
+            .args(args)
+            .spawn()?
+            .iter()?
+            .for_each(|event| match event {
+                FfmpegEvent::Progress(p) => on_progress(p.time),
+                FfmpegEvent::Log(_, line) => lines.push(line),
+                _ => {}
+            });
+        Ok(lines)
+    })
+}
+
+/// `peak_normalize` with the FFmpeg runner passed in; `run` gets the arguments
+/// of each pass and returns its log lines.
+fn peak_normalize_with<R>(
+    input: &Path,
+    output: &Path,
+    target_peak_dbfs: f64,
+    mut run: R,
+) -> Result<f64>
+where
+    R: FnMut(Vec<String>) -> Result<Vec<String>>,
+{
+    if !PEAK_TARGET_RANGE_DBFS.contains(&target_peak_dbfs) {
+        return Err(anyhow!(
+            "Target peak must be between {} and {} dBFS, got {}",
+            PEAK_TARGET_RANGE_DBFS.start(),
+            PEAK_TARGET_RANGE_DBFS.end(),
+            target_peak_dbfs
+        ));
+    }
+    if !input.exists() {
+        return Err(anyhow!("Input file does not exist"));
+    }
+
+    let lines = run(build_volumedetect_args(input))?;
+    let peak =
+        parse_max_volume(&lines).ok_or_else(|| anyhow!("No volumedetect output from ffmpeg"))?;
+    if peak <= SILENT_PEAK_DB {
+        return Err(anyhow!("{:?} is silent, can't normalize its peak", input));
+    }
+
+    let gain = peak_gain_db(peak, target_peak_dbfs);
+    info!(
+        "Peak {:.1} dB, applying {:+.2} dB to reach {:.1} dBFS",
+        peak, gain, target_peak_dbfs
+    );
+    run(build_peak_gain_args(input, output, gain))?;
+
+    if !output.exists() {
+        return Err(anyhow!("FFmpeg failed to create {:?}", output));
+    }
+    Ok(gain)
+}
+
+/// Length of the frames the RMS envelope for `find_audio_peaks` is computed
+/// over. Short enough to place a clapper within a frame at 25 fps.
+const PEAK_FRAME_SECS: f64 = 0.01;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AudioPeak {
+    /// Center of the loudest RMS frame.
+    pub timestamp_secs: f64,
+    /// RMS of that frame, 0.0 to 1.0.
+    pub amplitude: f64,
+}
+
+/// The `top_n` loudest moments (e.g. clapper boards) of the first audio track,
+/// loudest first and at least `min_gap_secs` apart, to sync camera angles.
+#[tauri::command]
+pub async fn find_audio_peaks(
+    path: String,
+    top_n: u32,
+    min_gap_secs: f64,
+) -> Result<Vec<AudioPeak>, String> {
+    if !(min_gap_secs.is_finite() && min_gap_secs >= 0.0) {
+        return Err(format!("Invalid minimum peak gap: {}", min_gap_secs));
+    }
+    let envelope = rms_envelope(Path::new(&path), PEAK_FRAME_SECS).map_err(|e| e.to_string())?;
+    Ok(pick_peaks(&envelope, PEAK_FRAME_SECS, top_n as usize, min_gap_secs))
+}
+
+/// Mono RMS per frame of `frame_secs`, decoded with symphonia while streaming
+/// so long recordings aren't held in memory.
+fn rms_envelope(path: &Path, frame_secs: f64) -> Result<Vec<f64>> {
+    let src = std::fs::File::open(path)?;
+    let mss = MediaSourceStream::new(Box::new(src), Default::default());
+    let probed = symphonia::default::get_probe().format(
+        &Hint::new(),
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| anyhow!("No supported audio track in {:?}", path))?;
+    let track_id = track.id;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut frames: Option<RmsFrames> = None;
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(symphonia::core::errors::Error::DecodeError(e)) => {
+                debug!("Skipping undecodable packet: {}", e);
+                continue;
+            }
+            Err(e) => return Err(anyhow!("Decode error in {:?}: {}", path, e)),
+        };
+        let frames = frames.get_or_insert_with(|| RmsFrames::new(decoded.spec().rate, frame_secs));
+
+        let mut buf = AudioBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        decoded.convert(&mut buf);
+        let planes = buf.planes();
+        let channels = planes.planes().len();
+        for i in 0..planes.planes()[0].len() {
+            let sum: f32 = planes.planes().iter().map(|p| p[i]).sum();
+            frames.push(sum / channels as f32);
+        }
+    }
+
+    frames
+        .map(RmsFrames::finish)
+        .ok_or_else(|| anyhow!("No audio decoded from {:?}", path))
+}
+
+/// Accumulates samples into RMS values of fixed-length frames.
+struct RmsFrames {
+    frame_len: usize,
+    sum_squares: f64,
+    count: usize,
+    rms: Vec<f64>,
+}
+
+impl RmsFrames {
+    fn new(sample_rate: u32, frame_secs: f64) -> Self {
+        Self {
+            frame_len: ((sample_rate as f64 * frame_secs).round() as usize).max(1),
+            sum_squares: 0.0,
+            count: 0,
+            rms: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.sum_squares += (sample as f64).powi(2);
+        self.count += 1;
+        if self.count == self.frame_len {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        self.rms.push((self.sum_squares / self.count as f64).sqrt());
+        self.sum_squares = 0.0;
+        self.count = 0;
+    }
+
+    /// Includes the last, partial frame.
+    fn finish(mut self) -> Vec<f64> {
+        if self.count > 0 {
+            self.flush();
+        }
+        self.rms
+    }
+}
+
+/// Non-maximum suppression over the envelope: local maxima are taken loudest
+/// first, dropping any closer than `min_gap_secs` to one already taken.
+fn pick_peaks(envelope: &[f64], frame_secs: f64, top_n: usize, min_gap_secs: f64) -> Vec<AudioPeak> {
+    let mut candidates: Vec<usize> = (0..envelope.len())
+        .filter(|&i| {
+            let value = envelope[i];
+            value > 0.0
+                && (i == 0 || envelope[i - 1] <= value)
+                && (i + 1 == envelope.len() || envelope[i + 1] <= value)
+        })
+        .collect();
+    candidates.sort_by(|a, b| envelope[*b].total_cmp(&envelope[*a]));
+
+    let mut peaks: Vec<AudioPeak> = Vec::new();
+    for i in candidates {
+        if peaks.len() >= top_n {
+            break;
+        }
+        let timestamp_secs = (i as f64 + 0.5) * frame_secs;
+        if peaks
+            .iter()
+            .all(|p| (p.timestamp_secs - timestamp_secs).abs() >= min_gap_secs)
+        {
+            peaks.push(AudioPeak {
+                timestamp_secs,
+                amplitude: envelope[i],
+            });
+        }
+    }
+    peaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::probe_media;
+
+    #[test]
+    fn test_build_preview_input_args() {
+        assert_eq!(
+            build_preview_input_args("00:10", "00:15").unwrap(),
+            vec!["-ss", "10.000", "-t", "5.000"]
+        );
+        // Long ranges are capped at 30s
+        assert_eq!(
+            build_preview_input_args("01:00", "05:00").unwrap(),
+            vec!["-ss", "60.000", "-t", "30.000"]
+        );
+        assert!(build_preview_input_args("00:20", "00:10").is_err());
+    }
+
+    fn audio_info(duration: f64, channels: u32) -> MediaInfo {
+        MediaInfo {
+            duration,
+            streams: vec![crate::probe::StreamInfo {
+                codec_type: "audio".to_string(),
+                channels: Some(channels),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_build_ai_audio_args_mono_downmix() {
+        let threshold = (DEFAULT_MONO_DOWNMIX_THRESHOLD_MB * 1024.0 * 1024.0) as u64;
+        let has_downmix = |args: Vec<String>| args.windows(2).any(|w| w == ["-ac", "1"]);
+        let vorbis = AudioFmt::Vorbis;
+        let build_ai_audio_args = |info: &MediaInfo, format, threshold, quality| {
+            build_ai_audio_args(info, format, threshold, quality, None).unwrap()
+        };
+
+        // 10 minutes of stereo is ~9.6 MB, 30 minutes ~28.8 MB
+        assert!(!has_downmix(build_ai_audio_args(&audio_info(600.0, 2), vorbis, threshold, None)));
+        assert!(has_downmix(build_ai_audio_args(&audio_info(1800.0, 2), vorbis, threshold, None)));
+        // Mono is never touched
+        assert!(!has_downmix(build_ai_audio_args(&audio_info(3600.0, 1), vorbis, threshold, None)));
+        assert_eq!(
+            build_ai_audio_args(&audio_info(600.0, 2), vorbis, threshold, None),
+            vec!["-vn", "-c:a", "libvorbis", "-q:a", "4"]
+        );
+        // At the lowest quality 30 minutes of stereo are only ~14.4 MB
+        let lowest = build_ai_audio_args(&audio_info(1800.0, 2), vorbis, threshold, Some(0));
+        assert!(!has_downmix(lowest));
+        let best = build_ai_audio_args(&audio_info(600.0, 2), vorbis, threshold, Some(10));
+        assert!(has_downmix(best));
+
+        assert_eq!(
+            build_ai_audio_args(&audio_info(600.0, 2), AudioFmt::Mp3, threshold, None),
+            vec!["-vn", "-c:a", "libmp3lame", "-q:a", "5"]
+        );
+    }
+
+    #[test]
+    fn test_build_ai_audio_args_stream_index() {
+        // Stereo music first, mono speech second
+        let mut info = audio_info(1800.0, 2);
+        info.streams.extend(audio_info(1800.0, 1).streams);
+        let threshold = (DEFAULT_MONO_DOWNMIX_THRESHOLD_MB * 1024.0 * 1024.0) as u64;
+
+        assert_eq!(
+            build_ai_audio_args(&info, AudioFmt::Vorbis, threshold, None, Some(1)).unwrap(),
+            vec!["-vn", "-map", "0:a:1", "-c:a", "libvorbis", "-q:a", "4"]
+        );
+        // The first stream is downmixed, no stream is mapped
+        let args = build_ai_audio_args(&info, AudioFmt::Vorbis, threshold, None, None).unwrap();
+        assert!(!args.contains(&"-map".to_string()));
+        assert!(args.windows(2).any(|w| w == ["-ac", "1"]));
+
+        assert!(build_ai_audio_args(&info, AudioFmt::Vorbis, threshold, None, Some(2)).is_err());
+        assert!(
+            build_ai_audio_args(&MediaInfo::default(), AudioFmt::Vorbis, threshold, None, Some(0))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_ai_audio_output() {
+        assert_eq!(
+            ai_audio_output(Path::new("/media/talk.mp4"), MediaType::Video),
+            (PathBuf::from("/media/talk.ogg"), AudioFmt::Vorbis)
+        );
+        // Keeps the format, without overwriting the input
+        assert_eq!(
+            ai_audio_output(Path::new("/media/talk.mp3"), MediaType::Audio),
+            (PathBuf::from("/media/talk.ai.mp3"), AudioFmt::Mp3)
+        );
+        assert_eq!(
+            ai_audio_output(Path::new("/media/talk.ogg"), MediaType::Audio),
+            (PathBuf::from("/media/talk.ai.ogg"), AudioFmt::Vorbis)
+        );
+        assert_eq!(
+            ai_audio_output(Path::new("/media/talk.FLAC"), MediaType::Audio),
+            (PathBuf::from("/media/talk.ai.FLAC"), AudioFmt::Flac)
+        );
+        // Formats we don't encode become OGG
+        assert_eq!(
+            ai_audio_output(Path::new("/media/talk.wav"), MediaType::Audio),
+            (PathBuf::from("/media/talk.ai.ogg"), AudioFmt::Vorbis)
+        );
+    }
+
+    #[test]
+    fn test_build_multi_format_args() {
+        let outputs = [
+            (
+                PathBuf::from("/media/talk.ogg"),
+                AudioConvertFormat {
+                    format: AudioFmt::Vorbis,
+                    quality: None,
+                },
+            ),
+            (
+                PathBuf::from("/media/talk.mp3"),
+                AudioConvertFormat {
+                    format: AudioFmt::Mp3,
+                    quality: Some(10),
+                },
+            ),
+        ];
+        assert_eq!(
+            build_multi_format_args(&outputs),
+            vec![
+                "-y", "-map", "0:a:0", "-c:a", "libvorbis", "-q:a", "4", "/media/talk.ogg",
+                "-map", "0:a:0", "-c:a", "libmp3lame", "-q:a", "0", "/media/talk.mp3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_converted_audio_output() {
+        assert_eq!(
+            converted_audio_output(Path::new("/media/talk.mp4"), MediaType::Video, AudioFmt::Mp3),
+            PathBuf::from("/media/talk.mp3")
+        );
+        // The input itself is kept
+        assert_eq!(
+            converted_audio_output(Path::new("/media/talk.mp3"), MediaType::Audio, AudioFmt::Mp3),
+            PathBuf::from("/media/talk.ai.mp3")
+        );
+    }
+
+    #[test]
+    fn test_convert_audio_formats_keeps_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut info = audio_info(10.0, 2);
+        info.streams.push(crate::probe::StreamInfo {
+            codec_type: "video".to_string(),
+            codec_name: "h264".to_string(),
+            ..Default::default()
+        });
+        let vorbis = [AudioConvertFormat {
+            format: AudioFmt::Vorbis,
+            quality: None,
+        }];
+
+        for name in ["clip.ogg", "clip.OGG"] {
+            let input = dir.path().join(name);
+            std::fs::write(&input, b"video").unwrap();
+            let err = convert_audio_formats(&input, &info, &vorbis, |_| {}).unwrap_err();
+            assert!(err.to_string().contains("overwrite the input"), "{}", err);
+            assert_eq!(std::fs::read(&input).unwrap(), b"video");
+        }
+    }
+
+    #[test]
+    fn test_convert_audio_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("test_podcast.m4a");
+        let mut source = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        source.push("../dev-resources/test-data/test_podcast.m4a");
+        std::fs::copy(&source, &input).unwrap();
+        let info = probe_media(&input).unwrap();
+
+        let formats = [
+            AudioConvertFormat {
+                format: AudioFmt::Vorbis,
+                quality: None,
+            },
+            AudioConvertFormat {
+                format: AudioFmt::Mp3,
+                quality: Some(2),
+            },
+        ];
+        let outputs = convert_audio_formats(&input, &info, &formats, |_| {}).unwrap();
+
+        assert_eq!(
+            outputs,
+            vec![dir.path().join("test_podcast.ai.ogg"), dir.path().join("test_podcast.ai.mp3")]
+        );
+        for output in &outputs {
+            let duration = probe_media(output).unwrap().duration;
+            assert!((duration - info.duration).abs() < 0.5, "{:?}: {}", output, duration);
+        }
+
+        let duplicate = [formats[0], formats[0]];
+        assert!(convert_audio_formats(&input, &info, &duplicate, |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_codec_quality_args() {
+        let args = |format: AudioFmt, quality: Option<i32>| codec_quality_args(&format, quality);
+        assert_eq!(args(AudioFmt::Vorbis, None), vec!["-q:a", "4"]);
+        assert_eq!(args(AudioFmt::Vorbis, Some(7)), vec!["-q:a", "7"]);
+        assert_eq!(args(AudioFmt::Mp3, Some(10)), vec!["-q:a", "0"]);
+        assert_eq!(args(AudioFmt::Mp3, Some(0)), vec!["-q:a", "9"]);
+        assert_eq!(args(AudioFmt::Aac, Some(4)), vec!["-b:a", "96k"]);
+        assert_eq!(args(AudioFmt::Opus, Some(0)), vec!["-b:a", "32k"]);
+        assert_eq!(args(AudioFmt::Flac, Some(8)), vec!["-compression_level", "8"]);
+
+        // Out of range values are clamped
+        assert_eq!(args(AudioFmt::Vorbis, Some(15)), vec!["-q:a", "10"]);
+        assert_eq!(args(AudioFmt::Mp3, Some(-3)), vec!["-q:a", "9"]);
+        assert_eq!(args(AudioFmt::Aac, Some(11)), vec!["-b:a", "192k"]);
+        assert_eq!(args(AudioFmt::Flac, Some(-1)), vec!["-compression_level", "0"]);
+    }
+
+    #[test]
+    fn test_peak_gain_db() {
+        assert_eq!(peak_gain_db(-6.5, -1.0), 5.5);
+        assert_eq!(peak_gain_db(0.0, -3.0), -3.0);
+        assert_eq!(peak_gain_db(-20.0, -20.0), 0.0);
+    }
+
+    #[test]
+    fn test_peak_normalize_two_passes() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.wav");
+        let output = dir.path().join("out.wav");
+        std::fs::write(&input, b"").unwrap();
+
+        let mut passes = Vec::new();
+        let gain = peak_normalize_with(&input, &output, -1.0, |args| {
+            passes.push(args.join(" "));
+            if args.last().is_some_and(|a| a == "-") {
+                Ok(vec![
+                    "[Parsed_volumedetect_0 @ 0x7f8] mean_volume: -24.3 dB".to_string(),
+                    "[Parsed_volumedetect_0 @ 0x7f8] max_volume: -7.5 dB".to_string(),
+                ])
+            } else {
+                std::fs::write(&output, b"").unwrap();
+                Ok(vec![])
+            }
+        })
+        .unwrap();
+
+        assert_eq!(gain, 6.5);
+        assert_eq!(
+            passes,
+            vec![
+                format!("-i {} -vn -af volumedetect -f null -", input.display()),
+                format!(
+                    "-y -i {} -af volume=6.50dB -c:v copy {}",
+                    input.display(),
+                    output.display()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_peak_normalize_rejects_invalid_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.wav");
+        let output = dir.path().join("out.wav");
+        std::fs::write(&input, b"").unwrap();
+        let never_run = |_: Vec<String>| -> Result<Vec<String>> { panic!("ffmpeg must not run") };
+
+        assert!(peak_normalize_with(&input, &output, 0.5, never_run).is_err());
+        assert!(peak_normalize_with(&input, &output, -61.0, never_run).is_err());
+        assert!(peak_normalize_with(&input, &output, f64::NAN, never_run).is_err());
+
+        let silent = peak_normalize_with(&input, &output, -1.0, |_| {
+            Ok(vec!["[Parsed_volumedetect_0 @ 0x7f8] max_volume: -91.0 dB".to_string()])
+        });
+        assert!(silent.is_err());
+    }
+
+    const ASTATS_FIXTURE: &str = "\
+[Parsed_astats_0 @ 0x600000c04000] [info] Channel: 1
+[Parsed_astats_0 @ 0x600000c04000] [info] DC offset: -0.000012
+[Parsed_astats_0 @ 0x600000c04000] [info] Peak level dB: -0.000000
+[Parsed_astats_0 @ 0x600000c04000] [info] RMS level dB: -21.500000
+[Parsed_astats_0 @ 0x600000c04000] [info] Zero crossings rate: 0.020000
+[Parsed_astats_0 @ 0x600000c04000] [info] Channel: 2
+[Parsed_astats_0 @ 0x600000c04000] [info] DC offset: -0.000010
+[Parsed_astats_0 @ 0x600000c04000] [info] Zero crossings rate: 0.030000
+[Parsed_astats_0 @ 0x600000c04000] [info] Overall
+[Parsed_astats_0 @ 0x600000c04000] [info] DC offset: -0.000011
+[Parsed_astats_0 @ 0x600000c04000] [info] Peak level dB: -0.000000
+[Parsed_astats_0 @ 0x600000c04000] [info] RMS level dB: -20.000000
+[Parsed_astats_0 @ 0x600000c04000] [info] Noise floor dB: -65.000000
+[Parsed_astats_0 @ 0x600000c04000] [info] Peak count: 48
+[Parsed_astats_0 @ 0x600000c04000] [info] Number of samples: 480000
+[Parsed_silencedetect_1 @ 0x600000c08000] [info] silence_start: 2.5
+[Parsed_silencedetect_1 @ 0x600000c08000] [info] silence_end: 4.5 | silence_duration: 2
+[Parsed_silencedetect_1 @ 0x600000c08000] [info] silence_start: 9";
+
+    fn fixture_lines() -> Vec<String> {
+        ASTATS_FIXTURE.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_astats() {
+        let astats = parse_astats(&fixture_lines()).unwrap();
+        assert_eq!(astats.peak_level_db, 0.0);
+        assert_eq!(astats.rms_level_db, -20.0);
+        assert_eq!(astats.noise_floor_db, -65.0);
+        assert_eq!(astats.peak_count, 48.0);
+        assert_eq!(astats.samples, 480000.0);
+        assert_eq!(astats.channels, 2);
+        assert!((astats.zero_crossings_rate - 0.025).abs() < 1e-9);
+
+        assert!(parse_astats(&["Stream mapping:".to_string()]).is_none());
+    }
+
+    #[test]
+    fn test_build_quality_report() {
+        let lines = fixture_lines();
+        let astats = parse_astats(&lines).unwrap();
+        // 2s of silence in the middle plus the last second
+        let silence = total_silence(&lines, 10.0);
+        assert!((silence - 3.0).abs() < 1e-9);
+
+        let report = build_quality_report(&astats, silence, 10.0, 48000);
+        assert_eq!(report.snr_db, 45.0);
+        assert!((report.clipping_fraction - 0.00005).abs() < 1e-12);
+        assert!((report.silence_fraction - 0.3).abs() < 1e-9);
+        assert!((report.speech_fraction - 0.7).abs() < 1e-9);
+        assert!((report.dominant_frequency_hz - 600.0).abs() < 1e-6);
+        // snr 1.0 * 0.4 + clipping 0.95 * 0.25 + speech 1.0 * 0.25 + freq 1.0 * 0.1
+        assert!((report.quality_score - 0.9875).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_quality_report_silent_noise_floor() {
+        let astats = AstatsSummary {
+            peak_level_db: -6.0,
+            rms_level_db: -30.0,
+            noise_floor_db: f64::NEG_INFINITY,
+            peak_count: 10.0,
+            samples: 1000.0,
+            channels: 1,
+            zero_crossings_rate: 0.0,
+        };
+        let report = build_quality_report(&astats, 0.0, 5.0, 16000);
+        assert_eq!(report.snr_db, MAX_SNR_DB);
+        // Peaks below full scale are not clipping
+        assert_eq!(report.clipping_fraction, 0.0);
+    }
+
+    #[test]
+    fn test_extract_preview_audio() {
+        let mut input = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        input.push("../dev-resources/test-data/test_podcast.m4a");
+        let out_dir = tempfile::tempdir().unwrap();
+
+        let output = extract_preview_audio(&input, "00:00", "10:00", out_dir.path()).unwrap();
+        assert!(output.exists(), "Preview file should exist");
+
+        let info = probe_media(&output).unwrap();
+        assert!(info.duration <= MAX_PREVIEW_SECS + 0.1, "Preview too long: {}", info.duration);
+        assert_eq!(info.audio_streams().next().unwrap().channels, Some(1));
+    }
+
+    #[test]
+    fn test_find_peaks_in_synthetic_signal() {
+        let sample_rate = 8000;
+        // Quiet noise floor with one frame long clicks of different loudness
+        let mut signal: Vec<f32> = (0..sample_rate * 10)
+            .map(|i| if i % 2 == 0 { 0.01 } else { -0.01 })
+            .collect();
+        for (at, amplitude) in [(2.0, 0.9), (5.0, 0.5), (5.3, 0.6), (8.0, 0.7)] {
+            let start = (at * sample_rate as f64) as usize;
+            for (i, sample) in signal[start..start + 80].iter_mut().enumerate() {
+                *sample = if i % 2 == 0 { amplitude } else { -amplitude };
+            }
+        }
+
+        let mut frames = RmsFrames::new(sample_rate as u32, PEAK_FRAME_SECS);
+        signal.iter().for_each(|s| frames.push(*s));
+        let envelope = frames.finish();
+        assert_eq!(envelope.len(), 1000);
+
+        // The click at 5.0s is within a second of the louder one at 5.3s
+        let peaks = pick_peaks(&envelope, PEAK_FRAME_SECS, 3, 1.0);
+        let times: Vec<f64> = peaks.iter().map(|p| p.timestamp_secs).collect();
+        assert_eq!(times.len(), 3);
+        for (time, expected) in times.iter().zip([2.005, 8.005, 5.305]) {
+            assert!((time - expected).abs() < 1e-9, "{:?}", times);
+        }
+        assert!((peaks[0].amplitude - 0.9).abs() < 1e-6);
+
+        let peaks = pick_peaks(&envelope, PEAK_FRAME_SECS, 10, 0.1);
+        assert_eq!(peaks.len(), 10);
+        assert!((peaks[3].timestamp_secs - 5.005).abs() < 1e-9);
+        assert!(pick_peaks(&envelope, PEAK_FRAME_SECS, 0, 1.0).is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::state::AppState;
+use lru::LruCache;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a cached API response is reused.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Responses kept per session, least recently used ones are dropped first.
+const DEFAULT_CACHE_CAPACITY: usize = 64;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub ttl_secs: u64,
+}
+
+struct CacheInner {
+    entries: LruCache<String, (String, Instant)>,
+    hits: u64,
+    misses: u64,
+}
+
+/// Session cache for responses of idempotent API calls like `generate_clips`.
+pub struct ApiCache {
+    inner: Mutex<CacheInner>,
+    ttl: Duration,
+}
+
+impl Default for ApiCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_CAPACITY, DEFAULT_CACHE_TTL)
+    }
+}
+
+impl ApiCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            inner: Mutex::new(CacheInner {
+                entries: LruCache::new(capacity),
+                hits: 0,
+                misses: 0,
+            }),
+            ttl,
+        }
+    }
+
+    /// SHA-256 (hex) of all request parameters, e.g. the transcript and the
+    /// generation settings.
+    pub fn key(parts: &[&str]) -> String {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part.as_bytes());
+            // Separator, so ("ab", "c") and ("a", "bc") get different keys
+            hasher.update([0]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Cached response for `key`, unless it is older than the TTL.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let mut inner = self.inner.lock().unwrap();
+        let fresh = match inner.entries.get(key) {
+            Some((value, inserted)) if inserted.elapsed() < self.ttl => Some(value.clone()),
+            Some(_) => {
+                inner.entries.pop(key);
+                None
+            }
+            None => None,
+        };
+        if fresh.is_some() {
+            inner.hits += 1;
+        } else {
+            inner.misses += 1;
+        }
+        fresh
+    }
+
+    pub fn insert(&self, key: String, value: String) {
+        self.inner
+            .lock()
+            .unwrap()
+            .entries
+            .put(key, (value, Instant::now()));
+    }
+
+    /// Drops all cached responses; the hit/miss counters are kept.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            entries: inner.entries.len(),
+            capacity: inner.entries.cap().get(),
+            hits: inner.hits,
+            misses: inner.misses,
+            ttl_secs: self.ttl.as_secs(),
+        }
+    }
+}
+
+#[tauri::command]
+pub fn clear_api_cache(state: tauri::State<AppState>) {
+    state.api_cache.clear();
+}
+
+#[tauri::command]
+pub fn get_api_cache_stats(state: tauri::State<AppState>) -> CacheStats {
+    state.api_cache.stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_hit_and_miss() {
+        let cache = ApiCache::default();
+        let key = ApiCache::key(&["transcript", "3"]);
+        assert_eq!(cache.get(&key), None);
+
+        cache.insert(key.clone(), "clips".to_string());
+        assert_eq!(cache.get(&key), Some("clips".to_string()));
+        assert_eq!(cache.get(&ApiCache::key(&["transcript", "4"])), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 2));
+        assert_eq!(stats.ttl_secs, 600);
+    }
+
+    #[test]
+    fn test_cache_expiry() {
+        let cache = ApiCache::new(4, Duration::from_millis(20));
+        cache.insert("key".to_string(), "value".to_string());
+        assert!(cache.get("key").is_some());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get("key"), None);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn test_cache_clear_and_capacity() {
+        let cache = ApiCache::new(2, DEFAULT_CACHE_TTL);
+        for key in ["a", "b", "c"] {
+            cache.insert(key.to_string(), key.to_string());
+        }
+        // "a" was the least recently used one
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.stats().entries, 2);
+
+        cache.clear();
+        assert_eq!(cache.get("c"), None);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(ApiCache::key(&["a"]), ApiCache::key(&["a"]));
+        assert_ne!(ApiCache::key(&["ab", "c"]), ApiCache::key(&["a", "bc"]));
+        assert_eq!(ApiCache::key(&["a"]).len(), 64);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::probe::ChapterInfo;
+use crate::time_utils::{parse_timestamp_to_seconds_raw, seconds_to_timestamp};
+use crate::video::TranscriptSegment;
+use anyhow::{anyhow, Result};
+use log::warn;
+use regex::Regex;
+
+const CHAPTER_SPEAKER: &str = "Chapter";
+
+/// Imports the chapter list of a YouTube description (`0:00 Intro`, one per
+/// line) as segments spanning from each chapter to the next. The last chapter
+/// ends at `video_duration` (seconds). Lines without a leading timestamp are
+/// ignored.
+#[tauri::command]
+pub fn parse_youtube_chapters(
+    description: String,
+    video_duration: f64,
+) -> Result<Vec<TranscriptSegment>, String> {
+    build_chapter_segments(&description, video_duration).map_err(|e| e.to_string())
+}
+
+pub fn build_chapter_segments(
+    description: &str,
+    video_duration: f64,
+) -> Result<Vec<TranscriptSegment>> {
+    if !(video_duration.is_finite() && video_duration > 0.0) {
+        return Err(anyhow!("Invalid video duration: {}", video_duration));
+    }
+
+    let chapters = parse_chapter_lines(description);
+    if let Some((start, label)) = chapters.last() {
+        if *start >= video_duration {
+            return Err(anyhow!(
+                "Chapter {:?} starts at {}s, after the end of the video ({}s)",
+                label,
+                start,
+                video_duration
+            ));
+        }
+    }
+
+    let ends = chapters
+        .iter()
+        .skip(1)
+        .map(|(start, _)| *start)
+        .chain(std::iter::once(video_duration));
+    Ok(chapters
+        .iter()
+        .zip(ends)
+        .map(|((start, label), end)| TranscriptSegment {
+            start: seconds_to_timestamp(*start),
+            end: seconds_to_timestamp(end),
+            speaker: CHAPTER_SPEAKER.to_string(),
+            text: label.clone(),
+        })
+        .collect())
+}
+
+/// Groups consecutive chapters into parts of at most `max_part_secs`, as
+/// `(start, end)` in seconds. A chapter longer than that becomes a part of its
+/// own, parts are only ever split at chapter boundaries. Without a limit every
+/// chapter is a part.
+pub fn group_chapters_into_parts(
+    chapters: &[ChapterInfo],
+    max_part_secs: Option<f64>,
+) -> Vec<(f64, f64)> {
+    let mut parts: Vec<(f64, f64)> = Vec::new();
+    for chapter in chapters {
+        match (parts.last_mut(), max_part_secs) {
+            (Some((start, end)), Some(max)) if chapter.end - *start <= max => *end = chapter.end,
+            _ => parts.push((chapter.start, chapter.end)),
+        }
+    }
+    parts
+}
+
+/// Start (seconds) and label of every chapter line, e.g. `1:23 Topic`,
+/// `01:02:03 - Topic` or `(12:00) Topic`. Chapters that don't start after the
+/// previous one are skipped.
+fn parse_chapter_lines(description: &str) -> Vec<(f64, String)> {
+    let re =
+        Regex::new(r"^\s*[(\[]?((?:\d{1,2}:)?\d{1,2}:\d{2})[)\]]?\s+(?:[-–—:|]\s*)?(\S.*?)\s*$")
+            .unwrap();
+
+    let mut chapters: Vec<(f64, String)> = Vec::new();
+    for line in description.lines() {
+        let Some(caps) = re.captures(line) else {
+            continue;
+        };
+        let Ok(start) = parse_timestamp_to_seconds_raw(&caps[1]) else {
+            warn!("Skipping chapter line with invalid timestamp: {:?}", line);
+            continue;
+        };
+        if chapters
+            .last()
+            .is_some_and(|(previous, _)| start <= *previous)
+        {
+            warn!("Skipping chapter that is out of order: {:?}", line);
+            continue;
+        }
+        chapters.push((start, caps[2].to_string()));
+    }
+    chapters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_youtube_chapters() {
+        let description = "In this episode we talk about editing.\n\
+            \n\
+            Chapters:\n\
+            0:00 Intro\n\
+            1:23 Topic Name\n\
+            12:05 - Interview with a guest\n\
+            (45:00) Q&A\n\
+            1:02:03 Outro\n\
+            \n\
+            Follow us on social media!";
+
+        let segments = parse_youtube_chapters(description.to_string(), 3800.0).unwrap();
+        let spans: Vec<_> = segments
+            .iter()
+            .map(|s| (s.start.as_str(), s.end.as_str(), s.text.as_str()))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("00:00:00.000", "00:01:23.000", "Intro"),
+                ("00:01:23.000", "00:12:05.000", "Topic Name"),
+                ("00:12:05.000", "00:45:00.000", "Interview with a guest"),
+                ("00:45:00.000", "01:02:03.000", "Q&A"),
+                ("01:02:03.000", "01:03:20.000", "Outro"),
+            ]
+        );
+        assert!(segments.iter().all(|s| s.speaker == "Chapter"));
+    }
+
+    #[test]
+    fn test_malformed_chapter_lines() {
+        let description = "0:00 Intro\n\
+            1:23\n\
+            99 Problems\n\
+            1:2 Too short\n\
+            2:00 Main part\n\
+            1:30 Out of order\n\
+            see 3:00 for the best part";
+
+        let segments = build_chapter_segments(description, 600.0).unwrap();
+        let labels: Vec<_> = segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(labels, ["Intro", "Main part"]);
+        assert_eq!(segments[0].end, "00:02:00.000");
+
+        assert!(build_chapter_segments("no chapters here", 600.0)
+            .unwrap()
+            .is_empty());
+        assert!(build_chapter_segments(description, 90.0).is_err());
+        assert!(build_chapter_segments(description, f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_group_probed_chapters() {
+        // ffprobe -v quiet -of json -show_chapters
+        let json: serde_json::Value = serde_json::from_str(
+            r#"{
+                "chapters": [
+                    {"id": 0, "time_base": "1/1000", "start": 0, "start_time": "0.000000",
+                     "end": 300000, "end_time": "300.000000", "tags": {"title": "Intro"}},
+                    {"id": 1, "time_base": "1/1000", "start": 300000, "start_time": "300.000000",
+                     "end": 900000, "end_time": "900.000000", "tags": {"title": "Part 1"}},
+                    {"id": 2, "time_base": "1/1000", "start": 900000, "start_time": "900.000000",
+                     "end": 2700000, "end_time": "2700.000000", "tags": {"title": "Part 2"}},
+                    {"id": 3, "time_base": "1/1000", "start": 2700000, "start_time": "2700.000000",
+                     "end": 3000000, "end_time": "3000.000000", "tags": {"title": "Q&A"}},
+                    {"id": 4, "time_base": "1/1000", "start": 3000000, "start_time": "3000.000000",
+                     "end": 3120500, "end_time": "3120.500000"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let chapters = crate::probe::parse_chapters(&json);
+        assert_eq!(chapters.len(), 5);
+        assert_eq!(chapters[0].title.as_deref(), Some("Intro"));
+        assert_eq!(chapters[4].title, None);
+        assert_eq!(chapters[4].end, 3120.5);
+
+        // 5 + 10 minutes fit into 20 minutes, the 30 minute chapter is too long
+        // on its own and the last two are grouped again
+        assert_eq!(
+            group_chapters_into_parts(&chapters, Some(1200.0)),
+            vec![(0.0, 900.0), (900.0, 2700.0), (2700.0, 3120.5)]
+        );
+        assert_eq!(
+            group_chapters_into_parts(&chapters, Some(10_000.0)),
+            vec![(0.0, 3120.5)]
+        );
+        assert_eq!(group_chapters_into_parts(&chapters, None).len(), 5);
+        assert!(group_chapters_into_parts(&[], Some(60.0)).is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+use crate::state::AppState;
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use std::path::PathBuf;
+use tauri::{Emitter, Manager};
+use tauri_plugin_deep_link::DeepLinkExt;
+
+pub const DEEP_LINK_SCHEME: &str = "aimc";
+pub const PROJECT_EXTENSION: &str = "aimc";
+/// Emitted with an `OpenProjectEvent` payload for every opened project.
+pub const OPEN_PROJECT_EVENT: &str = "open_project";
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OpenProjectEvent {
+    pub path: String,
+}
+
+/// Decodes the project path from `aimc://project/<encoded_path>` or a
+/// `file://` URL pointing at a `.aimc` file.
+pub fn decode_project_url(url: &str) -> Result<PathBuf> {
+    let url = url.trim();
+    let encoded = if let Some(rest) = url.strip_prefix("aimc://project/") {
+        rest
+    } else if let Some(rest) = url.strip_prefix("file://") {
+        // file:///C:/x.aimc -> C:/x.aimc on Windows, /x.aimc elsewhere
+        if cfg!(windows) {
+            rest.trim_start_matches('/')
+        } else {
+            rest
+        }
+    } else {
+        return Err(anyhow!("Unsupported deep link: {}", url));
+    };
+
+    let path = percent_decode_str(encoded)
+        .decode_utf8()
+        .map_err(|e| anyhow!("Invalid encoding in deep link {}: {}", url, e))?
+        .to_string();
+    if path.is_empty() {
+        return Err(anyhow!("Deep link has no project path: {}", url));
+    }
+
+    let path = PathBuf::from(path);
+    if path.extension().and_then(|e| e.to_str()) != Some(PROJECT_EXTENSION) {
+        return Err(anyhow!(
+            "Not a .{} project file: {:?}",
+            PROJECT_EXTENSION,
+            path
+        ));
+    }
+    Ok(path)
+}
+
+/// Decodes the link and remembers the project in the app state, so that the
+/// frontend can still pick it up if it wasn't listening yet.
+fn open_project_link(url: &str, state: &AppState) -> Result<PathBuf> {
+    let path = decode_project_url(url)?;
+    info!("Opening project from deep link: {:?}", path);
+    *state.pending_project.lock().unwrap() = Some(path.clone());
+    Ok(path)
+}
+
+/// Handles every URL of a deep link event and passes an `OpenProjectEvent` to
+/// `emit` for each project. Invalid links are logged and skipped.
+pub fn dispatch_deep_links<I, S, F>(urls: I, state: &AppState, mut emit: F)
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+    F: FnMut(OpenProjectEvent),
+{
+    for url in urls {
+        match open_project_link(url.as_ref(), state) {
+            Ok(path) => emit(OpenProjectEvent {
+                path: path.to_string_lossy().to_string(),
+            }),
+            Err(e) => warn!("Ignoring deep link: {}", e),
+        }
+    }
+}
+
+/// Forwards deep links to the frontend as `open_project` events.
+///
+/// Two things need to be configured in `tauri.conf.json` for this to work:
+/// - `plugins > deep-link > desktop > schemes` must contain `"aimc"` so that
+///   `aimc://project/<encoded_path>` links are routed to the app.
+/// - `bundle > fileAssociations` must contain an entry with `"ext": ["aimc"]` so
+///   that the installer registers the app for double-clicked project files.
+///   macOS delivers those as `file://` URLs, which are accepted as well.
+pub fn setup_deep_links(app: &tauri::App) -> Result<()> {
+    // Installed builds register the scheme through the bundle, dev builds have to
+    // do it at runtime (only supported on Linux and Windows).
+    #[cfg(all(debug_assertions, any(target_os = "linux", windows)))]
+    app.deep_link().register_all()?;
+
+    let handle = app.handle().clone();
+    app.deep_link().on_open_url(move |event| {
+        let urls: Vec<String> = event.urls().iter().map(|u| u.to_string()).collect();
+        dispatch_deep_links(urls, &handle.state::<AppState>(), |payload| {
+            let _ = handle.emit(OPEN_PROJECT_EVENT, payload);
+        });
+    });
+    Ok(())
+}
+
+/// Opens a project link the frontend received itself (e.g. from the launch
+/// arguments) and returns the decoded path.
+#[tauri::command]
+pub fn handle_deep_link(url: String, state: tauri::State<AppState>) -> Result<String, String> {
+    open_project_link(&url, &state)
+        .map(|path| path.to_string_lossy().to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Returns (and clears) the project opened by the last deep link.
+#[tauri::command]
+pub fn take_pending_project(state: tauri::State<AppState>) -> Option<String> {
+    state
+        .pending_project
+        .lock()
+        .unwrap()
+        .take()
+        .map(|p| p.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_project_url() {
+        let path = decode_project_url("aimc://project/%2Fhome%2Fme%2FMy%20Podcast.aimc").unwrap();
+        assert_eq!(path, PathBuf::from("/home/me/My Podcast.aimc"));
+
+        let path = decode_project_url("aimc://project/C%3A%5CProjekte%5CFolge%C3%BC.aimc").unwrap();
+        assert_eq!(path, PathBuf::from("C:\\Projekte\\Folgeü.aimc"));
+
+        assert!(decode_project_url("aimc://project/").is_err());
+        assert!(decode_project_url("aimc://project/%2Ftmp%2Fvideo.mp4").is_err());
+        assert!(decode_project_url("https://example.com/a.aimc").is_err());
+        assert!(decode_project_url("aimc://project/%FF.aimc").is_err());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_decode_file_url() {
+        let path = decode_project_url("file:///home/me/Show%201.aimc").unwrap();
+        assert_eq!(path, PathBuf::from("/home/me/Show 1.aimc"));
+    }
+
+    #[test]
+    fn test_dispatch_deep_links_emits_events() {
+        let state = AppState::default();
+        let mut events = Vec::new();
+        dispatch_deep_links(
+            [
+                "aimc://project/%2Fa%2Ffirst.aimc",
+                "aimc://other/x",
+                "aimc://project/%2Fa%2Fsecond.aimc",
+            ],
+            &state,
+            |event| events.push(event),
+        );
+
+        assert_eq!(
+            events,
+            vec![
+                OpenProjectEvent {
+                    path: "/a/first.aimc".to_string()
+                },
+                OpenProjectEvent {
+                    path: "/a/second.aimc".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            *state.pending_project.lock().unwrap(),
+            Some(PathBuf::from("/a/second.aimc"))
+        );
+    }
+}
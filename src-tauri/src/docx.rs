@@ -0,0 +1,222 @@
+use crate::time_utils::{parse_timestamp_to_seconds_raw, seconds_to_timestamp};
+use crate::transcript::VOICE_OVER_WPM;
+use crate::video::TranscriptSegment;
+use anyhow::{anyhow, Context, Result};
+use log::info;
+use quick_xml::escape::resolve_xml_entity;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use regex::Regex;
+use std::io::Read;
+use std::path::Path;
+
+/// `[MM:SS]` (or `[HH:MM:SS]`) at the start of a paragraph.
+pub const DEFAULT_DOCX_TIMESTAMP_PATTERN: &str = r"^\[(\d{1,2}:\d{2}(?::\d{2})?(?:\.\d+)?)\]";
+
+const DEFAULT_SPEAKER: &str = "Speaker 1";
+
+/// Imports a transcript written in Word. Every paragraph matching the timestamp
+/// pattern starts a new segment, paragraphs without one are appended to the
+/// previous segment. The pattern's first capture group is the timestamp.
+#[tauri::command]
+pub fn import_docx_transcript(
+    path: String,
+    timestamp_pattern: Option<String>,
+) -> Result<Vec<TranscriptSegment>, String> {
+    let pattern = timestamp_pattern
+        .as_deref()
+        .unwrap_or(DEFAULT_DOCX_TIMESTAMP_PATTERN);
+    read_docx_transcript(Path::new(&path), pattern).map_err(|e| e.to_string())
+}
+
+pub fn read_docx_transcript(
+    path: &Path,
+    timestamp_pattern: &str,
+) -> Result<Vec<TranscriptSegment>> {
+    let pattern = Regex::new(timestamp_pattern)
+        .map_err(|e| anyhow!("Invalid timestamp pattern {:?}: {}", timestamp_pattern, e))?;
+    if pattern.captures_len() < 2 {
+        return Err(anyhow!(
+            "Timestamp pattern {:?} needs a capture group around the timestamp",
+            timestamp_pattern
+        ));
+    }
+
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut archive = zip::ZipArchive::new(file).context("Not a .docx file")?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .context("The .docx file has no word/document.xml")?
+        .read_to_string(&mut xml)?;
+
+    let segments = parse_transcript_paragraphs(&docx_paragraphs(&xml)?, &pattern)?;
+    info!("Imported {} segments from {:?}", segments.len(), path);
+    Ok(segments)
+}
+
+/// Plain text of every paragraph (`w:p`) of a WordprocessingML document.
+/// Read with quick-xml rather than `docx-rs`, which isn't a dependency, and
+/// `calamine`, which only reads spreadsheets; paragraph text is all we need.
+fn docx_paragraphs(xml: &str) -> Result<Vec<String>> {
+    let mut reader = Reader::from_str(xml);
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    let mut in_text = false;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.local_name().as_ref() == b"t" => in_text = true,
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => paragraphs.push(std::mem::take(&mut current)),
+                _ => {}
+            },
+            Event::Empty(e) => match e.local_name().as_ref() {
+                b"tab" => current.push('\t'),
+                b"br" | b"cr" => current.push(' '),
+                b"p" => paragraphs.push(String::new()),
+                _ => {}
+            },
+            Event::Text(t) if in_text => current.push_str(&t.decode()?),
+            Event::GeneralRef(r) if in_text => {
+                if let Some(c) = r.resolve_char_ref()? {
+                    current.push(c);
+                } else if let Some(s) = resolve_xml_entity(&r.decode()?) {
+                    current.push_str(s);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(paragraphs)
+}
+
+/// Builds segments from paragraphs. A segment ends where the next one starts;
+/// the last one gets the time needed to read it aloud.
+fn parse_transcript_paragraphs(
+    paragraphs: &[String],
+    pattern: &Regex,
+) -> Result<Vec<TranscriptSegment>> {
+    let mut entries: Vec<(f64, String)> = Vec::new();
+    for paragraph in paragraphs {
+        let paragraph = paragraph.trim();
+        if paragraph.is_empty() {
+            continue;
+        }
+        match pattern.captures(paragraph) {
+            Some(caps) => {
+                let timestamp = caps.get(1).map_or("", |m| m.as_str());
+                let start = parse_timestamp_to_seconds_raw(timestamp)
+                    .with_context(|| format!("Invalid timestamp in {:?}", paragraph))?;
+                let whole = caps.get(0).unwrap();
+                let text = format!(
+                    "{} {}",
+                    &paragraph[..whole.start()],
+                    &paragraph[whole.end()..]
+                );
+                entries.push((start, text.trim().to_string()));
+            }
+            // Continuation of the previous segment; text before the first
+            // timestamp (title, notes) is skipped
+            None => {
+                if let Some((_, text)) = entries.last_mut() {
+                    text.push(' ');
+                    text.push_str(paragraph);
+                }
+            }
+        }
+    }
+
+    let segments = entries
+        .iter()
+        .enumerate()
+        .map(|(i, (start, text))| {
+            let end = match entries.get(i + 1) {
+                Some((next, _)) => next.max(*start),
+                None => {
+                    let words = text.split_whitespace().count() as f64;
+                    start + (words / VOICE_OVER_WPM * 60.0).max(1.0)
+                }
+            };
+            TranscriptSegment {
+                start: seconds_to_timestamp(*start),
+                end: seconds_to_timestamp(end),
+                speaker: DEFAULT_SPEAKER.to_string(),
+                text: text.clone(),
+            }
+        })
+        .collect();
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Minimal .docx: a title, three timestamped paragraphs and a continuation line.
+    fn fixture_docx() -> Vec<u8> {
+        let document = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:body>
+<w:p><w:r><w:t>Interview Folge 12</w:t></w:r></w:p>
+<w:p><w:r><w:t>[00:05] Welcome to the show.</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">[01:10] Tom </w:t></w:r><w:r><w:t>&amp; Jerry are here</w:t></w:r></w:p>
+<w:p><w:r><w:t>and they brought snacks.</w:t></w:r></w:p>
+<w:p/>
+<w:p><w:r><w:t>[02:00]</w:t></w:r><w:r><w:tab/><w:t>Thanks for listening!</w:t></w:r></w:p>
+</w:body>
+</w:document>"#;
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("[Content_Types].xml", options).unwrap();
+        zip.write_all(br#"<?xml version="1.0" encoding="UTF-8"?><Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"/>"#).unwrap();
+        zip.start_file("word/document.xml", options).unwrap();
+        zip.write_all(document.as_bytes()).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_import_docx_transcript() {
+        let path = std::env::temp_dir().join(format!("transcript_{}.docx", std::process::id()));
+        std::fs::write(&path, fixture_docx()).unwrap();
+        let segments = import_docx_transcript(path.to_string_lossy().to_string(), None);
+        let _ = std::fs::remove_file(&path);
+        let segments = segments.unwrap();
+
+        let rows: Vec<(&str, &str, &str)> = segments
+            .iter()
+            .map(|s| (s.start.as_str(), s.end.as_str(), s.text.as_str()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("00:00:05.000", "00:01:10.000", "Welcome to the show."),
+                (
+                    "00:01:10.000",
+                    "00:02:00.000",
+                    "Tom & Jerry are here and they brought snacks."
+                ),
+                ("00:02:00.000", "00:02:01.200", "Thanks for listening!"),
+            ]
+        );
+        assert!(segments.iter().all(|s| s.speaker == "Speaker 1"));
+    }
+
+    #[test]
+    fn test_custom_timestamp_pattern() {
+        let pattern = Regex::new(r"\((\d+:\d+)\)$").unwrap();
+        let paragraphs = vec!["Hello there (00:03)".to_string(), "Bye (00:07)".to_string()];
+        let segments = parse_transcript_paragraphs(&paragraphs, &pattern).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].text, "Hello there");
+        assert_eq!(segments[1].start, "00:00:07.000");
+
+        let path = Path::new("missing.docx");
+        assert!(read_docx_transcript(path, r"\[\d+:\d+\]").is_err());
+        assert!(read_docx_transcript(path, r"[").is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use crate::time_utils::parse_timestamp_to_seconds_raw;
+use crate::video::Segment;
+use anyhow::{anyhow, Result};
+
+/// Record timecode of the first event, the usual start of an NLE timeline.
+const RECORD_START_TIMECODE: &str = "01:00:00:00";
+
+/// Reel used when the caller doesn't name one ("auxiliary source").
+const DEFAULT_REEL: &str = "AX";
+
+/// Frame rates supported by `export_edl`. All timecodes are non-drop frame;
+/// 23.976 fps is counted in 24 frames per timecode second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdlFrameRate {
+    Fps23_976,
+    Fps25,
+    Fps30,
+}
+
+impl EdlFrameRate {
+    pub fn from_fps(fps: f64) -> Result<Self> {
+        if (fps - 23.976).abs() < 0.01 {
+            Ok(Self::Fps23_976)
+        } else if fps == 25.0 {
+            Ok(Self::Fps25)
+        } else if fps == 30.0 {
+            Ok(Self::Fps30)
+        } else {
+            Err(anyhow!(
+                "Unsupported EDL frame rate {}, use 23.976, 25 or 30",
+                fps
+            ))
+        }
+    }
+
+    /// Frames per timecode second.
+    fn timecode_base(self) -> u64 {
+        match self {
+            Self::Fps23_976 => 24,
+            Self::Fps25 => 25,
+            Self::Fps30 => 30,
+        }
+    }
+
+    /// Actual frames per second of media time.
+    fn frames_per_sec(self) -> f64 {
+        match self {
+            Self::Fps23_976 => 24000.0 / 1001.0,
+            Self::Fps25 => 25.0,
+            Self::Fps30 => 30.0,
+        }
+    }
+}
+
+#[tauri::command]
+pub fn export_edl(
+    segments: Vec<Segment>,
+    source_timecode_offset: String,
+    reel_name: String,
+    frame_rate: Option<f64>,
+) -> Result<String, String> {
+    let frame_rate =
+        EdlFrameRate::from_fps(frame_rate.unwrap_or(25.0)).map_err(|e| e.to_string())?;
+    build_edl(&segments, &source_timecode_offset, &reel_name, frame_rate).map_err(|e| e.to_string())
+}
+
+/// Renders the segments as a CMX 3600 EDL with one video cut event per segment.
+/// Source timecodes are the segment times shifted by `source_timecode_offset`
+/// (the start timecode of the source media); the record side plays the
+/// segments back to back from `RECORD_START_TIMECODE`.
+pub fn build_edl(
+    segments: &[Segment],
+    source_timecode_offset: &str,
+    reel_name: &str,
+    frame_rate: EdlFrameRate,
+) -> Result<String> {
+    let source_offset = if source_timecode_offset.trim().is_empty() {
+        0
+    } else {
+        parse_timecode(source_timecode_offset, frame_rate)?
+    };
+    let reel = edl_reel_name(reel_name);
+
+    let mut edl = format!(
+        "TITLE: {}\nFCM: NON-DROP FRAME\n\n",
+        if reel_name.trim().is_empty() {
+            "Untitled"
+        } else {
+            reel_name.trim()
+        }
+    );
+    let mut record_in = parse_timecode(RECORD_START_TIMECODE, frame_rate)?;
+
+    for (i, segment) in segments.iter().enumerate() {
+        let start = seconds_to_frames(parse_timestamp_to_seconds_raw(&segment.start)?, frame_rate);
+        let end = seconds_to_frames(parse_timestamp_to_seconds_raw(&segment.end)?, frame_rate);
+        if end <= start {
+            return Err(anyhow!(
+                "Segment {} ends before it starts ({} - {})",
+                i + 1,
+                segment.start,
+                segment.end
+            ));
+        }
+        let record_out = record_in + (end - start);
+
+        edl.push_str(&format!(
+            "{:03}  {:<8} V     C        {} {} {} {}\n",
+            i + 1,
+            reel,
+            format_timecode(source_offset + start, frame_rate),
+            format_timecode(source_offset + end, frame_rate),
+            format_timecode(record_in, frame_rate),
+            format_timecode(record_out, frame_rate)
+        ));
+        record_in = record_out;
+    }
+    Ok(edl)
+}
+
+/// Reel names are at most 8 characters without spaces in CMX 3600.
+fn edl_reel_name(name: &str) -> String {
+    let reel: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .take(8)
+        .collect::<String>()
+        .to_uppercase();
+    if reel.is_empty() {
+        DEFAULT_REEL.to_string()
+    } else {
+        reel
+    }
+}
+
+fn seconds_to_frames(seconds: f64, frame_rate: EdlFrameRate) -> u64 {
+    (seconds.max(0.0) * frame_rate.frames_per_sec()).round() as u64
+}
+
+/// Parses `HH:MM:SS:FF` (or `HH:MM:SS;FF`) into a frame count.
+pub fn parse_timecode(timecode: &str, frame_rate: EdlFrameRate) -> Result<u64> {
+    let parts: Vec<u64> = timecode
+        .trim()
+        .split([':', ';'])
+        .map(|p| p.parse::<u64>())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| anyhow!("Invalid timecode: {}", timecode))?;
+    let base = frame_rate.timecode_base();
+    match parts[..] {
+        [h, m, s, f] if m < 60 && s < 60 && f < base => Ok(((h * 60 + m) * 60 + s) * base + f),
+        _ => Err(anyhow!("Invalid timecode: {}", timecode)),
+    }
+}
+
+/// Formats a frame count as non-drop `HH:MM:SS:FF`.
+pub fn format_timecode(frames: u64, frame_rate: EdlFrameRate) -> String {
+    let base = frame_rate.timecode_base();
+    let (seconds, f) = (frames / base, frames % base);
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        f
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> Vec<Segment> {
+        vec![
+            Segment {
+                start: "00:10".to_string(),
+                end: "00:20.500".to_string(),
+            },
+            Segment {
+                start: "01:00".to_string(),
+                end: "01:05".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_build_edl_25fps() {
+        let edl = build_edl(
+            &fixture(),
+            "00:00:00:00",
+            "Interview A",
+            EdlFrameRate::Fps25,
+        )
+        .unwrap();
+        assert_eq!(
+            edl,
+            "TITLE: Interview A\nFCM: NON-DROP FRAME\n\n\
+             001  INTERVIE V     C        00:00:10:00 00:00:20:13 01:00:00:00 01:00:10:13\n\
+             002  INTERVIE V     C        00:01:00:00 00:01:05:00 01:00:10:13 01:00:15:13\n"
+        );
+    }
+
+    #[test]
+    fn test_build_edl_30fps() {
+        let edl = build_edl(&fixture(), "10:00:00:00", "", EdlFrameRate::Fps30).unwrap();
+        assert_eq!(
+            edl,
+            "TITLE: Untitled\nFCM: NON-DROP FRAME\n\n\
+             001  AX       V     C        10:00:10:00 10:00:20:15 01:00:00:00 01:00:10:15\n\
+             002  AX       V     C        10:01:00:00 10:01:05:00 01:00:10:15 01:00:15:15\n"
+        );
+    }
+
+    #[test]
+    fn test_build_edl_23_976fps() {
+        let edl = build_edl(
+            &fixture(),
+            "",
+            "A001",
+            EdlFrameRate::from_fps(23.976).unwrap(),
+        )
+        .unwrap();
+        // 60s of media time are 1438.56 frames, so timecodes fall behind the clock
+        assert_eq!(
+            edl,
+            "TITLE: A001\nFCM: NON-DROP FRAME\n\n\
+             001  A001     V     C        00:00:10:00 00:00:20:12 01:00:00:00 01:00:10:12\n\
+             002  A001     V     C        00:00:59:23 00:01:04:22 01:00:10:12 01:00:15:11\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_timecode() {
+        assert_eq!(
+            parse_timecode("01:00:00:00", EdlFrameRate::Fps25).unwrap(),
+            90000
+        );
+        assert_eq!(
+            parse_timecode("00:00:01;05", EdlFrameRate::Fps30).unwrap(),
+            35
+        );
+        assert!(parse_timecode("00:00:00:25", EdlFrameRate::Fps25).is_err());
+        assert!(parse_timecode("00:00:10", EdlFrameRate::Fps25).is_err());
+        assert!(EdlFrameRate::from_fps(29.97).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use anyhow::{anyhow, Context, Result};
+use ffmpeg_sidecar::command::ffmpeg_is_installed;
+use log::{debug, info};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    Zip,
+    TarGz,
+    TarXz,
+}
+
+impl ArchiveKind {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::TarGz => "tar.gz",
+            Self::TarXz => "tar.xz",
+        }
+    }
+}
+
+/// FFmpeg archive to install: an HTTPS URL and the expected SHA-256 of the
+/// downloaded file.
+#[derive(Debug, Clone)]
+pub struct FfmpegArchive {
+    url: String,
+    sha256: String,
+    kind: ArchiveKind,
+}
+
+impl FfmpegArchive {
+    pub fn new(url: &str, sha256: &str) -> Result<Self> {
+        let kind = validate_download_url(url)?;
+        Self::with_kind(url, sha256, kind)
+    }
+
+    /// Skips the HTTPS check, for tests against a local plain HTTP server.
+    #[cfg(test)]
+    fn without_https_check(url: &str, sha256: &str) -> Result<Self> {
+        Self::with_kind(url, sha256, archive_kind(url)?)
+    }
+
+    fn with_kind(url: &str, sha256: &str, kind: ArchiveKind) -> Result<Self> {
+        let sha256 = sha256.trim().to_lowercase();
+        if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(anyhow!("Invalid SHA-256 checksum: {}", sha256));
+        }
+        Ok(Self {
+            url: url.to_string(),
+            sha256,
+            kind,
+        })
+    }
+}
+
+/// Installs FFmpeg from a custom download URL, e.g. an internal mirror, instead
+/// of the default `auto_download` source. The archive must match `sha256`. The
+/// binaries are placed next to the executable, where `ffmpeg_sidecar` looks first.
+#[tauri::command]
+pub async fn init_ffmpeg_from_url(url: String, sha256: String) -> Result<String, String> {
+    let archive = FfmpegArchive::new(&url, &sha256).map_err(|e| e.to_string())?;
+    let install_dir = std::env::current_exe()
+        .map_err(|e| e.to_string())?
+        .parent()
+        .ok_or("Executable has no parent directory")?
+        .to_path_buf();
+
+    let ffmpeg = download_and_install(&archive, &install_dir)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !ffmpeg_is_installed() {
+        return Err(format!(
+            "FFmpeg was installed to {} but can't be run",
+            ffmpeg.display()
+        ));
+    }
+    Ok(format!("FFmpeg installed to {}", ffmpeg.display()))
+}
+
+/// Only HTTPS downloads of a supported archive type are accepted.
+pub fn validate_download_url(url: &str) -> Result<ArchiveKind> {
+    if !url.starts_with("https://") {
+        return Err(anyhow!("FFmpeg download URL must use HTTPS: {}", url));
+    }
+    archive_kind(url)
+}
+
+/// Archive type from the file name of the URL, ignoring query and fragment.
+pub fn archive_kind(url: &str) -> Result<ArchiveKind> {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+    if path.ends_with(".zip") {
+        Ok(ArchiveKind::Zip)
+    } else if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+        Ok(ArchiveKind::TarGz)
+    } else if path.ends_with(".tar.xz") {
+        Ok(ArchiveKind::TarXz)
+    } else {
+        Err(anyhow!(
+            "Unsupported FFmpeg archive, expected .zip, .tar.gz or .tar.xz: {}",
+            url
+        ))
+    }
+}
+
+/// Downloads the archive, verifies its checksum, extracts it and copies `ffmpeg`
+/// (and `ffprobe`, if the archive has it) into `install_dir`. Returns the path
+/// of the installed `ffmpeg`.
+pub async fn download_and_install(archive: &FfmpegArchive, install_dir: &Path) -> Result<PathBuf> {
+    // Removed on drop, also when the download or the extraction fails
+    let work_dir = tempfile::tempdir()?;
+
+    let archive_path = work_dir
+        .path()
+        .join(format!("ffmpeg.{}", archive.kind.extension()));
+    info!("Downloading FFmpeg from {}", archive.url);
+    let response = reqwest::get(&archive.url).await?.error_for_status()?;
+    let bytes = response.bytes().await?;
+    let actual = format!("{:x}", Sha256::digest(&bytes));
+    if actual != archive.sha256 {
+        return Err(anyhow!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            archive.url,
+            archive.sha256,
+            actual
+        ));
+    }
+    std::fs::write(&archive_path, &bytes)?;
+
+    let extract_dir = work_dir.path().join("extracted");
+    std::fs::create_dir_all(&extract_dir)?;
+    extract_archive(&archive_path, archive.kind, &extract_dir)?;
+    install_binaries(&extract_dir, install_dir)
+}
+
+fn extract_archive(archive_path: &Path, kind: ArchiveKind, dest: &Path) -> Result<()> {
+    match kind {
+        ArchiveKind::Zip => {
+            let file = std::fs::File::open(archive_path)?;
+            zip::ZipArchive::new(file)
+                .context("Invalid zip archive")?
+                .extract(dest)
+                .context("Failed to extract zip archive")?;
+        }
+        // tar ships with Linux, macOS and Windows 10+ and handles both compressions
+        ArchiveKind::TarGz | ArchiveKind::TarXz => {
+            let output = Command::new("tar")
+                .arg("-xf")
+                .arg(archive_path)
+                .arg("-C")
+                .arg(dest)
+                .output()
+                .context("Failed to run tar")?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "Failed to extract {}: {}",
+                    archive_path.display(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn binary_name(name: &str) -> String {
+    if cfg!(windows) {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Depth-first search for a file called `name` below `dir`.
+fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    let entries = std::fs::read_dir(dir).ok()?;
+    let mut subdirs = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            subdirs.push(path);
+        } else if path.file_name().is_some_and(|n| n == name) {
+            return Some(path);
+        }
+    }
+    subdirs.iter().find_map(|d| find_file(d, name))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("exe"))
+}
+
+fn install_binaries(extract_dir: &Path, install_dir: &Path) -> Result<PathBuf> {
+    let ffmpeg_name = binary_name("ffmpeg");
+    let ffmpeg = find_file(extract_dir, &ffmpeg_name)
+        .ok_or_else(|| anyhow!("The archive doesn't contain {}", ffmpeg_name))?;
+    let mut binaries = vec![ffmpeg];
+    binaries.extend(find_file(extract_dir, &binary_name("ffprobe")));
+
+    if let Some(binary) = binaries.iter().find(|b| !is_executable(b)) {
+        return Err(anyhow!(
+            "{} in the archive is not executable",
+            binary.display()
+        ));
+    }
+
+    std::fs::create_dir_all(install_dir)?;
+    let mut installed = Vec::new();
+    for binary in binaries {
+        let target = install_dir.join(binary.file_name().unwrap());
+        debug!("Installing {} to {}", binary.display(), target.display());
+        // copy keeps the permission bits
+        std::fs::copy(&binary, &target)?;
+        installed.push(target);
+    }
+    Ok(installed.swap_remove(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_download_url() {
+        assert_eq!(
+            validate_download_url("https://mirror.corp/ffmpeg/ffmpeg-7.0-essentials.zip").unwrap(),
+            ArchiveKind::Zip
+        );
+        assert_eq!(
+            validate_download_url("https://mirror.corp/ffmpeg-release-amd64-static.tar.xz?token=1")
+                .unwrap(),
+            ArchiveKind::TarXz
+        );
+        assert_eq!(
+            validate_download_url("https://mirror.corp/ffmpeg.TAR.GZ").unwrap(),
+            ArchiveKind::TarGz
+        );
+        assert!(validate_download_url("http://mirror.corp/ffmpeg.zip").is_err());
+        assert!(validate_download_url("https://mirror.corp/ffmpeg.7z").is_err());
+        assert!(validate_download_url("https://mirror.corp/ffmpeg.zip/").is_err());
+    }
+
+    #[test]
+    fn test_archive_requires_https_and_checksum() {
+        let sha256 = "ab".repeat(32);
+        assert!(FfmpegArchive::new("https://mirror.corp/ffmpeg.zip", &sha256).is_ok());
+        assert!(FfmpegArchive::new("http://mirror.corp/ffmpeg.zip", &sha256).is_err());
+        assert!(FfmpegArchive::new("https://mirror.corp/ffmpeg.zip", "").is_err());
+        assert!(FfmpegArchive::new("https://mirror.corp/ffmpeg.zip", &"zz".repeat(32)).is_err());
+    }
+
+    /// Zip with an `ffmpeg` and `ffprobe` stand-in below a versioned directory,
+    /// like the release builds.
+    #[cfg(unix)]
+    fn dummy_ffmpeg_zip(mode: u32) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default().unix_permissions(mode);
+        for name in ["ffmpeg", "ffprobe"] {
+            zip.start_file(format!("ffmpeg-7.0-custom/bin/{}", name), options)
+                .unwrap();
+            zip.write_all(b"#!/bin/sh\necho 'ffmpeg version 7.0'\n")
+                .unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn sha256_hex(bytes: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(bytes))
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_install_ffmpeg_from_mirror() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let body = dummy_ffmpeg_zip(0o755);
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/mirror/ffmpeg-7.0.zip")
+            .with_status(200)
+            .with_header("content-type", "application/zip")
+            .with_body(&body)
+            .create_async()
+            .await;
+
+        let install_dir = tempfile::tempdir().unwrap();
+        let url = format!("{}/mirror/ffmpeg-7.0.zip", server.url());
+        let archive = FfmpegArchive::without_https_check(&url, &sha256_hex(&body)).unwrap();
+        let ffmpeg = download_and_install(&archive, install_dir.path())
+            .await
+            .unwrap();
+
+        assert_eq!(ffmpeg, install_dir.path().join("ffmpeg"));
+        for name in ["ffmpeg", "ffprobe"] {
+            let mode = std::fs::metadata(install_dir.path().join(name))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_ne!(mode & 0o111, 0, "{} is not executable", name);
+        }
+        mock.assert_async().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_install_ffmpeg_rejects_bad_archives() {
+        let non_executable = dummy_ffmpeg_zip(0o644);
+        let executable = dummy_ffmpeg_zip(0o755);
+        let mut server = mockito::Server::new_async().await;
+        let _non_executable = server
+            .mock("GET", "/ffmpeg.zip")
+            .with_status(200)
+            .with_body(&non_executable)
+            .create_async()
+            .await;
+        let _tampered = server
+            .mock("GET", "/tampered.zip")
+            .with_status(200)
+            .with_body(&executable)
+            .create_async()
+            .await;
+        let _missing = server
+            .mock("GET", "/missing.tar.xz")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let install_dir = tempfile::tempdir().unwrap();
+        let archive = |path: &str, body: &[u8]| {
+            let url = format!("{}{}", server.url(), path);
+            FfmpegArchive::without_https_check(&url, &sha256_hex(body)).unwrap()
+        };
+
+        let err =
+            download_and_install(&archive("/ffmpeg.zip", &non_executable), install_dir.path())
+                .await
+                .unwrap_err();
+        assert!(err.to_string().contains("not executable"), "{}", err);
+
+        let err = download_and_install(
+            &archive("/tampered.zip", &non_executable),
+            install_dir.path(),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("Checksum mismatch"), "{}", err);
+        assert!(!install_dir.path().join("ffmpeg").exists());
+
+        assert!(
+            download_and_install(&archive("/missing.tar.xz", b""), install_dir.path())
+                .await
+                .is_err()
+        );
+    }
+}
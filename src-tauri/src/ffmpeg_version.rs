@@ -0,0 +1,140 @@
+use anyhow::{anyhow, Result};
+use ffmpeg_sidecar::paths::ffmpeg_path;
+use log::{info, warn};
+use regex::Regex;
+use serde::Serialize;
+use std::process::Command;
+
+/// Oldest FFmpeg release the app is tested with.
+pub const MIN_FFMPEG_VERSION: (u32, u32) = (4, 0);
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FfmpegVersionInfo {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// The `configuration:` line, i.e. the `--enable-...` flags of the build.
+    pub build_config: String,
+}
+
+#[tauri::command]
+pub async fn get_ffmpeg_version() -> Result<FfmpegVersionInfo, String> {
+    detect_ffmpeg_version().map_err(|e| e.to_string())
+}
+
+pub fn detect_ffmpeg_version() -> Result<FfmpegVersionInfo> {
+    let output = Command::new(ffmpeg_path())
+        .arg("-version")
+        .output()
+        .map_err(|e| anyhow!("Failed to run ffmpeg: {}", e))?;
+    parse_ffmpeg_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the output of `ffmpeg -version`. Release builds report e.g.
+/// "ffmpeg version 6.1.1-3ubuntu5" or "ffmpeg version n7.0"; git snapshots
+/// ("ffmpeg version N-113100-g...") carry no release number and are rejected.
+pub fn parse_ffmpeg_version(output: &str) -> Result<FfmpegVersionInfo> {
+    let re = Regex::new(r"ffmpeg version n?(\d+)\.(\d+)(?:\.(\d+))?").unwrap();
+    let caps = re.captures(output).ok_or_else(|| {
+        anyhow!(
+            "Unrecognized ffmpeg version: {:?}",
+            output.lines().next().unwrap_or("")
+        )
+    })?;
+    let number = |i: usize| caps.get(i).map_or(Ok(0), |m| m.as_str().parse::<u32>());
+
+    let build_config = output
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("configuration:"))
+        .unwrap_or("")
+        .trim()
+        .to_string();
+
+    Ok(FfmpegVersionInfo {
+        major: number(1)?,
+        minor: number(2)?,
+        patch: number(3)?,
+        build_config,
+    })
+}
+
+pub fn is_version_sufficient(
+    info: &FfmpegVersionInfo,
+    required_major: u32,
+    required_minor: u32,
+) -> bool {
+    (info.major, info.minor) >= (required_major, required_minor)
+}
+
+/// Fails if the installed FFmpeg is known to be older than `required_major.required_minor`.
+/// If the version can't be determined (e.g. git snapshots) the command is allowed
+/// to run and FFmpeg reports any missing feature itself.
+pub fn require_ffmpeg_version(
+    required_major: u32,
+    required_minor: u32,
+    feature: &str,
+) -> Result<()> {
+    match detect_ffmpeg_version() {
+        Ok(info) if !is_version_sufficient(&info, required_major, required_minor) => Err(anyhow!(
+            "{} requires FFmpeg {}.{} or newer, found {}.{}.{}",
+            feature,
+            required_major,
+            required_minor,
+            info.major,
+            info.minor,
+            info.patch
+        )),
+        Ok(info) => {
+            info!(
+                "FFmpeg {}.{}.{} supports {}",
+                info.major, info.minor, info.patch, feature
+            );
+            Ok(())
+        }
+        Err(e) => {
+            warn!(
+                "Could not determine FFmpeg version, assuming {} is supported: {}",
+                feature, e
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str =
+        "ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023 the FFmpeg developers
+built with gcc 13 (Ubuntu 13.2.0-23ubuntu3)
+configuration: --prefix=/usr --enable-gpl --enable-libx264 --enable-libvidstab
+libavutil      58. 29.100 / 58. 29.100
+libavcodec     60. 31.102 / 60. 31.102
+";
+
+    #[test]
+    fn test_parse_ffmpeg_version() {
+        let info = parse_ffmpeg_version(FIXTURE).unwrap();
+        assert_eq!((info.major, info.minor, info.patch), (6, 1, 1));
+        assert_eq!(
+            info.build_config,
+            "--prefix=/usr --enable-gpl --enable-libx264 --enable-libvidstab"
+        );
+
+        let info = parse_ffmpeg_version("ffmpeg version n7.0 Copyright (c) 2000-2024").unwrap();
+        assert_eq!((info.major, info.minor, info.patch), (7, 0, 0));
+        assert_eq!(info.build_config, "");
+
+        assert!(parse_ffmpeg_version("ffmpeg version N-113100-g0a5ba9d Copyright").is_err());
+    }
+
+    #[test]
+    fn test_is_version_sufficient() {
+        let info = parse_ffmpeg_version(FIXTURE).unwrap();
+        assert!(is_version_sufficient(&info, 4, 0));
+        assert!(is_version_sufficient(&info, 6, 1));
+        assert!(!is_version_sufficient(&info, 6, 2));
+        assert!(!is_version_sufficient(&info, 7, 0));
+    }
+}
@@ -0,0 +1,298 @@
+use crate::time_utils::parse_timestamp_to_seconds_raw;
+use crate::video::ClipSegment;
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use std::path::Path;
+use symphonia::core::audio::AudioBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::formats::{FormatOptions, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::Time;
+
+/// Fraction of matching fingerprint bits above which two clips count as duplicates.
+pub const DUPLICATE_THRESHOLD: f64 = 0.9;
+
+/// Analysis window length in seconds. Windows overlap by 3/4 so that regions
+/// starting at slightly different times still line up.
+const WINDOW_SECS: f64 = 0.128;
+/// 33 bands give one 32 bit hash per window.
+const BANDS: usize = 33;
+const MIN_BAND_HZ: f64 = 300.0;
+const MAX_BAND_HZ: f64 = 2000.0;
+
+/// Spectral hash in the style of Haitsma & Kalker: for every window, each bit
+/// is the sign of the change (relative to the previous window) of the energy
+/// difference between two neighbouring bands. Band energies are measured with
+/// the Goertzel algorithm at log-spaced center frequencies.
+pub fn compute_audio_fingerprint(audio: &[f32], sr: u32) -> Vec<u32> {
+    let window = ((sr as f64 * WINDOW_SECS) as usize).max(1);
+    let hop = (window / 4).max(1);
+    if audio.len() < window {
+        return Vec::new();
+    }
+
+    let coefficients: Vec<f64> = (0..BANDS)
+        .map(|b| {
+            let ratio = b as f64 / (BANDS - 1) as f64;
+            let freq = MIN_BAND_HZ * (MAX_BAND_HZ / MIN_BAND_HZ).powf(ratio);
+            2.0 * (2.0 * std::f64::consts::PI * freq / sr as f64).cos()
+        })
+        .collect();
+
+    let energies: Vec<[f64; BANDS]> = (0..=(audio.len() - window) / hop)
+        .map(|w| {
+            let frame = &audio[w * hop..w * hop + window];
+            let mut bands = [0.0; BANDS];
+            for (band, &coeff) in bands.iter_mut().zip(&coefficients) {
+                let (mut s1, mut s2) = (0.0f64, 0.0f64);
+                for &x in frame {
+                    let s0 = x as f64 + coeff * s1 - s2;
+                    s2 = s1;
+                    s1 = s0;
+                }
+                *band = s1 * s1 + s2 * s2 - coeff * s1 * s2;
+            }
+            bands
+        })
+        .collect();
+
+    energies
+        .windows(2)
+        .map(|pair| {
+            let (prev, cur) = (&pair[0], &pair[1]);
+            (0..BANDS - 1).fold(0u32, |hash, b| {
+                let delta = (cur[b] - cur[b + 1]) - (prev[b] - prev[b + 1]);
+                if delta > 0.0 {
+                    hash | (1 << b)
+                } else {
+                    hash
+                }
+            })
+        })
+        .collect()
+}
+
+/// Slides the shorter fingerprint over the longer one and reports a duplicate if
+/// any alignment has at least `threshold` (0.0-1.0) matching bits. This also
+/// catches a clip that is fully contained in another one.
+pub fn clips_are_duplicate(fp1: &[u32], fp2: &[u32], threshold: f64) -> bool {
+    let (short, long) = if fp1.len() <= fp2.len() {
+        (fp1, fp2)
+    } else {
+        (fp2, fp1)
+    };
+    if short.is_empty() {
+        return false;
+    }
+
+    let total_bits = (short.len() * 32) as f64;
+    (0..=long.len() - short.len()).any(|offset| {
+        let differing: u32 = short
+            .iter()
+            .zip(&long[offset..])
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum();
+        1.0 - differing as f64 / total_bits >= threshold
+    })
+}
+
+/// Fingerprints the audio of every clip and returns the index pairs of clips
+/// that sound the same. Clips made of several segments are fingerprinted as
+/// their concatenated audio.
+pub fn find_duplicate_clips(
+    input_path: &Path,
+    clips: &[ClipSegment],
+) -> Result<Vec<(usize, usize)>> {
+    let mut fingerprints = Vec::with_capacity(clips.len());
+    for clip in clips {
+        let mut audio = Vec::new();
+        let mut sample_rate = 0;
+        for segment in &clip.segments {
+            let start = parse_timestamp_to_seconds_raw(&segment.start)?;
+            let end = parse_timestamp_to_seconds_raw(&segment.end)?;
+            let (samples, sr) = load_audio_range(input_path, start, end)?;
+            audio.extend(samples);
+            sample_rate = sr;
+        }
+        fingerprints.push(compute_audio_fingerprint(&audio, sample_rate));
+    }
+
+    let mut duplicates = Vec::new();
+    for i in 0..fingerprints.len() {
+        for j in i + 1..fingerprints.len() {
+            if clips_are_duplicate(&fingerprints[i], &fingerprints[j], DUPLICATE_THRESHOLD) {
+                duplicates.push((i, j));
+            }
+        }
+    }
+    Ok(duplicates)
+}
+
+/// Logs a warning for every pair of clips with (nearly) identical audio.
+/// Fingerprinting is best effort: decoding problems are logged and ignored.
+pub fn warn_duplicate_clips(input_path: &Path, clips: &[ClipSegment]) {
+    if clips.len() < 2 {
+        return;
+    }
+    match find_duplicate_clips(input_path, clips) {
+        Ok(duplicates) => {
+            for (a, b) in duplicates {
+                warn!(
+                    "Clips {} and {} contain the same audio ({:?} / {:?})",
+                    a + 1,
+                    b + 1,
+                    clips[a].label,
+                    clips[b].label
+                );
+            }
+        }
+        Err(e) => warn!("Skipping duplicate clip check: {}", e),
+    }
+}
+
+/// Decodes `start..end` (seconds) of the first audio track as mono samples,
+/// seeking instead of decoding the whole file.
+fn load_audio_range(path: &Path, start: f64, end: f64) -> Result<(Vec<f32>, u32)> {
+    let src = std::fs::File::open(path)?;
+    let mss = MediaSourceStream::new(Box::new(src), Default::default());
+    let probed = symphonia::default::get_probe().format(
+        &Hint::new(),
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| anyhow!("no supported audio tracks"))?;
+    let track_id = track.id;
+    let time_base = track.codec_params.time_base;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    format.seek(
+        SeekMode::Accurate,
+        SeekTo::Time {
+            time: Time::from(start),
+            track_id: Some(track_id),
+        },
+    )?;
+    decoder.reset();
+
+    let mut samples = Vec::new();
+    let mut sample_rate = 0;
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(symphonia::core::errors::Error::DecodeError(e)) => {
+                debug!("Skipping undecodable packet: {}", e);
+                continue;
+            }
+            Err(e) => return Err(anyhow!("Decode error: {}", e)),
+        };
+        sample_rate = decoded.spec().rate;
+
+        let packet_start = match time_base {
+            Some(tb) => {
+                let t = tb.calc_time(packet.ts());
+                t.seconds as f64 + t.frac
+            }
+            None => packet.ts() as f64 / sample_rate as f64,
+        };
+        if packet_start >= end {
+            break;
+        }
+
+        let mut buf = AudioBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        decoded.convert(&mut buf);
+        let planes = buf.planes();
+        let channels = planes.planes().len();
+        for i in 0..planes.planes()[0].len() {
+            let t = packet_start + i as f64 / sample_rate as f64;
+            if t < start {
+                continue;
+            }
+            if t >= end {
+                break;
+            }
+            let sum: f32 = planes.planes().iter().map(|p| p[i]).sum();
+            samples.push(sum / channels as f32);
+        }
+    }
+
+    if sample_rate == 0 {
+        return Err(anyhow!("No audio decoded between {}s and {}s", start, end));
+    }
+    Ok((samples, sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 16000;
+
+    /// Deterministic noise with a few tones on top, different for every seed.
+    fn test_signal(seed: u64, secs: f64) -> Vec<f32> {
+        let mut state = seed;
+        (0..(SR as f64 * secs) as usize)
+            .map(|i| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let noise = ((state >> 33) as f32 / (1u64 << 31) as f32) - 0.5;
+                let t = i as f32 / SR as f32;
+                let tone = (2.0 * std::f32::consts::PI * (400.0 + seed as f32 * 150.0) * t).sin();
+                // Amplitude envelope so energies change over time
+                0.3 * noise + 0.5 * tone * (t * 3.0).sin().abs()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identical_regions_are_duplicates() {
+        let audio = test_signal(1, 3.0);
+        let fp1 = compute_audio_fingerprint(&audio, SR);
+        let fp2 = compute_audio_fingerprint(&audio.clone(), SR);
+        assert!(!fp1.is_empty());
+        assert!(clips_are_duplicate(&fp1, &fp2, DUPLICATE_THRESHOLD));
+    }
+
+    #[test]
+    fn test_contained_region_is_duplicate() {
+        let audio = test_signal(2, 4.0);
+        let hop = (SR as f64 * WINDOW_SECS) as usize / 4;
+        let full = compute_audio_fingerprint(&audio, SR);
+        let part = compute_audio_fingerprint(&audio[hop * 10..hop * 60], SR);
+        assert!(clips_are_duplicate(&full, &part, DUPLICATE_THRESHOLD));
+        assert!(clips_are_duplicate(&part, &full, DUPLICATE_THRESHOLD));
+    }
+
+    #[test]
+    fn test_different_regions_are_not_duplicates() {
+        let fp1 = compute_audio_fingerprint(&test_signal(1, 3.0), SR);
+        let fp2 = compute_audio_fingerprint(&test_signal(3, 3.0), SR);
+        assert!(!clips_are_duplicate(&fp1, &fp2, DUPLICATE_THRESHOLD));
+
+        // Different parts of the same recording
+        let audio = test_signal(4, 6.0);
+        let half = audio.len() / 2;
+        let fp1 = compute_audio_fingerprint(&audio[..half], SR);
+        let fp2 = compute_audio_fingerprint(&audio[half..], SR);
+        assert!(!clips_are_duplicate(&fp1, &fp2, DUPLICATE_THRESHOLD));
+    }
+
+    #[test]
+    fn test_empty_fingerprints() {
+        assert!(compute_audio_fingerprint(&[0.0; 10], SR).is_empty());
+        assert!(!clips_are_duplicate(&[], &[1, 2, 3], DUPLICATE_THRESHOLD));
+    }
+}
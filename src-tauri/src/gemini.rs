@@ -1,8 +1,50 @@
-use crate::video::TranscriptSegment;
+use crate::time_utils::parse_timestamp_to_seconds_raw;
+use crate::transcript::validate_transcript_segments;
+use crate::upload::detect_upload_mime;
+use crate::video::{filter_clips_by_duration, ClipSegment, TranscriptSegment};
 use anyhow::Result;
-use reqwest::Client;
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use log::{info, error, debug};
+use log::{info, error, debug, warn};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// JSONL file that API requests and responses are recorded to, if enabled.
+static API_LOG_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Fields whose values never end up in the API log: keys and base64 audio blobs.
+const REDACTED_FIELDS: &[&str] = &["key", "api_key", "data"];
+
+/// Translation chunk size if the caller doesn't pick one, roughly 20 typical segments.
+pub const DEFAULT_MAX_TOKENS_PER_CHUNK: usize = 1000;
+
+/// Segments sent per request by `restore_punctuation`.
+const PUNCTUATION_BATCH_SIZE: usize = 50;
+
+/// Transcript words `generate_video_description` sends as context.
+const DESCRIPTION_CONTEXT_WORDS: usize = 2000;
+
+/// Largest base64 audio payload (18 MiB) sent inline. The Google API silently
+/// truncates bigger request bodies, those files have to be uploaded.
+pub const MAX_INLINE_PAYLOAD_BYTES: u64 = 18_874_368;
+
+/// How long a key that ran into the rate limit (HTTP 429) is skipped.
+const RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Enables (`Some`) or disables (`None`) recording of all API traffic.
+pub fn set_api_log_path(path: Option<PathBuf>) {
+    *API_LOG_PATH.lock().unwrap() = path;
+}
+
+/// The file API traffic is currently recorded to, if any.
+pub fn api_log_path() -> Option<PathBuf> {
+    API_LOG_PATH.lock().unwrap().clone()
+}
 
 struct OutputFormat;
 
@@ -20,34 +62,244 @@ impl OutputFormat {
     }
 }
 
+/// Transcript segment with the text in two languages, see
+/// `GeminiClient::analyze_audio_bilingual`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BilingualSegment {
+    pub start: String,
+    pub end: String,
+    pub speaker: String,
+    /// Text in the first output language.
+    pub text_primary: String,
+    /// Text in the second output language.
+    pub text_secondary: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LanguageDetectionResult {
+    pub language: String,
+    /// 0.0 if the model didn't report a confidence.
+    pub confidence: f64,
+    pub bcp47_code: String,
+}
+
+/// A model the API offers, for the model picker.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    /// Model name to pass as `model`, e.g. "gemini-1.5-flash".
+    pub id: String,
+    pub display_name: String,
+    /// Guessed from the model name, the APIs don't report input modalities.
+    pub supports_audio: bool,
+    pub context_window_tokens: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct RawLanguageDetection {
+    language: String,
+    #[serde(alias = "bcp47_code")]
+    bcp47: String,
+    #[serde(default)]
+    confidence: f64,
+}
+
+/// Kind of video, which decides where good clips start and end.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Genre {
+    Interview,
+    Lecture,
+    Tutorial,
+    Entertainment,
+    Sports,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClipGenrePrompt {
+    pub genre: Genre,
+    /// Appended to the clip constraints, e.g. "Skip the sponsor segment".
+    pub custom_instructions: Option<String>,
+}
+
+/// Kind of description written by `generate_video_description`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DescriptionStyle {
+    /// Summary with chapter timestamps for a regular video.
+    Youtube,
+    /// Show notes with the topics of an episode.
+    Podcast,
+    /// A few lines for Shorts, Reels and TikTok.
+    Short,
+}
+
+impl DescriptionStyle {
+    fn instructions(self) -> &'static str {
+        match self {
+            Self::Youtube => "Write a YouTube video description: a two or three sentence hook summarizing the video, followed by a chapter list with one 'MM:SS Title' line per topic, the first one at 00:00.",
+            Self::Podcast => "Write podcast show notes: a short paragraph introducing the episode and its guests, a bullet list of the topics discussed, and a chapter list with one 'MM:SS Title' line per topic, the first one at 00:00.",
+            Self::Short => "Write a description for a short vertical video: at most three short lines, with at most two timestamps ('MM:SS') of the key moments.",
+        }
+    }
+}
+
+/// Used by `generate_clips` when no genre is given.
+const DEFAULT_CLIPS_SYSTEM_PROMPT: &str = "You are a viral content expert. Your goal is to identify the most engaging moments in a video transcript for social media clips (TikTok, Reels, Shorts).";
+
+pub fn system_prompt_for_genre(genre: &Genre) -> &'static str {
+    match genre {
+        Genre::Interview => "You are an experienced interview and podcast editor. Your goal is to find the moments where a guest shares a surprising insight, a strong opinion or a personal story. Start each clip with the question or setup that leads to it and end it once the point has landed.",
+        Genre::Lecture => "You are an educational content editor. Your goal is to find self-contained explanations in a lecture transcript. Each clip should cover exactly one concept, cut at the transitions between concepts, never in the middle of an explanation or example.",
+        Genre::Tutorial => "You are a how-to video editor. Your goal is to split a tutorial (e.g. a cooking or DIY video) into clips of complete steps. Each clip should start where a step is introduced and end when its result is shown.",
+        Genre::Entertainment => DEFAULT_CLIPS_SYSTEM_PROMPT,
+        Genre::Sports => "You are a sports highlights editor. Your goal is to find the key plays, scoring moments, turning points and emotional reactions in a match commentary transcript. Include the build-up before each play and the reaction after it.",
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RankedClip {
+    pub clip: ClipSegment,
+    /// Normalized to 0.0..=1.0.
+    pub virality_score: f64,
+    pub reasoning: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexedText {
+    index: usize,
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct RawClipScore {
+    index: usize,
+    #[serde(alias = "virality_score")]
+    score: f64,
+    #[serde(default)]
+    reasoning: String,
+}
+
+/// API keys that are used in turn, so that batch jobs spread their requests
+/// over several quotas. Rate limited keys are skipped until their cooldown ends.
+#[derive(Default)]
+pub struct ApiKeyPool {
+    keys: RwLock<Vec<String>>,
+    current_index: AtomicUsize,
+    unavailable_until: Mutex<HashMap<String, Instant>>,
+}
+
+impl ApiKeyPool {
+    pub fn new(keys: Vec<String>) -> Self {
+        let pool = Self::default();
+        for key in keys {
+            pool.add_key(key);
+        }
+        pool
+    }
+
+    /// Adds a key to the end of the rotation. Duplicates are ignored.
+    pub fn add_key(&self, key: String) {
+        let mut keys = self.keys.write().unwrap();
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    /// Returns false if the key wasn't in the pool.
+    pub fn remove_key(&self, key: &str) -> bool {
+        self.unavailable_until.lock().unwrap().remove(key);
+        let mut keys = self.keys.write().unwrap();
+        let len = keys.len();
+        keys.retain(|k| k != key);
+        keys.len() != len
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Next key in round-robin order, skipping keys that are on cooldown. If all
+    /// of them are, the next key is used anyway and the API decides.
+    pub fn next_key(&self) -> Option<String> {
+        let keys = self.keys.read().unwrap();
+        if keys.is_empty() {
+            return None;
+        }
+        let now = Instant::now();
+        let mut unavailable = self.unavailable_until.lock().unwrap();
+        unavailable.retain(|_, until| *until > now);
+
+        let start = self.current_index.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..keys.len() {
+            let key = &keys[(start + offset) % keys.len()];
+            if !unavailable.contains_key(key) {
+                // Continue the rotation after the key that was actually used
+                self.current_index.fetch_add(offset, Ordering::Relaxed);
+                return Some(key.clone());
+            }
+        }
+        Some(keys[start % keys.len()].clone())
+    }
+
+    /// Skips the key for `RATE_LIMIT_COOLDOWN`.
+    pub fn mark_rate_limited(&self, key: &str) {
+        self.unavailable_until
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), Instant::now() + RATE_LIMIT_COOLDOWN);
+    }
+}
+
 #[derive(Clone)]
 pub struct GeminiClient {
     client: Client,
-    api_key: String,
+    keys: Arc<ApiKeyPool>,
     base_url: String,
     model: String,
+    /// MIME type of audio attachments, see `with_audio_file`.
+    audio_mime_type: &'static str,
 }
 
 impl GeminiClient {
     pub fn new(api_key: String, base_url: String, model: String) -> Self {
+        Self::with_key_pool(vec![api_key], base_url, model)
+    }
+
+    /// Uses the keys round-robin, one per request.
+    pub fn with_key_pool(keys: Vec<String>, base_url: String, model: String) -> Self {
+        Self::with_shared_key_pool(Arc::new(ApiKeyPool::new(keys)), base_url, model)
+    }
+
+    /// Like `with_key_pool`, but the rotation and cooldowns are shared with
+    /// every other client using the same pool.
+    pub fn with_shared_key_pool(keys: Arc<ApiKeyPool>, base_url: String, model: String) -> Self {
         Self {
             client: Client::new(),
-            api_key,
+            keys,
             base_url,
             model,
+            audio_mime_type: "audio/ogg",
         }
     }
 
+    /// Declares audio attachments in the format of `path`, the file the base64
+    /// data or the upload was made from. Without it they are declared as OGG.
+    pub fn with_audio_file(mut self, path: &Path) -> Self {
+        self.audio_mime_type = detect_upload_mime(path);
+        self
+    }
+
     pub async fn translate_transcript(
         &self,
         transcript: Vec<TranscriptSegment>,
         target_language: String,
         context: String,
+        max_tokens_per_chunk: usize,
     ) -> Result<String> {
         info!("Starting translation of {} segments to {}", transcript.len(), target_language);
-        let chunk_size = 20;
-        let chunks: Vec<Vec<TranscriptSegment>> =
-            transcript.chunks(chunk_size).map(|c| c.to_vec()).collect();
+        let chunks = chunk_by_tokens(transcript, max_tokens_per_chunk);
 
         let mut handles = vec![];
 
@@ -71,21 +323,13 @@ impl GeminiClient {
 
         let mut all_segments = vec![];
         // Await in order to preserve order
-        for handle in handles {
+        for (i, handle) in handles.into_iter().enumerate() {
             let res_str = handle.await??;
 
-            // Clean up markdown code blocks if present
-            let json_str = if let Some(start) = res_str.find('[') {
-                if let Some(end) = res_str.rfind(']') {
-                    &res_str[start..=end]
-                } else {
-                    &res_str
-                }
-            } else {
-                &res_str
-            };
-
-            let segments: Vec<TranscriptSegment> = serde_json::from_str(json_str)?;
+            let json_str = extract_json_array(&res_str)
+                .map_err(|e| anyhow::anyhow!("Translation chunk #{}: {}", i, e))?;
+            let segments = validate_transcript_segments(&json_str)
+                .map_err(|e| anyhow::anyhow!("Translation chunk #{}: {}", i, e))?;
             all_segments.extend(segments);
         }
 
@@ -126,10 +370,9 @@ impl GeminiClient {
             target_language, context, chunk_index + 1, transcript_json
         );
 
-        // Determine if this is a Google API or OpenAI-compatible API
-        let is_google_api = self.base_url.contains("generativelanguage.googleapis.com");
+        let provider = self.provider();
 
-        let payload = if is_google_api {
+        let payload = if provider == ApiProvider::Google {
             // Google format
             json!({
                 "contents": [{
@@ -161,36 +404,404 @@ impl GeminiClient {
             })
         };
 
-        let base_url = self.base_url.trim_end_matches('/');
-        let url = if is_google_api {
-            format!(
-                "{}/v1beta/models/{}:generateContent?key={}",
-                base_url, self.model, self.api_key
+        let response = self.post_with_key_rotation(&payload, false).await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("API failed: {}", response.text().await?));
+        }
+
+        let res_json: Value = response.json().await?;
+
+        let text = if provider == ApiProvider::Google {
+            res_json["candidates"][0]["content"]["parts"][0]["text"]
+                .as_str()
+                .unwrap_or("No text response")
+                .to_string()
+        } else {
+            res_json["choices"][0]["message"]["content"]
+                .as_str()
+                .unwrap_or("No text response")
+                .to_string()
+        };
+
+        Ok(text)
+    }
+
+    pub async fn analyze_audio(
+        &self,
+        context: &str,
+        glossary: &str,
+        speaker_count: Option<u32>,
+        remove_filler_words: bool,
+        audio_uri: Option<&str>,
+        audio_base64: Option<&str>,
+    ) -> Result<String> {
+        let (system_prompt, user_prompt) =
+            analysis_prompts(context, glossary, speaker_count, remove_filler_words, &[]);
+        self.generate_with_audio(&system_prompt, &user_prompt, audio_uri, audio_base64)
+            .await
+    }
+
+    /// Transcribes bilingual content with the text of every segment in both
+    /// `output_languages` (e.g. `["en", "de"]`); the first one becomes
+    /// `text_primary`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn analyze_audio_bilingual(
+        &self,
+        context: &str,
+        glossary: &str,
+        speaker_count: Option<u32>,
+        remove_filler_words: bool,
+        output_languages: &[String],
+        audio_uri: Option<&str>,
+        audio_base64: Option<&str>,
+    ) -> Result<Vec<BilingualSegment>> {
+        let (system_prompt, user_prompt) = bilingual_analysis_prompts(
+            context,
+            glossary,
+            speaker_count,
+            remove_filler_words,
+            output_languages,
+        )?;
+        let text = self
+            .generate_with_audio(&system_prompt, &user_prompt, audio_uri, audio_base64)
+            .await?;
+        parse_bilingual_segments(&text, &output_languages[0], &output_languages[1])
+    }
+
+    /// Same as `analyze_audio_bilingual`, but streams the response and calls
+    /// `on_partial` with the text received so far after every chunk.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn analyze_audio_bilingual_streaming<F>(
+        &self,
+        context: &str,
+        glossary: &str,
+        speaker_count: Option<u32>,
+        remove_filler_words: bool,
+        output_languages: &[String],
+        audio_uri: Option<&str>,
+        audio_base64: Option<&str>,
+        on_partial: F,
+    ) -> Result<Vec<BilingualSegment>>
+    where
+        F: FnMut(&str),
+    {
+        let (system_prompt, user_prompt) = bilingual_analysis_prompts(
+            context,
+            glossary,
+            speaker_count,
+            remove_filler_words,
+            output_languages,
+        )?;
+        let text = self
+            .generate_with_audio_streaming(
+                &system_prompt,
+                &user_prompt,
+                audio_uri,
+                audio_base64,
+                on_partial,
             )
+            .await?;
+        parse_bilingual_segments(&text, &output_languages[0], &output_languages[1])
+    }
+
+    /// Same as `analyze_audio`, but streams the response and calls `on_partial`
+    /// with the text received so far after every chunk.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn analyze_audio_streaming<F>(
+        &self,
+        context: &str,
+        glossary: &str,
+        speaker_count: Option<u32>,
+        remove_filler_words: bool,
+        audio_uri: Option<&str>,
+        audio_base64: Option<&str>,
+        on_partial: F,
+    ) -> Result<String>
+    where
+        F: FnMut(&str),
+    {
+        let (system_prompt, user_prompt) =
+            analysis_prompts(context, glossary, speaker_count, remove_filler_words, &[]);
+        self.generate_with_audio_streaming(
+            &system_prompt,
+            &user_prompt,
+            audio_uri,
+            audio_base64,
+            on_partial,
+        )
+        .await
+    }
+
+    /// Detects the spoken language with a short prompt, so the user doesn't have
+    /// to mention it in the analysis context.
+    pub async fn detect_language(
+        &self,
+        audio_uri: Option<&str>,
+        audio_base64: Option<&str>,
+    ) -> Result<LanguageDetectionResult> {
+        let system_prompt = "You are a language identification expert. Your only task is to identify the main spoken language of the audio.";
+        let user_prompt = "Which language is spoken in this audio? Respond with a strict JSON object only: {\"language\": \"<English name>\", \"bcp47\": \"<BCP-47 code>\", \"confidence\": <0.0-1.0>}";
+
+        let text = self
+            .generate_with_audio(system_prompt, user_prompt, audio_uri, audio_base64)
+            .await?;
+        debug!("Language detection response: {}", text);
+
+        let json_str = match (text.find('{'), text.rfind('}')) {
+            (Some(start), Some(end)) if start < end => &text[start..=end],
+            _ => text.as_str(),
+        };
+        let raw: RawLanguageDetection = serde_json::from_str(json_str)?;
+
+        Ok(LanguageDetectionResult {
+            language: raw.language,
+            confidence: raw.confidence.clamp(0.0, 1.0),
+            bcp47_code: raw.bcp47,
+        })
+    }
+
+    /// Scores all clips in a single request and returns them sorted by predicted
+    /// virality, highest first. Clips the model skipped get a score of 0.0.
+    pub async fn rank_clips_by_virality(
+        &self,
+        clips: Vec<ClipSegment>,
+        transcript: &[TranscriptSegment],
+    ) -> Result<Vec<RankedClip>> {
+        let system_prompt = "You are a viral content expert. Your goal is to predict how well short clips will perform on social media (TikTok, Reels, Shorts).";
+
+        let mut user_prompt = "Rate each of the following clips with a virality score between 0.0 (will not perform) and 1.0 (very likely to go viral).\n\
+            Return a strict JSON array of objects with fields: 'index' (the clip number), 'score' (0.0-1.0), 'reasoning' (one sentence).\n".to_string();
+
+        for (i, clip) in clips.iter().enumerate() {
+            user_prompt.push_str(&format!(
+                "\nClip {}: {}\n{}\n",
+                i,
+                clip.label.as_deref().unwrap_or("Untitled"),
+                clip_transcript_context(clip, transcript)
+            ));
+        }
+
+        let text = self
+            .generate_with_audio(system_prompt, &user_prompt, None, None)
+            .await?;
+        debug!("Virality ranking response: {}", text);
+
+        let mut scores: Vec<RawClipScore> = serde_json::from_str(&extract_json_array(&text)?)?;
+        normalize_virality_scores(&mut scores);
+
+        let mut ranked: Vec<RankedClip> = clips
+            .into_iter()
+            .enumerate()
+            .map(|(i, clip)| match scores.iter().find(|s| s.index == i) {
+                Some(s) => RankedClip {
+                    clip,
+                    virality_score: s.score,
+                    reasoning: s.reasoning.clone(),
+                },
+                None => RankedClip {
+                    clip,
+                    virality_score: 0.0,
+                    reasoning: "Not scored by the model".to_string(),
+                },
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.virality_score.total_cmp(&a.virality_score));
+
+        Ok(ranked)
+    }
+
+    /// Adds punctuation and capitalization to the segment texts in batches of
+    /// `PUNCTUATION_BATCH_SIZE`. Only the text is sent, so timestamps and speakers
+    /// are kept as they are; a text whose words the model changed is left alone.
+    pub async fn restore_punctuation(
+        &self,
+        mut segments: Vec<TranscriptSegment>,
+    ) -> Result<Vec<TranscriptSegment>> {
+        let system_prompt = "You are a careful copy editor. You add punctuation to raw speech transcripts without changing a single word.";
+
+        for (batch_index, batch) in segments.chunks_mut(PUNCTUATION_BATCH_SIZE).enumerate() {
+            debug!("Restoring punctuation of batch #{} ({} segments)", batch_index, batch.len());
+            let texts: Vec<IndexedText> = batch
+                .iter()
+                .enumerate()
+                .map(|(index, s)| IndexedText {
+                    index,
+                    text: s.text.clone(),
+                })
+                .collect();
+
+            let user_prompt = format!(
+                "Add punctuation and sentence capitalization to the 'text' of each of the following items.
+                Constraints:
+                - Do not add, remove, reorder or replace any words.
+                - Keep the 'index' of every item.
+                - Return a strict JSON array of objects with fields: 'index', 'text'.
+
+                Items:
+                {}",
+                serde_json::to_string(&texts)?
+            );
+
+            let text = self
+                .generate_with_audio(system_prompt, &user_prompt, None, None)
+                .await?;
+            let punctuated: Vec<IndexedText> = serde_json::from_str(&extract_json_array(&text)?)?;
+
+            for item in punctuated {
+                let Some(segment) = batch.get_mut(item.index) else {
+                    warn!("Punctuation response has unknown index {}", item.index);
+                    continue;
+                };
+                if same_words(&segment.text, &item.text) {
+                    segment.text = item.text;
+                } else {
+                    warn!(
+                        "Model changed the words of segment {}, keeping the original",
+                        batch_index * PUNCTUATION_BATCH_SIZE + item.index
+                    );
+                }
+            }
+        }
+
+        Ok(segments)
+    }
+
+    /// Writes a video description in `style` with timestamps, hashtags (including
+    /// the `keywords`) and a call to action. The first
+    /// `DESCRIPTION_CONTEXT_WORDS` words of the transcript are sent as context.
+    pub async fn generate_video_description(
+        &self,
+        transcript: &[TranscriptSegment],
+        title: &str,
+        keywords: &[String],
+        style: DescriptionStyle,
+    ) -> Result<String> {
+        let system_prompt = "You are a social media manager who writes engaging video descriptions that rank well in search.";
+
+        let keywords = if keywords.is_empty() {
+            "none".to_string()
         } else {
-            format!("{}/v1/chat/completions", base_url)
+            keywords.join(", ")
         };
+        let user_prompt = format!(
+            "{}
+            Requirements:
+            - Only use timestamps that appear in the transcript.
+            - End with a call to action, e.g. to like, subscribe or comment.
+            - Finish with 3 to 8 relevant hashtags, including the keywords.
+            - Write in the language of the transcript.
+            - Return the description as plain text only, without any preamble.
+
+            Title: {}
+            Keywords: {}
+
+            Transcript:
+            {}",
+            style.instructions(),
+            title,
+            keywords,
+            description_transcript_context(transcript, DESCRIPTION_CONTEXT_WORDS)
+        );
 
-        let mut request = self.client.post(&url).json(&payload);
+        let text = self
+            .generate_with_audio(system_prompt, &user_prompt, None, None)
+            .await?;
+        let description = text.trim();
+        if description.is_empty() {
+            return Err(anyhow::anyhow!("The model returned an empty description"));
+        }
+        Ok(description.to_string())
+    }
+
+    /// POSTs a JSON payload, adding the bearer token for OpenAI-compatible APIs.
+    /// When the API log is enabled, the redacted request and response are
+    /// appended to it; the response body is buffered for that and handed back
+    /// unchanged. Streamed responses are only logged with their status, as
+    /// buffering them would hold back every chunk until the stream ends.
+    async fn logged_post(
+        &self,
+        url: &str,
+        api_key: &str,
+        payload: &Value,
+        stream: bool,
+    ) -> Result<Response> {
+        let mut request = self.client.post(url).json(payload);
 
-        if !is_google_api {
-            request = request.header("Authorization", format!("Bearer {}", self.api_key));
+        // Google uses the key query parameter instead
+        if self.provider() != ApiProvider::Google {
+            request = request.header("Authorization", format!("Bearer {}", api_key));
         }
 
         let response = request.send().await?;
 
+        let Some(log_path) = API_LOG_PATH.lock().unwrap().clone() else {
+            return Ok(response);
+        };
+
+        let status = response.status();
+        let mut entry = json!({
+            "timestamp": chrono::Local::now().to_rfc3339(),
+            "url": redact_url(url),
+            "request": redact_json(payload),
+            "status": status.as_u16(),
+        });
+        if stream {
+            entry["streamed"] = json!(true);
+            if let Err(e) = append_api_log(&log_path, &entry) {
+                warn!("Failed to write API log {:?}: {}", log_path, e);
+            }
+            return Ok(response);
+        }
+
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+
+        let response_json = serde_json::from_slice(&body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).to_string()));
+        entry["response"] = redact_json(&response_json);
+        if let Err(e) = append_api_log(&log_path, &entry) {
+            warn!("Failed to write API log {:?}: {}", log_path, e);
+        }
+
+        let mut rebuilt = http::Response::builder().status(status);
+        if let Some(h) = rebuilt.headers_mut() {
+            *h = headers;
+        }
+        Ok(Response::from(rebuilt.body(body)?))
+    }
+
+    /// Sends a prompt together with an optional audio attachment (Files API uri
+    /// or inline base64) and returns the model's text response.
+    async fn generate_with_audio(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        audio_uri: Option<&str>,
+        audio_base64: Option<&str>,
+    ) -> Result<String> {
+        let provider = self.provider();
+        if let (None, Some(base64)) = (audio_uri, audio_base64) {
+            check_inline_payload_size(&self.base_url, &self.model, base64.len() as u64)?;
+        }
+        let payload = self.audio_payload(system_prompt, user_prompt, audio_uri, audio_base64);
+
+        let response = self.post_with_key_rotation(&payload, false).await?;
+
         if !response.status().is_success() {
             return Err(anyhow::anyhow!("API failed: {}", response.text().await?));
         }
 
         let res_json: Value = response.json().await?;
 
-        let text = if is_google_api {
+        // Extract text from response (handle both Google and OpenAI formats)
+        let text = if provider == ApiProvider::Google {
             res_json["candidates"][0]["content"]["parts"][0]["text"]
                 .as_str()
                 .unwrap_or("No text response")
                 .to_string()
         } else {
+            // OpenAI format
             res_json["choices"][0]["message"]["content"]
                 .as_str()
                 .unwrap_or("No text response")
@@ -200,36 +811,157 @@ impl GeminiClient {
         Ok(text)
     }
 
-    pub async fn analyze_audio(
+    /// Streaming variant of `generate_with_audio` using server-sent events.
+    /// `on_partial` receives the accumulated text after every chunk.
+    async fn generate_with_audio_streaming<F>(
         &self,
-        context: &str,
-        glossary: &str,
-        speaker_count: Option<u32>,
-        remove_filler_words: bool,
+        system_prompt: &str,
+        user_prompt: &str,
         audio_uri: Option<&str>,
         audio_base64: Option<&str>,
-    ) -> Result<String> {
-        let mut system_prompt = "You are a professional video editor assistant. Your task is to transcribe the audio and identify logical segments.".to_string();
+        mut on_partial: F,
+    ) -> Result<String>
+    where
+        F: FnMut(&str),
+    {
+        let provider = self.provider();
+        if let (None, Some(base64)) = (audio_uri, audio_base64) {
+            check_inline_payload_size(&self.base_url, &self.model, base64.len() as u64)?;
+        }
+        let mut payload = self.audio_payload(system_prompt, user_prompt, audio_uri, audio_base64);
+        if provider != ApiProvider::Google {
+            payload["stream"] = json!(true);
+        }
 
-        if let Some(count) = speaker_count {
-            system_prompt.push_str(&format!(" There are {} speakers in this audio. Please label them as Speaker 1, Speaker 2, etc.", count));
+        let mut response = self.post_with_key_rotation(&payload, true).await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("API failed: {}", response.text().await?));
         }
 
-        let mut user_prompt = format!(
-            "Analyze the following audio.\nContext: {}\nGlossary: {}\n[WISH FOR TIMESTAMPS]: Please output the transcription in a strict JSON format with 'start', 'end', 'speaker', and 'text' fields. Ensure timestamps are in 'MM:SS' format.\n",
-            context, glossary
-        );
+        let mut text = String::new();
+        let mut pending = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            pending.extend_from_slice(&chunk);
+            // Only complete lines can be parsed, keep the rest for the next chunk
+            while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=pos).collect();
+                if let Some(delta) = parse_sse_text(&String::from_utf8_lossy(&line), provider) {
+                    text.push_str(&delta);
+                    on_partial(&text);
+                }
+            }
+        }
+        if let Some(delta) = parse_sse_text(&String::from_utf8_lossy(&pending), provider) {
+            text.push_str(&delta);
+            on_partial(&text);
+        }
+
+        Ok(text)
+    }
+
+    /// Models available to the first API key of the pool.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let api_key = self
+            .keys
+            .next_key()
+            .ok_or_else(|| anyhow::anyhow!("No API key configured"))?;
+        let request = if self.provider() == ApiProvider::Google {
+            self.client.get(self.models_url(&api_key))
+        } else {
+            self.client
+                .get(build_api_url(&self.base_url, "v1/models"))
+                .bearer_auth(&api_key)
+        };
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("API failed: {}", response.text().await?));
+        }
+        let json: Value = response.json().await?;
+        Ok(if self.provider() == ApiProvider::Google {
+            parse_google_models(&json)
+        } else {
+            parse_openai_models(&json)
+        })
+    }
+
+    fn provider(&self) -> ApiProvider {
+        detect_provider(&self.base_url, &self.model)
+    }
 
-        user_prompt.push_str(&format!("Example Output: {}\n", OutputFormat::example()));
+    /// Sends the payload to the generation endpoint with the next key of the
+    /// pool. On a 429 the key is put on cooldown and the request is repeated
+    /// with the next one, until every key has been tried once.
+    async fn post_with_key_rotation(&self, payload: &Value, stream: bool) -> Result<Response> {
+        let attempts = self.keys.len();
+        let mut attempt = 0;
+        loop {
+            let api_key = self
+                .keys
+                .next_key()
+                .ok_or_else(|| anyhow::anyhow!("No API key configured"))?;
+            let url = self.generate_url(stream, &api_key);
+            let response = self.logged_post(&url, &api_key, payload, stream).await?;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                return Ok(response);
+            }
 
-        if remove_filler_words {
-            user_prompt.push_str("IMPORTANT: Remove all filler words (um, uh, like, you know) and non-voice sounds (coughs, breaths) from the 'text' field. The transcript should be clean and ready for subtitles.\n");
+            self.keys.mark_rate_limited(&api_key);
+            attempt += 1;
+            if attempt >= attempts {
+                return Ok(response);
+            }
+            warn!(
+                "API key {} of {} is rate limited, retrying with the next one",
+                attempt, attempts
+            );
         }
+    }
+
+    /// Endpoint for (streaming) content generation.
+    /// Google's `models.list` endpoint, see `generate_url`.
+    fn models_url(&self, api_key: &str) -> String {
+        let models = if self.base_url.contains(VERTEX_AI_HOST) {
+            "v1/publishers/google/models"
+        } else {
+            "v1beta/models"
+        };
+        // Without pageSize only the first 50 models are returned
+        let path = format!("{}?pageSize=1000&key={}", models, api_key);
+        build_api_url(&self.base_url, &path)
+    }
 
-        // Determine if this is a Google API or OpenAI-compatible API
-        let is_google_api = self.base_url.contains("generativelanguage.googleapis.com");
+    fn generate_url(&self, stream: bool, api_key: &str) -> String {
+        if self.provider() == ApiProvider::Google {
+            // Google uses query parameter for API key
+            let method = if stream {
+                "streamGenerateContent?alt=sse&"
+            } else {
+                "generateContent?"
+            };
+            // Vertex AI express mode, which also takes an API key
+            let models = if self.base_url.contains(VERTEX_AI_HOST) {
+                "v1/publishers/google/models"
+            } else {
+                "v1beta/models"
+            };
+            let path = format!("{}/{}:{}key={}", models, self.model, method, api_key);
+            build_api_url(&self.base_url, &path)
+        } else {
+            // OpenAI/LiteLLM use path-based endpoint
+            build_api_url(&self.base_url, "v1/chat/completions")
+        }
+    }
 
-        let payload = if is_google_api {
+    fn audio_payload(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        audio_uri: Option<&str>,
+        audio_base64: Option<&str>,
+    ) -> Value {
+        if self.provider() == ApiProvider::Google {
             // Google format
             let mut contents = vec![json!({
                 "role": "user",
@@ -239,14 +971,14 @@ impl GeminiClient {
             if let Some(uri) = audio_uri {
                 contents[0]["parts"].as_array_mut().unwrap().push(json!({
                     "file_data": {
-                        "mime_type": "audio/ogg",
+                        "mime_type": self.audio_mime_type,
                         "file_uri": uri
                     }
                 }));
             } else if let Some(base64) = audio_base64 {
                 contents[0]["parts"].as_array_mut().unwrap().push(json!({
                     "inline_data": {
-                        "mime_type": "audio/ogg",
+                        "mime_type": self.audio_mime_type,
                         "data": base64
                     }
                 }));
@@ -272,7 +1004,7 @@ impl GeminiClient {
                     "type": "input_audio",
                     "input_audio": {
                         "data": base64,
-                        "format": "ogg"
+                        "format": openai_audio_format(self.audio_mime_type)
                     }
                 }));
             }
@@ -290,52 +1022,10 @@ impl GeminiClient {
                     }
                 ]
             })
-        };
-
-        let base_url = self.base_url.trim_end_matches('/');
-        let url = if is_google_api {
-            // Google uses query parameter for API key
-            format!(
-                "{}/v1beta/models/{}:generateContent?key={}",
-                base_url, self.model, self.api_key
-            )
-        } else {
-            // OpenAI/LiteLLM use path-based endpoint
-            format!("{}/v1/chat/completions", base_url)
-        };
-
-        let mut request = self.client.post(&url).json(&payload);
-
-        // Add Authorization header for non-Google APIs
-        if !is_google_api {
-            request = request.header("Authorization", format!("Bearer {}", self.api_key));
-        }
-
-        let response = request.send().await?;
-
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("API failed: {}", response.text().await?));
-        }
-
-        let res_json: Value = response.json().await?;
-
-        // Extract text from response (handle both Google and OpenAI formats)
-        let text = if is_google_api {
-            res_json["candidates"][0]["content"]["parts"][0]["text"]
-                .as_str()
-                .unwrap_or("No text response")
-                .to_string()
-        } else {
-            // OpenAI format
-            res_json["choices"][0]["message"]["content"]
-                .as_str()
-                .unwrap_or("No text response")
-                .to_string()
-        };
-
-        Ok(text)
-    }
+        }
+    }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn generate_clips(
         &self,
         transcript: &str,
@@ -344,8 +1034,11 @@ impl GeminiClient {
         max_duration: u32,
         topic: Option<String>,
         splicing: bool,
+        genre: Option<&ClipGenrePrompt>,
     ) -> Result<String> {
-        let system_prompt = "You are a viral content expert. Your goal is to identify the most engaging moments in a video transcript for social media clips (TikTok, Reels, Shorts).";
+        let system_prompt = genre
+            .map(|g| system_prompt_for_genre(&g.genre))
+            .unwrap_or(DEFAULT_CLIPS_SYSTEM_PROMPT);
 
         let mut user_prompt = format!(
             "Analyze the following transcript and identify the top {} most interesting clips.
@@ -360,11 +1053,18 @@ impl GeminiClient {
             user_prompt.push_str(&format!("- Focus specifically on the topic: '{}'.\n", t));
         }
 
+        if let Some(instructions) = genre
+            .and_then(|g| g.custom_instructions.as_deref())
+            .filter(|i| !i.trim().is_empty())
+        {
+            user_prompt.push_str(&format!("- {}\n", instructions.trim()));
+        }
+
         if splicing {
             user_prompt.push_str("- You MAY combine multiple non-contiguous segments into a single clip if they form a coherent narrative. \n");
-            user_prompt.push_str("- Return a strict JSON array of objects with fields: 'segments' (array of {start, end}), 'title' (catchy title), 'reason' (why this is good).\n");
+            user_prompt.push_str("- Return a strict JSON array of objects with fields: 'segments' (array of {start, end}), 'title' (catchy title), 'reason' (why this is good), 'score' (engagement from 1 to 10).\n");
         } else {
-            user_prompt.push_str("- Return a strict JSON array of objects with fields: 'segments' (array with ONE {start, end} object), 'title' (catchy title), 'reason' (why this is good).\n");
+            user_prompt.push_str("- Return a strict JSON array of objects with fields: 'segments' (array with ONE {start, end} object), 'title' (catchy title), 'reason' (why this is good), 'score' (engagement from 1 to 10).\n");
         }
 
         user_prompt.push_str(&format!(
@@ -373,10 +1073,9 @@ impl GeminiClient {
             transcript
         ));
 
-        // Determine if this is a Google API or OpenAI-compatible API
-        let is_google_api = self.base_url.contains("generativelanguage.googleapis.com");
+        let provider = self.provider();
 
-        let payload = if is_google_api {
+        let payload = if provider == ApiProvider::Google {
             // Google format
             json!({
                 "contents": [{
@@ -404,23 +1103,7 @@ impl GeminiClient {
             })
         };
 
-        let base_url = self.base_url.trim_end_matches('/');
-        let url = if is_google_api {
-            format!(
-                "{}/v1beta/models/{}:generateContent?key={}",
-                base_url, self.model, self.api_key
-            )
-        } else {
-            format!("{}/v1/chat/completions", base_url)
-        };
-
-        let mut request = self.client.post(&url).json(&payload);
-
-        if !is_google_api {
-            request = request.header("Authorization", format!("Bearer {}", self.api_key));
-        }
-
-        let response = request.send().await?;
+        let response = self.post_with_key_rotation(&payload, false).await?;
 
         if !response.status().is_success() {
             return Err(anyhow::anyhow!("API failed: {}", response.text().await?));
@@ -428,7 +1111,7 @@ impl GeminiClient {
 
         let res_json: Value = response.json().await?;
 
-        let text = if is_google_api {
+        let text = if provider == ApiProvider::Google {
             res_json["candidates"][0]["content"]["parts"][0]["text"]
                 .as_str()
                 .unwrap_or("No text response")
@@ -443,3 +1126,1182 @@ impl GeminiClient {
         Ok(text)
     }
 }
+
+/// Rough token estimate used to size translation chunks (~4 characters per token).
+fn estimate_tokens(text: &str) -> usize {
+    text.len() / 4
+}
+
+/// Splits the transcript into consecutive chunks whose estimated token count
+/// stays under `max_tokens`. A segment that is too long on its own gets its own
+/// chunk rather than being split.
+pub fn chunk_by_tokens(
+    transcript: Vec<TranscriptSegment>,
+    max_tokens: usize,
+) -> Vec<Vec<TranscriptSegment>> {
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    let mut chunk_tokens = 0;
+    for segment in transcript {
+        let tokens = estimate_tokens(&segment.text);
+        if !chunk.is_empty() && chunk_tokens + tokens > max_tokens {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_tokens = 0;
+        }
+        chunk_tokens += tokens;
+        chunk.push(segment);
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// True if both texts have the same words, ignoring punctuation and case.
+fn same_words(a: &str, b: &str) -> bool {
+    let words = |text: &str| -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .map(|w| w.trim_matches('\'').to_lowercase())
+            .filter(|w| !w.is_empty())
+            .collect()
+    };
+    words(a) == words(b)
+}
+
+/// Transcript as `[start] text` lines, cut off after `max_words` words.
+fn description_transcript_context(transcript: &[TranscriptSegment], max_words: usize) -> String {
+    let mut lines = Vec::new();
+    let mut remaining = max_words;
+    for segment in transcript {
+        if remaining == 0 {
+            break;
+        }
+        let words: Vec<&str> = segment.text.split_whitespace().take(remaining).collect();
+        remaining -= words.len();
+        lines.push(format!("[{}] {}", segment.start, words.join(" ")));
+    }
+    lines.join("\n")
+}
+
+/// Transcript lines overlapping any of the clip's segments, one per line.
+fn clip_transcript_context(clip: &ClipSegment, transcript: &[TranscriptSegment]) -> String {
+    let ranges: Vec<(f64, f64)> = clip
+        .segments
+        .iter()
+        .filter_map(|s| {
+            let start = parse_timestamp_to_seconds_raw(&s.start).ok()?;
+            let end = parse_timestamp_to_seconds_raw(&s.end).ok()?;
+            Some((start, end))
+        })
+        .collect();
+
+    transcript
+        .iter()
+        .filter(|t| {
+            let (Ok(start), Ok(end)) = (
+                parse_timestamp_to_seconds_raw(&t.start),
+                parse_timestamp_to_seconds_raw(&t.end),
+            ) else {
+                return false;
+            };
+            ranges.iter().any(|&(s, e)| start < e && end > s)
+        })
+        .map(|t| format!("[{} - {}] {}: {}", t.start, t.end, t.speaker, t.text))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Models don't always stick to the requested 0.0-1.0 range and answer on a
+/// 0-10 or 0-100 scale instead. Detect the scale from the largest score so all
+/// clips stay comparable, then clamp.
+fn normalize_virality_scores(scores: &mut [RawClipScore]) {
+    let max = scores.iter().map(|s| s.score).fold(0.0, f64::max);
+    let divisor = if max > 10.0 {
+        100.0
+    } else if max > 1.0 {
+        10.0
+    } else {
+        1.0
+    };
+    for s in scores.iter_mut() {
+        s.score = (s.score / divisor).clamp(0.0, 1.0);
+    }
+}
+
+/// Makes the virality scores of several `rank_clips_by_virality` runs
+/// comparable. Each session is min-max normalized to 0.0..=1.0 and then
+/// scaled by its median score relative to the highest session median, so a
+/// session of weaker clips doesn't get a top score of its own. Sessions whose
+/// clips all score the same keep their scores.
+pub fn normalize_clip_scores(sessions: Vec<Vec<RankedClip>>) -> Vec<Vec<RankedClip>> {
+    let medians: Vec<Option<f64>> = sessions.iter().map(|clips| median_score(clips)).collect();
+    let best_median = medians.iter().flatten().copied().fold(0.0, f64::max);
+
+    sessions
+        .into_iter()
+        .zip(medians)
+        .map(|(mut clips, median)| {
+            let min = clips.iter().map(|c| c.virality_score).fold(f64::INFINITY, f64::min);
+            let max = clips.iter().map(|c| c.virality_score).fold(f64::NEG_INFINITY, f64::max);
+            if max > min {
+                let weight = match median {
+                    Some(median) if best_median > 0.0 => median / best_median,
+                    _ => 1.0,
+                };
+                for clip in &mut clips {
+                    clip.virality_score = (clip.virality_score - min) / (max - min) * weight;
+                }
+            }
+            clips
+        })
+        .collect()
+}
+
+fn median_score(clips: &[RankedClip]) -> Option<f64> {
+    let mut scores: Vec<f64> = clips.iter().map(|c| c.virality_score).collect();
+    scores.sort_by(f64::total_cmp);
+    let mid = scores.len() / 2;
+    match scores.len() {
+        0 => None,
+        n if n % 2 == 0 => Some((scores[mid - 1] + scores[mid]) / 2.0),
+        _ => Some(scores[mid]),
+    }
+}
+
+fn analysis_prompts(
+    context: &str,
+    glossary: &str,
+    speaker_count: Option<u32>,
+    remove_filler_words: bool,
+    output_languages: &[String],
+) -> (String, String) {
+    let mut system_prompt = "You are a professional video editor assistant. Your task is to transcribe the audio and identify logical segments.".to_string();
+
+    if let Some(count) = speaker_count {
+        system_prompt.push_str(&format!(" There are {} speakers in this audio. Please label them as Speaker 1, Speaker 2, etc.", count));
+    }
+
+    if output_languages.len() > 1 {
+        let fields: Vec<String> = output_languages.iter().map(|l| format!("'text_{}'", l)).collect();
+        system_prompt.push_str(&format!(
+            " Output dual-language segments: provide `text_<lang>` for each language in each segment ({}) instead of 'text'.",
+            fields.join(", ")
+        ));
+    }
+
+    let mut user_prompt = format!(
+        "Analyze the following audio.\nContext: {}\nGlossary: {}\n[WISH FOR TIMESTAMPS]: Please output the transcription in a strict JSON format with 'start', 'end', 'speaker', and 'text' fields. Ensure timestamps are in 'MM:SS' format.\n",
+        context, glossary
+    );
+
+    user_prompt.push_str(&format!("Example Output: {}\n", OutputFormat::example()));
+
+    if remove_filler_words {
+        user_prompt.push_str("IMPORTANT: Remove all filler words (um, uh, like, you know) and non-voice sounds (coughs, breaths) from the 'text' field. The transcript should be clean and ready for subtitles.\n");
+    }
+
+    (system_prompt, user_prompt)
+}
+
+/// Drops the clips of a `generate_clips` response whose duration is outside
+/// `min_secs..=max_secs`. The other clips are returned as a JSON array with all
+/// fields of the response, e.g. `title`, and old flat `start`/`end` clips are
+/// normalized to `segments`.
+pub fn filter_generated_clips(response: &str, min_secs: f64, max_secs: f64) -> Result<String> {
+    let mut raw: Vec<serde_json::Map<String, Value>> =
+        serde_json::from_str(&extract_json_array(response)?)?;
+
+    let mut clips = Vec::with_capacity(raw.len());
+    for clip in raw.iter_mut() {
+        if !clip.contains_key("segments") {
+            let segment = json!({ "start": clip.get("start"), "end": clip.get("end") });
+            clip.insert("segments".to_string(), json!([segment]));
+        }
+        let mut parsed: ClipSegment = serde_json::from_value(Value::Object(clip.clone()))?;
+        if parsed.label.is_none() {
+            parsed.label = clip.get("title").and_then(Value::as_str).map(str::to_string);
+        }
+        clips.push(parsed);
+    }
+
+    let (_, rejected) = filter_clips_by_duration(clips.clone(), min_secs, max_secs);
+    let valid: Vec<_> = raw
+        .into_iter()
+        .zip(clips)
+        .filter(|(_, clip)| !rejected.contains(clip))
+        .map(|(raw, _)| raw)
+        .collect();
+    Ok(serde_json::to_string(&valid)?)
+}
+
+/// `analysis_prompts` for exactly two output languages.
+fn bilingual_analysis_prompts(
+    context: &str,
+    glossary: &str,
+    speaker_count: Option<u32>,
+    remove_filler_words: bool,
+    output_languages: &[String],
+) -> Result<(String, String)> {
+    if output_languages.len() != 2 {
+        return Err(anyhow::anyhow!(
+            "Bilingual transcripts need exactly two output languages, got {:?}",
+            output_languages
+        ));
+    }
+    Ok(analysis_prompts(
+        context,
+        glossary,
+        speaker_count,
+        remove_filler_words,
+        output_languages,
+    ))
+}
+
+/// Reads the `text_<lang>` fields of a dual-language transcript response.
+/// A plain `text` field is accepted for the primary language.
+fn parse_bilingual_segments(
+    text: &str,
+    primary: &str,
+    secondary: &str,
+) -> Result<Vec<BilingualSegment>> {
+    let raw: Vec<serde_json::Map<String, Value>> =
+        serde_json::from_str(&extract_json_array(text)?)?;
+
+    raw.iter()
+        .enumerate()
+        .map(|(i, segment)| {
+            let field = |name: &str| segment.get(name).and_then(Value::as_str).map(str::to_string);
+            let text_field = |lang: &str| field(&format!("text_{}", lang));
+            Ok(BilingualSegment {
+                start: field("start").unwrap_or_default(),
+                end: field("end").unwrap_or_default(),
+                speaker: field("speaker").unwrap_or_default(),
+                text_primary: text_field(primary)
+                    .or_else(|| field("text"))
+                    .ok_or_else(|| anyhow::anyhow!("Segment {} has no 'text_{}'", i + 1, primary))?,
+                text_secondary: text_field(secondary)
+                    .ok_or_else(|| anyhow::anyhow!("Segment {} has no 'text_{}'", i + 1, secondary))?,
+            })
+        })
+        .collect()
+}
+
+/// Extracts the JSON array from a model response. Markdown code fences and
+/// text around the array are dropped, and trailing commas before `]` or `}`
+/// are removed if the array doesn't parse as it is. Brackets and commas
+/// inside strings are left alone.
+pub fn extract_json_array(raw: &str) -> Result<String> {
+    let text: String = raw
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut last_error = None;
+    // Text before the array may contain brackets as well, e.g. "[1/2]"
+    for (start, _) in text.match_indices('[') {
+        let Some(end) = balanced_array_end(&text[start..]) else {
+            continue;
+        };
+        let json = &text[start..start + end];
+        match serde_json::from_str::<Value>(json) {
+            Ok(_) => return Ok(json.to_string()),
+            Err(_) => {
+                let repaired = remove_trailing_commas(json);
+                match serde_json::from_str::<Value>(&repaired) {
+                    Ok(_) => return Ok(repaired),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+        }
+    }
+    Err(match last_error {
+        Some(e) => anyhow::anyhow!("Invalid JSON array in response: {}", e),
+        None => anyhow::anyhow!("No complete JSON array in response"),
+    })
+}
+
+/// Tracks whether a scan through JSON text is inside a string literal.
+#[derive(Default)]
+struct JsonStringState {
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonStringState {
+    /// Advances past `c`. Returns whether `c` is part of a string literal,
+    /// including its quotes.
+    fn in_string(&mut self, c: char) -> bool {
+        if self.in_string {
+            match c {
+                _ if self.escaped => self.escaped = false,
+                '\\' => self.escaped = true,
+                '"' => self.in_string = false,
+                _ => {}
+            }
+            return true;
+        }
+        self.in_string = c == '"';
+        self.in_string
+    }
+}
+
+/// Length of the array `text` starts with, up to and including the `]` that
+/// closes it. `None` if it isn't closed.
+fn balanced_array_end(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut strings = JsonStringState::default();
+    for (i, c) in text.char_indices() {
+        if strings.in_string(c) {
+            continue;
+        }
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Drops commas that are followed by `]` or `}`, outside of strings.
+fn remove_trailing_commas(json: &str) -> String {
+    let mut repaired = String::with_capacity(json.len());
+    let mut strings = JsonStringState::default();
+    for (i, c) in json.char_indices() {
+        if !strings.in_string(c) && c == ',' {
+            let next = json[i + 1..].trim_start().chars().next();
+            if matches!(next, Some(']' | '}')) {
+                continue;
+            }
+        }
+        repaired.push(c);
+    }
+    repaired
+}
+
+/// Size of the base64 encoding of `path`. Errors if it is too large to be
+/// sent inline to Google's API (`MAX_INLINE_PAYLOAD_BYTES`), the file has to
+/// be uploaded instead.
+pub fn estimate_base64_payload_size(path: &Path) -> Result<u64> {
+    let file_size = std::fs::metadata(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read metadata of {:?}: {}", path, e))?
+        .len();
+    let payload_bytes = base64_payload_size(file_size);
+    if payload_bytes > MAX_INLINE_PAYLOAD_BYTES {
+        return Err(inline_payload_too_large(payload_bytes, "use upload_file instead"));
+    }
+    Ok(payload_bytes)
+}
+
+fn base64_payload_size(file_size: u64) -> u64 {
+    (file_size * 4).div_ceil(3)
+}
+
+/// Only Google's API truncates large inline data, other providers have their
+/// own, documented request limits.
+fn check_inline_payload_size(base_url: &str, model: &str, payload_size: u64) -> Result<()> {
+    if detect_provider(base_url, model) != ApiProvider::Google
+        || payload_size <= MAX_INLINE_PAYLOAD_BYTES
+    {
+        return Ok(());
+    }
+    let hint = if supports_files_api(base_url) {
+        "use upload_file instead"
+    } else {
+        "shorten or compress the audio"
+    };
+    Err(inline_payload_too_large(payload_size, hint))
+}
+
+fn inline_payload_too_large(payload_size: u64, hint: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "File too large for inline mode ({} bytes as base64, at most {}), {}",
+        payload_size,
+        MAX_INLINE_PAYLOAD_BYTES,
+        hint
+    )
+}
+
+const GEMINI_API_HOST: &str = "generativelanguage.googleapis.com";
+const VERTEX_AI_HOST: &str = "aiplatform.googleapis.com";
+const ANTHROPIC_API_HOST: &str = "api.anthropic.com";
+
+/// Who serves the API configured as base URL and model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApiProvider {
+    /// Gemini API or Vertex AI, with Google's own request format.
+    Google,
+    /// Anthropic's OpenAI-compatible endpoint.
+    Anthropic,
+    /// OpenAI or a proxy such as LiteLLM.
+    OpenAiCompatible,
+}
+
+/// `input_audio.format` of the OpenAI API for an audio MIME type, e.g. "mp3"
+/// for "audio/mpeg".
+fn openai_audio_format(mime_type: &str) -> &str {
+    match mime_type {
+        "audio/mpeg" => "mp3",
+        "audio/mp4" => "m4a",
+        other => other.rsplit('/').next().unwrap_or(other),
+    }
+}
+
+/// Known API hosts decide the provider. Other hosts are proxies, which speak
+/// the OpenAI format whichever model they forward to: `claude-` models are
+/// attributed to Anthropic, everything else (`gpt-`, but also `gemini-`) is
+/// OpenAI-compatible.
+pub fn detect_provider(base_url: &str, model: &str) -> ApiProvider {
+    if base_url.contains(GEMINI_API_HOST) || base_url.contains(VERTEX_AI_HOST) {
+        return ApiProvider::Google;
+    }
+    if base_url.contains(ANTHROPIC_API_HOST) {
+        return ApiProvider::Anthropic;
+    }
+    let model = model.rsplit('/').next().unwrap_or(model);
+    if model.starts_with("claude-") {
+        ApiProvider::Anthropic
+    } else {
+        ApiProvider::OpenAiCompatible
+    }
+}
+
+/// Only the Gemini API has the Files API, Vertex AI takes inline data instead.
+pub fn supports_files_api(base_url: &str) -> bool {
+    base_url.contains(GEMINI_API_HOST)
+}
+
+/// Appends the endpoint `path` to `base_url` with exactly one slash between
+/// them. Proxies are often configured with part of the endpoint already in the
+/// base URL (e.g. `https://proxy.example.com/api/v1`), so path segments the base
+/// URL already ends with aren't repeated.
+pub fn build_api_url(base_url: &str, path: &str) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let path = path.trim_start_matches('/');
+
+    let after_scheme = base_url.split_once("://").map_or(base_url, |(_, rest)| rest);
+    let base_path = after_scheme.split_once('/').map_or("", |(_, path)| path);
+    let base_segments: Vec<&str> = base_path.split('/').filter(|s| !s.is_empty()).collect();
+    let path_segments: Vec<&str> = path.split('/').collect();
+
+    // Longest suffix of the base path that is a prefix of the endpoint path
+    let overlap = (1..=base_segments.len().min(path_segments.len()))
+        .rev()
+        .find(|&n| base_segments[base_segments.len() - n..] == path_segments[..n])
+        .unwrap_or(0);
+    let rest = path_segments[overlap..].join("/");
+    if rest.is_empty() {
+        base_url.to_string()
+    } else {
+        format!("{}/{}", base_url, rest)
+    }
+}
+
+/// Parses a Google `models.list` response, or the `publisherModels` of Vertex
+/// AI. Models that can't generate content (e.g. embeddings) are skipped; Vertex
+/// doesn't report the methods, only its Gemini models are kept there.
+fn parse_google_models(json: &Value) -> Vec<ModelInfo> {
+    let (models, is_vertex) = match json["models"].as_array() {
+        Some(models) => (models, false),
+        None => match json["publisherModels"].as_array() {
+            Some(models) => (models, true),
+            None => return Vec::new(),
+        },
+    };
+    models
+        .iter()
+        .filter(|m| {
+            m["supportedGenerationMethods"]
+                .as_array()
+                .is_none_or(|methods| methods.iter().any(|v| v == "generateContent"))
+        })
+        .filter_map(|m| {
+            // "models/gemini-1.5-flash" or "publishers/google/models/gemini-2.0-flash"
+            let name = m["name"].as_str()?;
+            let id = name.rsplit('/').next().unwrap_or(name).to_string();
+            if is_vertex && !id.starts_with("gemini-") {
+                return None;
+            }
+            Some(ModelInfo {
+                display_name: m["displayName"].as_str().unwrap_or(&id).to_string(),
+                supports_audio: model_supports_audio(&id),
+                context_window_tokens: token_count(&m["inputTokenLimit"]),
+                id,
+            })
+        })
+        .collect()
+}
+
+/// Parses an OpenAI compatible `/v1/models` response. Only some providers
+/// (e.g. Groq, OpenRouter) report the context window.
+fn parse_openai_models(json: &Value) -> Vec<ModelInfo> {
+    let Some(models) = json["data"].as_array() else {
+        return Vec::new();
+    };
+    models
+        .iter()
+        .filter_map(|m| {
+            let id = m["id"].as_str()?.to_string();
+            let context_window = token_count(&m["context_window"])
+                .or_else(|| token_count(&m["context_length"]));
+            Some(ModelInfo {
+                display_name: m["name"].as_str().unwrap_or(&id).to_string(),
+                supports_audio: model_supports_audio(&id),
+                context_window_tokens: context_window,
+                id,
+            })
+        })
+        .collect()
+}
+
+fn token_count(value: &Value) -> Option<u32> {
+    value.as_u64().map(|n| n.min(u32::MAX as u64) as u32)
+}
+
+/// Whether the model takes audio input, judged by its name: Gemini 1.5 and
+/// later (except embedding, image and TTS models), OpenAI audio models and
+/// Whisper. A provider prefix like "gemini/" (LiteLLM) is ignored.
+fn model_supports_audio(id: &str) -> bool {
+    let id = id.to_lowercase();
+    let name = id.rsplit('/').next().unwrap_or(&id);
+    if ["embedding", "imagen", "image", "tts", "aqa", "veo"]
+        .iter()
+        .any(|s| name.contains(s))
+    {
+        return false;
+    }
+    if let Some(version) = name.strip_prefix("gemini-") {
+        // Gemini 1.0 ("gemini-pro", "gemini-1.0-pro") is text and vision only
+        return !(version.starts_with("1.0") || version.starts_with("pro"));
+    }
+    name.contains("audio") || name.contains("transcribe") || name.starts_with("whisper")
+}
+
+/// Extracts the text delta from one server-sent event line.
+fn parse_sse_text(line: &str, provider: ApiProvider) -> Option<String> {
+    let data = line.trim().strip_prefix("data:")?.trim();
+    if data == "[DONE]" {
+        return None;
+    }
+    let json: Value = serde_json::from_str(data).ok()?;
+    let delta = if provider == ApiProvider::Google {
+        &json["candidates"][0]["content"]["parts"][0]["text"]
+    } else {
+        &json["choices"][0]["delta"]["content"]
+    };
+    delta.as_str().map(|s| s.to_string())
+}
+
+fn append_api_log(path: &PathBuf, entry: &Value) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", entry)?;
+    Ok(())
+}
+
+/// Replaces the values of all `REDACTED_FIELDS`, at any depth.
+fn redact_json(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| {
+                    if REDACTED_FIELDS.contains(&k.as_str()) {
+                        (k.clone(), Value::String("[REDACTED]".to_string()))
+                    } else {
+                        (k.clone(), redact_json(v))
+                    }
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact_json).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Redacts the Google `key` query parameter.
+fn redact_url(url: &str) -> String {
+    let re = regex::Regex::new(r"([?&](?:key|api_key)=)[^&]*").unwrap();
+    re.replace_all(url, "${1}[REDACTED]").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            start: "00:00".to_string(),
+            end: "00:05".to_string(),
+            speaker: "Speaker 1".to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_chunk_by_tokens() {
+        let transcript = vec![
+            segment(&"a".repeat(400)),
+            segment(&"b".repeat(400)),
+            segment(&"c".repeat(10000)),
+            segment(&"d".repeat(400)),
+        ];
+        let chunks = chunk_by_tokens(transcript, DEFAULT_MAX_TOKENS_PER_CHUNK);
+        let sizes: Vec<Vec<usize>> = chunks
+            .iter()
+            .map(|c| c.iter().map(|s| s.text.len()).collect())
+            .collect();
+        assert_eq!(sizes, vec![vec![400, 400], vec![10000], vec![400]]);
+
+        let many: Vec<_> = (0..50).map(|_| segment(&"x".repeat(100))).collect();
+        let chunks = chunk_by_tokens(many, 250);
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks.iter().all(|c| c.len() == 10));
+
+        assert!(chunk_by_tokens(vec![], 100).is_empty());
+    }
+
+    #[test]
+    fn test_system_prompt_for_genre() {
+        let genres = [
+            Genre::Interview,
+            Genre::Lecture,
+            Genre::Tutorial,
+            Genre::Entertainment,
+            Genre::Sports,
+        ];
+        let prompts: std::collections::HashSet<_> =
+            genres.iter().map(system_prompt_for_genre).collect();
+        assert_eq!(prompts.len(), genres.len());
+
+        assert!(system_prompt_for_genre(&Genre::Lecture).contains("concept"));
+        assert!(system_prompt_for_genre(&Genre::Tutorial).contains("step"));
+        assert!(system_prompt_for_genre(&Genre::Interview).contains("insight"));
+        assert_eq!(system_prompt_for_genre(&Genre::Entertainment), DEFAULT_CLIPS_SYSTEM_PROMPT);
+
+        let genre: ClipGenrePrompt =
+            serde_json::from_str(r#"{"genre": "Sports", "custom_instructions": null}"#).unwrap();
+        assert_eq!(genre.genre, Genre::Sports);
+    }
+
+    #[test]
+    fn test_description_transcript_context() {
+        let segment = |start: &str, text: &str| TranscriptSegment {
+            start: start.to_string(),
+            end: start.to_string(),
+            speaker: "Speaker 1".to_string(),
+            text: text.to_string(),
+        };
+        let transcript = vec![
+            segment("00:00", "Welcome to the show"),
+            segment("00:05", "today we talk about editing"),
+            segment("00:10", "and nothing else"),
+        ];
+
+        assert_eq!(
+            description_transcript_context(&transcript, 100),
+            "[00:00] Welcome to the show\n[00:05] today we talk about editing\n[00:10] and nothing else"
+        );
+        // Cut off in the middle of the second segment
+        assert_eq!(
+            description_transcript_context(&transcript, 6),
+            "[00:00] Welcome to the show\n[00:05] today we"
+        );
+    }
+
+    #[test]
+    fn test_normalize_clip_scores() {
+        let session = |scores: &[f64]| -> Vec<RankedClip> {
+            scores
+                .iter()
+                .map(|&virality_score| RankedClip {
+                    clip: ClipSegment {
+                        segments: Vec::new(),
+                        label: None,
+                        reason: None,
+                        name_template: None,
+                        score: None,
+                        audio_gain_db: None,
+                    },
+                    virality_score,
+                    reasoning: String::new(),
+                })
+                .collect()
+        };
+        let scores = |sessions: &[Vec<RankedClip>]| -> Vec<Vec<f64>> {
+            sessions
+                .iter()
+                .map(|clips| clips.iter().map(|c| c.virality_score).collect())
+                .collect()
+        };
+
+        // The first session is strong but narrow, the second one weaker and spread out
+        let normalized = normalize_clip_scores(vec![
+            session(&[0.9, 0.8, 0.85]),
+            session(&[0.1, 0.6, 0.3, 0.5]),
+        ]);
+        let normalized = scores(&normalized);
+        let assert_close = |actual: &[f64], expected: &[f64]| {
+            assert_eq!(actual.len(), expected.len());
+            for (a, e) in actual.iter().zip(expected) {
+                assert!((a - e).abs() < 1e-9, "{:?} != {:?}", actual, expected);
+            }
+        };
+        assert_close(&normalized[0], &[1.0, 0.0, 0.5]);
+        // Median 0.4 of 0.85
+        let weight = 0.4 / 0.85;
+        assert_close(&normalized[1], &[0.0, weight, 0.4 * weight, 0.8 * weight]);
+
+        // Nothing to spread
+        let normalized = normalize_clip_scores(vec![session(&[0.7, 0.7]), Vec::new()]);
+        assert_eq!(scores(&normalized), vec![vec![0.7, 0.7], vec![]]);
+    }
+
+    #[test]
+    fn test_filter_generated_clips() {
+        let response = r#"```json
+[
+  {"segments": [{"start": "00:10", "end": "00:11"}], "title": "Too short", "reason": "r", "score": 9},
+  {"segments": [{"start": "01:00", "end": "01:40"}], "title": "Keeper", "reason": "r", "score": 7},
+  {"start": "02:00", "end": "02:20", "title": "Flat", "reason": "r"}
+]
+```"#;
+        let filtered = filter_generated_clips(response, 5.0, 60.0).unwrap();
+        let clips: Vec<Value> = serde_json::from_str(&filtered).unwrap();
+        assert_eq!(clips.len(), 2);
+        assert_eq!(clips[0]["title"], "Keeper");
+        assert_eq!(clips[0]["score"], 7);
+        assert_eq!(clips[1]["segments"], json!([{"start": "02:00", "end": "02:20"}]));
+
+        assert!(filter_generated_clips("No clips found.", 5.0, 60.0).is_err());
+    }
+
+    #[test]
+    fn test_redact_json() {
+        let payload = json!({
+            "model": "gemini-1.5-flash",
+            "api_key": "secret",
+            "contents": [{
+                "parts": [
+                    { "text": "Transcribe this" },
+                    { "inline_data": { "mime_type": "audio/ogg", "data": "T2dnUw==" } }
+                ]
+            }],
+            "nested": { "key": "secret" }
+        });
+
+        let redacted = redact_json(&payload);
+        assert_eq!(redacted["api_key"], "[REDACTED]");
+        assert_eq!(redacted["nested"]["key"], "[REDACTED]");
+        assert_eq!(redacted["contents"][0]["parts"][1]["inline_data"]["data"], "[REDACTED]");
+        assert_eq!(redacted["contents"][0]["parts"][1]["inline_data"]["mime_type"], "audio/ogg");
+        assert_eq!(redacted["contents"][0]["parts"][0]["text"], "Transcribe this");
+        assert_eq!(redacted["model"], "gemini-1.5-flash");
+        assert!(!redacted.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn test_parse_sse_text() {
+        let openai = ApiProvider::OpenAiCompatible;
+        assert_eq!(
+            parse_sse_text(r#"data: {"choices":[{"delta":{"content":"[{\"start\""}}]}"#, openai),
+            Some("[{\"start\"".to_string())
+        );
+        assert_eq!(
+            parse_sse_text(
+                r#"data: {"candidates":[{"content":{"parts":[{"text":"Hello"}]}}]}"#,
+                ApiProvider::Google
+            ),
+            Some("Hello".to_string())
+        );
+        assert_eq!(parse_sse_text("data: [DONE]", openai), None);
+        assert_eq!(parse_sse_text(": keep-alive", openai), None);
+        // Role-only first chunk without content
+        assert_eq!(
+            parse_sse_text(r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#, openai),
+            None
+        );
+    }
+
+    #[test]
+    fn test_redact_url() {
+        assert_eq!(
+            redact_url("https://example.com/v1beta/models/m:generateContent?key=AIzaSecret"),
+            "https://example.com/v1beta/models/m:generateContent?key=[REDACTED]"
+        );
+        assert_eq!(
+            redact_url("http://localhost/v1/chat/completions"),
+            "http://localhost/v1/chat/completions"
+        );
+    }
+
+    #[test]
+    fn test_parse_bilingual_segments() {
+        let response = r#"```json
+[{"start": "00:00", "end": "00:04", "speaker": "Speaker 1", "text_en": "Good morning", "text_de": "Guten Morgen"},
+ {"start": "00:04", "end": "00:07", "speaker": "Speaker 2", "text": "Thanks", "text_de": "Danke"}]
+```"#;
+        let segments = parse_bilingual_segments(response, "en", "de").unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].text_primary, "Good morning");
+        assert_eq!(segments[0].text_secondary, "Guten Morgen");
+        assert_eq!(segments[1].text_primary, "Thanks");
+
+        let missing = r#"[{"start": "00:00", "end": "00:04", "speaker": "A", "text_en": "Hi"}]"#;
+        assert!(parse_bilingual_segments(missing, "en", "de").is_err());
+    }
+
+    #[test]
+    fn test_same_words() {
+        assert!(same_words("hello world how are you", "Hello, world! How are you?"));
+        // Contractions count as a changed word
+        assert!(!same_words("dont do that", "Don't do that."));
+        assert!(same_words("it's fine", "It's fine."));
+        assert!(!same_words("hello world", "Hello, new world."));
+    }
+
+    #[test]
+    fn test_key_pool_round_robin() {
+        let pool = ApiKeyPool::new(vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        let keys: Vec<String> = (0..5).map(|_| pool.next_key().unwrap()).collect();
+        assert_eq!(keys, vec!["a", "b", "c", "a", "b"]);
+
+        pool.add_key("b".to_string());
+        assert_eq!(pool.len(), 3);
+        assert!(pool.remove_key("a"));
+        assert!(!pool.remove_key("a"));
+        assert!(ApiKeyPool::default().next_key().is_none());
+    }
+
+    #[test]
+    fn test_key_pool_skips_rate_limited() {
+        let pool = ApiKeyPool::new(vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        pool.mark_rate_limited("b");
+        let keys: Vec<String> = (0..4).map(|_| pool.next_key().unwrap()).collect();
+        assert_eq!(keys, vec!["a", "c", "a", "c"]);
+
+        // With every key on cooldown the rotation goes on regardless
+        pool.mark_rate_limited("a");
+        pool.mark_rate_limited("c");
+        assert!(pool.next_key().is_some());
+    }
+
+    #[test]
+    fn test_extract_json_array() {
+        let expected = json!([{ "start": "00:01", "end": "00:02", "text": "Hi" }]);
+        let responses = [
+            // Fenced with a language tag
+            "```json\n[{\"start\": \"00:01\", \"end\": \"00:02\", \"text\": \"Hi\"}]\n```",
+            // Fenced without one, the closing fence followed by text
+            "```\n[{\"start\": \"00:01\", \"end\": \"00:02\", \"text\": \"Hi\"}]\n```\nDone.",
+            // Prose around the array
+            "Here is the transcript:\n[{\"start\": \"00:01\", \"end\": \"00:02\", \"text\": \"Hi\"}]\nLet me know!",
+            // Trailing comma after the last element
+            "[{\"start\": \"00:01\", \"end\": \"00:02\", \"text\": \"Hi\"},\n]",
+            // Trailing comma after the last field
+            "[{\"start\": \"00:01\", \"end\": \"00:02\", \"text\": \"Hi\",\n}]",
+            // Brackets in the prose before the array
+            "Result [1/1]:\n[{\"start\": \"00:01\", \"end\": \"00:02\", \"text\": \"Hi\"}]",
+            // A bracket in the prose after the array, which rfind(']') picked up
+            "[{\"start\": \"00:01\", \"end\": \"00:02\", \"text\": \"Hi\"}]\nNote: timestamps are [mm:ss]",
+        ];
+        for response in responses {
+            let json = extract_json_array(response).unwrap();
+            assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), expected, "{}", response);
+        }
+
+        // Brackets and commas inside strings are left alone
+        let json = extract_json_array(
+            "```json\n[{\"text\": \"He said [laughs], ]\", \"segments\": [{\"start\": \"00:01\"},],},]\n```",
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&json).unwrap(),
+            json!([{ "text": "He said [laughs], ]", "segments": [{ "start": "00:01" }] }])
+        );
+
+        // Cut off by the output token limit
+        assert!(extract_json_array("```json\n[{\"text\": \"Hi\"}, {\"text\": \"Th").is_err());
+        assert!(extract_json_array("Sorry, I can't help with that.").is_err());
+    }
+
+    #[test]
+    fn test_base64_payload_size() {
+        assert_eq!(base64_payload_size(0), 0);
+        assert_eq!(base64_payload_size(3), 4);
+        assert_eq!(base64_payload_size(4), 6);
+        assert_eq!(base64_payload_size(14_155_776), MAX_INLINE_PAYLOAD_BYTES);
+
+        let gemini = "https://generativelanguage.googleapis.com";
+        let too_large = MAX_INLINE_PAYLOAD_BYTES + 1;
+        assert!(check_inline_payload_size(gemini, "gemini-2.5-flash", MAX_INLINE_PAYLOAD_BYTES)
+            .is_ok());
+        let err = check_inline_payload_size(gemini, "gemini-2.5-flash", too_large).unwrap_err();
+        assert!(err.to_string().contains("use upload_file instead"), "{}", err);
+
+        // Vertex AI has no Files API
+        let vertex = "https://europe-west4-aiplatform.googleapis.com";
+        let err = check_inline_payload_size(vertex, "gemini-2.5-flash", too_large).unwrap_err();
+        assert!(!err.to_string().contains("upload_file"), "{}", err);
+
+        // The limit is Google's
+        assert!(check_inline_payload_size("https://api.openai.com/v1", "gpt-4o-audio", too_large)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_estimate_base64_payload_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audio.ogg");
+        let file = std::fs::File::create(&path).unwrap();
+
+        file.set_len(3).unwrap();
+        assert_eq!(estimate_base64_payload_size(&path).unwrap(), 4);
+        file.set_len(14_155_776).unwrap();
+        assert_eq!(estimate_base64_payload_size(&path).unwrap(), MAX_INLINE_PAYLOAD_BYTES);
+
+        file.set_len(14_155_777).unwrap();
+        let err = estimate_base64_payload_size(&path).unwrap_err();
+        assert!(err.to_string().contains("use upload_file instead"), "{}", err);
+        assert!(estimate_base64_payload_size(&dir.path().join("missing.ogg")).is_err());
+    }
+
+    #[test]
+    fn test_build_api_url_slashes() {
+        let endpoint = "https://api.openai.com/v1/chat/completions";
+        for base in ["https://api.openai.com", "https://api.openai.com/"] {
+            for path in ["v1/chat/completions", "/v1/chat/completions"] {
+                assert_eq!(build_api_url(base, path), endpoint, "{} + {}", base, path);
+            }
+        }
+
+        let endpoint = "https://proxy.company.com/api/v2/v1/chat/completions";
+        for base in ["https://proxy.company.com/api/v2", "https://proxy.company.com/api/v2/"] {
+            for path in ["v1/chat/completions", "/v1/chat/completions"] {
+                assert_eq!(build_api_url(base, path), endpoint, "{} + {}", base, path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_api_url_strips_duplicate_prefix() {
+        assert_eq!(
+            build_api_url("https://proxy.company.com/api/v1/", "/v1/chat/completions"),
+            "https://proxy.company.com/api/v1/chat/completions"
+        );
+        assert_eq!(
+            build_api_url("http://localhost:4000/v1/chat", "v1/chat/completions"),
+            "http://localhost:4000/v1/chat/completions"
+        );
+        assert_eq!(
+            build_api_url("http://localhost:4000/v1/chat/completions", "v1/chat/completions"),
+            "http://localhost:4000/v1/chat/completions"
+        );
+        assert_eq!(
+            build_api_url(
+                "https://generativelanguage.googleapis.com/v1beta",
+                "v1beta/models/gemini-1.5-flash:generateContent?key=abc"
+            ),
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-1.5-flash:generateContent?key=abc"
+        );
+        // Only whole segments count
+        assert_eq!(
+            build_api_url("https://proxy.company.com/av1", "v1/models"),
+            "https://proxy.company.com/av1/v1/models"
+        );
+    }
+
+    #[test]
+    fn test_detect_provider() {
+        let cases = [
+            ("https://generativelanguage.googleapis.com", "gemini-1.5-flash", ApiProvider::Google),
+            (
+                "https://us-central1-aiplatform.googleapis.com/v1",
+                "gemini-2.0-flash",
+                ApiProvider::Google,
+            ),
+            ("https://api.anthropic.com/v1/", "claude-sonnet-4-5", ApiProvider::Anthropic),
+            ("https://api.openai.com", "gpt-4o", ApiProvider::OpenAiCompatible),
+            // Proxies speak the OpenAI format, the model tells who is behind them
+            ("http://localhost:4000", "anthropic/claude-3-5-haiku", ApiProvider::Anthropic),
+            ("http://localhost:4000", "gemini-1.5-pro", ApiProvider::OpenAiCompatible),
+        ];
+        for (base_url, model, expected) in cases {
+            assert_eq!(detect_provider(base_url, model), expected, "{} {}", base_url, model);
+        }
+
+        assert!(supports_files_api("https://generativelanguage.googleapis.com"));
+        assert!(!supports_files_api("https://aiplatform.googleapis.com"));
+
+        let vertex = GeminiClient::new(
+            "abc".to_string(),
+            "https://aiplatform.googleapis.com".to_string(),
+            "gemini-2.0-flash".to_string(),
+        );
+        assert_eq!(
+            vertex.generate_url(false, "abc"),
+            "https://aiplatform.googleapis.com/v1/publishers/google/models/gemini-2.0-flash:generateContent?key=abc"
+        );
+        assert_eq!(
+            vertex.models_url("abc"),
+            "https://aiplatform.googleapis.com/v1/publishers/google/models?pageSize=1000&key=abc"
+        );
+        let gemini = GeminiClient::new(
+            "abc".to_string(),
+            "https://generativelanguage.googleapis.com".to_string(),
+            "gemini-2.0-flash".to_string(),
+        );
+        assert_eq!(
+            gemini.models_url("abc"),
+            "https://generativelanguage.googleapis.com/v1beta/models?pageSize=1000&key=abc"
+        );
+    }
+
+    #[test]
+    fn test_audio_payload_format() {
+        let client = |base_url: &str, audio: &str| {
+            GeminiClient::new("abc".to_string(), base_url.to_string(), "model".to_string())
+                .with_audio_file(Path::new(audio))
+        };
+
+        let google = client("https://generativelanguage.googleapis.com", "/media/talk.ai.mp3");
+        let payload = google.audio_payload("system", "user", None, Some("AAAA"));
+        assert_eq!(payload["contents"][0]["parts"][1]["inline_data"]["mime_type"], "audio/mpeg");
+        let payload = google.audio_payload("system", "user", Some("files/1"), None);
+        assert_eq!(payload["contents"][0]["parts"][1]["file_data"]["mime_type"], "audio/mpeg");
+
+        for (audio, format) in [
+            ("talk.mp3", "mp3"),
+            ("talk.wav", "wav"),
+            ("talk.ogg", "ogg"),
+            ("talk.flac", "flac"),
+            ("talk.m4a", "m4a"),
+        ] {
+            let openai = client("https://api.openai.com/v1", audio);
+            let payload = openai.audio_payload("system", "user", None, Some("AAAA"));
+            assert_eq!(payload["messages"][1]["content"][1]["input_audio"]["format"], format);
+        }
+
+        // Without a file, the audio is the OGG of `prepare_audio_for_ai`
+        let default = GeminiClient::new(
+            "abc".to_string(),
+            "https://api.openai.com/v1".to_string(),
+            "model".to_string(),
+        );
+        let payload = default.audio_payload("system", "user", None, Some("AAAA"));
+        assert_eq!(payload["messages"][1]["content"][1]["input_audio"]["format"], "ogg");
+    }
+
+    #[test]
+    fn test_parse_google_models() {
+        let json = json!({
+            "models": [
+                {
+                    "name": "models/gemini-1.5-flash",
+                    "displayName": "Gemini 1.5 Flash",
+                    "inputTokenLimit": 1000000,
+                    "supportedGenerationMethods": ["generateContent", "countTokens"]
+                },
+                {
+                    "name": "models/gemini-1.0-pro",
+                    "displayName": "Gemini 1.0 Pro",
+                    "inputTokenLimit": 30720,
+                    "supportedGenerationMethods": ["generateContent"]
+                },
+                {
+                    "name": "models/text-embedding-004",
+                    "displayName": "Text Embedding 004",
+                    "inputTokenLimit": 2048,
+                    "supportedGenerationMethods": ["embedContent"]
+                }
+            ],
+            "nextPageToken": ""
+        });
+        assert_eq!(
+            parse_google_models(&json),
+            vec![
+                ModelInfo {
+                    id: "gemini-1.5-flash".to_string(),
+                    display_name: "Gemini 1.5 Flash".to_string(),
+                    supports_audio: true,
+                    context_window_tokens: Some(1000000),
+                },
+                ModelInfo {
+                    id: "gemini-1.0-pro".to_string(),
+                    display_name: "Gemini 1.0 Pro".to_string(),
+                    supports_audio: false,
+                    context_window_tokens: Some(30720),
+                },
+            ]
+        );
+        assert!(parse_google_models(&json!({})).is_empty());
+
+        let vertex = json!({
+            "publisherModels": [
+                { "name": "publishers/google/models/gemini-2.0-flash-001", "versionId": "001" },
+                { "name": "publishers/google/models/imagen-3.0-generate-002" }
+            ]
+        });
+        assert_eq!(
+            parse_google_models(&vertex),
+            vec![ModelInfo {
+                id: "gemini-2.0-flash-001".to_string(),
+                display_name: "gemini-2.0-flash-001".to_string(),
+                supports_audio: true,
+                context_window_tokens: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_openai_models() {
+        let json = json!({
+            "object": "list",
+            "data": [
+                { "id": "gpt-4o-audio-preview", "object": "model", "owned_by": "system" },
+                { "id": "whisper-large-v3", "object": "model", "context_window": 448 },
+                { "id": "gemini/gemini-2.0-flash", "object": "model" }
+            ]
+        });
+        let models = parse_openai_models(&json);
+        assert_eq!(models.len(), 3);
+        assert_eq!(models[0].display_name, "gpt-4o-audio-preview");
+        assert_eq!(models[0].context_window_tokens, None);
+        assert_eq!(models[1].context_window_tokens, Some(448));
+        assert!(models.iter().all(|m| m.supports_audio));
+    }
+
+    #[test]
+    fn test_model_supports_audio() {
+        for id in [
+            "gemini-1.5-pro",
+            "gemini-2.0-flash-lite",
+            "gemini-2.5-flash-preview-05-20",
+            "gpt-4o-mini-audio-preview",
+            "gpt-4o-transcribe",
+            "whisper-1",
+        ] {
+            assert!(model_supports_audio(id), "{}", id);
+        }
+        for id in [
+            "gemini-pro",
+            "gemini-pro-vision",
+            "gemini-1.0-pro-001",
+            "gemini-embedding-exp",
+            "gemini-2.0-flash-exp-image-generation",
+            "gemini-2.5-flash-preview-tts",
+            "imagen-3.0-generate-002",
+            "gpt-4o-mini",
+            "llama-3.1-8b-instant",
+        ] {
+            assert!(!model_supports_audio(id), "{}", id);
+        }
+    }
+}
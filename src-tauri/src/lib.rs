@@ -14,6 +14,15 @@ use log::{info, warn, error};
 #[tauri::command]
 async fn init_ffmpeg() -> Result<String, String> {
     if ffmpeg_is_installed() {
+        let (major, minor) = MIN_FFMPEG_VERSION;
+        if let Ok(version) = detect_ffmpeg_version() {
+            if !is_version_sufficient(&version, major, minor) {
+                return Err(format!(
+                    "FFmpeg {}.{}.{} is too old, please install FFmpeg {}.{} or newer.",
+                    version.major, version.minor, version.patch, major, minor
+                ));
+            }
+        }
         info!("FFmpeg is already installed.");
         return Ok("FFmpeg is already installed.".to_string());
     }
@@ -105,42 +114,164 @@ async fn init_ffmpeg() -> Result<String, String> {
     Ok("FFmpeg downloaded but verification failed. Please restart the app.".to_string())
 }
 
-use ffmpeg_sidecar::command::FfmpegCommand;
-use serde::Serialize;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Payload of every `progress` event, see `ProgressEvent` in `src/types`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProgressEvent {
+    /// Command that reports the progress, e.g. "cut_video".
+    pub job_id: String,
+    /// 0-100.
+    pub percentage: f64,
+    pub message: Option<String>,
+    /// FFmpeg's output position, if FFmpeg is running.
+    pub current_time: Option<String>,
+}
+
+impl ProgressEvent {
+    pub fn message(job_id: &str, percentage: f64, message: &str) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            percentage,
+            message: Some(message.to_string()),
+            current_time: None,
+        }
+    }
+
+    /// Progress estimated from the output size, see `watch_progress_by_size`.
+    pub fn estimated(job_id: &str, percentage: f64) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            percentage,
+            message: None,
+            current_time: None,
+        }
+    }
+
+    /// Progress of FFmpeg runs producing `total_secs` of output. The percentage
+    /// stays at 0 if the total is unknown.
+    pub fn from_ffmpeg_time(job_id: &str, time: &str, total_secs: f64) -> Self {
+        let percentage = match time_utils::parse_timestamp_to_seconds_raw(time) {
+            Ok(secs) if total_secs > 0.0 => (secs / total_secs * 100.0).clamp(0.0, 100.0),
+            _ => 0.0,
+        };
+        Self {
+            job_id: job_id.to_string(),
+            percentage,
+            message: None,
+            current_time: Some(time.to_string()),
+        }
+    }
+}
+
+/// Reports progress from the growing output file if the duration of the job is
+/// unknown, in which case FFmpeg's time can't be turned into a percentage.
+/// Progress stops when the returned watcher is dropped.
+fn watch_progress_by_size(
+    window: &tauri::Window,
+    job_id: &'static str,
+    total_secs: f64,
+    input: &std::path::Path,
+    output: &std::path::Path,
+    compression_ratio: f64,
+) -> Option<OutputSizeWatcher> {
+    let input_size = std::fs::metadata(input).map(|m| m.len()).unwrap_or(0);
+    let estimated_size = progress::fallback_output_size(total_secs, input_size, compression_ratio)?;
+    info!(
+        "Duration unknown, estimating {} progress from an expected {} bytes",
+        job_id, estimated_size
+    );
+
+    let window = window.clone();
+    Some(progress::watch_output_size(
+        output.to_path_buf(),
+        estimated_size,
+        std::time::Duration::from_millis(progress::DEFAULT_PROGRESS_INTERVAL_MS),
+        move |percentage| {
+            let _ = window.emit("progress", ProgressEvent::estimated(job_id, percentage));
+        },
+    ))
+}
+
+/// Duration of a media file for progress reporting, 0 if it can't be probed.
+fn progress_total(path: &std::path::Path) -> f64 {
+    probe::probe_media(path).map(|info| info.duration).unwrap_or(0.0)
+}
 
 #[derive(Serialize)]
 struct AudioInfo {
     path: String,
     size: u64,
+    /// Whether the input is a video. `prepare_audio_for_ai` discards the
+    /// video stream then.
+    source_type: probe::MediaType,
 }
 
 #[tauri::command]
 async fn prepare_audio_for_ai(
     window: tauri::Window,
     input_path: String,
+    mono_downmix_threshold_mb: Option<f64>,
+    quality: Option<i32>,
+    audio_stream_index: Option<u32>,
 ) -> Result<AudioInfo, String> {
     let input = PathBuf::from(&input_path);
     if !input.exists() {
         return Err("Input file does not exist".to_string());
     }
 
-    let output_path = input.with_extension("ogg");
+    let threshold_mb = mono_downmix_threshold_mb.unwrap_or(audio::DEFAULT_MONO_DOWNMIX_THRESHOLD_MB);
+    let threshold_bytes = (threshold_mb * 1024.0 * 1024.0) as u64;
+    let (info, source_type) = match probe::probe_media(&input) {
+        Ok(info) => {
+            let source_type = info.media_type();
+            (info, source_type)
+        }
+        // The requested stream can't be validated without probing
+        Err(e) if audio_stream_index.is_some() => return Err(e.to_string()),
+        Err(e) => {
+            warn!("Could not probe {:?}, keeping channel layout: {}", input, e);
+            (Default::default(), probe::MediaType::Video)
+        }
+    };
+    let (output_path, format) = audio::ai_audio_output(&input, source_type);
+    let audio_args =
+        audio::build_ai_audio_args(&info, format, threshold_bytes, quality, audio_stream_index)
+            .map_err(|e| e.to_string())?;
 
-    // ffmpeg -i input.mp4 -vn -c:a libvorbis -q:a 4 output.ogg
-    FfmpegCommand::new()
+    let size_watcher = watch_progress_by_size(
+        &window,
+        "prepare_audio_for_ai",
+        info.duration,
+        &input,
+        &output_path,
+        progress::AUDIO_EXTRACT_SIZE_RATIO,
+    );
+
+    // ffmpeg -i input.mp4 -vn [-map 0:a:<index>] -c:a libvorbis -q:a <quality> [-ac 1] output.ogg
+    let events = base_ffmpeg_command()
         .input(input.to_str().unwrap())
-        .args(&["-vn", "-c:a", "libvorbis", "-q:a", "4"])
+        .args(audio_args)
         .output(output_path.to_str().unwrap())
         .spawn()
         .map_err(|e| e.to_string())?
         .iter()
-        .map_err(|e| e.to_string())?
-        .for_each(|event| {
-            if let FfmpegEvent::Progress(progress) = event {
-                let _ = window.emit("progress", progress.time);
-            }
-        });
+        .map_err(|e| e.to_string())?;
+    for_each_buffered(events, |event| match event {
+        FfmpegEvent::Progress(progress) if size_watcher.is_none() => {
+            let _ = window.emit(
+                "progress",
+                ProgressEvent::from_ffmpeg_time(
+                    "prepare_audio_for_ai",
+                    &progress.time,
+                    info.duration,
+                ),
+            );
+        }
+        _ => {}
+    });
+    drop(size_watcher);
 
     // Check size
     let metadata = std::fs::metadata(&output_path).map_err(|e| e.to_string())?;
@@ -149,37 +280,270 @@ async fn prepare_audio_for_ai(
     Ok(AudioInfo {
         path: output_path.to_string_lossy().to_string(),
         size,
+        source_type,
     })
 }
 
+#[tauri::command]
+async fn convert_audio_multi_format(
+    window: tauri::Window,
+    input_path: String,
+    formats: Vec<audio::AudioConvertFormat>,
+) -> Result<Vec<AudioInfo>, String> {
+    let input = PathBuf::from(&input_path);
+    if !input.exists() {
+        return Err("Input file does not exist".to_string());
+    }
+    let info = probe::probe_media(&input).map_err(|e| e.to_string())?;
+    let source_type = info.media_type();
+    let total = info.duration;
+    let on_progress = throttled(None, move |time| {
+        let _ = window.emit(
+            "progress",
+            ProgressEvent::from_ffmpeg_time("convert_audio_multi_format", &time, total),
+        );
+    });
+    let outputs = audio::convert_audio_formats(&input, &info, &formats, on_progress)
+        .map_err(|e| e.to_string())?;
+
+    outputs
+        .into_iter()
+        .map(|path| {
+            let size = std::fs::metadata(&path).map_err(|e| e.to_string())?.len();
+            Ok(AudioInfo {
+                path: path.to_string_lossy().to_string(),
+                size,
+                source_type,
+            })
+        })
+        .collect()
+}
+
 mod alignment;
+pub mod audio;
+pub mod cache;
+pub mod chapters;
+pub mod deep_link;
+pub mod docx;
+pub mod edl;
+pub mod ffmpeg_download;
+pub mod ffmpeg_version;
+pub mod fingerprint;
 pub mod gemini;
+pub mod opml;
+pub mod probe;
+pub mod progress;
 pub mod silence;
+pub mod stems;
+mod state;
+pub mod subtitles;
+pub mod thumbnails;
 pub mod time_utils;
+pub mod transcript;
+pub mod tts;
+pub mod undo;
 mod upload;
 pub mod video;
 
-use crate::alignment::align_transcript;
-use crate::gemini::GeminiClient;
-use crate::silence::{detect_silence, remove_silence};
+use crate::alignment::{
+    align_transcript, load_local_alignment_model, preload_alignment_model, unload_alignment_model,
+};
+use crate::audio::{
+    analyze_audio_quality, cleanup_temp_previews, extract_clip_preview_audio, find_audio_peaks,
+};
+use crate::ffmpeg_version::{
+    detect_ffmpeg_version, get_ffmpeg_version, is_version_sufficient, MIN_FFMPEG_VERSION,
+};
+use crate::gemini::{
+    filter_generated_clips, normalize_clip_scores, ClipGenrePrompt, DescriptionStyle, GeminiClient,
+    LanguageDetectionResult, ModelInfo, RankedClip,
+};
+use crate::progress::{for_each_buffered, throttled, OutputSizeWatcher};
+use crate::deep_link::{handle_deep_link, setup_deep_links, take_pending_project};
+use crate::edl::export_edl;
+use crate::opml::export_opml;
+use crate::cache::{clear_api_cache, get_api_cache_stats, ApiCache};
+use crate::chapters::parse_youtube_chapters;
+use crate::docx::import_docx_transcript;
+use crate::stems::separate_stems;
+use crate::subtitles::{import_subtitles, preview_subtitle_burn};
+use crate::ffmpeg_download::init_ffmpeg_from_url;
+use crate::silence::{
+    detect_silence, fill_silence_with_bed, remove_silence, remove_silence_batch_with_progress,
+};
+use crate::state::AppState;
+use crate::thumbnails::generate_seek_thumbnails;
+use crate::transcript::{
+    analyze_transcript_coverage, auto_detect_sync_offset, auto_normalize_speaker_labels,
+    convert_utc_timestamps, find_partial_transcripts, remove_hallucinated_segments,
+    remove_hallucinated_timestamps, rename_speakers, repair_transcript_timing,
+    shift_transcript_timestamps, transcript_statistics, validate_transcript,
+    PartialTranscriptSaver,
+};
+use crate::undo::{push_transcript_edit, redo_transcript, undo_transcript};
 use crate::upload::upload_file_and_wait;
 use crate::video::{
-    cut_video as cut_video_fn, export_clips as export_clips_fn, ClipSegment, Segment,
-    TranscriptSegment,
+    add_watermark as add_watermark_fn, base_ffmpeg_command, concat_videos as concat_videos_fn,
+    create_video_grid as create_video_grid_fn, cut_video as cut_video_fn, deduplicate_clips,
+    export_clips as export_clips_fn, export_comparison_video as export_comparison_video_fn,
+    flatten_timeline as flatten_timeline_fn, reorder_timeline as reorder_timeline_fn,
+    select_highlight_segments, select_poster_frame as select_poster_frame_fn,
+    split_by_chapters as split_by_chapters_fn,
+    subtract_pts_offset, trim_clip as trim_clip_fn, ClipExportResult, ClipSegment, ComparisonLayout,
+    OutputProfile, Segment,
+    Timeline, TranscriptSegment, WatermarkPosition,
 };
 
+/// Peak-normalizes the audio of `input_path` to `target_peak_dbfs`
+/// (-60 to 0 dBFS), see `audio::peak_normalize`.
+#[tauri::command]
+async fn peak_normalize_audio(
+    window: tauri::Window,
+    input_path: String,
+    output_path: String,
+    target_peak_dbfs: f64,
+) -> Result<AudioInfo, String> {
+    let input = PathBuf::from(&input_path);
+    let output = PathBuf::from(&output_path);
+    let total = progress_total(&input);
+    let _ = window.emit(
+        "progress",
+        ProgressEvent::message("peak_normalize_audio", 0.0, "Measuring peak level..."),
+    );
+    audio::peak_normalize(&input, &output, target_peak_dbfs, |time| {
+        let _ = window.emit(
+            "progress",
+            ProgressEvent::from_ffmpeg_time("peak_normalize_audio", &time, total),
+        );
+    })
+    .map_err(|e| e.to_string())?;
+
+    let size = std::fs::metadata(&output).map_err(|e| e.to_string())?.len();
+    let source_type = probe::probe_media(&input)
+        .map(|info| info.media_type())
+        .unwrap_or(probe::MediaType::Audio);
+    Ok(AudioInfo {
+        path: output_path,
+        size,
+        source_type,
+    })
+}
+
+/// Synthesizes the (translated) transcript into a WAV file for previewing.
+/// With `dry_run`, only the segment timing is validated and nothing is written.
 #[tauri::command]
+async fn synthesize_speech(
+    api_key: String,
+    base_url: String,
+    segments: Vec<TranscriptSegment>,
+    voice: String,
+    output_path: String,
+    dry_run: bool,
+) -> Result<AudioInfo, String> {
+    if dry_run {
+        tts::validate_segment_timing(&segments).map_err(|e| e.to_string())?;
+        return Ok(AudioInfo {
+            path: output_path,
+            size: 0,
+            source_type: probe::MediaType::Audio,
+        });
+    }
+
+    let output = PathBuf::from(&output_path);
+    tts::synthesize_speech_to_wav(&api_key, &base_url, &segments, &voice, &output)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let size = std::fs::metadata(&output).map_err(|e| e.to_string())?.len();
+    Ok(AudioInfo {
+        path: output_path,
+        size,
+        source_type: probe::MediaType::Audio,
+    })
+}
+
+/// Client using the key pool if keys were added to it, `api_key` otherwise.
+fn gemini_client(state: &AppState, api_key: String, base_url: String, model: String) -> GeminiClient {
+    if state.api_key_pool.is_empty() {
+        GeminiClient::new(api_key, base_url, model)
+    } else {
+        GeminiClient::with_shared_key_pool(state.api_key_pool.clone(), base_url, model)
+    }
+}
+
+/// Adds a key to the rotation used for all AI requests (e.g. for batch transcription).
+#[tauri::command]
+async fn add_api_key(state: tauri::State<'_, AppState>, key: String) -> Result<(), String> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Err("API key is empty".to_string());
+    }
+    state.api_key_pool.add_key(key.to_string());
+    info!("API key added, {} key(s) in pool", state.api_key_pool.len());
+    Ok(())
+}
+
+#[tauri::command]
+async fn remove_api_key(state: tauri::State<'_, AppState>, key: String) -> Result<(), String> {
+    if !state.api_key_pool.remove_key(key.trim()) {
+        return Err("API key not found".to_string());
+    }
+    info!("API key removed, {} key(s) in pool", state.api_key_pool.len());
+    Ok(())
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn translate_transcript(
+    state: tauri::State<'_, AppState>,
     api_key: String,
     base_url: String,
     model: String,
     transcript: Vec<TranscriptSegment>,
     target_language: String,
     context: String,
+    max_tokens_per_chunk: Option<u32>,
 ) -> Result<String, String> {
-    let client = GeminiClient::new(api_key, base_url, model);
+    let client = gemini_client(&state, api_key, base_url, model);
+    let max_tokens_per_chunk = max_tokens_per_chunk
+        .map(|t| t as usize)
+        .unwrap_or(gemini::DEFAULT_MAX_TOKENS_PER_CHUNK);
+    client
+        .translate_transcript(transcript, target_language, context, max_tokens_per_chunk)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn restore_punctuation(
+    state: tauri::State<'_, AppState>,
+    api_key: String,
+    base_url: String,
+    model: String,
+    segments: Vec<TranscriptSegment>,
+) -> Result<Vec<TranscriptSegment>, String> {
+    let client = gemini_client(&state, api_key, base_url, model);
     client
-        .translate_transcript(transcript, target_language, context)
+        .restore_punctuation(segments)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_video_description(
+    state: tauri::State<'_, AppState>,
+    api_key: String,
+    base_url: String,
+    model: String,
+    transcript: Vec<TranscriptSegment>,
+    title: String,
+    keywords: Vec<String>,
+    description_style: DescriptionStyle,
+) -> Result<String, String> {
+    let client = gemini_client(&state, api_key, base_url, model);
+    client
+        .generate_video_description(&transcript, &title, &keywords, description_style)
         .await
         .map_err(|e| e.to_string())
 }
@@ -196,8 +560,14 @@ async fn upload_file(
         .map_err(|e| e.to_string())
 }
 
+/// With `input_path` and a non-zero `auto_save_interval_secs`, the response is
+/// streamed and the partial text is saved next to the input file every N
+/// seconds (see `find_partial_transcripts`). `audio_path` is the file the audio
+/// was uploaded or encoded from, its format is declared to the API.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn analyze_audio(
+    state: tauri::State<'_, AppState>,
     api_key: String,
     base_url: String,
     model: String,
@@ -207,32 +577,312 @@ async fn analyze_audio(
     remove_filler_words: bool,
     audio_uri: Option<String>,
     audio_base64: Option<String>,
+    audio_path: Option<String>,
+    input_path: Option<String>,
+    auto_save_interval_secs: Option<u64>,
+    check_hallucinations: Option<bool>,
+    audio_duration_secs: Option<f64>,
+    output_languages: Option<Vec<String>>,
 ) -> Result<String, String> {
-    let client = GeminiClient::new(api_key, base_url, model);
-    client
-        .analyze_audio(
-            &context,
-            &glossary,
-            speaker_count,
-            remove_filler_words,
-            audio_uri.as_deref(),
-            audio_base64.as_deref(),
-        )
+    let mut client = gemini_client(&state, api_key, base_url, model);
+    if let Some(audio_path) = &audio_path {
+        client = client.with_audio_file(Path::new(audio_path));
+    }
+    let auto_save_interval_secs = auto_save_interval_secs.filter(|&s| s > 0);
+    let check_hallucinations = check_hallucinations.unwrap_or(false);
+
+    // Bilingual content: a JSON array of `BilingualSegment` instead of the raw response
+    let output_languages = output_languages.unwrap_or_default();
+    if output_languages.len() > 1 {
+        let segments = match (&input_path, auto_save_interval_secs) {
+            (Some(input_path), Some(interval)) => {
+                let mut saver = PartialTranscriptSaver::new(&PathBuf::from(input_path), interval);
+                let segments = client
+                    .analyze_audio_bilingual_streaming(
+                        &context,
+                        &glossary,
+                        speaker_count,
+                        remove_filler_words,
+                        &output_languages,
+                        audio_uri.as_deref(),
+                        audio_base64.as_deref(),
+                        |partial| saver.update(partial),
+                    )
+                    .await
+                    .map_err(|e| e.to_string())?;
+                saver.finish();
+                segments
+            }
+            _ => client
+                .analyze_audio_bilingual(
+                    &context,
+                    &glossary,
+                    speaker_count,
+                    remove_filler_words,
+                    &output_languages,
+                    audio_uri.as_deref(),
+                    audio_base64.as_deref(),
+                )
+                .await
+                .map_err(|e| e.to_string())?,
+        };
+        let segments = if check_hallucinations {
+            let duration = analyzed_audio_duration(audio_duration_secs, input_path.as_deref())?;
+            remove_hallucinated_segments(segments, duration)
+        } else {
+            segments
+        };
+        return serde_json::to_string(&segments).map_err(|e| e.to_string());
+    }
+
+    let text = match (&input_path, auto_save_interval_secs) {
+        (Some(input_path), Some(interval)) => {
+            let mut saver = PartialTranscriptSaver::new(&PathBuf::from(input_path), interval);
+            let text = client
+                .analyze_audio_streaming(
+                    &context,
+                    &glossary,
+                    speaker_count,
+                    remove_filler_words,
+                    audio_uri.as_deref(),
+                    audio_base64.as_deref(),
+                    |partial| saver.update(partial),
+                )
+                .await
+                .map_err(|e| e.to_string())?;
+            saver.finish();
+            text
+        }
+        _ => client
+            .analyze_audio(
+                &context,
+                &glossary,
+                speaker_count,
+                remove_filler_words,
+                audio_uri.as_deref(),
+                audio_base64.as_deref(),
+            )
+            .await
+            .map_err(|e| e.to_string())?,
+    };
+
+    if !check_hallucinations {
+        return Ok(text);
+    }
+    let duration = analyzed_audio_duration(audio_duration_secs, input_path.as_deref())?;
+    Ok(remove_hallucinated_timestamps(&text, duration))
+}
+
+/// Duration for the hallucination check. The analyzed audio may be shorter than
+/// the input (silence removal), so an explicit duration takes precedence.
+fn analyzed_audio_duration(
+    audio_duration_secs: Option<f64>,
+    input_path: Option<&str>,
+) -> Result<f64, String> {
+    match (audio_duration_secs, input_path) {
+        (Some(duration), _) => Ok(duration),
+        (None, Some(path)) => Ok(probe::probe_media(&PathBuf::from(path))
+            .map_err(|e| e.to_string())?
+            .duration),
+        (None, None) => Err("Hallucination check needs the audio duration".to_string()),
+    }
+}
+
+/// Models offered by the API behind `base_url`, for the model picker.
+#[tauri::command]
+async fn list_available_models(
+    api_key: String,
+    base_url: String,
+) -> Result<Vec<ModelInfo>, String> {
+    GeminiClient::new(api_key, base_url, String::new())
+        .list_models()
         .await
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+async fn detect_language(
+    state: tauri::State<'_, AppState>,
+    api_key: String,
+    base_url: String,
+    model: String,
+    audio_base64: Option<String>,
+    audio_uri: Option<String>,
+    audio_path: Option<String>,
+) -> Result<LanguageDetectionResult, String> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let audio_key = match (&audio_uri, &audio_base64) {
+        (Some(uri), _) => uri.clone(),
+        (None, Some(data)) => {
+            let mut hasher = DefaultHasher::new();
+            data.hash(&mut hasher);
+            format!("inline:{:x}", hasher.finish())
+        }
+        (None, None) => return Err("No audio given".to_string()),
+    };
+    // Another provider or model may detect a different language
+    let cache_key = format!("{}|{}|{}", base_url, model, audio_key);
+
+    if let Some(cached) = state.language_cache.lock().unwrap().get(&cache_key) {
+        info!("Using cached language detection for {}", cache_key);
+        return Ok(cached.clone());
+    }
+
+    let mut client = gemini_client(&state, api_key, base_url, model);
+    if let Some(audio_path) = &audio_path {
+        client = client.with_audio_file(Path::new(audio_path));
+    }
+    let result = client
+        .detect_language(audio_uri.as_deref(), audio_base64.as_deref())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .language_cache
+        .lock()
+        .unwrap()
+        .insert(cache_key, result.clone());
+    Ok(result)
+}
+
 #[tauri::command]
 async fn cut_video(
     window: tauri::Window,
     input_path: String,
     segments: Vec<Segment>,
     output_path: String,
+    min_progress_interval_ms: Option<u64>,
+    normalize_segments: Option<bool>,
+    silence_between_segments_ms: Option<u32>,
 ) -> Result<(), String> {
     let input = PathBuf::from(input_path);
     let output = PathBuf::from(output_path);
-    cut_video_fn(&input, &segments, &output, move |time| {
-        let _ = window.emit("progress", time);
+    let gap_count = segments.len().saturating_sub(1) as f64;
+    let total = video::segments_duration(&segments).map_err(|e| e.to_string())?
+        + gap_count * silence_between_segments_ms.unwrap_or(0) as f64 / 1000.0;
+    let size_watcher = watch_progress_by_size(
+        &window,
+        "cut_video",
+        total,
+        &input,
+        &output,
+        progress::REENCODE_SIZE_RATIO,
+    );
+    let use_ffmpeg_time = size_watcher.is_none();
+    let on_progress = throttled(min_progress_interval_ms, move |time| {
+        if use_ffmpeg_time {
+            let _ = window.emit(
+                "progress",
+                ProgressEvent::from_ffmpeg_time("cut_video", &time, total),
+            );
+        }
+    });
+    let result = cut_video_fn(
+        &input,
+        &segments,
+        &output,
+        normalize_segments.unwrap_or(false),
+        silence_between_segments_ms,
+        on_progress,
+    );
+    drop(size_watcher);
+    result.map_err(|e| e.to_string())
+}
+
+/// Splits the video into parts at its embedded chapters, grouping consecutive
+/// chapters into parts of at most `max_part_secs`. Returns the part files.
+#[tauri::command]
+async fn split_by_chapters(
+    window: tauri::Window,
+    input_path: String,
+    output_dir: String,
+    max_part_secs: Option<u32>,
+) -> Result<Vec<String>, String> {
+    let input = PathBuf::from(input_path);
+    let total = progress_total(&input);
+    let on_progress = throttled(None, move |time| {
+        let _ = window.emit(
+            "progress",
+            ProgressEvent::from_ffmpeg_time("split_by_chapters", &time, total),
+        );
+    });
+    let outputs = split_by_chapters_fn(
+        &input,
+        Path::new(&output_dir),
+        max_part_secs.map(f64::from),
+        on_progress,
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(outputs
+        .into_iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect())
+}
+
+/// Corrects segments for recordings whose first audio packet doesn't start at
+/// zero. Segments are returned unchanged if there is no offset.
+#[tauri::command]
+async fn detect_and_correct_pts_offset(
+    input_path: String,
+    segments: Vec<Segment>,
+) -> Result<Vec<Segment>, String> {
+    let input = PathBuf::from(input_path);
+    let offset = probe::detect_pts_offset(&input).map_err(|e| e.to_string())?;
+    if offset > 0.0 {
+        info!("Correcting segments for PTS offset of {:.3}s", offset);
+    }
+    subtract_pts_offset(&segments, offset).map_err(|e| e.to_string())
+}
+
+/// Color primaries, transfer and matrix of the first video stream, e.g. to
+/// suggest `normalize_colorspace` for mixed HDR/SDR footage.
+#[tauri::command]
+async fn probe_colorspace(input_path: String) -> Result<probe::ColorspaceInfo, String> {
+    probe::probe_colorspace(&PathBuf::from(input_path)).map_err(|e| e.to_string())
+}
+
+/// Average frame rate of the first video stream, e.g. to offer
+/// `frame_interpolation` targets.
+#[tauri::command]
+async fn probe_frame_rate(input_path: String) -> Result<f64, String> {
+    probe::probe_frame_rate(&PathBuf::from(input_path)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn reorder_timeline(timeline: Timeline, clip_id: String, new_index: usize) -> Timeline {
+    reorder_timeline_fn(timeline, &clip_id, new_index)
+}
+
+#[tauri::command]
+fn trim_clip(
+    timeline: Timeline,
+    clip_id: String,
+    new_start: String,
+    new_end: String,
+) -> Result<Timeline, String> {
+    trim_clip_fn(timeline, &clip_id, &new_start, &new_end).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn flatten_timeline(timeline: Timeline) -> Vec<Segment> {
+    flatten_timeline_fn(&timeline)
+}
+
+#[tauri::command]
+async fn concat_videos(
+    window: tauri::Window,
+    input_paths: Vec<String>,
+    output_path: String,
+) -> Result<(), String> {
+    let inputs: Vec<PathBuf> = input_paths.into_iter().map(PathBuf::from).collect();
+    let output = PathBuf::from(output_path);
+    let total: f64 = inputs.iter().map(|p| progress_total(p)).sum();
+    concat_videos_fn(&inputs, &output, move |time| {
+        let _ = window.emit(
+            "progress",
+            ProgressEvent::from_ffmpeg_time("concat_videos", &time, total),
+        );
     })
     .map_err(|e| e.to_string())
 }
@@ -243,15 +893,122 @@ async fn export_clips(
     input_path: String,
     segments: Vec<ClipSegment>,
     output_dir: String,
-) -> Result<(), String> {
+    profile: Option<OutputProfile>,
+    min_progress_interval_ms: Option<u64>,
+    transcript: Option<Vec<TranscriptSegment>>,
+) -> Result<Vec<ClipExportResult>, String> {
     let input = PathBuf::from(input_path);
     let output = PathBuf::from(output_dir);
-    export_clips_fn(&input, &segments, &output, move |time| {
-        let _ = window.emit("progress", time);
-    })
+    let profile = profile.unwrap_or_default();
+    let mut total = 0.0;
+    for clip in &segments {
+        total += video::segments_duration(&clip.segments).map_err(|e| e.to_string())?;
+    }
+    let on_progress = throttled(min_progress_interval_ms, move |time| {
+        let _ = window.emit(
+            "progress",
+            ProgressEvent::from_ffmpeg_time("export_clips", &time, total),
+        );
+    });
+    let transcript = transcript.unwrap_or_default();
+    export_clips_fn(&input, &segments, &transcript, &output, &profile, on_progress)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn add_watermark(
+    window: tauri::Window,
+    input_path: String,
+    watermark_path: String,
+    output_path: String,
+    position: WatermarkPosition,
+    opacity: f64,
+    scale: f64,
+) -> Result<(), String> {
+    let input = PathBuf::from(input_path);
+    let watermark = PathBuf::from(watermark_path);
+    let output = PathBuf::from(output_path);
+    let total = progress_total(&input);
+    let on_progress = throttled(None, move |time| {
+        let _ = window.emit(
+            "progress",
+            ProgressEvent::from_ffmpeg_time("add_watermark", &time, total),
+        );
+    });
+    add_watermark_fn(&input, &watermark, &output, position, opacity, scale, on_progress)
+        .map_err(|e| e.to_string())
+}
+
+/// Renders a before/after video of e.g. a color grade, see
+/// `video::export_comparison_video`.
+#[tauri::command]
+async fn export_comparison_video(
+    window: tauri::Window,
+    before_path: String,
+    after_path: String,
+    output_path: String,
+    layout: ComparisonLayout,
+) -> Result<(), String> {
+    let before = PathBuf::from(before_path);
+    let total = progress_total(&before);
+    let on_progress = throttled(None, move |time| {
+        let _ = window.emit(
+            "progress",
+            ProgressEvent::from_ffmpeg_time("export_comparison_video", &time, total),
+        );
+    });
+    export_comparison_video_fn(
+        &before,
+        &PathBuf::from(after_path),
+        &PathBuf::from(output_path),
+        layout,
+        on_progress,
+    )
     .map_err(|e| e.to_string())
 }
 
+/// Tiles up to 9 videos into one, e.g. to compare camera angles, see
+/// `video::create_video_grid`.
+#[tauri::command]
+async fn create_video_grid(
+    window: tauri::Window,
+    input_paths: Vec<String>,
+    output_path: String,
+    columns: u32,
+    sync_offsets: Vec<f64>,
+) -> Result<(), String> {
+    let inputs: Vec<PathBuf> = input_paths.iter().map(PathBuf::from).collect();
+    let total = inputs.first().map(|p| progress_total(p)).unwrap_or(0.0)
+        - sync_offsets.first().copied().unwrap_or(0.0);
+    let on_progress = throttled(None, move |time| {
+        let _ = window.emit(
+            "progress",
+            ProgressEvent::from_ffmpeg_time("create_video_grid", &time, total),
+        );
+    });
+    create_video_grid_fn(
+        &inputs,
+        &PathBuf::from(output_path),
+        columns,
+        &sync_offsets,
+        on_progress,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Timestamp (seconds) of the most eventful frame of a segment, for thumbnails.
+#[tauri::command]
+async fn select_poster_frame(input_path: String, start: String, end: String) -> Result<f64, String> {
+    select_poster_frame_fn(&PathBuf::from(input_path), &start, &end).map_err(|e| e.to_string())
+}
+
+/// Base64 size of the audio at `path`, an error if it is too large to be sent
+/// inline (`read_file_as_base64`) to Google's API and has to be uploaded.
+#[tauri::command]
+fn estimate_base64_payload_size(path: String) -> Result<u64, String> {
+    gemini::estimate_base64_payload_size(Path::new(&path)).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn read_file_as_base64(path: String) -> Result<String, String> {
     use base64::{engine::general_purpose, Engine as _};
@@ -264,7 +1021,9 @@ async fn read_file_as_base64(path: String) -> Result<String, String> {
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn generate_clips(
+    state: tauri::State<'_, AppState>,
     api_key: String,
     base_url: String,
     model: String,
@@ -274,9 +1033,31 @@ async fn generate_clips(
     max_duration: u32,
     topic: Option<String>,
     splicing: bool,
+    genre: Option<ClipGenrePrompt>,
 ) -> Result<String, String> {
-    let client = GeminiClient::new(api_key, base_url, model);
-    client
+    let genre_key = genre
+        .as_ref()
+        .map(|g| serde_json::to_string(g).unwrap_or_default())
+        .unwrap_or_default();
+    // Different endpoints can serve different models under the same name
+    let cache_key = ApiCache::key(&[
+        &base_url,
+        &model,
+        &transcript,
+        &count.to_string(),
+        &min_duration.to_string(),
+        &max_duration.to_string(),
+        topic.as_deref().unwrap_or(""),
+        &splicing.to_string(),
+        &genre_key,
+    ]);
+    if let Some(cached) = state.api_cache.get(&cache_key) {
+        info!("Using cached clip suggestions");
+        return Ok(cached);
+    }
+
+    let client = gemini_client(&state, api_key, base_url, model);
+    let clips = client
         .generate_clips(
             &transcript,
             count,
@@ -284,8 +1065,86 @@ async fn generate_clips(
             max_duration,
             topic,
             splicing,
+            genre.as_ref(),
         )
         .await
+        .map_err(|e| e.to_string())?;
+    // Unparsable responses are passed on, the frontend reports them
+    let clips = filter_generated_clips(&clips, min_duration as f64, max_duration as f64)
+        .unwrap_or_else(|e| {
+            warn!("Could not filter clips by duration: {}", e);
+            clips
+        });
+    state.api_cache.insert(cache_key, clips.clone());
+    Ok(clips)
+}
+
+/// Cuts the best ranked clips that fit into `max_duration_secs` into one video,
+/// in the order they appear in the input.
+#[tauri::command]
+async fn assemble_highlight_reel(
+    window: tauri::Window,
+    input_path: String,
+    clips: Vec<RankedClip>,
+    output_path: String,
+    max_duration_secs: u32,
+) -> Result<(), String> {
+    let segments = select_highlight_segments(&clips, max_duration_secs as f64)
+        .map_err(|e| e.to_string())?;
+    if segments.is_empty() {
+        return Err(format!("No clip fits into {} seconds", max_duration_secs));
+    }
+
+    let total = video::segments_duration(&segments).map_err(|e| e.to_string())?;
+    let on_progress = throttled(None, move |time| {
+        let _ = window.emit(
+            "progress",
+            ProgressEvent::from_ffmpeg_time("assemble_highlight_reel", &time, total),
+        );
+    });
+    cut_video_fn(
+        &PathBuf::from(input_path),
+        &segments,
+        &PathBuf::from(output_path),
+        false,
+        None,
+        on_progress,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Makes the scores of several `rank_clips_by_virality` results comparable, see
+/// `normalize_clip_scores`.
+#[tauri::command]
+fn normalize_scores_across_sessions(sessions: Vec<Vec<RankedClip>>) -> Vec<Vec<RankedClip>> {
+    normalize_clip_scores(sessions)
+}
+
+/// Removes clips from a `generate_clips` result that mostly repeat a better one.
+#[tauri::command]
+fn deduplicate_generated_clips(
+    clips: Vec<ClipSegment>,
+    min_overlap_secs: f64,
+) -> Result<Vec<ClipSegment>, String> {
+    if !min_overlap_secs.is_finite() || min_overlap_secs < 0.0 {
+        return Err(format!("Invalid minimum overlap: {}", min_overlap_secs));
+    }
+    Ok(deduplicate_clips(clips, min_overlap_secs))
+}
+
+#[tauri::command]
+async fn rank_clips_by_virality(
+    state: tauri::State<'_, AppState>,
+    api_key: String,
+    base_url: String,
+    model: String,
+    clips: Vec<ClipSegment>,
+    transcript: Vec<TranscriptSegment>,
+) -> Result<Vec<RankedClip>, String> {
+    let client = gemini_client(&state, api_key, base_url, model);
+    client
+        .rank_clips_by_virality(clips, &transcript)
+        .await
         .map_err(|e| e.to_string())
 }
 
@@ -361,33 +1220,353 @@ async fn zip_logs(app: tauri::AppHandle, target_path: String) -> Result<(), Stri
     Ok(())
 }
 
+/// Prefix of the archives `rotate_logs` writes into the log directory.
+const LOG_ARCHIVE_PREFIX: &str = "logs-archive-";
+
+/// Keeps the `max_files` most recently modified log files and moves the older
+/// ones into a dated zip archive in the log directory. The API request log is
+/// left alone while it is enabled. Returns how many files were archived.
+#[tauri::command]
+async fn rotate_logs(app: tauri::AppHandle, max_files: u32) -> Result<u32, String> {
+    use tauri::Manager;
+
+    let log_dir = app.path().app_log_dir().map_err(|e| e.to_string())?;
+    if !log_dir.exists() {
+        return Ok(0);
+    }
+    let archive_stem = format!(
+        "{}{}",
+        LOG_ARCHIVE_PREFIX,
+        chrono::Local::now().format("%Y-%m-%d_%H%M%S")
+    );
+    let api_log = gemini::api_log_path();
+    rotate_log_dir(&log_dir, max_files as usize, &archive_stem, api_log.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+/// `active_log` is a file that is still written to and is neither archived nor
+/// deleted. The archive is `<archive_stem>.zip`, or `<archive_stem>-N.zip` if
+/// that exists already, e.g. after two rotations within the same second.
+fn rotate_log_dir(
+    log_dir: &Path,
+    max_files: usize,
+    archive_stem: &str,
+    active_log: Option<&Path>,
+) -> anyhow::Result<u32> {
+    use std::io::Write;
+
+    // The newest file is the one being written to
+    if max_files == 0 {
+        return Err(anyhow::anyhow!("At least one log file has to be kept"));
+    }
+
+    let mut logs = Vec::new();
+    for entry in std::fs::read_dir(log_dir)? {
+        let path = entry?.path();
+        let is_archive = path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with(LOG_ARCHIVE_PREFIX));
+        let is_active = active_log.is_some_and(|active| active == path);
+        if path.is_file() && !is_archive && !is_active {
+            logs.push((std::fs::metadata(&path)?.modified()?, path));
+        }
+    }
+    if logs.len() <= max_files {
+        return Ok(0);
+    }
+    // Newest first
+    logs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    let old_logs = &logs[max_files..];
+
+    let (file, archive_name) = create_new_archive(log_dir, archive_stem)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::FileOptions::<()>::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for (_, path) in old_logs {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        zip.start_file(name, options)?;
+        zip.write_all(&std::fs::read(path)?)?;
+    }
+    zip.finish()?;
+
+    // Only deleted once the archive is complete
+    for (_, path) in old_logs {
+        std::fs::remove_file(path)?;
+    }
+    info!("Archived {} old log files into {}", old_logs.len(), archive_name);
+    Ok(old_logs.len() as u32)
+}
+
+/// Creates `<stem>.zip` in `dir`, or the first free `<stem>-N.zip`. Existing
+/// archives are never overwritten.
+fn create_new_archive(dir: &Path, stem: &str) -> anyhow::Result<(std::fs::File, String)> {
+    for n in 0.. {
+        let name = match n {
+            0 => format!("{}.zip", stem),
+            n => format!("{}-{}.zip", stem, n),
+        };
+        match std::fs::File::create_new(dir.join(&name)) {
+            Ok(file) => return Ok((file, name)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    unreachable!("ran out of archive names")
+}
+
+/// Records all AI API requests and responses (with keys and audio redacted) to
+/// `api_requests.jsonl` in the log directory, so they end up in `zip_logs`.
+#[tauri::command]
+async fn enable_api_log(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    use tauri::Manager;
+
+    if !enabled {
+        gemini::set_api_log_path(None);
+        return Ok(());
+    }
+
+    let log_dir = app.path().app_log_dir().map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&log_dir).map_err(|e| e.to_string())?;
+    let path = log_dir.join("api_requests.jsonl");
+    info!("API request log enabled: {:?}", path);
+    gemini::set_api_log_path(Some(path));
+    Ok(())
+}
+
+/// Registers the plugins of the app. `tauri_plugin_log` has to be the first
+/// plugin, otherwise log output of the plugins registered before it is lost.
+/// Tauri can't list the registered plugins, so the order is enforced by this
+/// builder instead: it can only be created with the log plugin and only adds
+/// further plugins after it.
+pub struct AppBuilder<R: tauri::Runtime> {
+    builder: tauri::Builder<R>,
+    plugin_names: Vec<&'static str>,
+}
+
+impl<R: tauri::Runtime> AppBuilder<R> {
+    pub fn new(builder: tauri::Builder<R>, log: tauri_plugin_log::Builder) -> Self {
+        let log = log.build();
+        Self {
+            plugin_names: vec![tauri::plugin::Plugin::name(&log)],
+            builder: builder.plugin(log),
+        }
+    }
+
+    pub fn plugin<P: tauri::plugin::Plugin<R> + 'static>(mut self, plugin: P) -> Self {
+        self.plugin_names.push(plugin.name());
+        self.builder = self.builder.plugin(plugin);
+        self
+    }
+
+    /// Names of the registered plugins in registration order.
+    pub fn plugin_names(&self) -> &[&'static str] {
+        &self.plugin_names
+    }
+
+    pub fn build(self) -> tauri::Builder<R> {
+        self.builder
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_log::Builder::default().build())
+    AppBuilder::new(tauri::Builder::default(), tauri_plugin_log::Builder::default())
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_deep_link::init())
+        .build()
+        .manage(AppState::default())
+        .setup(|app| {
+            setup_deep_links(app)?;
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
             init_ffmpeg,
+            init_ffmpeg_from_url,
+            import_docx_transcript,
+            parse_youtube_chapters,
+            clear_api_cache,
+            get_api_cache_stats,
+            separate_stems,
+            get_ffmpeg_version,
+            handle_deep_link,
+            take_pending_project,
             prepare_audio_for_ai,
+            convert_audio_multi_format,
+            peak_normalize_audio,
             upload_file,
+            add_api_key,
+            remove_api_key,
             analyze_audio,
+            analyze_audio_quality,
+            find_audio_peaks,
+            list_available_models,
+            detect_language,
             cut_video,
+            split_by_chapters,
+            detect_and_correct_pts_offset,
+            probe_colorspace,
+            probe_frame_rate,
+            reorder_timeline,
+            trim_clip,
+            flatten_timeline,
+            concat_videos,
             export_clips,
+            export_edl,
+            export_opml,
+            validate_transcript,
+            preview_subtitle_burn,
+            import_subtitles,
+            add_watermark,
+            create_video_grid,
+            export_comparison_video,
+            generate_seek_thumbnails,
+            select_poster_frame,
             read_file_as_base64,
+            estimate_base64_payload_size,
             generate_clips,
+            deduplicate_generated_clips,
+            normalize_scores_across_sessions,
+            assemble_highlight_reel,
+            rank_clips_by_virality,
             open_folder,
             write_text_file,
             read_text_file,
             align_transcript,
+            preload_alignment_model,
+            load_local_alignment_model,
+            unload_alignment_model,
             detect_silence,
             remove_silence,
+            remove_silence_batch_with_progress,
+            push_transcript_edit,
+            undo_transcript,
+            redo_transcript,
+            fill_silence_with_bed,
             translate_transcript,
-            zip_logs
+            restore_punctuation,
+            generate_video_description,
+            synthesize_speech,
+            zip_logs,
+            rotate_logs,
+            enable_api_log,
+            rename_speakers,
+            auto_normalize_speaker_labels,
+            convert_utc_timestamps,
+            shift_transcript_timestamps,
+            auto_detect_sync_offset,
+            repair_transcript_timing,
+            transcript_statistics,
+            analyze_transcript_coverage,
+            find_partial_transcripts,
+            extract_clip_preview_audio,
+            cleanup_temp_previews
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_builder_registers_log_plugin_first() {
+        let app = AppBuilder::new(
+            tauri::Builder::<tauri::test::MockRuntime>::new(),
+            tauri_plugin_log::Builder::default(),
+        )
+            .plugin(tauri_plugin_opener::init())
+            .plugin(tauri_plugin_process::init());
+        assert_eq!(app.plugin_names(), ["log", "opener", "process"]);
+    }
+
+    #[test]
+    fn test_progress_event_shape() {
+        let event = ProgressEvent::from_ffmpeg_time("cut_video", "00:00:15.00", 60.0);
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "job_id": "cut_video",
+                "percentage": 25.0,
+                "message": null,
+                "current_time": "00:00:15.00"
+            })
+        );
+
+        // Payload as typed by `ProgressEvent` in src/types/index.ts
+        let parsed = serde_json::from_str::<ProgressEvent>(
+            r#"{"job_id":"align_transcript","percentage":20,"message":"Aligning...","current_time":null}"#,
+        )
+        .unwrap();
+        assert_eq!(parsed, ProgressEvent::message("align_transcript", 20.0, "Aligning..."));
+    }
+
+    #[test]
+    fn test_rotate_log_dir_keeps_newest() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = std::time::SystemTime::now();
+        for (i, name) in ["app.log", "app.1.log", "app.2.log", "app.3.log", "api_requests.jsonl"]
+            .iter()
+            .enumerate()
+        {
+            let path = dir.path().join(name);
+            std::fs::write(&path, name).unwrap();
+            let modified = now - std::time::Duration::from_secs(60 * i as u64);
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+
+        let remaining = |dir: &Path| {
+            let mut names: Vec<_> = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+        let archived_names = |name: &str| {
+            let archive_file = std::fs::File::open(dir.path().join(name)).unwrap();
+            let archive = zip::ZipArchive::new(archive_file).unwrap();
+            let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+            names.sort();
+            names
+        };
+
+        // The enabled API log is still written to
+        let api_log = dir.path().join("api_requests.jsonl");
+        let archived = rotate_log_dir(dir.path(), 2, "logs-archive-1", Some(&api_log)).unwrap();
+        assert_eq!(archived, 2);
+        assert_eq!(
+            remaining(dir.path()),
+            ["api_requests.jsonl", "app.1.log", "app.log", "logs-archive-1.zip"]
+        );
+        assert_eq!(archived_names("logs-archive-1.zip"), ["app.2.log", "app.3.log"]);
+
+        // A second rotation within the same second doesn't overwrite the archive
+        let archived = rotate_log_dir(dir.path(), 2, "logs-archive-1", None).unwrap();
+        assert_eq!(archived, 1);
+        assert_eq!(archived_names("logs-archive-1.zip"), ["app.2.log", "app.3.log"]);
+        assert_eq!(archived_names("logs-archive-1-1.zip"), ["api_requests.jsonl"]);
+
+        // Archives don't count as log files
+        assert_eq!(rotate_log_dir(dir.path(), 2, "logs-archive-2", None).unwrap(), 0);
+        assert!(!dir.path().join("logs-archive-2.zip").exists());
+        assert!(rotate_log_dir(dir.path(), 0, "logs-archive-3", None).is_err());
+    }
+
+    #[test]
+    fn test_progress_event_percentage_bounds() {
+        assert_eq!(ProgressEvent::from_ffmpeg_time("x", "00:02:00.00", 60.0).percentage, 100.0);
+        assert_eq!(ProgressEvent::from_ffmpeg_time("x", "00:00:10.00", 0.0).percentage, 0.0);
+        assert_eq!(ProgressEvent::from_ffmpeg_time("x", "N/A", 60.0).percentage, 0.0);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::time_utils::parse_timestamp_to_seconds_raw;
+use crate::video::TranscriptSegment;
+use anyhow::Result;
+use quick_xml::escape::escape;
+
+/// Show notes outline: one `<outline>` per speaker turn (consecutive segments
+/// of the same speaker) with one child per sentence.
+#[tauri::command]
+pub fn export_opml(
+    segments: Vec<TranscriptSegment>,
+    title: String,
+    author: String,
+) -> Result<String, String> {
+    build_opml(&segments, &title, &author).map_err(|e| e.to_string())
+}
+
+struct Sentence {
+    start: f64,
+    text: String,
+}
+
+struct SpeakerTurn<'a> {
+    speaker: &'a str,
+    start: f64,
+    sentences: Vec<Sentence>,
+}
+
+pub fn build_opml(segments: &[TranscriptSegment], title: &str, author: &str) -> Result<String> {
+    let mut turns: Vec<SpeakerTurn> = Vec::new();
+    for segment in segments {
+        let start = parse_timestamp_to_seconds_raw(&segment.start)?;
+        let end = parse_timestamp_to_seconds_raw(&segment.end)?;
+        let sentences = segment_sentences(&segment.text, start, end);
+        match turns.last_mut() {
+            Some(turn) if turn.speaker == segment.speaker => turn.sentences.extend(sentences),
+            _ => turns.push(SpeakerTurn {
+                speaker: &segment.speaker,
+                start,
+                sentences,
+            }),
+        }
+    }
+
+    let mut opml =
+        String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n");
+    opml.push_str("  <head>\n");
+    opml.push_str(&format!("    <title>{}</title>\n", escape(title)));
+    opml.push_str(&format!("    <ownerName>{}</ownerName>\n", escape(author)));
+    opml.push_str("  </head>\n  <body>\n");
+    for turn in &turns {
+        let text = format!("[{}] {}", format_outline_time(turn.start), turn.speaker);
+        opml.push_str(&format!("    <outline text=\"{}\">\n", escape(&text)));
+        for sentence in &turn.sentences {
+            let text = format!(
+                "[{}] {}",
+                format_outline_time(sentence.start),
+                sentence.text
+            );
+            opml.push_str(&format!("      <outline text=\"{}\"/>\n", escape(&text)));
+        }
+        opml.push_str("    </outline>\n");
+    }
+    opml.push_str("  </body>\n</opml>\n");
+    Ok(opml)
+}
+
+/// Splits the text after `.`, `!` and `?`. Segments carry no word timing, so
+/// each sentence's start is interpolated from its position in the text.
+fn segment_sentences(text: &str, start: f64, end: f64) -> Vec<Sentence> {
+    let text = text.trim();
+    let total_chars = text.chars().count().max(1) as f64;
+    let duration = (end - start).max(0.0);
+
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut sentence_offset = 0;
+    let mut chars = text.chars().enumerate().peekable();
+    while let Some((i, c)) = chars.next() {
+        if current.is_empty() {
+            if c.is_whitespace() {
+                continue;
+            }
+            sentence_offset = i;
+        }
+        current.push(c);
+        let at_boundary = matches!(c, '.' | '!' | '?')
+            && chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if at_boundary || chars.peek().is_none() {
+            sentences.push(Sentence {
+                start: start + duration * sentence_offset as f64 / total_chars,
+                text: std::mem::take(&mut current),
+            });
+        }
+    }
+    sentences
+}
+
+/// `MM:SS`, with minutes going past 59 for long episodes.
+fn format_outline_time(seconds: f64) -> String {
+    let total = seconds.max(0.0).floor() as u64;
+    format!("{:02}:{:02}", total / 60, total % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    fn segment(start: &str, end: &str, speaker: &str, text: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            start: start.to_string(),
+            end: end.to_string(),
+            speaker: speaker.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    fn fixture() -> Vec<TranscriptSegment> {
+        vec![
+            segment(
+                "00:00",
+                "00:10",
+                "Host",
+                "Welcome back. Today: R&D at <scale>!",
+            ),
+            segment("00:10", "00:14", "Host", "Let's start."),
+            segment("01:05", "01:09", "Guest", "Thanks for having me \"here\"."),
+        ]
+    }
+
+    #[test]
+    fn test_build_opml() {
+        let opml = build_opml(&fixture(), "Episode 42", "Tech & Talk").unwrap();
+        assert_eq!(
+            opml,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head>
+    <title>Episode 42</title>
+    <ownerName>Tech &amp; Talk</ownerName>
+  </head>
+  <body>
+    <outline text="[00:00] Host">
+      <outline text="[00:00] Welcome back."/>
+      <outline text="[00:03] Today: R&amp;D at &lt;scale&gt;!"/>
+      <outline text="[00:10] Let&apos;s start."/>
+    </outline>
+    <outline text="[01:05] Guest">
+      <outline text="[01:05] Thanks for having me &quot;here&quot;."/>
+    </outline>
+  </body>
+</opml>
+"#
+        );
+    }
+
+    #[test]
+    fn test_opml_is_well_formed() {
+        let opml = build_opml(&fixture(), "A <b> & c", "").unwrap();
+        let mut reader = Reader::from_str(&opml);
+        let mut outline_texts = Vec::new();
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"outline" => {
+                    let text = e.try_get_attribute("text").unwrap().unwrap();
+                    outline_texts.push(text.unescape_value().unwrap().to_string());
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        assert_eq!(outline_texts.len(), 6);
+        assert_eq!(outline_texts[2], "[00:03] Today: R&D at <scale>!");
+        assert_eq!(outline_texts[5], "[01:05] Thanks for having me \"here\".");
+    }
+}
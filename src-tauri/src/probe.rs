@@ -0,0 +1,578 @@
+use anyhow::{anyhow, Context, Result};
+use ffmpeg_sidecar::ffprobe::ffprobe_path;
+use ffmpeg_sidecar::paths::ffmpeg_path;
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::process::Command;
+
+/// Runs `ffprobe -v quiet -of json <args> <path>` and returns the parsed JSON output.
+/// ffprobe is looked for next to the executable (see `init_ffmpeg`), then in PATH.
+fn run_ffprobe_json(path: &Path, args: &[&str]) -> Result<Value> {
+    ffprobe_json(&ffprobe_path(), path, args)
+}
+
+fn ffprobe_json(ffprobe: &Path, path: &Path, args: &[&str]) -> Result<Value> {
+    let output = Command::new(ffprobe)
+        .args(["-v", "quiet", "-of", "json"])
+        .args(args)
+        .arg(path)
+        .output()
+        .with_context(|| format!("Failed to run {:?}", ffprobe))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffprobe failed for {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct MediaInfo {
+    pub duration: f64,
+    pub streams: Vec<StreamInfo>,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct StreamInfo {
+    pub index: u32,
+    /// "video", "audio", "subtitle", ...
+    pub codec_type: String,
+    pub codec_name: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Rational frame rate as reported by ffprobe, e.g. "30000/1001".
+    pub frame_rate: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u32>,
+}
+
+/// Whether a file contains a video or only audio.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MediaType {
+    Audio,
+    Video,
+}
+
+/// Codecs of the still images audio files embed as cover art, which ffprobe
+/// lists as video streams.
+const COVER_ART_CODECS: &[&str] = &["mjpeg", "png", "bmp", "gif", "webp"];
+
+impl MediaInfo {
+    /// `Video` if there is a video stream that isn't cover art of an audio file.
+    pub fn media_type(&self) -> MediaType {
+        let has_audio = self.audio_streams().next().is_some();
+        let is_video = self
+            .video_streams()
+            .any(|s| !(has_audio && COVER_ART_CODECS.contains(&s.codec_name.as_str())));
+        if is_video {
+            MediaType::Video
+        } else {
+            MediaType::Audio
+        }
+    }
+
+    pub fn video_streams(&self) -> impl Iterator<Item = &StreamInfo> {
+        self.streams.iter().filter(|s| s.codec_type == "video")
+    }
+
+    pub fn audio_streams(&self) -> impl Iterator<Item = &StreamInfo> {
+        self.streams.iter().filter(|s| s.codec_type == "audio")
+    }
+}
+
+/// Probes container duration and stream layout of a media file.
+pub fn probe_media(path: &Path) -> Result<MediaInfo> {
+    let json = run_ffprobe_json(path, &["-show_format", "-show_streams"])?;
+    Ok(parse_media_info(&json))
+}
+
+fn parse_media_info(json: &Value) -> MediaInfo {
+    // ffprobe reports most numbers as strings
+    let as_u32 = |v: &Value| {
+        v.as_u64()
+            .map(|n| n as u32)
+            .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+    };
+
+    let duration = parse_format_duration(json).unwrap_or(0.0);
+
+    let streams = json["streams"]
+        .as_array()
+        .map(|streams| {
+            streams
+                .iter()
+                .map(|s| StreamInfo {
+                    index: as_u32(&s["index"]).unwrap_or(0),
+                    codec_type: s["codec_type"].as_str().unwrap_or_default().to_string(),
+                    codec_name: s["codec_name"].as_str().unwrap_or_default().to_string(),
+                    width: as_u32(&s["width"]),
+                    height: as_u32(&s["height"]),
+                    frame_rate: s["r_frame_rate"].as_str().map(|r| r.to_string()),
+                    sample_rate: as_u32(&s["sample_rate"]),
+                    channels: as_u32(&s["channels"]),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    MediaInfo { duration, streams }
+}
+
+/// Container duration in seconds from `ffprobe -show_entries format=duration`.
+/// If ffprobe isn't installed, the `Duration:` line `ffmpeg -i` prints is used.
+pub fn probe_duration_json(path: &str) -> Result<f64> {
+    probe_duration_with(path, &ffprobe_path(), &ffmpeg_path())
+}
+
+fn probe_duration_with(path: &str, ffprobe: &Path, ffmpeg: &Path) -> Result<f64> {
+    match ffprobe_json(
+        ffprobe,
+        Path::new(path),
+        &["-show_entries", "format=duration"],
+    ) {
+        Ok(json) => parse_format_duration(&json)
+            .ok_or_else(|| anyhow!("ffprobe reported no duration for {:?}", path)),
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) =>
+        {
+            warn!(
+                "ffprobe not found, reading the duration of {:?} from ffmpeg",
+                path
+            );
+            probe_duration_ffmpeg(path, ffmpeg)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn parse_format_duration(json: &Value) -> Option<f64> {
+    json["format"]["duration"]
+        .as_str()
+        .and_then(|d| d.parse().ok())
+}
+
+/// Fallback for systems without ffprobe; `ffmpeg -i` exits with an error
+/// because no output is given, but prints the input information first.
+fn probe_duration_ffmpeg(path: &str, ffmpeg: &Path) -> Result<f64> {
+    let output = Command::new(ffmpeg)
+        .arg("-i")
+        .arg(path)
+        .output()
+        .with_context(|| format!("Failed to run {:?}", ffmpeg))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    parse_ffmpeg_duration(&stderr)
+        .ok_or_else(|| anyhow!("Failed to parse duration from ffmpeg output: {}", stderr))
+}
+
+fn parse_ffmpeg_duration(stderr: &str) -> Option<f64> {
+    let re = Regex::new(r"Duration: (\d+):(\d{2}):(\d{2}(?:\.\d+)?)").unwrap();
+    let caps = re.captures(stderr)?;
+    let hours: f64 = caps[1].parse().ok()?;
+    let minutes: f64 = caps[2].parse().ok()?;
+    let seconds: f64 = caps[3].parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Reads the display matrix rotation (in degrees) of the first video stream.
+/// Returns 0.0 if the stream carries no rotation side data.
+pub fn probe_rotation(path: &Path) -> Result<f64> {
+    let json = run_ffprobe_json(
+        path,
+        &[
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream_side_data=rotation",
+        ],
+    )?;
+    Ok(parse_rotation(&json))
+}
+
+fn parse_rotation(json: &Value) -> f64 {
+    json["streams"]
+        .as_array()
+        .and_then(|streams| streams.first())
+        .and_then(|stream| stream["side_data_list"].as_array())
+        .and_then(|side_data| side_data.iter().find_map(|d| d["rotation"].as_f64()))
+        .unwrap_or(0.0)
+}
+
+/// Start PTS (in seconds) of the first audio packet. Broadcast recordings often
+/// don't start at zero, which shifts every timestamp taken from the stream clock.
+pub fn detect_pts_offset(path: &Path) -> Result<f64> {
+    let json = run_ffprobe_json(
+        path,
+        &[
+            "-show_packets",
+            "-select_streams",
+            "a:0",
+            "-read_intervals",
+            "%+#1",
+        ],
+    )?;
+    parse_first_packet_pts(&json).ok_or_else(|| anyhow!("No audio packets found in {:?}", path))
+}
+
+/// Average frame rate of the first video stream, e.g. 29.97 for NTSC footage.
+pub fn probe_frame_rate(path: &Path) -> Result<f64> {
+    let json = run_ffprobe_json(
+        path,
+        &[
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=avg_frame_rate,r_frame_rate",
+        ],
+    )?;
+    parse_frame_rate(&json).ok_or_else(|| anyhow!("No video frame rate found in {:?}", path))
+}
+
+/// Prefers `avg_frame_rate`, `r_frame_rate` is only a guess for variable
+/// frame rate recordings. ffprobe reports "0/0" when a rate is unknown.
+fn parse_frame_rate(json: &Value) -> Option<f64> {
+    let stream = json["streams"].as_array()?.first()?;
+    ["avg_frame_rate", "r_frame_rate"]
+        .iter()
+        .filter_map(|key| stream[*key].as_str().and_then(parse_rational))
+        .find(|fps| fps.is_finite() && *fps > 0.0)
+}
+
+/// "30000/1001" or "25"
+fn parse_rational(value: &str) -> Option<f64> {
+    match value.split_once('/') {
+        Some((num, den)) => Some(num.parse::<f64>().ok()? / den.parse::<f64>().ok()?),
+        None => value.parse().ok(),
+    }
+}
+
+/// Color description of the first video stream. Fields are `None` when the
+/// file doesn't tag them (ffprobe reports "unknown" or leaves them out).
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ColorspaceInfo {
+    /// e.g. "bt709", "bt2020"
+    pub color_primaries: Option<String>,
+    /// e.g. "bt709", "smpte2084" (PQ), "arib-std-b67" (HLG)
+    pub color_transfer: Option<String>,
+    /// Matrix coefficients, e.g. "bt709", "bt2020nc"
+    pub color_space: Option<String>,
+    /// PQ or HLG transfer.
+    pub is_hdr: bool,
+}
+
+pub fn probe_colorspace(path: &Path) -> Result<ColorspaceInfo> {
+    let json = run_ffprobe_json(
+        path,
+        &[
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=color_primaries,color_transfer,color_space",
+        ],
+    )?;
+    parse_colorspace(&json).ok_or_else(|| anyhow!("No video stream found in {:?}", path))
+}
+
+pub(crate) fn parse_colorspace(json: &Value) -> Option<ColorspaceInfo> {
+    let stream = json["streams"].as_array()?.first()?;
+    let field = |name: &str| {
+        stream[name]
+            .as_str()
+            .filter(|v| !v.is_empty() && *v != "unknown")
+            .map(str::to_string)
+    };
+    let color_transfer = field("color_transfer");
+    let is_hdr = matches!(
+        color_transfer.as_deref(),
+        Some("smpte2084") | Some("arib-std-b67")
+    );
+    Some(ColorspaceInfo {
+        color_primaries: field("color_primaries"),
+        color_transfer,
+        color_space: field("color_space"),
+        is_hdr,
+    })
+}
+
+/// A chapter embedded in the container, times in seconds.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ChapterInfo {
+    pub start: f64,
+    pub end: f64,
+    pub title: Option<String>,
+}
+
+/// Chapters of the container in order, e.g. from an MKV or an MP4 with a
+/// chapter track. Empty if there are none.
+pub fn probe_chapters(path: &Path) -> Result<Vec<ChapterInfo>> {
+    let json = run_ffprobe_json(path, &["-show_chapters"])?;
+    Ok(parse_chapters(&json))
+}
+
+pub(crate) fn parse_chapters(json: &Value) -> Vec<ChapterInfo> {
+    let as_secs = |v: &Value| {
+        v.as_str()
+            .and_then(|s| s.parse().ok())
+            .or_else(|| v.as_f64())
+    };
+    json["chapters"]
+        .as_array()
+        .map(|chapters| {
+            chapters
+                .iter()
+                .filter_map(|c| {
+                    Some(ChapterInfo {
+                        start: as_secs(&c["start_time"])?,
+                        end: as_secs(&c["end_time"])?,
+                        title: c["tags"]["title"].as_str().map(|t| t.to_string()),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parse_first_packet_pts(json: &Value) -> Option<f64> {
+    let packet = json["packets"].as_array()?.first()?;
+    packet["pts_time"]
+        .as_str()
+        .and_then(|t| t.parse().ok())
+        .or_else(|| packet["pts_time"].as_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_format_duration() {
+        // ffprobe -v quiet -of json -show_entries format=duration input.mp4
+        let json: Value = serde_json::from_str(
+            r#"{
+    "programs": [
+
+    ],
+    "stream_groups": [
+
+    ],
+    "format": {
+        "duration": "3725.480000"
+    }
+}"#,
+        )
+        .unwrap();
+        assert_eq!(parse_format_duration(&json), Some(3725.48));
+        assert_eq!(parse_format_duration(&json!({ "format": {} })), None);
+        assert_eq!(
+            parse_format_duration(&json!({ "format": { "duration": "N/A" } })),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_ffmpeg_duration() {
+        let stderr = "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'in.m4a':\n  Duration: 01:02:05.48, start: 0.000000, bitrate: 128 kb/s\nAt least one output file must be specified\n";
+        assert_eq!(parse_ffmpeg_duration(stderr), Some(3725.48));
+        assert_eq!(parse_ffmpeg_duration("Duration: N/A, bitrate: N/A"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_probe_duration_falls_back_to_ffmpeg() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let ffmpeg = dir.path().join("ffmpeg");
+        std::fs::write(
+            &ffmpeg,
+            "#!/bin/sh\necho '  Duration: 00:01:30.25, start: 0.000000, bitrate: 64 kb/s' >&2\nexit 1\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let missing_ffprobe = dir.path().join("no-ffprobe");
+        let duration = probe_duration_with("input.m4a", &missing_ffprobe, &ffmpeg).unwrap();
+        assert_eq!(duration, 90.25);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_probe_duration_runs_given_ffprobe() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Not in PATH, like the ffprobe `init_ffmpeg` installs next to the executable
+        let dir = tempfile::tempdir().unwrap();
+        let ffprobe = dir.path().join("ffprobe");
+        std::fs::write(
+            &ffprobe,
+            "#!/bin/sh\necho '{\"format\": {\"duration\": \"12.500000\"}}'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&ffprobe, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let missing_ffmpeg = dir.path().join("no-ffmpeg");
+        let duration = probe_duration_with("input.m4a", &ffprobe, &missing_ffmpeg).unwrap();
+        assert_eq!(duration, 12.5);
+    }
+
+    #[test]
+    fn test_parse_media_info() {
+        let json = json!({
+            "streams": [
+                {
+                    "index": 0,
+                    "codec_name": "h264",
+                    "codec_type": "video",
+                    "width": 1920,
+                    "height": 1080,
+                    "r_frame_rate": "30000/1001"
+                },
+                {
+                    "index": 1,
+                    "codec_name": "aac",
+                    "codec_type": "audio",
+                    "sample_rate": "48000",
+                    "channels": 2
+                }
+            ],
+            "format": { "duration": "12.345000" }
+        });
+
+        let info = parse_media_info(&json);
+        assert_eq!(info.duration, 12.345);
+        assert_eq!(info.video_streams().count(), 1);
+
+        let video = info.video_streams().next().unwrap();
+        assert_eq!(video.codec_name, "h264");
+        assert_eq!((video.width, video.height), (Some(1920), Some(1080)));
+        assert_eq!(video.frame_rate.as_deref(), Some("30000/1001"));
+
+        let audio = info.audio_streams().next().unwrap();
+        assert_eq!(audio.sample_rate, Some(48000));
+        assert_eq!(audio.channels, Some(2));
+        assert_eq!(info.media_type(), MediaType::Video);
+    }
+
+    #[test]
+    fn test_media_type() {
+        let stream = |codec_type: &str, codec_name: &str| json!({ "codec_type": codec_type, "codec_name": codec_name });
+        let media_type =
+            |streams: Vec<Value>| parse_media_info(&json!({ "streams": streams })).media_type();
+
+        assert_eq!(media_type(vec![stream("audio", "mp3")]), MediaType::Audio);
+        // MP3 with cover art
+        assert_eq!(
+            media_type(vec![stream("audio", "mp3"), stream("video", "mjpeg")]),
+            MediaType::Audio
+        );
+        assert_eq!(
+            media_type(vec![stream("video", "h264"), stream("audio", "aac")]),
+            MediaType::Video
+        );
+        // Motion JPEG without audio is a video
+        assert_eq!(media_type(vec![stream("video", "mjpeg")]), MediaType::Video);
+    }
+
+    #[test]
+    fn test_parse_rotation() {
+        let json = json!({
+            "streams": [{
+                "side_data_list": [{
+                    "side_data_type": "Display Matrix",
+                    "rotation": -90
+                }]
+            }]
+        });
+        assert_eq!(parse_rotation(&json), -90.0);
+
+        let no_side_data = json!({ "streams": [{}] });
+        assert_eq!(parse_rotation(&no_side_data), 0.0);
+
+        let no_streams = json!({});
+        assert_eq!(parse_rotation(&no_streams), 0.0);
+    }
+
+    #[test]
+    fn test_parse_first_packet_pts() {
+        let json = json!({
+            "packets": [
+                { "codec_type": "audio", "stream_index": 1, "pts": 126000, "pts_time": "1.400000" }
+            ]
+        });
+        assert_eq!(parse_first_packet_pts(&json), Some(1.4));
+
+        assert_eq!(parse_first_packet_pts(&json!({ "packets": [] })), None);
+        assert_eq!(parse_first_packet_pts(&json!({})), None);
+    }
+
+    #[test]
+    fn test_parse_frame_rate() {
+        let ntsc = json!({
+            "streams": [{ "avg_frame_rate": "30000/1001", "r_frame_rate": "30000/1001" }]
+        });
+        assert!((parse_frame_rate(&ntsc).unwrap() - 29.97).abs() < 0.001);
+
+        let unknown_avg =
+            json!({ "streams": [{ "avg_frame_rate": "0/0", "r_frame_rate": "25/1" }] });
+        assert_eq!(parse_frame_rate(&unknown_avg), Some(25.0));
+
+        assert_eq!(
+            parse_frame_rate(&json!({ "streams": [{ "avg_frame_rate": "60" }] })),
+            Some(60.0)
+        );
+        assert_eq!(
+            parse_frame_rate(&json!({ "streams": [{ "r_frame_rate": "0/0" }] })),
+            None
+        );
+        assert_eq!(parse_frame_rate(&json!({ "streams": [] })), None);
+    }
+
+    #[test]
+    fn test_parse_colorspace() {
+        let hdr = json!({
+            "programs": [],
+            "streams": [{
+                "color_space": "bt2020nc",
+                "color_transfer": "smpte2084",
+                "color_primaries": "bt2020"
+            }]
+        });
+        assert_eq!(
+            parse_colorspace(&hdr),
+            Some(ColorspaceInfo {
+                color_primaries: Some("bt2020".to_string()),
+                color_transfer: Some("smpte2084".to_string()),
+                color_space: Some("bt2020nc".to_string()),
+                is_hdr: true,
+            })
+        );
+
+        let hlg = json!({ "streams": [{ "color_transfer": "arib-std-b67" }] });
+        assert!(parse_colorspace(&hlg).unwrap().is_hdr);
+
+        let sdr = json!({
+            "streams": [{
+                "color_space": "bt709",
+                "color_transfer": "bt709",
+                "color_primaries": "bt709"
+            }]
+        });
+        let sdr = parse_colorspace(&sdr).unwrap();
+        assert!(!sdr.is_hdr);
+        assert_eq!(sdr.color_primaries.as_deref(), Some("bt709"));
+
+        // Untagged phone footage
+        let untagged = json!({ "streams": [{ "color_primaries": "unknown" }] });
+        assert_eq!(parse_colorspace(&untagged), Some(ColorspaceInfo::default()));
+
+        assert_eq!(parse_colorspace(&json!({ "streams": [] })), None);
+    }
+}
@@ -0,0 +1,389 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Used when the frontend doesn't ask for a specific progress rate.
+pub const DEFAULT_PROGRESS_INTERVAL_MS: u64 = 250;
+
+/// Typical output/input size ratios for the size based progress fallback.
+/// Extracting Vorbis audio from a video keeps a small fraction of it.
+pub const AUDIO_EXTRACT_SIZE_RATIO: f64 = 0.1;
+/// Re-encoding to H.264/AAC roughly keeps the size of typical sources.
+pub const REENCODE_SIZE_RATIO: f64 = 1.0;
+
+/// FFmpeg events buffered between the reader thread and a slow handler.
+pub const EVENT_CHANNEL_CAPACITY: usize = 32;
+
+/// The size based progress never reports completion, FFmpeg's exit does.
+const MAX_ESTIMATED_PERCENTAGE: f64 = 99.0;
+
+/// Rate limiter for progress events. FFmpeg reports progress many times per
+/// second and every `window.emit` crosses the IPC bridge, so events arriving
+/// faster than `min_interval_ms` after the last forwarded one are dropped.
+#[derive(Debug)]
+pub struct ProgressThrottle {
+    min_interval_ms: u64,
+    /// None until the first event, which is always forwarded.
+    last_emit: Option<Instant>,
+}
+
+impl ProgressThrottle {
+    pub fn new(min_interval_ms: u64) -> Self {
+        Self {
+            min_interval_ms,
+            last_emit: None,
+        }
+    }
+
+    pub fn should_emit(&mut self) -> bool {
+        self.should_emit_at(Instant::now())
+    }
+
+    fn should_emit_at(&mut self, now: Instant) -> bool {
+        let due = match self.last_emit {
+            Some(last) => {
+                now.saturating_duration_since(last) >= Duration::from_millis(self.min_interval_ms)
+            }
+            None => true,
+        };
+        if due {
+            self.last_emit = Some(now);
+        }
+        due
+    }
+}
+
+/// State shared by the clones of a `throttled` callback. The last dropped
+/// event is forwarded when the last clone goes away, so the final position
+/// (usually the end of the job) always reaches the frontend.
+struct ThrottledCallback<F: Fn(String)> {
+    throttle: Mutex<ProgressThrottle>,
+    /// Latest event dropped by the throttle since the last forwarded one.
+    pending: Mutex<Option<String>>,
+    on_progress: F,
+}
+
+impl<F: Fn(String)> Drop for ThrottledCallback<F> {
+    fn drop(&mut self) {
+        if let Some(payload) = self.pending.get_mut().unwrap().take() {
+            (self.on_progress)(payload);
+        }
+    }
+}
+
+/// Wraps a progress callback so it is called at most once per interval
+/// (`DEFAULT_PROGRESS_INTERVAL_MS` if None). Clones share the same throttle.
+/// The last event is never dropped, it is forwarded once all clones are gone.
+pub fn throttled<F>(
+    min_interval_ms: Option<u64>,
+    on_progress: F,
+) -> impl Fn(String) + Send + Sync + Clone + 'static
+where
+    F: Fn(String) + Send + Sync + 'static,
+{
+    let callback = Arc::new(ThrottledCallback {
+        throttle: Mutex::new(ProgressThrottle::new(
+            min_interval_ms.unwrap_or(DEFAULT_PROGRESS_INTERVAL_MS),
+        )),
+        pending: Mutex::new(None),
+        on_progress,
+    });
+    move |payload| {
+        if callback.throttle.lock().unwrap().should_emit() {
+            *callback.pending.lock().unwrap() = None;
+            (callback.on_progress)(payload);
+        } else {
+            *callback.pending.lock().unwrap() = Some(payload);
+        }
+    }
+}
+
+/// Expected output size for progress reporting when the duration of the job is
+/// unknown (`total_secs` of 0), None if FFmpeg's time can be used instead.
+pub fn fallback_output_size(
+    total_secs: f64,
+    input_size: u64,
+    compression_ratio: f64,
+) -> Option<u64> {
+    if total_secs > 0.0 {
+        return None;
+    }
+    Some(((input_size as f64 * compression_ratio) as u64).max(1))
+}
+
+/// Percentage from the number of bytes written so far. It only ever increases
+/// and stays below 100 even if the output outgrows the estimate.
+#[derive(Debug)]
+pub struct SizeProgress {
+    estimated_size: u64,
+    last_percentage: f64,
+}
+
+impl SizeProgress {
+    pub fn new(estimated_size: u64) -> Self {
+        Self {
+            estimated_size: estimated_size.max(1),
+            last_percentage: 0.0,
+        }
+    }
+
+    /// The new percentage, or None if it didn't increase.
+    pub fn update(&mut self, written: u64) -> Option<f64> {
+        let percentage =
+            (written as f64 / self.estimated_size as f64 * 100.0).min(MAX_ESTIMATED_PERCENTAGE);
+        if percentage <= self.last_percentage {
+            return None;
+        }
+        self.last_percentage = percentage;
+        Some(percentage)
+    }
+}
+
+/// Background thread polling the size of an output file. Polling stops when
+/// the watcher is dropped.
+pub struct OutputSizeWatcher {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for OutputSizeWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Calls `on_percentage` every `interval` in which `output_path` grew, see
+/// `SizeProgress`.
+pub fn watch_output_size<F>(
+    output_path: PathBuf,
+    estimated_size: u64,
+    interval: Duration,
+    on_percentage: F,
+) -> OutputSizeWatcher
+where
+    F: Fn(f64) + Send + 'static,
+{
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let tick = move || {
+        std::thread::sleep(interval);
+        !stopped.load(Ordering::Relaxed)
+    };
+    OutputSizeWatcher {
+        stop,
+        handle: Some(spawn_size_watcher(
+            output_path,
+            estimated_size,
+            tick,
+            on_percentage,
+        )),
+    }
+}
+
+/// Checks the size of `output_path` right away and again after every `tick`,
+/// until `tick` returns false.
+fn spawn_size_watcher<T, F>(
+    output_path: PathBuf,
+    estimated_size: u64,
+    mut tick: T,
+    on_percentage: F,
+) -> JoinHandle<()>
+where
+    T: FnMut() -> bool + Send + 'static,
+    F: Fn(f64) + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let mut progress = SizeProgress::new(estimated_size);
+        loop {
+            let written = std::fs::metadata(&output_path)
+                .map(|m| m.len())
+                .unwrap_or(0);
+            if let Some(percentage) = progress.update(written) {
+                on_percentage(percentage);
+            }
+            if !tick() {
+                break;
+            }
+        }
+    })
+}
+
+/// Reads `events` on a separate thread and hands them to `handle` through a
+/// bounded channel, so a slow handler (e.g. `window.emit` serializing over IPC)
+/// doesn't hold up reading FFmpeg's output. Returns once all events were
+/// handled.
+///
+/// This is std's `sync_channel` rather than tokio's: the callers are
+/// synchronous, and tokio's `blocking_recv` panics on a runtime thread.
+pub fn for_each_buffered<I, F>(events: I, mut handle: F)
+where
+    I: IntoIterator,
+    I::IntoIter: Send + 'static,
+    I::Item: Send + 'static,
+    F: FnMut(I::Item),
+{
+    let (sender, receiver) = std::sync::mpsc::sync_channel(EVENT_CHANNEL_CAPACITY);
+    let events = events.into_iter();
+    let reader = std::thread::spawn(move || {
+        for event in events {
+            // Blocks while the channel is full, so no event is dropped
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    for event in receiver {
+        handle(event);
+    }
+    let _ = reader.join();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_each_buffered_keeps_all_events() {
+        let mut received = Vec::new();
+        for_each_buffered(0..EVENT_CHANNEL_CAPACITY * 10, |event| {
+            // Slower than the reader, so the channel runs full
+            if event % EVENT_CHANNEL_CAPACITY == 0 {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            received.push(event);
+        });
+        assert_eq!(
+            received,
+            (0..EVENT_CHANNEL_CAPACITY * 10).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_throttle_drops_intermediate_events() {
+        let mut throttle = ProgressThrottle::new(250);
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        assert!(throttle.should_emit_at(at(0)));
+        assert!(!throttle.should_emit_at(at(50)));
+        assert!(!throttle.should_emit_at(at(249)));
+        assert!(throttle.should_emit_at(at(250)));
+        // The interval restarts from the last forwarded event
+        assert!(!throttle.should_emit_at(at(400)));
+        assert!(throttle.should_emit_at(at(500)));
+    }
+
+    #[test]
+    fn test_throttled_callback() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let on_progress = throttled(Some(60_000), move |p| sink.lock().unwrap().push(p));
+
+        for i in 0..100 {
+            on_progress(format!("00:00:{:02}", i));
+        }
+        // Clones share the throttle
+        on_progress.clone()("late".to_string());
+
+        assert_eq!(*received.lock().unwrap(), vec!["00:00:00".to_string()]);
+    }
+
+    #[test]
+    fn test_throttled_callback_flushes_last_event() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let on_progress = throttled(Some(60_000), move |p| sink.lock().unwrap().push(p));
+        let clone = on_progress.clone();
+
+        for i in 0..10 {
+            on_progress(format!("00:00:{:02}", i));
+        }
+        drop(on_progress);
+        // Another clone is still alive
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        drop(clone);
+        assert_eq!(*received.lock().unwrap(), vec!["00:00:00", "00:00:09"]);
+
+        // Nothing is repeated if the last event was forwarded
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let on_progress = throttled(Some(60_000), move |p| sink.lock().unwrap().push(p));
+        on_progress("00:00:00".to_string());
+        drop(on_progress);
+        assert_eq!(*received.lock().unwrap(), vec!["00:00:00"]);
+    }
+
+    #[test]
+    fn test_zero_interval_forwards_everything() {
+        let mut throttle = ProgressThrottle::new(0);
+        let now = Instant::now();
+        assert!(throttle.should_emit_at(now));
+        assert!(throttle.should_emit_at(now));
+    }
+
+    #[test]
+    fn test_fallback_only_without_duration() {
+        assert_eq!(
+            fallback_output_size(12.5, 1_000_000, REENCODE_SIZE_RATIO),
+            None
+        );
+        assert_eq!(
+            fallback_output_size(0.0, 1_000_000, AUDIO_EXTRACT_SIZE_RATIO),
+            Some(100_000)
+        );
+    }
+
+    #[test]
+    fn test_size_progress_monotonic() {
+        let mut progress = SizeProgress::new(1000);
+        assert_eq!(progress.update(0), None);
+        assert_eq!(progress.update(250), Some(25.0));
+        // Sizes that didn't grow are not reported again
+        assert_eq!(progress.update(250), None);
+        assert_eq!(progress.update(100), None);
+        assert_eq!(progress.update(900), Some(90.0));
+        assert_eq!(progress.update(5000), Some(99.0));
+        assert_eq!(progress.update(6000), None);
+    }
+
+    #[test]
+    fn test_watch_output_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("output.ogg");
+        // A job without duration, e.g. from an unprobeable 10 kB input
+        let estimated_size = fallback_output_size(0.0, 10_000, AUDIO_EXTRACT_SIZE_RATIO).unwrap();
+        assert_eq!(estimated_size, 1000);
+
+        let (tick_sender, ticks) = std::sync::mpsc::channel::<()>();
+        // Confirms that the size was checked, before waiting for the next tick
+        let (checked_sender, checked) = std::sync::mpsc::channel();
+        let (sender, received) = std::sync::mpsc::channel();
+        let watcher = spawn_size_watcher(
+            path.clone(),
+            estimated_size,
+            move || {
+                checked_sender.send(()).unwrap();
+                ticks.recv().is_ok()
+            },
+            move |p| sender.send(p).unwrap(),
+        );
+
+        checked.recv().unwrap();
+        for size in [200usize, 400, 400, 800] {
+            std::fs::write(&path, vec![0u8; size]).unwrap();
+            tick_sender.send(()).unwrap();
+            checked.recv().unwrap();
+        }
+        drop(tick_sender);
+        watcher.join().unwrap();
+
+        // The output not growing between two ticks isn't reported again
+        assert_eq!(received.iter().collect::<Vec<_>>(), vec![20.0, 40.0, 80.0]);
+    }
+}
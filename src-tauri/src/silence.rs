@@ -1,9 +1,14 @@
-use ffmpeg_sidecar::command::FfmpegCommand;
+use crate::ffmpeg_version::require_ffmpeg_version;
+use crate::probe::probe_duration_json;
+use crate::progress::for_each_buffered;
+use crate::video::{base_ffmpeg_command, base_ffmpeg_command_with_log_level};
+use crate::ProgressEvent;
 use ffmpeg_sidecar::event::FfmpegEvent;
 use log::{debug, info};
 use regex::Regex;
-use serde::Serialize;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::Emitter;
 
 #[derive(Serialize, Debug, Clone)]
 pub struct SilenceInterval {
@@ -25,12 +30,214 @@ pub struct ProcessedAudio {
     pub offsets: Vec<SegmentOffset>,
 }
 
+/// One file of `remove_silence_batch_with_progress`. Without an `output_path`
+/// the result is written next to the input, as by `remove_silence`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SilenceRemovalJob {
+    pub input_path: String,
+    pub output_path: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BatchSilenceResult {
+    pub input_path: String,
+    pub output: ProcessedAudio,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SilenceStage {
+    Detecting,
+    Removing,
+}
+
+/// Emitted as `batch_silence_progress` whenever a job of the batch enters a
+/// new stage.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BatchSilenceProgress {
+    pub job_index: usize,
+    pub total_jobs: usize,
+    pub file_name: String,
+    pub stage: SilenceStage,
+}
+
+/// Loudness of the audio around `time_secs`, see `detect_silence`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AudioLevel {
+    pub time_secs: f64,
+    pub level_db: f64,
+}
+
+/// Emits a `silence_detected` event with each `SilenceInterval` as soon as
+/// FFmpeg reports it, e.g. to annotate a waveform while the detection runs.
+/// With `meter_events` an `audio_level` event with the RMS level of every
+/// tenth of a second is emitted as well, e.g. for a live VU meter. The events
+/// carry the intervals as detected, the result has them merged, see
+/// `merge_silence_intervals`.
 #[tauri::command]
-pub async fn detect_silence(path: String, min_duration: Option<f64>) -> Result<Vec<SilenceInterval>, String> {
-    detect_silence_internal(&path, min_duration.unwrap_or(0.5)).await
+pub async fn detect_silence(
+    window: tauri::Window,
+    path: String,
+    min_duration: Option<f64>,
+    meter_events: Option<bool>,
+) -> Result<Vec<SilenceInterval>, String> {
+    let level_window = window.clone();
+    detect_silence_metered(
+        &path,
+        min_duration.unwrap_or(0.5),
+        meter_events.unwrap_or(false),
+        move |interval| {
+            let _ = window.emit("silence_detected", interval);
+        },
+        move |level| {
+            let _ = level_window.emit("audio_level", level);
+        },
+    )
+    .await
+    .map(|intervals| merge_silence_intervals(intervals, SILENCE_MERGE_GAP_SECS))
+}
+
+/// Intervals closer than this are one silence, `silencedetect` sometimes
+/// reports a silence in two adjacent parts.
+const SILENCE_MERGE_GAP_SECS: f64 = 0.1;
+
+/// Merges intervals that overlap or are less than `merge_gap_secs` apart. The
+/// result is sorted by start.
+fn merge_silence_intervals(
+    mut intervals: Vec<SilenceInterval>,
+    merge_gap_secs: f64,
+) -> Vec<SilenceInterval> {
+    intervals.sort_by(|a, b| a.start.total_cmp(&b.start));
+    let mut merged: Vec<SilenceInterval> = Vec::with_capacity(intervals.len());
+    for interval in intervals {
+        match merged.last_mut() {
+            Some(last) if interval.start - last.end < merge_gap_secs => {
+                last.end = last.end.max(interval.end);
+                last.duration = last.end - last.start;
+            }
+            _ => merged.push(interval),
+        }
+    }
+    merged
 }
 
-async fn detect_silence_internal(path: &str, min_duration: f64) -> Result<Vec<SilenceInterval>, String> {
+/// Turns `silencedetect` log lines into intervals, one per `silence_end`.
+struct SilenceLogParser {
+    re_start: Regex,
+    re_end: Regex,
+    current_start: Option<f64>,
+}
+
+impl SilenceLogParser {
+    fn new() -> Self {
+        Self {
+            // silence_start: 12.345
+            re_start: Regex::new(r"silence_start: (\d+(\.\d+)?)").unwrap(),
+            // silence_end: 15.678 | silence_duration: 3.333
+            re_end: Regex::new(r"silence_end: (\d+(\.\d+)?)").unwrap(),
+            current_start: None,
+        }
+    }
+
+    /// The interval completed by `line`, if any.
+    fn parse_line(&mut self, line: &str) -> Option<SilenceInterval> {
+        if let Some(caps) = self.re_start.captures(line) {
+            let start = caps[1].parse::<f64>().ok()?;
+            debug!("Silence start detected at {}", start);
+            self.current_start = Some(start);
+            None
+        } else if let Some(caps) = self.re_end.captures(line) {
+            let end = caps[1].parse::<f64>().ok()?;
+            let start = self.current_start.take()?;
+            debug!("Silence interval: {} - {} (duration: {})", start, end, end - start);
+            Some(SilenceInterval {
+                start,
+                end,
+                duration: end - start,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Samples per level measurement, a tenth of a second at 48 kHz.
+const METER_WINDOW_SAMPLES: u32 = 4800;
+
+/// `silencedetect` doesn't report levels, so metering adds `astats` and prints
+/// its RMS level of each window with `ametadata`.
+fn build_silence_detect_filter(min_duration: f64, meter: bool) -> String {
+    let mut filter = format!("silencedetect=noise=-30dB:d={}", min_duration);
+    if meter {
+        filter.push_str(&format!(
+            ",asetnsamples=n={},astats=metadata=1:reset=1,\
+             ametadata=print:key=lavfi.astats.Overall.RMS_level",
+            METER_WINDOW_SAMPLES
+        ));
+    }
+    filter
+}
+
+/// Turns the `ametadata` log lines of a metered detection into levels. Each
+/// frame is printed as a `pts_time` line followed by its RMS level.
+struct AudioLevelParser {
+    re_time: Regex,
+    re_level: Regex,
+    current_time: Option<f64>,
+}
+
+impl AudioLevelParser {
+    fn new() -> Self {
+        Self {
+            // [Parsed_ametadata_3 @ 0x55d0c8a3e1c0] frame:12   pts:57600   pts_time:1.2
+            re_time: Regex::new(r"frame:\d+\s+pts:\S+\s+pts_time:(\d+(\.\d+)?)").unwrap(),
+            // [Parsed_ametadata_3 @ 0x55d0c8a3e1c0] lavfi.astats.Overall.RMS_level=-23.456
+            re_level: Regex::new(r"lavfi\.astats\.Overall\.RMS_level=(-?\d+(\.\d+)?)").unwrap(),
+            current_time: None,
+        }
+    }
+
+    /// The level reported by `line`, if any. Silent windows are reported as
+    /// `-inf` and skipped.
+    fn parse_line(&mut self, line: &str) -> Option<AudioLevel> {
+        if let Some(caps) = self.re_time.captures(line) {
+            self.current_time = caps[1].parse::<f64>().ok();
+            None
+        } else if let Some(caps) = self.re_level.captures(line) {
+            let level_db = caps[1].parse::<f64>().ok()?;
+            Some(AudioLevel {
+                time_secs: self.current_time.take()?,
+                level_db,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+async fn detect_silence_internal<F>(
+    path: &str,
+    min_duration: f64,
+    on_interval: F,
+) -> Result<Vec<SilenceInterval>, String>
+where
+    F: Fn(&SilenceInterval),
+{
+    detect_silence_metered(path, min_duration, false, on_interval, |_| {}).await
+}
+
+/// `on_level` is only called with `meter`.
+async fn detect_silence_metered<F, L>(
+    path: &str,
+    min_duration: f64,
+    meter: bool,
+    on_interval: F,
+    on_level: L,
+) -> Result<Vec<SilenceInterval>, String>
+where
+    F: Fn(&SilenceInterval),
+    L: Fn(AudioLevel),
+{
     let input_path = PathBuf::from(path);
     if !input_path.exists() {
         return Err("File not found".to_string());
@@ -39,46 +246,25 @@ async fn detect_silence_internal(path: &str, min_duration: f64) -> Result<Vec<Si
     info!("Starting silence detection for {:?} with min_duration {}", input_path, min_duration);
 
     // ffmpeg -i input.mp4 -af silencedetect=noise=-30dB:d=min_duration -f null -
-    let events = FfmpegCommand::new()
+    let events = base_ffmpeg_command_with_log_level("level+info")
         .input(input_path.to_str().unwrap())
-        .args(&["-af", &format!("silencedetect=noise=-30dB:d={}", min_duration), "-f", "null", "-"])
+        .args(&["-af", &build_silence_detect_filter(min_duration, meter), "-f", "null", "-"])
         .spawn()
         .map_err(|e| e.to_string())?
         .iter()
         .map_err(|e| e.to_string())?;
 
     let mut intervals = Vec::new();
-    let mut current_start = None;
-
-    // Regex for start: silence_start: 12.345
-    let re_start = Regex::new(r"silence_start: (\d+(\.\d+)?)").unwrap();
-    // Regex for end: silence_end: 15.678
-    let re_end = Regex::new(r"silence_end: (\d+(\.\d+)?)").unwrap();
+    let mut parser = SilenceLogParser::new();
+    let mut level_parser = AudioLevelParser::new();
 
     for event in events {
         if let FfmpegEvent::Log(_, line) = event {
-            // debug!("[FFmpeg] {}", line); // Too verbose
-            if let Some(caps) = re_start.captures(&line) {
-                if let Some(m) = caps.get(1) {
-                    if let Ok(val) = m.as_str().parse::<f64>() {
-                        current_start = Some(val);
-                        debug!("Silence start detected at {}", val);
-                    }
-                }
-            } else if let Some(caps) = re_end.captures(&line) {
-                if let Some(m) = caps.get(1) {
-                    if let Ok(end_val) = m.as_str().parse::<f64>() {
-                        if let Some(start_val) = current_start {
-                            intervals.push(SilenceInterval {
-                                start: start_val,
-                                end: end_val,
-                                duration: end_val - start_val,
-                            });
-                            debug!("Silence interval: {} - {} (duration: {})", start_val, end_val, end_val - start_val);
-                            current_start = None;
-                        }
-                    }
-                }
+            if let Some(interval) = parser.parse_line(&line) {
+                on_interval(&interval);
+                intervals.push(interval);
+            } else if let Some(level) = level_parser.parse_line(&line) {
+                on_level(level);
             }
         }
     }
@@ -87,10 +273,49 @@ async fn detect_silence_internal(path: &str, min_duration: f64) -> Result<Vec<Si
     Ok(intervals)
 }
 
+/// With `crossfade_ms` the kept segments overlap by that many milliseconds and
+/// are faded into each other instead of being cut hard.
 #[tauri::command]
-pub async fn remove_silence(path: String, min_duration: Option<f64>) -> Result<ProcessedAudio, String> {
+pub async fn remove_silence(
+    window: tauri::Window,
+    path: String,
+    min_duration: Option<f64>,
+    crossfade_ms: Option<u32>,
+) -> Result<ProcessedAudio, String> {
+    remove_silence_internal(path, min_duration, crossfade_ms, move |event| {
+        let _ = window.emit("progress", event);
+    })
+    .await
+}
+
+async fn remove_silence_internal<F>(
+    path: String,
+    min_duration: Option<f64>,
+    crossfade_ms: Option<u32>,
+    on_progress: F,
+) -> Result<ProcessedAudio, String>
+where
+    F: Fn(ProgressEvent),
+{
+    on_progress(ProgressEvent::message("remove_silence", 0.0, "Detecting silence..."));
     let min_duration_val = min_duration.unwrap_or(10.0);
-    let silence_intervals = detect_silence_internal(&path, min_duration_val).await?;
+    let silence_intervals = detect_silence_internal(&path, min_duration_val, |_| {}).await?;
+    let silence_intervals = merge_silence_intervals(silence_intervals, SILENCE_MERGE_GAP_SECS);
+    remove_detected_silence(path, None, silence_intervals, crossfade_ms, on_progress)
+}
+
+/// Removes the already detected `silence_intervals` from `path`. Without an
+/// `output_path` the result is written to `{stem}_nosilence.ogg` next to it.
+fn remove_detected_silence<F>(
+    path: String,
+    output_path: Option<PathBuf>,
+    silence_intervals: Vec<SilenceInterval>,
+    crossfade_ms: Option<u32>,
+    on_progress: F,
+) -> Result<ProcessedAudio, String>
+where
+    F: Fn(ProgressEvent),
+{
     let input_path = PathBuf::from(&path);
     
     if silence_intervals.is_empty() {
@@ -101,10 +326,12 @@ pub async fn remove_silence(path: String, min_duration: Option<f64>) -> Result<P
         });
     }
 
-    let output_path = input_path.with_file_name(format!(
-        "{}_nosilence.ogg",
-        input_path.file_stem().unwrap().to_string_lossy()
-    ));
+    let output_path = output_path.unwrap_or_else(|| {
+        input_path.with_file_name(format!(
+            "{}_nosilence.ogg",
+            input_path.file_stem().unwrap().to_string_lossy()
+        ))
+    });
 
     // Calculate keep segments
     // Assuming audio starts at 0.0
@@ -128,7 +355,7 @@ pub async fn remove_silence(path: String, min_duration: Option<f64>) -> Result<P
     // Or we can probe.
     
     // Let's probe duration using ffmpeg output
-    let duration = probe_duration(&path).await.unwrap_or(last_end + 3600.0); 
+    let duration = probe_duration_json(&path).unwrap_or(last_end + 3600.0); 
     
     if duration > last_end {
         keep_segments.push((last_end, duration));
@@ -136,34 +363,26 @@ pub async fn remove_silence(path: String, min_duration: Option<f64>) -> Result<P
 
     info!("Removing silence. Keep segments: {:?}", keep_segments);
 
-    // Build filter complex
-    let mut filter_complex = String::new();
-    let mut inputs = String::new();
+    let crossfade = crossfade_duration(&keep_segments, crossfade_ms);
+    let filter_complex = build_silence_removal_filter(&keep_segments, crossfade);
+
+    // Each crossfade overlaps the end of a segment with the start of the next one
+    let overlap = crossfade.unwrap_or(0.0);
     let mut offsets = Vec::new();
     let mut current_new_time = 0.0;
 
-    for (i, (start, end)) in keep_segments.iter().enumerate() {
-        // If it's the last segment and we are not sure about duration, we can omit end?
-        // But we need to know the duration for the offset calculation of the *next* segment (if there was one).
-        // Since this is the last segment, omitting end is fine for atrim, but we need to be careful.
-        // However, we calculated duration above.
-        
-        filter_complex.push_str(&format!("[0:a]atrim=start={}:end={},asetpts=PTS-STARTPTS[a{}];", start, end, i));
-        inputs.push_str(&format!("[a{}]", i));
-        
+    for (start, end) in &keep_segments {
         offsets.push(SegmentOffset {
             min_time: current_new_time,
             offset: *start - current_new_time,
         });
-        
-        current_new_time += end - start;
+        current_new_time += end - start - overlap;
     }
-
-    filter_complex.push_str(&format!("{}concat=n={}:v=0:a=1[outa]", inputs, keep_segments.len()));
+    current_new_time += overlap;
 
     info!("Running FFmpeg to remove silence...");
     
-    FfmpegCommand::new()
+    let events = base_ffmpeg_command()
         .input(input_path.to_str().unwrap())
         .args(&[
             "-y",
@@ -176,14 +395,19 @@ pub async fn remove_silence(path: String, min_duration: Option<f64>) -> Result<P
         .spawn()
         .map_err(|e| e.to_string())?
         .iter()
-        .map_err(|e| e.to_string())?
-        .for_each(|event| {
-             if let FfmpegEvent::Log(_, msg) = event {
-                 debug!("[FFmpeg Remove Silence] {}", msg);
-             }
-        });
+        .map_err(|e| e.to_string())?;
+    for_each_buffered(events, |event| match event {
+        FfmpegEvent::Progress(p) => on_progress(ProgressEvent::from_ffmpeg_time(
+            "remove_silence",
+            &p.time,
+            current_new_time,
+        )),
+        FfmpegEvent::Log(_, msg) => debug!("[FFmpeg Remove Silence] {}", msg),
+        _ => {}
+    });
 
     info!("Silence removed. New file: {:?}", output_path);
+    on_progress(ProgressEvent::message("remove_silence", 100.0, "Silence removed"));
 
     Ok(ProcessedAudio {
         path: output_path.to_string_lossy().to_string(),
@@ -192,30 +416,191 @@ pub async fn remove_silence(path: String, min_duration: Option<f64>) -> Result<P
     })
 }
 
-async fn probe_duration(path: &str) -> Result<f64, String> {
-    use std::process::Command;
-    
-    // Try using ffmpeg -i path
-    // We assume ffmpeg is in PATH (which it should be if init_ffmpeg was called or if installed globally)
-    // In tests, we saw it works.
-    let output = Command::new("ffmpeg")
-        .arg("-i")
-        .arg(path)
-        .output()
-        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
-        
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    
-    let re_duration = Regex::new(r"Duration: (\d{2}):(\d{2}):(\d{2}\.\d{2})").unwrap();
-    
-    if let Some(caps) = re_duration.captures(&stderr) {
-        let hours: f64 = caps[1].parse().unwrap_or(0.0);
-        let minutes: f64 = caps[2].parse().unwrap_or(0.0);
-        let seconds: f64 = caps[3].parse().unwrap_or(0.0);
-        return Ok(hours * 3600.0 + minutes * 60.0 + seconds);
+/// Removes silence from each of `inputs` in turn. Besides the usual `progress`
+/// events of each removal, a `batch_silence_progress` event reports which file
+/// is being worked on and whether its silence is being detected or removed.
+/// Stops at the first file that fails.
+#[tauri::command]
+pub async fn remove_silence_batch_with_progress(
+    window: tauri::Window,
+    inputs: Vec<SilenceRemovalJob>,
+    min_duration: Option<f64>,
+) -> Result<Vec<BatchSilenceResult>, String> {
+    let progress_window = window.clone();
+    remove_silence_batch_internal(
+        inputs,
+        min_duration,
+        move |event| {
+            let _ = window.emit("batch_silence_progress", event);
+        },
+        move |event| {
+            let _ = progress_window.emit("progress", event);
+        },
+    )
+    .await
+}
+
+async fn remove_silence_batch_internal<S, F>(
+    jobs: Vec<SilenceRemovalJob>,
+    min_duration: Option<f64>,
+    on_stage: S,
+    on_progress: F,
+) -> Result<Vec<BatchSilenceResult>, String>
+where
+    S: Fn(BatchSilenceProgress),
+    F: Fn(ProgressEvent),
+{
+    let total_jobs = jobs.len();
+    let mut results = Vec::with_capacity(total_jobs);
+    for (job_index, job) in jobs.into_iter().enumerate() {
+        let file_name = Path::new(&job.input_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| job.input_path.clone());
+        let at_stage = |stage| BatchSilenceProgress {
+            job_index,
+            total_jobs,
+            file_name: file_name.clone(),
+            stage,
+        };
+
+        on_stage(at_stage(SilenceStage::Detecting));
+        let silence_intervals =
+            detect_silence_internal(&job.input_path, min_duration.unwrap_or(10.0), |_| {})
+                .await
+                .map_err(|e| format!("{}: {}", file_name, e))?;
+        let silence_intervals = merge_silence_intervals(silence_intervals, SILENCE_MERGE_GAP_SECS);
+
+        on_stage(at_stage(SilenceStage::Removing));
+        let output = remove_detected_silence(
+            job.input_path.clone(),
+            job.output_path.map(PathBuf::from),
+            silence_intervals,
+            None,
+            &on_progress,
+        )
+        .map_err(|e| format!("{}: {}", file_name, e))?;
+        results.push(BatchSilenceResult {
+            input_path: job.input_path,
+            output,
+        });
     }
-    
-    Err(format!("Failed to parse duration from ffmpeg output. Stderr: {}", stderr))
+    Ok(results)
+}
+
+/// Mixes the (silence-removed) speech over a background bed. The bed is looped
+/// until the speech ends and attenuated by `bed_volume_db`.
+#[tauri::command]
+pub async fn fill_silence_with_bed(
+    window: tauri::Window,
+    speech_path: String,
+    bed_path: String,
+    output_path: String,
+    bed_volume_db: f64,
+) -> Result<(), String> {
+    // amix gained the normalize option in FFmpeg 4.4
+    require_ffmpeg_version(4, 4, "Background bed mixing").map_err(|e| e.to_string())?;
+    let filter_complex = build_bed_mix_filter(bed_volume_db);
+    info!("Mixing {} over bed {}: {}", speech_path, bed_path, filter_complex);
+    let total = probe_duration_json(&speech_path).unwrap_or(0.0);
+
+    let mut last_error = None;
+    base_ffmpeg_command()
+        .input(&speech_path)
+        .args(["-stream_loop", "-1"])
+        .input(&bed_path)
+        .args([
+            "-y",
+            "-filter_complex", &filter_complex,
+            "-map", "[outa]",
+        ])
+        .output(&output_path)
+        .spawn()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map_err(|e| e.to_string())?
+        .for_each(|event| match event {
+            FfmpegEvent::Progress(p) => {
+                let _ = window.emit(
+                    "progress",
+                    ProgressEvent::from_ffmpeg_time("fill_silence_with_bed", &p.time, total),
+                );
+            }
+            FfmpegEvent::Log(_, msg) => debug!("[FFmpeg Bed Mix] {}", msg),
+            FfmpegEvent::Error(e) => last_error = Some(e),
+            _ => {}
+        });
+
+    if !PathBuf::from(&output_path).exists() {
+        return Err(format!(
+            "FFmpeg failed to mix background bed: {}",
+            last_error.unwrap_or_else(|| "Unknown error".to_string())
+        ));
+    }
+    Ok(())
+}
+
+/// Crossfade length in seconds for joining `keep_segments`, at most half of the
+/// shortest segment so neighbouring fades don't overlap.
+fn crossfade_duration(keep_segments: &[(f64, f64)], crossfade_ms: Option<u32>) -> Option<f64> {
+    let requested = crossfade_ms.filter(|ms| *ms > 0)? as f64 / 1000.0;
+    if keep_segments.len() < 2 {
+        return None;
+    }
+    let shortest = keep_segments
+        .iter()
+        .map(|(start, end)| end - start)
+        .fold(f64::INFINITY, f64::min);
+    Some(requested.min(shortest / 2.0))
+}
+
+/// Filter graph keeping `keep_segments` of the input audio, joined with
+/// `concat` or, with a `crossfade` (seconds), with a chain of `acrossfade`s
+/// between each pair of consecutive segments.
+fn build_silence_removal_filter(keep_segments: &[(f64, f64)], crossfade: Option<f64>) -> String {
+    let mut filters: Vec<String> = keep_segments
+        .iter()
+        .enumerate()
+        .map(|(i, (start, end))| {
+            format!("[0:a]atrim=start={}:end={},asetpts=PTS-STARTPTS[a{}]", start, end, i)
+        })
+        .collect();
+
+    match crossfade {
+        Some(duration) if keep_segments.len() > 1 => {
+            let mut previous = "a0".to_string();
+            for i in 1..keep_segments.len() {
+                let output = if i == keep_segments.len() - 1 {
+                    "outa".to_string()
+                } else {
+                    format!("x{}", i)
+                };
+                filters.push(format!(
+                    "[{}][a{}]acrossfade=d={:.3}:c1=tri:c2=tri[{}]",
+                    previous, i, duration, output
+                ));
+                previous = output;
+            }
+        }
+        _ => {
+            let inputs: String = (0..keep_segments.len()).map(|i| format!("[a{}]", i)).collect();
+            filters.push(format!("{}concat=n={}:v=0:a=1[outa]", inputs, keep_segments.len()));
+        }
+    }
+    filters.join(";")
+}
+
+fn db_to_linear(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// Speech keeps unity gain, the bed is weighted by its linear volume. amix
+/// normally divides by the sum of the weights, which would also lower the speech.
+fn build_bed_mix_filter(bed_volume_db: f64) -> String {
+    format!(
+        "[0:a][1:a]amix=inputs=2:duration=first:weights=1 {:.4}:normalize=0[outa]",
+        db_to_linear(bed_volume_db)
+    )
 }
 
 #[cfg(test)]
@@ -224,6 +609,95 @@ mod tests {
     use std::path::Path;
     use std::process::Command;
 
+    #[test]
+    fn test_silence_log_parser_reports_intervals_in_order() {
+        let log = [
+            "[silencedetect @ 0x6000] silence_end: 0.4 | silence_duration: 0.4",
+            "[silencedetect @ 0x6000] silence_start: 1.5",
+            "[silencedetect @ 0x6000] silence_end: 3.25 | silence_duration: 1.75",
+            "Stream mapping:",
+            "[silencedetect @ 0x6000] silence_start: 7",
+            "[silencedetect @ 0x6000] silence_end: 9.5 | silence_duration: 2.5",
+        ];
+
+        let mut parser = SilenceLogParser::new();
+        let mut emitted = Vec::new();
+        for (i, line) in log.iter().enumerate() {
+            if let Some(interval) = parser.parse_line(line) {
+                emitted.push((i, interval.start, interval.end, interval.duration));
+            }
+        }
+        // Each interval is reported on its silence_end line; the first end has no start
+        assert_eq!(emitted, vec![(2, 1.5, 3.25, 1.75), (5, 7.0, 9.5, 2.5)]);
+    }
+
+    #[test]
+    fn test_merge_silence_intervals() {
+        let interval = |start: f64, end: f64| SilenceInterval {
+            start,
+            end,
+            duration: end - start,
+        };
+        let spans = |intervals: Vec<SilenceInterval>| -> Vec<(f64, f64, f64)> {
+            intervals.iter().map(|i| (i.start, i.end, i.duration)).collect()
+        };
+
+        // Adjacent
+        let merged = merge_silence_intervals(vec![interval(0.0, 1.0), interval(1.0, 2.0)], 0.1);
+        assert_eq!(spans(merged), vec![(0.0, 2.0, 2.0)]);
+
+        // Gapped: just below the threshold is merged, a real pause isn't
+        let merged = merge_silence_intervals(
+            vec![interval(0.0, 1.0), interval(1.05, 2.0), interval(3.0, 4.0)],
+            0.1,
+        );
+        assert_eq!(spans(merged), vec![(0.0, 2.0, 2.0), (3.0, 4.0, 1.0)]);
+
+        // Overlapping, contained and out of order
+        let merged = merge_silence_intervals(
+            vec![interval(5.0, 6.0), interval(0.0, 1.5), interval(1.0, 2.5), interval(1.2, 1.8)],
+            0.1,
+        );
+        assert_eq!(spans(merged), vec![(0.0, 2.5, 2.5), (5.0, 6.0, 1.0)]);
+
+        assert!(merge_silence_intervals(Vec::new(), 0.1).is_empty());
+    }
+
+    #[test]
+    fn test_audio_level_parser() {
+        let log = [
+            "[Parsed_ametadata_3 @ 0x55d0c8a3e1c0] frame:0    pts:0       pts_time:0",
+            "[Parsed_ametadata_3 @ 0x55d0c8a3e1c0] lavfi.astats.Overall.RMS_level=-23.456",
+            "[Parsed_silencedetect_0 @ 0x55d0c8a3d000] silence_start: 0.1",
+            "[Parsed_ametadata_3 @ 0x55d0c8a3e1c0] frame:1    pts:4800    pts_time:0.1",
+            "[Parsed_ametadata_3 @ 0x55d0c8a3e1c0] lavfi.astats.Overall.RMS_level=-inf",
+            "[Parsed_ametadata_3 @ 0x55d0c8a3e1c0] [info] frame:2    pts:9600    pts_time:0.2",
+            "[Parsed_ametadata_3 @ 0x55d0c8a3e1c0] [info] lavfi.astats.Overall.RMS_level=-6",
+        ];
+
+        let mut parser = AudioLevelParser::new();
+        let emitted: Vec<AudioLevel> =
+            log.iter().filter_map(|line| parser.parse_line(line)).collect();
+        // The silent window is skipped
+        assert_eq!(
+            emitted,
+            vec![
+                AudioLevel { time_secs: 0.0, level_db: -23.456 },
+                AudioLevel { time_secs: 0.2, level_db: -6.0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_silence_detect_filter() {
+        assert_eq!(build_silence_detect_filter(0.5, false), "silencedetect=noise=-30dB:d=0.5");
+        assert_eq!(
+            build_silence_detect_filter(0.5, true),
+            "silencedetect=noise=-30dB:d=0.5,asetnsamples=n=4800,astats=metadata=1:reset=1,\
+             ametadata=print:key=lavfi.astats.Overall.RMS_level"
+        );
+    }
+
     fn get_test_file_path() -> PathBuf {
         let mut path = std::env::current_dir().unwrap();
         // If we are in src-tauri, go up one level
@@ -233,6 +707,141 @@ mod tests {
         path.join("dev-resources").join("test-data").join("test_podcast.m4a")
     }
 
+    #[test]
+    fn test_silence_removal_filter() {
+        let segments = [(0.0, 2.0), (3.5, 6.0), (8.0, 9.5)];
+        assert_eq!(
+            build_silence_removal_filter(&segments, None),
+            "[0:a]atrim=start=0:end=2,asetpts=PTS-STARTPTS[a0];\
+             [0:a]atrim=start=3.5:end=6,asetpts=PTS-STARTPTS[a1];\
+             [0:a]atrim=start=8:end=9.5,asetpts=PTS-STARTPTS[a2];\
+             [a0][a1][a2]concat=n=3:v=0:a=1[outa]"
+        );
+        assert_eq!(
+            build_silence_removal_filter(&segments, Some(0.2)),
+            "[0:a]atrim=start=0:end=2,asetpts=PTS-STARTPTS[a0];\
+             [0:a]atrim=start=3.5:end=6,asetpts=PTS-STARTPTS[a1];\
+             [0:a]atrim=start=8:end=9.5,asetpts=PTS-STARTPTS[a2];\
+             [a0][a1]acrossfade=d=0.200:c1=tri:c2=tri[x1];\
+             [x1][a2]acrossfade=d=0.200:c1=tri:c2=tri[outa]"
+        );
+    }
+
+    #[test]
+    fn test_crossfade_duration() {
+        let segments = [(0.0, 2.0), (3.5, 6.0), (8.0, 9.5)];
+        assert_eq!(crossfade_duration(&segments, Some(200)), Some(0.2));
+        // Limited by the 1.5s last segment
+        assert_eq!(crossfade_duration(&segments, Some(1000)), Some(0.75));
+        assert_eq!(crossfade_duration(&segments, Some(0)), None);
+        assert_eq!(crossfade_duration(&segments, None), None);
+        assert_eq!(crossfade_duration(&segments[..1], Some(200)), None);
+    }
+
+    #[test]
+    fn test_bed_mix_weights() {
+        assert_eq!(db_to_linear(0.0), 1.0);
+        assert!((db_to_linear(-6.0) - 0.5012).abs() < 1e-4);
+        assert!((db_to_linear(-20.0) - 0.1).abs() < 1e-9);
+        assert_eq!(
+            build_bed_mix_filter(-20.0),
+            "[0:a][1:a]amix=inputs=2:duration=first:weights=1 0.1000:normalize=0[outa]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_stops_at_missing_file() {
+        let stages = std::sync::Mutex::new(Vec::new());
+        let jobs = vec![
+            SilenceRemovalJob {
+                input_path: "/nonexistent/first.m4a".to_string(),
+                output_path: None,
+            },
+            SilenceRemovalJob {
+                input_path: "/nonexistent/second.m4a".to_string(),
+                output_path: None,
+            },
+        ];
+        let result = remove_silence_batch_internal(
+            jobs,
+            None,
+            |event| stages.lock().unwrap().push(event),
+            |_| {},
+        )
+        .await;
+
+        assert_eq!(result.unwrap_err(), "first.m4a: File not found");
+        assert_eq!(
+            stages.into_inner().unwrap(),
+            vec![BatchSilenceProgress {
+                job_index: 0,
+                total_jobs: 2,
+                file_name: "first.m4a".to_string(),
+                stage: SilenceStage::Detecting,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_silence_removal_event_order() {
+        let temp_dir = std::env::temp_dir().join("ai-media-cutter-tests");
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        // Tone, silence, tone
+        let mut jobs = Vec::new();
+        for name in ["batch_a.wav", "batch_b.wav"] {
+            let path = temp_dir.join(name);
+            let status = Command::new("ffmpeg")
+                .args([
+                    "-y",
+                    "-f", "lavfi", "-i", "sine=frequency=440:duration=1",
+                    "-f", "lavfi", "-i", "anullsrc=r=44100:cl=mono:d=2",
+                    "-f", "lavfi", "-i", "sine=frequency=440:duration=1",
+                    "-filter_complex", "[0:a][1:a][2:a]concat=n=3:v=0:a=1[out]",
+                    "-map", "[out]",
+                    "-ar", "44100",
+                    path.to_str().unwrap(),
+                ])
+                .status()
+                .expect("Failed to execute ffmpeg");
+            assert!(status.success(), "Failed to create {:?}", path);
+            jobs.push(SilenceRemovalJob {
+                input_path: path.to_string_lossy().to_string(),
+                output_path: None,
+            });
+        }
+        let custom_output = temp_dir.join("batch_b_custom.ogg");
+        let _ = std::fs::remove_file(&custom_output);
+        jobs[1].output_path = Some(custom_output.to_string_lossy().to_string());
+
+        let stages = std::sync::Mutex::new(Vec::new());
+        let results = remove_silence_batch_internal(
+            jobs,
+            Some(0.5),
+            |event| {
+                let mut stages = stages.lock().unwrap();
+                stages.push((event.job_index, event.total_jobs, event.file_name, event.stage));
+            },
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            stages.into_inner().unwrap(),
+            vec![
+                (0, 2, "batch_a.wav".to_string(), SilenceStage::Detecting),
+                (0, 2, "batch_a.wav".to_string(), SilenceStage::Removing),
+                (1, 2, "batch_b.wav".to_string(), SilenceStage::Detecting),
+                (1, 2, "batch_b.wav".to_string(), SilenceStage::Removing),
+            ]
+        );
+        assert_eq!(results.len(), 2);
+        assert!(results[0].output.path.ends_with("batch_a_nosilence.ogg"));
+        assert_eq!(results[1].output.path, custom_output.to_string_lossy());
+        assert!(custom_output.exists());
+    }
+
     #[tokio::test]
     async fn test_silence_detection_and_removal() {
         let original_path = get_test_file_path();
@@ -267,9 +876,17 @@ mod tests {
         assert!(test_file_path.exists());
 
         // 1. Test Detect Silence
-        let intervals = detect_silence_internal(test_file_path.to_str().unwrap(), 0.5).await.unwrap();
+        let emitted = std::sync::Mutex::new(Vec::new());
+        let intervals = detect_silence_internal(test_file_path.to_str().unwrap(), 0.5, |interval| {
+            emitted.lock().unwrap().push(interval.start);
+        })
+        .await
+        .unwrap();
         
         println!("Detected intervals: {:?}", intervals);
+        // Every interval was emitted while detecting, in order
+        let starts: Vec<f64> = intervals.iter().map(|i| i.start).collect();
+        assert_eq!(emitted.into_inner().unwrap(), starts);
         
         // We expect at least 2 intervals: one at start (approx 0-2s) and one at end.
         // Note: silencedetect might not be perfect at exact boundaries.
@@ -278,19 +895,19 @@ mod tests {
         assert!(start_silence.is_some(), "Should detect silence at the beginning");
         
         // 2. Test Remove Silence
-        let processed = remove_silence(test_file_path.to_str().unwrap().to_string(), Some(0.5)).await.unwrap();
+        let processed = remove_silence_internal(test_file_path.to_str().unwrap().to_string(), Some(0.5), None, |_| {}).await.unwrap();
         
         assert!(Path::new(&processed.path).exists(), "Processed file should exist");
         
         // Check duration of processed file
-        let processed_duration = probe_duration(&processed.path).await.unwrap();
+        let processed_duration = probe_duration_json(&processed.path).unwrap();
         
         // Calculate expected duration
         // We need the duration of the input file (test_file_path) which has the added silence
         // But we can't probe it easily here because we might have deleted it? No, we haven't.
         // But wait, remove_silence takes a path string.
         
-        let test_file_duration = probe_duration(test_file_path.to_str().unwrap()).await.unwrap();
+        let test_file_duration = probe_duration_json(test_file_path.to_str().unwrap()).unwrap();
         let total_silence_duration: f64 = processed.silence_intervals.iter().map(|i| i.duration).sum();
         let expected_duration = test_file_duration - total_silence_duration;
         
@@ -0,0 +1,28 @@
+use crate::alignment::ParakeetModel;
+use crate::cache::ApiCache;
+use crate::gemini::{ApiKeyPool, LanguageDetectionResult};
+use crate::undo::UndoStack;
+use crate::video::TranscriptSegment;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// Session-wide state managed by Tauri and shared between commands.
+#[derive(Default)]
+pub struct AppState {
+    /// Detected language per audio source (Files API uri or hash of the inline data).
+    pub language_cache: Mutex<HashMap<String, LanguageDetectionResult>>,
+    /// Project file opened via deep link that the frontend hasn't picked up yet.
+    pub pending_project: Mutex<Option<PathBuf>>,
+    /// Extra API keys rotated through by all AI commands; empty means the
+    /// key passed to each command is used.
+    pub api_key_pool: Arc<ApiKeyPool>,
+    /// Responses of `generate_clips`, keyed by a hash of transcript and settings.
+    pub api_cache: ApiCache,
+    /// Snapshots of the transcript for undo/redo of manual edits.
+    pub transcript_history: Mutex<UndoStack<Vec<TranscriptSegment>>>,
+    /// Alignment model kept loaded between `align_transcript` calls. Only
+    /// locked on blocking threads, see `alignment::with_model_slot`.
+    pub parakeet: Arc<Mutex<Option<ParakeetModel>>>,
+}
@@ -0,0 +1,277 @@
+use crate::ProgressEvent;
+use anyhow::{anyhow, Context, Result};
+use ffmpeg_sidecar::paths::sidecar_dir;
+use log::{debug, info};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tauri::Emitter;
+
+/// Pretrained Demucs model used for both variants.
+const DEMUCS_MODEL: &str = "htdemucs";
+
+/// Stderr lines kept for the error message of a failed run.
+const ERROR_CONTEXT_LINES: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum StemModel {
+    /// Vocals and everything else.
+    Demucs2Track,
+    /// Vocals, drums, bass and the remaining instruments.
+    Demucs4Track,
+}
+
+/// Separated stems as WAV files. With `Demucs4Track`, `accompaniment` holds
+/// only the instruments that are neither drums nor bass.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StemPaths {
+    pub vocals: String,
+    pub accompaniment: String,
+    pub drums: Option<String>,
+    pub bass: Option<String>,
+}
+
+/// Splits the audio into stems with Demucs, e.g. to transcribe a podcast
+/// recorded over music from the vocals only.
+#[tauri::command]
+pub async fn separate_stems(
+    window: tauri::Window,
+    input_path: String,
+    output_dir: String,
+    model: StemModel,
+) -> Result<StemPaths, String> {
+    run_demucs(
+        Path::new(&input_path),
+        Path::new(&output_dir),
+        model,
+        move |event| {
+            let _ = window.emit("progress", event);
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// The `demucs` binary bundled next to FFmpeg, falling back to the one on `PATH`.
+pub fn demucs_path() -> PathBuf {
+    let name = if cfg!(windows) {
+        "demucs.exe"
+    } else {
+        "demucs"
+    };
+    sidecar_dir()
+        .map(|dir| dir.join(name))
+        .ok()
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+pub fn build_demucs_args(input_path: &Path, output_dir: &Path, model: StemModel) -> Vec<String> {
+    let mut args = vec![
+        "-n".to_string(),
+        DEMUCS_MODEL.to_string(),
+        "-o".to_string(),
+        output_dir.to_string_lossy().to_string(),
+        // Flat file names, so the stems don't depend on the input name
+        "--filename".to_string(),
+        "{stem}.{ext}".to_string(),
+    ];
+    if model == StemModel::Demucs2Track {
+        args.extend(["--two-stems".to_string(), "vocals".to_string()]);
+    }
+    args.push(input_path.to_string_lossy().to_string());
+    args
+}
+
+/// Where Demucs writes the stems for `build_demucs_args`.
+pub fn stem_paths(output_dir: &Path, model: StemModel) -> StemPaths {
+    let dir = output_dir.join(DEMUCS_MODEL);
+    let stem = |name: &str| {
+        dir.join(format!("{}.wav", name))
+            .to_string_lossy()
+            .to_string()
+    };
+    match model {
+        StemModel::Demucs2Track => StemPaths {
+            vocals: stem("vocals"),
+            accompaniment: stem("no_vocals"),
+            drums: None,
+            bass: None,
+        },
+        StemModel::Demucs4Track => StemPaths {
+            vocals: stem("vocals"),
+            accompaniment: stem("other"),
+            drums: Some(stem("drums")),
+            bass: Some(stem("bass")),
+        },
+    }
+}
+
+/// Percentage of a tqdm progress bar line like ` 42%|████▏     | 12.3/29.3`.
+fn parse_demucs_progress(line: &str) -> Option<f64> {
+    let re = Regex::new(r"(\d{1,3})%\|").unwrap();
+    re.captures(line)
+        .and_then(|caps| caps[1].parse::<f64>().ok())
+        .map(|p| p.min(100.0))
+}
+
+pub fn run_demucs<F>(
+    input_path: &Path,
+    output_dir: &Path,
+    model: StemModel,
+    on_progress: F,
+) -> Result<StemPaths>
+where
+    F: Fn(ProgressEvent),
+{
+    if !input_path.exists() {
+        return Err(anyhow!("Input file not found: {:?}", input_path));
+    }
+    std::fs::create_dir_all(output_dir)
+        .with_context(|| format!("Failed to create output directory {:?}", output_dir))?;
+
+    let demucs = demucs_path();
+    let args = build_demucs_args(input_path, output_dir, model);
+    info!("Running {:?} {:?}", demucs, args);
+    on_progress(ProgressEvent::message(
+        "separate_stems",
+        0.0,
+        "Separating stems...",
+    ));
+
+    let mut child = Command::new(&demucs)
+        .args(&args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run Demucs ({:?}), is it installed?", demucs))?;
+
+    // tqdm redraws its bar with carriage returns
+    let mut last_lines = Vec::new();
+    if let Some(stderr) = child.stderr.take() {
+        for chunk in BufReader::new(stderr).split(b'\r') {
+            let chunk = chunk?;
+            for line in String::from_utf8_lossy(&chunk).lines() {
+                match parse_demucs_progress(line) {
+                    Some(percentage) => {
+                        on_progress(ProgressEvent::estimated("separate_stems", percentage))
+                    }
+                    None if !line.trim().is_empty() => {
+                        debug!("[demucs] {}", line);
+                        last_lines.push(line.to_string());
+                        if last_lines.len() > ERROR_CONTEXT_LINES {
+                            last_lines.remove(0);
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!(
+            "Demucs failed ({}): {}",
+            status,
+            last_lines.join("\n")
+        ));
+    }
+
+    let stems = stem_paths(output_dir, model);
+    if let Some(missing) = [
+        Some(&stems.vocals),
+        Some(&stems.accompaniment),
+        stems.drums.as_ref(),
+        stems.bass.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|path| !Path::new(path).exists())
+    {
+        return Err(anyhow!("Demucs finished but {} is missing", missing));
+    }
+
+    on_progress(ProgressEvent::message(
+        "separate_stems",
+        100.0,
+        "Stems separated",
+    ));
+    Ok(stems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `name` in the Demucs model directory below `/tmp/stems`, with the
+    /// platform's separators.
+    fn stem_file(name: &str) -> String {
+        Path::new("/tmp/stems")
+            .join(DEMUCS_MODEL)
+            .join(name)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn test_build_demucs_args_two_stems() {
+        let args = build_demucs_args(
+            Path::new("/media/podcast.wav"),
+            Path::new("/tmp/stems"),
+            StemModel::Demucs2Track,
+        );
+        assert_eq!(
+            args,
+            vec![
+                "-n",
+                "htdemucs",
+                "-o",
+                "/tmp/stems",
+                "--filename",
+                "{stem}.{ext}",
+                "--two-stems",
+                "vocals",
+                "/media/podcast.wav"
+            ]
+        );
+
+        let stems = stem_paths(Path::new("/tmp/stems"), StemModel::Demucs2Track);
+        assert_eq!(stems.vocals, stem_file("vocals.wav"));
+        assert_eq!(stems.accompaniment, stem_file("no_vocals.wav"));
+        assert_eq!((stems.drums, stems.bass), (None, None));
+    }
+
+    #[test]
+    fn test_build_demucs_args_four_stems() {
+        let args = build_demucs_args(
+            Path::new("/media/podcast.wav"),
+            Path::new("/tmp/stems"),
+            StemModel::Demucs4Track,
+        );
+        assert!(!args.contains(&"--two-stems".to_string()));
+        assert_eq!(args.last().unwrap(), "/media/podcast.wav");
+
+        let stems = stem_paths(Path::new("/tmp/stems"), StemModel::Demucs4Track);
+        assert_eq!(stems.accompaniment, stem_file("other.wav"));
+        assert_eq!(stems.drums, Some(stem_file("drums.wav")));
+        assert_eq!(stems.bass, Some(stem_file("bass.wav")));
+    }
+
+    #[test]
+    fn test_parse_demucs_progress() {
+        assert_eq!(
+            parse_demucs_progress(" 42%|████▏     | 12.3/29.25 [00:05<00:07,  2.31seconds/s]"),
+            Some(42.0)
+        );
+        assert_eq!(
+            parse_demucs_progress("100%|██████████| 29.25/29.25"),
+            Some(100.0)
+        );
+        assert_eq!(
+            parse_demucs_progress("Separated tracks will be stored in /tmp"),
+            None
+        );
+    }
+}
@@ -0,0 +1,536 @@
+use crate::time_utils::{parse_timestamp_to_seconds_raw, seconds_to_timestamp};
+use crate::video::{
+    base_ffmpeg_command, escape_filter_path, run_ffmpeg, ClipSegment, Segment, TranscriptSegment,
+};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Look of burned-in subtitles, passed to libass as `force_style`.
+/// Colors are `#RRGGBB`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SubtitleStyle {
+    pub font_name: Option<String>,
+    pub font_size: u32,
+    pub primary_color: String,
+    pub outline_color: String,
+    pub outline_width: f64,
+    /// Distance from the bottom edge, in pixels of the script resolution.
+    pub margin_v: u32,
+}
+
+impl Default for SubtitleStyle {
+    fn default() -> Self {
+        Self {
+            font_name: None,
+            font_size: 24,
+            primary_color: "#FFFFFF".to_string(),
+            outline_color: "#000000".to_string(),
+            outline_width: 2.0,
+            margin_v: 20,
+        }
+    }
+}
+
+/// `#RRGGBB` as an opaque ASS color (`&H00BBGGRR`).
+fn ass_color(color: &str) -> Result<String> {
+    let hex = color.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("Invalid color {:?}, expected #RRGGBB", color));
+    }
+    Ok(format!("&H00{}{}{}", &hex[4..6], &hex[2..4], &hex[0..2]).to_uppercase())
+}
+
+/// `subtitles` filter that burns `subtitle_path` into the video with `style`.
+pub fn build_subtitle_filter(subtitle_path: &Path, style: &SubtitleStyle) -> Result<String> {
+    let mut force_style = Vec::new();
+    if let Some(font_name) = &style.font_name {
+        // Separators of the style list and the filter graph can't be escaped here
+        let font_name: String = font_name
+            .chars()
+            .filter(|c| !matches!(c, ',' | '=' | ':' | '\'' | '\\'))
+            .collect();
+        force_style.push(format!("FontName={}", font_name.trim()));
+    }
+    force_style.push(format!("FontSize={}", style.font_size));
+    force_style.push(format!(
+        "PrimaryColour={}",
+        ass_color(&style.primary_color)?
+    ));
+    force_style.push(format!(
+        "OutlineColour={}",
+        ass_color(&style.outline_color)?
+    ));
+    force_style.push(format!("Outline={}", style.outline_width.max(0.0)));
+    force_style.push(format!("MarginV={}", style.margin_v));
+
+    Ok(format!(
+        "subtitles={}:force_style='{}'",
+        escape_filter_path(subtitle_path),
+        force_style.join(",")
+    ))
+}
+
+/// Arguments that render the single frame at `timestamp_secs` with the
+/// subtitles burned in. `-copyts` keeps the original timestamps after the input
+/// seek, so the filter picks the subtitle shown at that time.
+pub fn build_subtitle_preview_args(
+    input_path: &Path,
+    subtitle_filter: &str,
+    timestamp_secs: f64,
+    output_path: &Path,
+) -> Vec<String> {
+    vec![
+        "-y".to_string(),
+        "-ss".to_string(),
+        format!("{:.3}", timestamp_secs),
+        "-copyts".to_string(),
+        "-i".to_string(),
+        input_path.to_string_lossy().to_string(),
+        "-vf".to_string(),
+        subtitle_filter.to_string(),
+        "-vframes".to_string(),
+        "1".to_string(),
+        output_path.to_string_lossy().to_string(),
+    ]
+}
+
+/// Renders one frame with burned-in subtitles to check the style without
+/// processing the whole video.
+#[tauri::command]
+pub async fn preview_subtitle_burn(
+    input_path: String,
+    subtitle_path: String,
+    timestamp: String,
+    style: SubtitleStyle,
+    output_path: String,
+) -> Result<(), String> {
+    render_subtitle_preview(
+        Path::new(&input_path),
+        Path::new(&subtitle_path),
+        &timestamp,
+        &style,
+        &PathBuf::from(output_path),
+    )
+    .map_err(|e| e.to_string())
+}
+
+pub fn render_subtitle_preview(
+    input_path: &Path,
+    subtitle_path: &Path,
+    timestamp: &str,
+    style: &SubtitleStyle,
+    output_path: &Path,
+) -> Result<()> {
+    if !subtitle_path.is_file() {
+        return Err(anyhow!("Subtitle file not found: {:?}", subtitle_path));
+    }
+    let timestamp_secs = parse_timestamp_to_seconds_raw(timestamp)?;
+    let filter = build_subtitle_filter(subtitle_path, style)?;
+
+    // Overwritten by a new preview, so a stale one must not count as success
+    let _ = std::fs::remove_file(output_path);
+    let mut command = base_ffmpeg_command();
+    command.args(build_subtitle_preview_args(
+        input_path,
+        &filter,
+        timestamp_secs,
+        output_path,
+    ));
+    run_ffmpeg(&mut command, output_path, |_| {})
+}
+
+/// Formats seconds as an SRT timestamp (`HH:MM:SS,mmm`).
+pub fn format_srt_timestamp(seconds: f64) -> String {
+    seconds_to_timestamp(seconds).replace('.', ",")
+}
+
+/// Renders transcript segments as an SRT document. Cues are numbered in order;
+/// the speaker is not included in the caption text.
+pub fn build_srt(segments: &[TranscriptSegment]) -> Result<String> {
+    let mut srt = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let start = parse_timestamp_to_seconds_raw(&segment.start)?;
+        let end = parse_timestamp_to_seconds_raw(&segment.end)?;
+        srt.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            format_srt_timestamp(start),
+            format_srt_timestamp(end),
+            segment.text.trim()
+        ));
+    }
+    Ok(srt)
+}
+
+/// Picks the transcript segments that overlap the given clip segments and moves
+/// them onto the clip's own timeline, where the clip segments are played back to
+/// back starting at zero. Captions are cut at segment boundaries.
+pub fn clip_captions(
+    transcript: &[TranscriptSegment],
+    clip_segments: &[Segment],
+) -> Result<Vec<TranscriptSegment>> {
+    let mut captions = Vec::new();
+    let mut clip_offset = 0.0;
+
+    for clip_segment in clip_segments {
+        let clip_start = parse_timestamp_to_seconds_raw(&clip_segment.start)?;
+        let clip_end = parse_timestamp_to_seconds_raw(&clip_segment.end)?;
+
+        for segment in transcript {
+            let start = parse_timestamp_to_seconds_raw(&segment.start)?;
+            let end = parse_timestamp_to_seconds_raw(&segment.end)?;
+            if end <= clip_start || start >= clip_end {
+                continue;
+            }
+            captions.push(TranscriptSegment {
+                start: seconds_to_timestamp(start.max(clip_start) - clip_start + clip_offset),
+                end: seconds_to_timestamp(end.min(clip_end) - clip_start + clip_offset),
+                speaker: segment.speaker.clone(),
+                text: segment.text.clone(),
+            });
+        }
+
+        clip_offset += (clip_end - clip_start).max(0.0);
+    }
+    Ok(captions)
+}
+
+/// Transcript lines of one clip, timed relative to the clip start.
+pub fn extract_clip_subtitles(
+    all_segments: &[TranscriptSegment],
+    clip: &ClipSegment,
+) -> Result<Vec<TranscriptSegment>> {
+    clip_captions(all_segments, &clip.segments)
+}
+
+pub fn export_clip_srt(clip_segments: &[TranscriptSegment], path: &Path) -> Result<()> {
+    std::fs::write(path, build_srt(clip_segments)?)
+        .with_context(|| format!("Failed to write subtitles {:?}", path))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+    Unknown,
+}
+
+/// Speaker of imported cues that don't name one.
+const IMPORTED_SPEAKER: &str = "Speaker 1";
+
+/// SRT files start with the number of the first cue, WebVTT files with a
+/// `WEBVTT` line.
+pub fn detect_subtitle_format(content: &str) -> SubtitleFormat {
+    let first_line = content
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty());
+    match first_line {
+        Some(line) if line == "WEBVTT" || line.starts_with("WEBVTT ") => SubtitleFormat::Vtt,
+        Some(line) if line.chars().all(|c| c.is_ascii_digit()) => SubtitleFormat::Srt,
+        _ => SubtitleFormat::Unknown,
+    }
+}
+
+/// Reads an SRT or WebVTT file as transcript segments, whichever it is.
+#[tauri::command]
+pub fn import_subtitles(path: String) -> Result<Vec<TranscriptSegment>, String> {
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read subtitles {:?}: {}", path, e))?;
+    parse_subtitles(&content).map_err(|e| e.to_string())
+}
+
+pub fn parse_subtitles(content: &str) -> Result<Vec<TranscriptSegment>> {
+    match detect_subtitle_format(content) {
+        SubtitleFormat::Srt => parse_srt(content),
+        SubtitleFormat::Vtt => parse_vtt(content),
+        SubtitleFormat::Unknown => Err(anyhow!("Neither an SRT nor a WebVTT file")),
+    }
+}
+
+/// Cues of an SRT document: a number, `start --> end` and the text lines.
+pub fn parse_srt(content: &str) -> Result<Vec<TranscriptSegment>> {
+    cue_blocks(content)
+        .into_iter()
+        .map(|block| parse_cue(&block, |text| (IMPORTED_SPEAKER.to_string(), text)))
+        .collect()
+}
+
+/// Cues of a WebVTT document. Cue identifiers and settings, `NOTE`, `STYLE`
+/// and `REGION` blocks are skipped. A `<v Name>` voice tag becomes the speaker,
+/// other tags are removed from the text.
+pub fn parse_vtt(content: &str) -> Result<Vec<TranscriptSegment>> {
+    let voice = regex::Regex::new(r"^<v(?:\.[\w.]+)?\s+([^>]+)>").unwrap();
+    let tags = regex::Regex::new(r"</?[^>]+>").unwrap();
+    cue_blocks(content)
+        .into_iter()
+        .filter(|block| {
+            !block[0].starts_with("WEBVTT")
+                && !["NOTE", "STYLE", "REGION"]
+                    .iter()
+                    .any(|kind| block[0].split_whitespace().next() == Some(*kind))
+        })
+        .map(|block| {
+            parse_cue(&block, |text| {
+                let speaker = voice
+                    .captures(&text)
+                    .map_or(IMPORTED_SPEAKER.to_string(), |caps| {
+                        caps[1].trim().to_string()
+                    });
+                (speaker, tags.replace_all(&text, "").to_string())
+            })
+        })
+        .collect()
+}
+
+/// Non-empty lines of each blank line separated block.
+fn cue_blocks(content: &str) -> Vec<Vec<&str>> {
+    let mut blocks = Vec::new();
+    let mut block = Vec::new();
+    for line in content.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim_end();
+        if !line.trim().is_empty() {
+            block.push(line);
+        } else if !block.is_empty() {
+            blocks.push(std::mem::take(&mut block));
+        }
+    }
+    if !block.is_empty() {
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// One cue: the timing line (after an optional identifier) and the text,
+/// which `speaker_and_text` splits into speaker and caption.
+fn parse_cue<F>(block: &[&str], speaker_and_text: F) -> Result<TranscriptSegment>
+where
+    F: Fn(String) -> (String, String),
+{
+    let timing_index = block
+        .iter()
+        .position(|line| line.contains("-->"))
+        .ok_or_else(|| anyhow!("Cue without timing: {:?}", block.join("\n")))?;
+    let (start, rest) = block[timing_index].split_once("-->").unwrap();
+    // WebVTT cue settings follow the end time
+    let end = rest.split_whitespace().next().unwrap_or_default();
+    let parse = |ts: &str| {
+        parse_timestamp_to_seconds_raw(ts.trim())
+            .map(seconds_to_timestamp)
+            .with_context(|| format!("Invalid cue timing {:?}", block[timing_index]))
+    };
+    let (speaker, text) = speaker_and_text(block[timing_index + 1..].join("\n"));
+    Ok(TranscriptSegment {
+        start: parse(start)?,
+        end: parse(end)?,
+        speaker,
+        text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seg(start: &str, end: &str, text: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            start: start.to_string(),
+            end: end.to_string(),
+            speaker: "Speaker 1".to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    const SRT_FIXTURE: &str = "1\n\
+        00:00:01,000 --> 00:00:03,500\n\
+        Hello and welcome.\n\
+        \n\
+        2\n\
+        00:00:04,000 --> 00:00:06,250\n\
+        Today we talk\n\
+        about editing.\n";
+
+    const VTT_FIXTURE: &str = "WEBVTT - Episode 1\n\
+        \n\
+        NOTE exported by the recorder\n\
+        \n\
+        intro\n\
+        00:01.000 --> 00:03.500 align:start position:10%\n\
+        <v Alice>Hello and <i>welcome</i>.\n\
+        \n\
+        00:00:04.000 --> 00:00:06.250\n\
+        Today we talk\n\
+        about editing.\n";
+
+    #[test]
+    fn test_detect_subtitle_format() {
+        assert_eq!(detect_subtitle_format(SRT_FIXTURE), SubtitleFormat::Srt);
+        assert_eq!(detect_subtitle_format(VTT_FIXTURE), SubtitleFormat::Vtt);
+        assert_eq!(
+            detect_subtitle_format("\u{feff}WEBVTT\n"),
+            SubtitleFormat::Vtt
+        );
+        assert_eq!(detect_subtitle_format("\n\n  12\n"), SubtitleFormat::Srt);
+        assert_eq!(
+            detect_subtitle_format("[Script Info]\n"),
+            SubtitleFormat::Unknown
+        );
+        assert_eq!(detect_subtitle_format(""), SubtitleFormat::Unknown);
+    }
+
+    #[test]
+    fn test_parse_subtitles() {
+        let cues = |segments: Vec<TranscriptSegment>| -> Vec<(String, String, String, String)> {
+            segments
+                .into_iter()
+                .map(|s| (s.start, s.end, s.speaker, s.text))
+                .collect()
+        };
+        let cue = |start: &str, end: &str, speaker: &str, text: &str| {
+            (
+                start.to_string(),
+                end.to_string(),
+                speaker.to_string(),
+                text.to_string(),
+            )
+        };
+
+        assert_eq!(
+            cues(parse_subtitles(SRT_FIXTURE).unwrap()),
+            vec![
+                cue(
+                    "00:00:01.000",
+                    "00:00:03.500",
+                    "Speaker 1",
+                    "Hello and welcome."
+                ),
+                cue(
+                    "00:00:04.000",
+                    "00:00:06.250",
+                    "Speaker 1",
+                    "Today we talk\nabout editing."
+                ),
+            ]
+        );
+        assert_eq!(
+            cues(parse_subtitles(VTT_FIXTURE).unwrap()),
+            vec![
+                cue(
+                    "00:00:01.000",
+                    "00:00:03.500",
+                    "Alice",
+                    "Hello and welcome."
+                ),
+                cue(
+                    "00:00:04.000",
+                    "00:00:06.250",
+                    "Speaker 1",
+                    "Today we talk\nabout editing."
+                ),
+            ]
+        );
+        assert!(parse_subtitles("1\nnot a timing line\nText\n").is_err());
+        assert!(parse_subtitles("Just some text").is_err());
+    }
+
+    #[test]
+    fn test_build_srt() {
+        let srt = build_srt(&[seg("00:01", "00:02.500", "Hallo "), seg("01:02:03.004", "01:02:04", "Welt")])
+            .unwrap();
+        assert_eq!(
+            srt,
+            "1\n00:00:01,000 --> 00:00:02,500\nHallo\n\n2\n01:02:03,004 --> 01:02:04,000\nWelt\n\n"
+        );
+    }
+
+    #[test]
+    fn test_clip_captions_retimed() {
+        let transcript = vec![
+            seg("00:00", "00:05", "before"),
+            seg("00:09", "00:12", "first"),
+            seg("00:20", "00:25", "outside"),
+            seg("00:31", "00:33", "second"),
+        ];
+        let clip = vec![
+            Segment { start: "00:10".to_string(), end: "00:15".to_string() },
+            Segment { start: "00:30".to_string(), end: "00:35".to_string() },
+        ];
+
+        let captions = clip_captions(&transcript, &clip).unwrap();
+        let cues: Vec<(&str, &str, &str)> = captions
+            .iter()
+            .map(|c| (c.start.as_str(), c.end.as_str(), c.text.as_str()))
+            .collect();
+        assert_eq!(
+            cues,
+            vec![
+                ("00:00:00.000", "00:00:02.000", "first"),
+                ("00:00:06.000", "00:00:08.000", "second"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_clip_subtitles_rebased() {
+        let transcript = vec![
+            seg("00:28", "00:32", "overlaps the start"),
+            seg("00:35", "00:38.250", "inside"),
+            seg("00:45", "00:50", "after"),
+        ];
+        let clip = ClipSegment {
+            segments: vec![Segment {
+                start: "00:30".to_string(),
+                end: "00:40".to_string(),
+            }],
+            label: None,
+            reason: None,
+            name_template: None,
+            score: None,
+            audio_gain_db: None,
+        };
+
+        let subtitles = extract_clip_subtitles(&transcript, &clip).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clip.srt");
+        export_clip_srt(&subtitles, &path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "1\n00:00:00,000 --> 00:00:02,000\noverlaps the start\n\n\
+             2\n00:00:05,000 --> 00:00:08,250\ninside\n\n"
+        );
+    }
+
+    #[test]
+    fn test_subtitle_preview_args() {
+        let style = SubtitleStyle {
+            font_name: Some("Open Sans".to_string()),
+            primary_color: "#ffcc00".to_string(),
+            ..Default::default()
+        };
+        let filter = build_subtitle_filter(Path::new("/tmp/clip.srt"), &style).unwrap();
+        assert_eq!(
+            filter,
+            "subtitles='/tmp/clip.srt':force_style='FontName=Open Sans,FontSize=24,\
+             PrimaryColour=&H0000CCFF,OutlineColour=&H00000000,Outline=2,MarginV=20'"
+        );
+
+        let args = build_subtitle_preview_args(
+            Path::new("in.mp4"),
+            &filter,
+            75.5,
+            Path::new("preview.png"),
+        );
+        assert!(args.windows(2).any(|w| w == ["-vframes", "1"]));
+        assert!(args.windows(2).any(|w| w[0] == "-vf" && w[1] == filter));
+        assert!(args.windows(2).any(|w| w == ["-ss", "75.500"]));
+
+        let invalid = SubtitleStyle {
+            outline_color: "black".to_string(),
+            ..Default::default()
+        };
+        assert!(build_subtitle_filter(Path::new("a.srt"), &invalid).is_err());
+    }
+}
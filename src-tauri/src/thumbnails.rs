@@ -0,0 +1,203 @@
+use crate::probe::{probe_frame_rate, probe_media};
+use crate::time_utils::seconds_to_timestamp;
+use crate::video::{base_ffmpeg_command, run_ffmpeg};
+use anyhow::{anyhow, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Thumbnails per row of the sprite sheet.
+const SPRITE_COLUMNS: u32 = 10;
+
+/// Largest width or height of a JPEG image.
+const MAX_SPRITE_DIMENSION: u32 = 65_535;
+
+const VTT_FILE_NAME: &str = "thumbnails.vtt";
+const SPRITE_FILE_NAME: &str = "thumbnails.jpg";
+
+/// Files written by `generate_seek_thumbnails`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SeekThumbnailManifest {
+    pub vtt_path: String,
+    pub sprite_path: String,
+    pub frame_count: u32,
+}
+
+/// Writes a sprite sheet with one thumbnail every `interval_secs` and a WebVTT
+/// file mapping the time ranges to its tiles, as used by Video.js and similar
+/// players for seek previews. Thumbnails are `thumb_width` pixels wide and keep
+/// the aspect ratio of the video.
+#[tauri::command]
+pub async fn generate_seek_thumbnails(
+    input_path: String,
+    output_dir: String,
+    interval_secs: u32,
+    thumb_width: u32,
+) -> Result<SeekThumbnailManifest, String> {
+    write_seek_thumbnails(
+        Path::new(&input_path),
+        Path::new(&output_dir),
+        interval_secs,
+        thumb_width,
+    )
+    .map_err(|e| e.to_string())
+}
+
+pub fn write_seek_thumbnails(
+    input_path: &Path,
+    output_dir: &Path,
+    interval_secs: u32,
+    thumb_width: u32,
+) -> Result<SeekThumbnailManifest> {
+    if interval_secs == 0 {
+        return Err(anyhow!("Thumbnail interval must be at least 1 second"));
+    }
+    if thumb_width < 2 {
+        return Err(anyhow!("Invalid thumbnail width: {}", thumb_width));
+    }
+
+    let info = probe_media(input_path)?;
+    let (width, height) = info
+        .video_streams()
+        .next()
+        .and_then(|s| Some((s.width?, s.height?)))
+        .filter(|(width, height)| *width > 0 && *height > 0)
+        .ok_or_else(|| anyhow!("No video stream found in {:?}", input_path))?;
+    if info.duration <= 0.0 {
+        return Err(anyhow!("Unknown duration of {:?}", input_path));
+    }
+    // Even, most encoders reject odd sizes
+    let thumb_size = (
+        thumb_width & !1,
+        ((thumb_width as f64 * height as f64 / width as f64 / 2.0).round() as u32 * 2).max(2),
+    );
+    let frame_count = (info.duration / interval_secs as f64).ceil() as u32;
+    let rows = frame_count.div_ceil(SPRITE_COLUMNS);
+    if rows * thumb_size.1 > MAX_SPRITE_DIMENSION {
+        return Err(anyhow!(
+            "{} thumbnails don't fit into one sprite, use a longer interval or smaller thumbnails",
+            frame_count
+        ));
+    }
+    let frames_per_interval = (probe_frame_rate(input_path)? * interval_secs as f64)
+        .round()
+        .max(1.0) as u32;
+
+    std::fs::create_dir_all(output_dir)
+        .map_err(|e| anyhow!("Failed to create output directory {:?}: {}", output_dir, e))?;
+    let vtt_path = output_dir.join(VTT_FILE_NAME);
+    let sprite_path = output_dir.join(SPRITE_FILE_NAME);
+
+    info!(
+        "Generating {} seek thumbnails of {}x{} for {:?}",
+        frame_count, thumb_size.0, thumb_size.1, input_path
+    );
+    // Overwritten by a new run, so a stale sprite must not count as success
+    let _ = std::fs::remove_file(&sprite_path);
+    let mut command = base_ffmpeg_command();
+    command
+        .input(input_path.to_str().unwrap())
+        .args([
+            "-y",
+            "-vf",
+            &build_sprite_filter(frames_per_interval, thumb_size, rows),
+            "-frames:v",
+            "1",
+            "-q:v",
+            "5",
+        ])
+        .output(sprite_path.to_str().unwrap());
+    run_ffmpeg(&mut command, &sprite_path, |_| {})?;
+
+    let vtt = build_seek_vtt(
+        SPRITE_FILE_NAME,
+        frame_count,
+        interval_secs as f64,
+        info.duration,
+        thumb_size,
+    );
+    std::fs::write(&vtt_path, vtt).map_err(|e| anyhow!("Failed to write {:?}: {}", vtt_path, e))?;
+
+    Ok(SeekThumbnailManifest {
+        vtt_path: vtt_path.to_string_lossy().to_string(),
+        sprite_path: sprite_path.to_string_lossy().to_string(),
+        frame_count,
+    })
+}
+
+/// Keeps every `frames_per_interval`th frame, scales it down before anything is
+/// buffered and tiles all of them into a single image.
+fn build_sprite_filter(frames_per_interval: u32, (width, height): (u32, u32), rows: u32) -> String {
+    format!(
+        "select='not(mod(n,{}))',scale={}:{},tile={}x{}",
+        frames_per_interval, width, height, SPRITE_COLUMNS, rows
+    )
+}
+
+/// One cue per thumbnail, pointing to its tile with a `#xywh=` media fragment.
+/// The last cue ends at `duration`.
+fn build_seek_vtt(
+    sprite_name: &str,
+    frame_count: u32,
+    interval_secs: f64,
+    duration: f64,
+    (width, height): (u32, u32),
+) -> String {
+    let mut vtt = String::from("WEBVTT\n");
+    for i in 0..frame_count {
+        let start = i as f64 * interval_secs;
+        let end = (start + interval_secs).min(duration);
+        let x = (i % SPRITE_COLUMNS) * width;
+        let y = (i / SPRITE_COLUMNS) * height;
+        vtt.push_str(&format!(
+            "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+            seconds_to_timestamp(start),
+            seconds_to_timestamp(end),
+            sprite_name,
+            x,
+            y,
+            width,
+            height
+        ));
+    }
+    vtt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seek_vtt_cues() {
+        let vtt = build_seek_vtt("thumbnails.jpg", 12, 10.0, 115.5, (160, 90));
+        let cues: Vec<&str> = vtt.split("\n\n").collect();
+        assert_eq!(cues.len(), 13);
+        assert_eq!(cues[0], "WEBVTT");
+        assert_eq!(
+            cues[1],
+            "00:00:00.000 --> 00:00:10.000\nthumbnails.jpg#xywh=0,0,160,90"
+        );
+        assert_eq!(
+            cues[2],
+            "00:00:10.000 --> 00:00:20.000\nthumbnails.jpg#xywh=160,0,160,90"
+        );
+        // Second row of the sprite
+        assert_eq!(
+            cues[11],
+            "00:01:40.000 --> 00:01:50.000\nthumbnails.jpg#xywh=0,90,160,90"
+        );
+        // Ends with the video
+        assert_eq!(
+            cues[12],
+            "00:01:50.000 --> 00:01:55.500\nthumbnails.jpg#xywh=160,90,160,90\n"
+        );
+    }
+
+    #[test]
+    fn test_sprite_filter() {
+        assert_eq!(
+            build_sprite_filter(300, (160, 90), 3),
+            "select='not(mod(n,300))',scale=160:90,tile=10x3"
+        );
+    }
+}
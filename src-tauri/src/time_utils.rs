@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 
 /// Raw timestamp parser without correction logic - used internally.
 /// Replicates the logic from the provided Python snippet.
@@ -10,6 +11,16 @@ pub fn parse_timestamp_to_seconds_raw(ts: &str) -> Result<f64> {
         return Err(anyhow!("Empty timestamp"));
     }
 
+    // SRT uses a decimal comma (00:01:23,456)
+    if ts.contains(',') {
+        if ts.contains('.') {
+            return Err(anyhow!("Timestamp mixes '.' and ',': {}", ts));
+        }
+        if let Some(pos) = ts.rfind(',') {
+            ts.replace_range(pos..=pos, ".");
+        }
+    }
+
     let mut milliseconds = 0.0;
     let mut new_ts_string: Option<String> = None;
 
@@ -119,9 +130,277 @@ pub fn parse_timestamp_to_seconds_raw(ts: &str) -> Result<f64> {
     Ok((h as f64 * 3600.0) + (m as f64 * 60.0) + (s as f64) + milliseconds)
 }
 
+/// What the timestamps of a recording count from. Broadcast files often carry
+/// the wall clock instead of the position in the file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TimeReferenceKind {
+    /// Position in the file, e.g. `00:01:23.456`.
+    Relative,
+    /// Seconds since the Unix epoch, e.g. `1700000000.5`.
+    UtcEpoch,
+    /// Time of day timecode `HH:MM:SS:FF`, or `HH:MM:SS;FF` for drop frame at
+    /// 29.97 or 59.94 fps.
+    Smpte { frame_rate: f64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TimeReference {
+    pub kind: TimeReferenceKind,
+    /// Offset of the clock the timestamps were taken from to UTC, e.g. 3600 for
+    /// a recorder running on CET. Subtracted to get UTC.
+    pub utc_offset_secs: Option<i64>,
+}
+
+impl TimeReference {
+    pub const RELATIVE: Self = Self {
+        kind: TimeReferenceKind::Relative,
+        utc_offset_secs: None,
+    };
+}
+
+/// Parses `ts` according to `reference`. Relative timestamps are parsed by
+/// `parse_timestamp_to_seconds_raw`, epoch and SMPTE timestamps give seconds
+/// since the epoch or midnight (UTC, with the `utc_offset_secs` applied).
+pub fn parse_timestamp_with_reference(ts: &str, reference: &TimeReference) -> Result<f64> {
+    let offset = reference.utc_offset_secs.unwrap_or(0) as f64;
+    match reference.kind {
+        TimeReferenceKind::Relative => parse_timestamp_to_seconds_raw(ts),
+        TimeReferenceKind::UtcEpoch => {
+            let epoch: f64 = ts
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid epoch timestamp: {}", ts))?;
+            if !epoch.is_finite() || epoch < 0.0 {
+                return Err(anyhow!("Invalid epoch timestamp: {}", ts));
+            }
+            Ok(epoch - offset)
+        }
+        TimeReferenceKind::Smpte { frame_rate } => {
+            if !(frame_rate.is_finite() && frame_rate > 0.0) {
+                return Err(anyhow!("Invalid frame rate: {}", frame_rate));
+            }
+            let ts = ts.trim();
+            let (time, frames) = ts
+                .rsplit_once([':', ';'])
+                .ok_or_else(|| anyhow!("Invalid SMPTE timecode: {}", ts))?;
+            let frames: u32 = frames
+                .parse()
+                .map_err(|_| anyhow!("Invalid SMPTE timecode: {}", ts))?;
+            if frames as f64 >= frame_rate.ceil() {
+                return Err(anyhow!("Frame {} out of range at {} fps", frames, frame_rate));
+            }
+            let secs = parse_timestamp_to_seconds_raw(time)?;
+            if ts[time.len()..].starts_with(';') {
+                return Ok(drop_frame_seconds(secs, frames, frame_rate)
+                    .map_err(|e| anyhow!("Invalid SMPTE timecode {}: {}", ts, e))?
+                    - offset);
+            }
+            Ok(secs + frames as f64 / frame_rate - offset)
+        }
+    }
+}
+
+/// Seconds of the drop frame timecode `secs` (the `HH:MM:SS` part) plus
+/// `frames`. The first two frame numbers (four at 59.94 fps) of every minute
+/// but each tenth are skipped, so the timecode keeps up with the clock.
+fn drop_frame_seconds(secs: f64, frames: u32, frame_rate: f64) -> Result<f64> {
+    let nominal = frame_rate.round() as u64;
+    let dropped = match nominal {
+        30 => 2,
+        60 => 4,
+        _ => return Err(anyhow!("drop frame needs 29.97 or 59.94 fps, not {}", frame_rate)),
+    };
+    if secs.fract() != 0.0 {
+        return Err(anyhow!("seconds can't have a fraction"));
+    }
+    let secs = secs as u64;
+    let minutes = secs / 60;
+    if secs.is_multiple_of(60) && !minutes.is_multiple_of(10) && (frames as u64) < dropped {
+        return Err(anyhow!("frame {} is dropped at the start of minute {}", frames, minutes));
+    }
+    let frame_number = secs * nominal + frames as u64 - dropped * (minutes - minutes / 10);
+    Ok(frame_number as f64 * 1001.0 / (nominal * 1000) as f64)
+}
+
+/// Position in the recording of the epoch time `epoch`, given the epoch time
+/// the recording started at. Negative before the start.
+pub fn epoch_to_relative_secs(epoch: f64, start_epoch: f64) -> f64 {
+    epoch - start_epoch
+}
+
+/// Fractional digits written by `seconds_to_timestamp_string`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimestampPrecision {
+    /// `HH:MM:SS`
+    Seconds,
+    /// `HH:MM:SS.cc`
+    Centiseconds,
+    /// `HH:MM:SS.mmm`
+    Milliseconds,
+}
+
+impl TimestampPrecision {
+    fn digits(self) -> u32 {
+        match self {
+            Self::Seconds => 0,
+            Self::Centiseconds => 2,
+            Self::Milliseconds => 3,
+        }
+    }
+}
+
+/// Formats seconds as `HH:MM:SS.mmm`, which both ffmpeg and our parser accept.
+/// Negative values are clamped to zero.
+pub fn seconds_to_timestamp(seconds: f64) -> String {
+    seconds_to_timestamp_string(seconds, TimestampPrecision::Milliseconds)
+}
+
+/// Inverse of `parse_timestamp_to_seconds_raw`. The value is rounded to the
+/// precision before it is split, so e.g. 59.9996s becomes `00:01:00.000`.
+/// Negative values are clamped to zero.
+pub fn seconds_to_timestamp_string(secs: f64, precision: TimestampPrecision) -> String {
+    let digits = precision.digits();
+    let units_per_sec = 10u64.pow(digits);
+    let total_units = (secs.max(0.0) * units_per_sec as f64).round() as u64;
+    let fraction = total_units % units_per_sec;
+    let total_secs = total_units / units_per_sec;
+    let hms = format!(
+        "{:02}:{:02}:{:02}",
+        total_secs / 3600,
+        (total_secs / 60) % 60,
+        total_secs % 60
+    );
+    if digits == 0 {
+        hms
+    } else {
+        format!("{}.{:0width$}", hms, fraction, width = digits as usize)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse_timestamp_with_reference() {
+        assert_eq!(
+            parse_timestamp_with_reference("01:02:03.5", &TimeReference::RELATIVE).unwrap(),
+            3723.5
+        );
+
+        let epoch = TimeReference {
+            kind: TimeReferenceKind::UtcEpoch,
+            utc_offset_secs: None,
+        };
+        assert_eq!(
+            parse_timestamp_with_reference("1700000000.25", &epoch).unwrap(),
+            1_700_000_000.25
+        );
+        assert!(parse_timestamp_with_reference("00:01:00", &epoch).is_err());
+        // Recorder clock on CET
+        let cet = TimeReference {
+            utc_offset_secs: Some(3600),
+            ..epoch
+        };
+        assert_eq!(parse_timestamp_with_reference("1700003600", &cet).unwrap(), 1_700_000_000.0);
+
+        let smpte = TimeReference {
+            kind: TimeReferenceKind::Smpte { frame_rate: 25.0 },
+            utc_offset_secs: Some(-18000),
+        };
+        let secs = parse_timestamp_with_reference("10:00:00:05", &smpte).unwrap();
+        assert!((secs - (36000.2 + 18000.0)).abs() < 1e-9, "{}", secs);
+        assert!(parse_timestamp_with_reference("10:00:00:25", &smpte).is_err());
+    }
+
+    #[test]
+    fn test_parse_drop_frame_timecode() {
+        let smpte = |frame_rate| TimeReference {
+            kind: TimeReferenceKind::Smpte { frame_rate },
+            utc_offset_secs: None,
+        };
+        let parse = |ts, frame_rate| parse_timestamp_with_reference(ts, &smpte(frame_rate));
+        let frames_at_29_97 = |frames: f64| frames * 1001.0 / 30000.0;
+
+        // 00:00:59;29 is followed by 00:01:00;02
+        let before = parse("00:00:59;29", 29.97).unwrap();
+        let after = parse("00:01:00;02", 29.97).unwrap();
+        assert!((before - frames_at_29_97(1799.0)).abs() < 1e-9, "{}", before);
+        assert!((after - frames_at_29_97(1800.0)).abs() < 1e-9, "{}", after);
+        assert!(parse("00:01:00;00", 29.97).is_err());
+        // Every tenth minute keeps its first frames
+        let tenth = parse("00:10:00;00", 29.97).unwrap();
+        assert!((tenth - frames_at_29_97(17982.0)).abs() < 1e-9, "{}", tenth);
+        // An hour of drop frame timecode is an hour of clock time
+        let hour = parse("01:00:00;00", 29.97).unwrap();
+        assert!((hour - 3600.0).abs() < 0.01, "{}", hour);
+        let hour = parse("01:00:00;00", 59.94).unwrap();
+        assert!((hour - 3600.0).abs() < 0.01, "{}", hour);
+        assert!(parse("00:01:00;03", 59.94).is_err());
+
+        // Non-drop frame counts the frame numbers as they are
+        assert!((parse("01:00:00:00", 29.97).unwrap() - 3600.0).abs() < 1e-9);
+        assert!(parse("00:00:01;00", 25.0).is_err());
+    }
+
+    #[test]
+    fn test_epoch_to_relative_secs() {
+        let start = 1_700_000_000.0;
+        assert_eq!(epoch_to_relative_secs(1_700_000_090.5, start), 90.5);
+        assert_eq!(epoch_to_relative_secs(start, start), 0.0);
+        assert_eq!(epoch_to_relative_secs(1_699_999_990.0, start), -10.0);
+    }
+
+    #[test]
+    fn test_seconds_to_timestamp() {
+        assert_eq!(seconds_to_timestamp(0.0), "00:00:00.000");
+        assert_eq!(seconds_to_timestamp(90.5), "00:01:30.500");
+        assert_eq!(seconds_to_timestamp(3725.0004), "01:02:05.000");
+        assert_eq!(seconds_to_timestamp(59.9996), "00:01:00.000");
+        assert_eq!(seconds_to_timestamp(-3.0), "00:00:00.000");
+        assert_eq!(
+            parse_timestamp_to_seconds_raw(&seconds_to_timestamp(4321.123)).unwrap(),
+            4321.123
+        );
+    }
+
+    #[test]
+    fn test_seconds_to_timestamp_string_precision() {
+        use TimestampPrecision::*;
+        assert_eq!(seconds_to_timestamp_string(3725.456, Seconds), "01:02:05");
+        assert_eq!(seconds_to_timestamp_string(3725.5, Seconds), "01:02:06");
+        assert_eq!(
+            seconds_to_timestamp_string(3725.456, Centiseconds),
+            "01:02:05.46"
+        );
+        assert_eq!(
+            seconds_to_timestamp_string(3725.456, Milliseconds),
+            "01:02:05.456"
+        );
+        assert_eq!(
+            seconds_to_timestamp_string(59.999, Centiseconds),
+            "00:01:00.00"
+        );
+        assert_eq!(
+            seconds_to_timestamp_string(0.05, Centiseconds),
+            "00:00:00.05"
+        );
+        assert_eq!(
+            seconds_to_timestamp_string(86400.0, Milliseconds),
+            "24:00:00.000"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_timestamp_round_trip(x in 0.0f64..=86400.0) {
+            let timestamp = seconds_to_timestamp_string(x, TimestampPrecision::Milliseconds);
+            let parsed = parse_timestamp_to_seconds_raw(&timestamp).unwrap();
+            // Rounding to milliseconds loses at most half a millisecond
+            prop_assert!((parsed - x).abs() <= 0.0005 + 1e-9, "{} -> {} -> {}", x, timestamp, parsed);
+        }
+    }
 
     #[test]
     fn test_parse_normal() {
@@ -143,6 +422,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_decimal_comma() {
+        assert_eq!(parse_timestamp_to_seconds_raw("00:01:23,456").unwrap(), 83.456);
+        assert_eq!(parse_timestamp_to_seconds_raw("01:23,456").unwrap(), 83.456);
+        assert_eq!(parse_timestamp_to_seconds_raw("00:00:10,5").unwrap(), 10.5);
+        assert!(parse_timestamp_to_seconds_raw("00:01:23.456,789").is_err());
+        assert!(parse_timestamp_to_seconds_raw("00:01,23.456").is_err());
+    }
+
     #[test]
     fn test_parse_overflow_fix() {
         // 60 seconds -> 1 minute
@@ -0,0 +1,1226 @@
+use crate::time_utils::{
+    epoch_to_relative_secs, parse_timestamp_to_seconds_raw, parse_timestamp_with_reference,
+    seconds_to_timestamp, seconds_to_timestamp_string, TimeReference, TimeReferenceKind,
+    TimestampPrecision,
+};
+use crate::gemini::{extract_json_array, BilingualSegment};
+use crate::video::TranscriptSegment;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// Suffix appended to the media path for auto-saved, unfinished analyses.
+const PARTIAL_TRANSCRIPT_SUFFIX: &str = ".transcript.partial.json";
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TimingFixReport {
+    /// Index of the trimmed segment in the repaired (sorted) transcript.
+    pub segment_index: usize,
+    pub original_end: String,
+    pub corrected_end: String,
+}
+
+/// A start time shared by this many segments is treated as made up by the model.
+const REPEATED_TIMESTAMP_LIMIT: usize = 3;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HallucinationReport {
+    /// Index of the segment in the transcript as returned by the model.
+    pub index: usize,
+    /// "start" or "end".
+    pub field: String,
+    pub value: String,
+    pub reason: String,
+}
+
+/// Segment with model-generated `start` and `end` timestamps, checked by
+/// `detect_hallucinated_timestamps`.
+pub trait TimedSegment: Clone {
+    fn start(&self) -> &str;
+    fn end(&self) -> &str;
+    fn set_end(&mut self, end: String);
+}
+
+impl TimedSegment for TranscriptSegment {
+    fn start(&self) -> &str {
+        &self.start
+    }
+    fn end(&self) -> &str {
+        &self.end
+    }
+    fn set_end(&mut self, end: String) {
+        self.end = end;
+    }
+}
+
+impl TimedSegment for BilingualSegment {
+    fn start(&self) -> &str {
+        &self.start
+    }
+    fn end(&self) -> &str {
+        &self.end
+    }
+    fn set_end(&mut self, end: String) {
+        self.end = end;
+    }
+}
+
+/// Typical narration speed used to estimate voice-over reading time.
+pub(crate) const VOICE_OVER_WPM: f64 = 150.0;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SpeakerStats {
+    pub word_count: u32,
+    pub speaking_time_secs: f64,
+    /// Share of the total speaking time, 0.0-1.0.
+    pub talk_ratio: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TranscriptStats {
+    pub total_words: u32,
+    pub total_chars: u32,
+    /// Based on the summed segment durations, so pauses between segments don't count.
+    pub words_per_minute: f64,
+    pub speaker_stats: HashMap<String, SpeakerStats>,
+    /// Time needed to read the text aloud at `VOICE_OVER_WPM`.
+    pub estimated_reading_time_secs: f64,
+}
+
+/// Time not covered by any transcript segment, e.g. a music interlude.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GapInfo {
+    pub start_secs: f64,
+    pub end_secs: f64,
+    pub duration_secs: f64,
+}
+
+/// Time covered by two segments at once.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OverlapInfo {
+    /// Indices of the overlapping segments in the transcript as given.
+    pub first_index: usize,
+    pub second_index: usize,
+    pub start_secs: f64,
+    pub end_secs: f64,
+    pub duration_secs: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TranscriptCoverageReport {
+    /// Share of the media duration covered by at least one segment, 0.0-1.0.
+    pub coverage_fraction: f64,
+    pub gaps: Vec<GapInfo>,
+    pub overlaps: Vec<OverlapInfo>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PartialTranscript {
+    pub source_path: String,
+    pub transcript_path: String,
+    /// Seconds since the Unix epoch.
+    pub last_modified: u64,
+}
+
+/// Periodically writes the response of a running analysis next to the media
+/// file, so it can be recovered if the app crashes before the analysis ends.
+pub struct PartialTranscriptSaver {
+    source_path: PathBuf,
+    path: PathBuf,
+    interval: Duration,
+    last_save: Option<Instant>,
+}
+
+impl PartialTranscriptSaver {
+    pub fn new(source_path: &Path, interval_secs: u64) -> Self {
+        Self {
+            source_path: source_path.to_path_buf(),
+            path: partial_transcript_path(source_path),
+            interval: Duration::from_secs(interval_secs),
+            last_save: None,
+        }
+    }
+
+    /// Saves `text` if the interval has passed since the last save.
+    pub fn update(&mut self, text: &str) {
+        let due = self
+            .last_save
+            .is_none_or(|last| last.elapsed() >= self.interval);
+        if due {
+            if let Err(e) = self.save(text) {
+                warn!("Failed to auto-save partial transcript {:?}: {}", self.path, e);
+            }
+            self.last_save = Some(Instant::now());
+        }
+    }
+
+    fn save(&self, text: &str) -> Result<()> {
+        let content = json!({
+            "source_path": self.source_path,
+            "saved_at": chrono::Local::now().to_rfc3339(),
+            "partial_response": text,
+        });
+        std::fs::write(&self.path, serde_json::to_string_pretty(&content)?)?;
+        Ok(())
+    }
+
+    /// Removes the partial file once the analysis completed.
+    pub fn finish(self) {
+        if self.path.exists() {
+            if let Err(e) = std::fs::remove_file(&self.path) {
+                warn!("Failed to remove partial transcript {:?}: {}", self.path, e);
+            }
+        }
+    }
+}
+
+pub fn partial_transcript_path(source_path: &Path) -> PathBuf {
+    let mut name = source_path.as_os_str().to_owned();
+    name.push(PARTIAL_TRANSCRIPT_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Lists auto-saved transcripts of analyses that never finished, newest first.
+#[tauri::command]
+pub async fn find_partial_transcripts(dir: String) -> Result<Vec<PartialTranscript>, String> {
+    scan_partial_transcripts(Path::new(&dir)).map_err(|e| e.to_string())
+}
+
+fn scan_partial_transcripts(dir: &Path) -> Result<Vec<PartialTranscript>> {
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let Some(source_name) = name.strip_suffix(PARTIAL_TRANSCRIPT_SUFFIX) else {
+            continue;
+        };
+        if !path.is_file() || source_name.is_empty() {
+            continue;
+        }
+
+        let last_modified = std::fs::metadata(&path)?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        found.push(PartialTranscript {
+            source_path: dir.join(source_name).to_string_lossy().to_string(),
+            transcript_path: path.to_string_lossy().to_string(),
+            last_modified,
+        });
+    }
+
+    found.sort_by_key(|p| std::cmp::Reverse(p.last_modified));
+    info!("Found {} partial transcripts in {:?}", found.len(), dir);
+    Ok(found)
+}
+
+#[tauri::command]
+pub fn rename_speakers(
+    segments: Vec<TranscriptSegment>,
+    mapping: HashMap<String, String>,
+) -> Vec<TranscriptSegment> {
+    segments
+        .into_iter()
+        .map(|mut s| {
+            if let Some(new_name) = mapping.get(&s.speaker) {
+                s.speaker = new_name.clone();
+            }
+            s
+        })
+        .collect()
+}
+
+/// Converts segments timed with UTC epoch seconds (as written by some broadcast
+/// loggers) to positions in the recording, which started at `start_epoch_secs`.
+/// Timestamps that aren't epoch seconds are left as they are, times before the
+/// start are clamped to zero.
+#[tauri::command]
+pub fn convert_utc_timestamps(
+    segments: Vec<TranscriptSegment>,
+    start_epoch_secs: f64,
+) -> Vec<TranscriptSegment> {
+    let reference = TimeReference {
+        kind: TimeReferenceKind::UtcEpoch,
+        utc_offset_secs: None,
+    };
+    let convert = |ts: &mut String| match parse_timestamp_with_reference(ts, &reference) {
+        Ok(epoch) => *ts = seconds_to_timestamp(epoch_to_relative_secs(epoch, start_epoch_secs)),
+        Err(e) => warn!("Keeping timestamp {:?}: {}", ts, e),
+    };
+    segments
+        .into_iter()
+        .map(|mut s| {
+            convert(&mut s.start);
+            convert(&mut s.end);
+            s
+        })
+        .collect()
+}
+
+/// Moves every segment by `offset_secs` (negative moves them earlier), e.g. to
+/// fix subtitles of a recording that started before the video. Fails if a
+/// segment would start before zero.
+#[tauri::command]
+pub fn shift_transcript_timestamps(
+    segments: Vec<TranscriptSegment>,
+    offset_secs: f64,
+) -> Result<Vec<TranscriptSegment>, String> {
+    if !offset_secs.is_finite() {
+        return Err(format!("Invalid offset: {}", offset_secs));
+    }
+    let shift = |i: usize, ts: &str| -> Result<String, String> {
+        let secs = parse_timestamp_to_seconds_raw(ts)
+            .map_err(|e| format!("Segment {}: invalid timestamp {:?}: {}", i + 1, ts, e))?;
+        Ok(seconds_to_timestamp_string(secs + offset_secs, TimestampPrecision::Milliseconds))
+    };
+    segments
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut s)| {
+            let start = parse_timestamp_to_seconds_raw(&s.start)
+                .map_err(|e| format!("Segment {}: invalid start {:?}: {}", i + 1, s.start, e))?;
+            if start + offset_secs < 0.0 {
+                return Err(format!(
+                    "Segment {} would start at {:.3}s, before the beginning",
+                    i + 1,
+                    start + offset_secs
+                ));
+            }
+            s.start = shift(i, &s.start)?;
+            s.end = shift(i, &s.end)?;
+            Ok(s)
+        })
+        .collect()
+}
+
+/// Words shorter than this are too common to tell where a transcript is.
+const SYNC_MIN_WORD_LEN: usize = 3;
+
+/// Width of the offset histogram bins of `auto_detect_sync_offset`.
+const SYNC_BIN_SECS: f64 = 0.25;
+
+/// Estimates the offset to pass to `shift_transcript_timestamps` so that
+/// `segments` line up with `reference_segments`, e.g. captions from another
+/// source for the same recording. Words are spread evenly over their segment;
+/// every pair of equal words in both transcripts votes for the offset between
+/// them, and the mean offset of the most voted bin wins, which amounts to the
+/// peak of the cross-correlation of both word sequences.
+#[tauri::command]
+pub fn auto_detect_sync_offset(
+    segments: Vec<TranscriptSegment>,
+    reference_segments: Vec<TranscriptSegment>,
+) -> Result<f64, String> {
+    let words = timed_words(&segments).map_err(|e| e.to_string())?;
+    let reference = timed_words(&reference_segments).map_err(|e| e.to_string())?;
+
+    let mut reference_times: HashMap<&str, Vec<f64>> = HashMap::new();
+    for (word, time) in &reference {
+        reference_times.entry(word.as_str()).or_default().push(*time);
+    }
+
+    // Bin -> (votes, sum of offsets)
+    let mut bins: HashMap<i64, (u32, f64)> = HashMap::new();
+    for (word, time) in &words {
+        for reference_time in reference_times.get(word.as_str()).into_iter().flatten() {
+            let offset = reference_time - time;
+            let bin = bins.entry((offset / SYNC_BIN_SECS).round() as i64).or_default();
+            bin.0 += 1;
+            bin.1 += offset;
+        }
+    }
+
+    bins.into_iter()
+        .max_by(|(a_bin, (a_votes, _)), (b_bin, (b_votes, _))| {
+            // Ties go to the smaller shift
+            a_votes.cmp(b_votes).then(b_bin.abs().cmp(&a_bin.abs()))
+        })
+        .map(|(_, (votes, sum))| sum / votes as f64)
+        .ok_or_else(|| "The transcripts have no words in common".to_string())
+}
+
+/// Lowercase words of at least `SYNC_MIN_WORD_LEN` characters with the time
+/// they are (roughly) spoken at.
+fn timed_words(segments: &[TranscriptSegment]) -> Result<Vec<(String, f64)>> {
+    let mut timed = Vec::new();
+    for (i, s) in segments.iter().enumerate() {
+        let start = parse_timestamp_to_seconds_raw(&s.start)
+            .map_err(|e| anyhow!("Segment {}: invalid start {:?}: {}", i + 1, s.start, e))?;
+        let end = parse_timestamp_to_seconds_raw(&s.end)
+            .map_err(|e| anyhow!("Segment {}: invalid end {:?}: {}", i + 1, s.end, e))?;
+        let words: Vec<String> = s
+            .text
+            .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .map(|w| w.trim_matches('\'').to_lowercase())
+            .filter(|w| w.chars().count() >= SYNC_MIN_WORD_LEN)
+            .collect();
+        let step = (end - start).max(0.0) / words.len().max(1) as f64;
+        for (j, word) in words.into_iter().enumerate() {
+            timed.push((word, start + (j as f64 + 0.5) * step));
+        }
+    }
+    Ok(timed)
+}
+
+/// Normalizes generic speaker labels ("SPEAKER_00", "spk_0", "Speaker A", "S1", ...)
+/// to "Speaker 1", "Speaker 2", etc. Labels that don't look generic (e.g. real
+/// names) are left untouched.
+#[tauri::command]
+pub fn auto_normalize_speaker_labels(segments: Vec<TranscriptSegment>) -> Vec<TranscriptSegment> {
+    let parsed: Vec<Option<SpeakerIndex>> = segments
+        .iter()
+        .map(|s| parse_speaker_label(&s.speaker))
+        .collect();
+
+    // Diarization tools like pyannote count from zero
+    let zero_based = parsed
+        .iter()
+        .any(|p| matches!(p, Some(SpeakerIndex::Number(0))));
+
+    segments
+        .into_iter()
+        .zip(parsed)
+        .map(|(mut s, idx)| {
+            let number = match idx {
+                Some(SpeakerIndex::Number(n)) if zero_based => Some(n + 1),
+                Some(SpeakerIndex::Number(n)) => Some(n),
+                Some(SpeakerIndex::Letter(n)) => Some(n),
+                None => None,
+            };
+            if let Some(n) = number {
+                s.speaker = format!("Speaker {}", n);
+            }
+            s
+        })
+        .collect()
+}
+
+/// Word and timing statistics for voice-over budgets. Segments with invalid
+/// timestamps still count towards the word totals but not towards speaking time.
+#[tauri::command]
+pub fn transcript_statistics(segments: Vec<TranscriptSegment>) -> TranscriptStats {
+    let mut total_words = 0u32;
+    let mut total_chars = 0u32;
+    let mut total_secs = 0.0;
+    let mut speaker_stats: HashMap<String, SpeakerStats> = HashMap::new();
+
+    for s in &segments {
+        let words = s.text.split_whitespace().count() as u32;
+        let duration = match (
+            parse_timestamp_to_seconds_raw(&s.start),
+            parse_timestamp_to_seconds_raw(&s.end),
+        ) {
+            (Ok(start), Ok(end)) => (end - start).max(0.0),
+            _ => 0.0,
+        };
+
+        total_words += words;
+        total_chars += s.text.trim().chars().count() as u32;
+        total_secs += duration;
+
+        let stats = speaker_stats.entry(s.speaker.clone()).or_insert(SpeakerStats {
+            word_count: 0,
+            speaking_time_secs: 0.0,
+            talk_ratio: 0.0,
+        });
+        stats.word_count += words;
+        stats.speaking_time_secs += duration;
+    }
+
+    for stats in speaker_stats.values_mut() {
+        if total_secs > 0.0 {
+            stats.talk_ratio = stats.speaking_time_secs / total_secs;
+        }
+    }
+
+    TranscriptStats {
+        total_words,
+        total_chars,
+        words_per_minute: if total_secs > 0.0 {
+            total_words as f64 / (total_secs / 60.0)
+        } else {
+            0.0
+        },
+        speaker_stats,
+        estimated_reading_time_secs: total_words as f64 / VOICE_OVER_WPM * 60.0,
+    }
+}
+
+/// Gaps and overlaps of the segments within `0..total_duration` seconds.
+/// Segments with invalid or reversed timestamps are ignored.
+#[tauri::command]
+pub fn analyze_transcript_coverage(
+    segments: Vec<TranscriptSegment>,
+    total_duration: f64,
+) -> TranscriptCoverageReport {
+    let (gaps, overlaps) = scan_transcript_coverage(&segments, total_duration);
+    let uncovered: f64 = gaps.iter().map(|g| g.duration_secs).sum();
+    TranscriptCoverageReport {
+        coverage_fraction: if total_duration > 0.0 {
+            ((total_duration - uncovered) / total_duration).clamp(0.0, 1.0)
+        } else {
+            0.0
+        },
+        gaps,
+        overlaps,
+    }
+}
+
+/// Stretches of `0..total_duration_secs` no segment covers, in order.
+pub fn find_transcript_gaps(
+    segments: &[TranscriptSegment],
+    total_duration_secs: f64,
+) -> Vec<GapInfo> {
+    scan_transcript_coverage(segments, total_duration_secs).0
+}
+
+/// Sweeps over the segments sorted by start, clamped to the media duration.
+/// Overlaps are reported against the segment reaching furthest so far.
+fn scan_transcript_coverage(
+    segments: &[TranscriptSegment],
+    total_duration_secs: f64,
+) -> (Vec<GapInfo>, Vec<OverlapInfo>) {
+    let total = total_duration_secs.max(0.0);
+    let mut intervals: Vec<(usize, f64, f64)> = segments
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| {
+            let start = parse_timestamp_to_seconds_raw(&s.start).ok()?.clamp(0.0, total);
+            let end = parse_timestamp_to_seconds_raw(&s.end).ok()?.clamp(0.0, total);
+            (end > start).then_some((i, start, end))
+        })
+        .collect();
+    intervals.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let mut gaps = Vec::new();
+    let mut overlaps = Vec::new();
+    let mut covered_until = 0.0;
+    let mut furthest: Option<usize> = None;
+    for (i, start, end) in intervals {
+        if start > covered_until {
+            gaps.push(GapInfo {
+                start_secs: covered_until,
+                end_secs: start,
+                duration_secs: start - covered_until,
+            });
+        } else if let Some(previous) = furthest.filter(|_| start < covered_until) {
+            let overlap_end = end.min(covered_until);
+            overlaps.push(OverlapInfo {
+                first_index: previous,
+                second_index: i,
+                start_secs: start,
+                end_secs: overlap_end,
+                duration_secs: overlap_end - start,
+            });
+        }
+        if end > covered_until {
+            covered_until = end;
+            furthest = Some(i);
+        }
+    }
+    if total > covered_until {
+        gaps.push(GapInfo {
+            start_secs: covered_until,
+            end_secs: total,
+            duration_secs: total - covered_until,
+        });
+    }
+    (gaps, overlaps)
+}
+
+#[tauri::command]
+pub fn repair_transcript_timing(
+    mut segments: Vec<TranscriptSegment>,
+) -> Result<(Vec<TranscriptSegment>, Vec<TimingFixReport>), String> {
+    let reports = fix_srt_timing(&mut segments).map_err(|e| e.to_string())?;
+    Ok((segments, reports))
+}
+
+/// Sorts segments by start time and trims overlaps so that each segment ends no
+/// later than the next one starts. Cue numbers are derived from the position in
+/// the list on export, so sorting also renumbers the cues from 1.
+pub fn fix_srt_timing(segments: &mut [TranscriptSegment]) -> Result<Vec<TimingFixReport>> {
+    let mut keyed = Vec::with_capacity(segments.len());
+    for (i, s) in segments.iter().enumerate() {
+        let start = parse_timestamp_to_seconds_raw(&s.start)
+            .map_err(|e| anyhow!("Segment {}: invalid start {:?}: {}", i + 1, s.start, e))?;
+        let end = parse_timestamp_to_seconds_raw(&s.end)
+            .map_err(|e| anyhow!("Segment {}: invalid end {:?}: {}", i + 1, s.end, e))?;
+        keyed.push((start, end));
+    }
+
+    // Stable sort keeps the original order of cues that start at the same time
+    let mut order: Vec<usize> = (0..segments.len()).collect();
+    order.sort_by(|&a, &b| keyed[a].0.total_cmp(&keyed[b].0));
+    let sorted: Vec<TranscriptSegment> = order.iter().map(|&i| segments[i].clone()).collect();
+    let keyed: Vec<(f64, f64)> = order.iter().map(|&i| keyed[i]).collect();
+    segments.clone_from_slice(&sorted);
+
+    let mut reports = Vec::new();
+    for i in 0..segments.len().saturating_sub(1) {
+        let next_start = keyed[i + 1].0;
+        if keyed[i].1 > next_start {
+            let corrected_end = segments[i + 1].start.clone();
+            reports.push(TimingFixReport {
+                segment_index: i,
+                original_end: segments[i].end.clone(),
+                corrected_end: corrected_end.clone(),
+            });
+            segments[i].end = corrected_end;
+        }
+    }
+
+    Ok(reports)
+}
+
+/// Finds timestamps the model can't have taken from the audio. Segments with
+/// invalid, negative, reversed or out-of-range times are dropped, as are repeats
+/// of a start time used by `REPEATED_TIMESTAMP_LIMIT` or more segments (the first
+/// one is kept). An end slightly past the audio is clamped to the duration.
+pub fn detect_hallucinated_timestamps<S: TimedSegment>(
+    segments: Vec<S>,
+    actual_duration_secs: f64,
+) -> (Vec<S>, Vec<HallucinationReport>) {
+    let mut reports = Vec::new();
+    let mut report = |index: usize, field: &str, value: &str, reason: String| {
+        reports.push(HallucinationReport {
+            index,
+            field: field.to_string(),
+            value: value.to_string(),
+            reason,
+        });
+    };
+
+    let mut start_counts: HashMap<&str, usize> = HashMap::new();
+    for s in &segments {
+        *start_counts.entry(s.start().trim()).or_default() += 1;
+    }
+    let mut seen_starts: HashMap<&str, usize> = HashMap::new();
+
+    let mut kept = Vec::with_capacity(segments.len());
+    for (i, mut segment) in segments.iter().cloned().enumerate() {
+        let start = checked_seconds(segment.start())
+            .map_err(|reason| report(i, "start", segment.start(), reason));
+        let end = checked_seconds(segment.end())
+            .map_err(|reason| report(i, "end", segment.end(), reason));
+        let (Ok(start), Ok(end)) = (start, end) else {
+            continue;
+        };
+
+        if start > end {
+            report(i, "start", segment.start(), format!("start is after end {}", segment.end()));
+            continue;
+        }
+        if start >= actual_duration_secs {
+            report(
+                i,
+                "start",
+                segment.start(),
+                format!("start is beyond the audio duration of {:.1}s", actual_duration_secs),
+            );
+            continue;
+        }
+
+        let start_key = segments[i].start().trim();
+        let repeats = seen_starts.entry(start_key).or_default();
+        *repeats += 1;
+        if start_counts[start_key] >= REPEATED_TIMESTAMP_LIMIT && *repeats > 1 {
+            report(
+                i,
+                "start",
+                segment.start(),
+                format!("start time is shared by {} segments", start_counts[start_key]),
+            );
+            continue;
+        }
+
+        if end > actual_duration_secs {
+            let clamped = seconds_to_timestamp(actual_duration_secs);
+            report(
+                i,
+                "end",
+                segment.end(),
+                format!("end is beyond the audio duration, clamped to {}", clamped),
+            );
+            segment.set_end(clamped);
+        }
+        kept.push(segment);
+    }
+
+    (kept, reports)
+}
+
+/// Why a transcript from an external source was rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    /// Not a JSON array of objects.
+    Json(String),
+    /// `index` is the zero-based position of the segment in the array.
+    Field {
+        index: usize,
+        field: &'static str,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(message) => write!(f, "Invalid transcript JSON: {}", message),
+            Self::Field {
+                index,
+                field,
+                message,
+            } => write!(f, "Segment {}: '{}' {}", index + 1, field, message),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Parses a JSON array of transcript segments, requiring `start`, `end`,
+/// `speaker` and `text` as strings, valid timestamps and a non-empty text,
+/// instead of accepting incomplete segments.
+pub fn validate_transcript_segments(
+    json: &str,
+) -> std::result::Result<Vec<TranscriptSegment>, ValidationError> {
+    let raw: Vec<Value> =
+        serde_json::from_str(json).map_err(|e| ValidationError::Json(e.to_string()))?;
+
+    raw.iter()
+        .enumerate()
+        .map(|(index, segment)| {
+            let invalid = |field: &'static str, message: String| ValidationError::Field {
+                index,
+                field,
+                message,
+            };
+            let object = segment.as_object().ok_or_else(|| {
+                ValidationError::Json(format!("segment {} is not an object", index + 1))
+            })?;
+            let field = |name: &'static str| match object.get(name) {
+                Some(Value::String(value)) => Ok(value.clone()),
+                Some(_) => Err(invalid(name, "is not a string".to_string())),
+                None => Err(invalid(name, "is missing".to_string())),
+            };
+
+            let start = field("start")?;
+            checked_seconds(&start).map_err(|e| invalid("start", e))?;
+            let end = field("end")?;
+            checked_seconds(&end).map_err(|e| invalid("end", e))?;
+            let speaker = field("speaker")?;
+            let text = field("text")?;
+            if text.trim().is_empty() {
+                return Err(invalid("text", "is empty".to_string()));
+            }
+            Ok(TranscriptSegment {
+                start,
+                end,
+                speaker,
+                text,
+            })
+        })
+        .collect()
+}
+
+/// Validates a transcript read from a file or model response, see
+/// `validate_transcript_segments`.
+#[tauri::command]
+pub fn validate_transcript(json: String) -> Result<Vec<TranscriptSegment>, String> {
+    validate_transcript_segments(&json).map_err(|e| e.to_string())
+}
+
+fn checked_seconds(value: &str) -> std::result::Result<f64, String> {
+    let secs = parse_timestamp_to_seconds_raw(value)
+        .map_err(|e| format!("invalid timestamp: {}", e))?;
+    // "-00:05" parses as 5 seconds, so check the sign on the text as well
+    if secs < 0.0 || value.trim_start().starts_with('-') {
+        return Err("negative timestamp".to_string());
+    }
+    Ok(secs)
+}
+
+/// Runs `detect_hallucinated_timestamps` and logs what was dropped or clamped.
+pub fn remove_hallucinated_segments<S: TimedSegment>(
+    segments: Vec<S>,
+    actual_duration_secs: f64,
+) -> Vec<S> {
+    let (segments, reports) = detect_hallucinated_timestamps(segments, actual_duration_secs);
+    for r in &reports {
+        warn!("Segment {}: {} {:?}: {}", r.index + 1, r.field, r.value, r.reason);
+    }
+    segments
+}
+
+/// Runs `detect_hallucinated_timestamps` on the JSON array in a model response
+/// (see `extract_json_array`) and returns the cleaned array without the text
+/// around it. Responses that don't contain a transcript array are returned
+/// unchanged.
+pub fn remove_hallucinated_timestamps(response: &str, actual_duration_secs: f64) -> String {
+    let array = match extract_json_array(response) {
+        Ok(array) => array,
+        Err(e) => {
+            warn!("No transcript array in response, skipping hallucination check: {}", e);
+            return response.to_string();
+        }
+    };
+    let segments: Vec<TranscriptSegment> = match serde_json::from_str(&array) {
+        Ok(segments) => segments,
+        Err(e) => {
+            warn!("Could not parse transcript for hallucination check: {}", e);
+            return response.to_string();
+        }
+    };
+
+    let segments = remove_hallucinated_segments(segments, actual_duration_secs);
+    serde_json::to_string_pretty(&segments).unwrap_or_else(|_| response.to_string())
+}
+
+enum SpeakerIndex {
+    Number(u32),
+    /// A = 1, B = 2, ...
+    Letter(u32),
+}
+
+fn parse_speaker_label(label: &str) -> Option<SpeakerIndex> {
+    let re = Regex::new(r"(?i)^(?:speaker|spk|spkr|s)[\s_\-]*(\d+|[a-z])$").unwrap();
+    let caps = re.captures(label.trim())?;
+    let idx = &caps[1];
+
+    if let Ok(n) = idx.parse::<u32>() {
+        return Some(SpeakerIndex::Number(n));
+    }
+    let c = idx.chars().next()?.to_ascii_uppercase();
+    Some(SpeakerIndex::Letter(c as u32 - 'A' as u32 + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seg(speaker: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            start: "00:00".to_string(),
+            end: "00:05".to_string(),
+            speaker: speaker.to_string(),
+            text: "Hello".to_string(),
+        }
+    }
+
+    fn speakers(segments: &[TranscriptSegment]) -> Vec<&str> {
+        segments.iter().map(|s| s.speaker.as_str()).collect()
+    }
+
+    fn timed(start: &str, end: &str, text: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            start: start.to_string(),
+            end: end.to_string(),
+            speaker: "Speaker 1".to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_fix_srt_timing_overlap() {
+        let mut segments = vec![
+            timed("00:00:01.000", "00:00:05.500", "first"),
+            timed("00:00:05.000", "00:00:08.000", "second"),
+            timed("00:00:08.000", "00:00:10.000", "third"),
+        ];
+
+        let reports = fix_srt_timing(&mut segments).unwrap();
+
+        assert_eq!(
+            reports,
+            vec![TimingFixReport {
+                segment_index: 0,
+                original_end: "00:00:05.500".to_string(),
+                corrected_end: "00:00:05.000".to_string(),
+            }]
+        );
+        assert_eq!(segments[0].end, "00:00:05.000");
+        // Touching segments are not overlaps
+        assert_eq!(segments[1].end, "00:00:08.000");
+    }
+
+    #[test]
+    fn test_fix_srt_timing_out_of_order() {
+        let mut segments = vec![
+            timed("00:10", "00:12", "third"),
+            timed("00:00", "00:04", "first"),
+            timed("00:03", "00:09", "second"),
+        ];
+
+        let reports = fix_srt_timing(&mut segments).unwrap();
+
+        let texts: Vec<&str> = segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, vec!["first", "second", "third"]);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].segment_index, 0);
+        assert_eq!(segments[0].end, "00:03");
+    }
+
+    #[test]
+    fn test_repair_transcript_timing_invalid() {
+        assert!(repair_transcript_timing(vec![timed("abc", "00:01", "bad")]).is_err());
+    }
+
+    fn spoken(speaker: &str, start: &str, end: &str, text: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            speaker: speaker.to_string(),
+            ..timed(start, end, text)
+        }
+    }
+
+    #[test]
+    fn test_transcript_statistics() {
+        // 60 seconds of speech: Alice 40s, Bob 20s, with a pause in between
+        let segments = vec![
+            spoken("Alice", "00:00", "00:30", &"word ".repeat(75)),
+            spoken("Bob", "00:35", "00:55", &"word ".repeat(40)),
+            spoken("Alice", "00:55", "01:05", &"word ".repeat(35)),
+        ];
+
+        let stats = transcript_statistics(segments);
+        assert_eq!(stats.total_words, 150);
+        assert_eq!(stats.total_chars, 3 * 4 + 147 * 5);
+        assert!((stats.words_per_minute - 150.0).abs() < 1e-9);
+        assert!((stats.estimated_reading_time_secs - 60.0).abs() < 1e-9);
+
+        let alice = &stats.speaker_stats["Alice"];
+        assert_eq!(alice.word_count, 110);
+        assert!((alice.speaking_time_secs - 40.0).abs() < 1e-9);
+        assert!((alice.talk_ratio - 2.0 / 3.0).abs() < 1e-9);
+
+        let bob = &stats.speaker_stats["Bob"];
+        assert_eq!(bob.word_count, 40);
+        assert!((bob.talk_ratio - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_transcript_coverage() {
+        let segments = vec![
+            spoken("Alice", "00:25", "00:40", "after the intro music"),
+            spoken("Alice", "00:00", "00:10", "hello"),
+            spoken("Bob", "00:08", "00:20", "hi"),
+            spoken("Bob", "00:45", "00:50", "bye"),
+            spoken("Bob", "abc", "00:50", "invalid"),
+        ];
+
+        let gaps = find_transcript_gaps(&segments, 60.0);
+        let gap_secs: Vec<_> = gaps.iter().map(|g| (g.start_secs, g.end_secs)).collect();
+        assert_eq!(gap_secs, [(20.0, 25.0), (40.0, 45.0), (50.0, 60.0)]);
+        assert_eq!(gaps.iter().map(|g| g.duration_secs).sum::<f64>(), 20.0);
+
+        let report = analyze_transcript_coverage(segments, 60.0);
+        assert!((report.coverage_fraction - 40.0 / 60.0).abs() < 1e-9);
+        assert_eq!(report.gaps, gaps);
+        assert_eq!(
+            report.overlaps,
+            [OverlapInfo {
+                first_index: 1,
+                second_index: 2,
+                start_secs: 8.0,
+                end_secs: 10.0,
+                duration_secs: 2.0,
+            }]
+        );
+
+        // Segments running past the end are cut off at the media duration
+        let report =
+            analyze_transcript_coverage(vec![spoken("Alice", "00:05", "01:30", "long")], 60.0);
+        assert_eq!(report.gaps.len(), 1);
+        assert_eq!(report.gaps[0].duration_secs, 5.0);
+        assert!((report.coverage_fraction - 55.0 / 60.0).abs() < 1e-9);
+
+        assert_eq!(analyze_transcript_coverage(vec![], 0.0).coverage_fraction, 0.0);
+    }
+
+    #[test]
+    fn test_transcript_statistics_invalid_times() {
+        let stats = transcript_statistics(vec![spoken("Alice", "abc", "00:05", "one two")]);
+        assert_eq!(stats.total_words, 2);
+        assert_eq!(stats.words_per_minute, 0.0);
+        assert_eq!(stats.speaker_stats["Alice"].talk_ratio, 0.0);
+    }
+
+    #[test]
+    fn test_partial_transcript_saved_and_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("interview.mp4");
+
+        let mut saver = PartialTranscriptSaver::new(&source, 3600);
+        saver.update("[{\"start\": \"00:00\"");
+        // Within the interval, nothing is written
+        saver.update("[{\"start\": \"00:00\", \"end\"");
+
+        let partial = dir.path().join("interview.mp4.transcript.partial.json");
+        let content: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&partial).unwrap()).unwrap();
+        assert_eq!(content["partial_response"], "[{\"start\": \"00:00\"");
+
+        std::fs::write(dir.path().join("notes.json"), "{}").unwrap();
+        let found = scan_partial_transcripts(dir.path()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].source_path, source.to_string_lossy());
+        assert_eq!(found[0].transcript_path, partial.to_string_lossy());
+        assert!(found[0].last_modified > 0);
+
+        saver.finish();
+        assert!(!partial.exists());
+        assert!(scan_partial_transcripts(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rename_speakers() {
+        let mut mapping = HashMap::new();
+        mapping.insert("Speaker 1".to_string(), "Alice".to_string());
+
+        let renamed = rename_speakers(vec![seg("Speaker 1"), seg("Speaker 2")], mapping);
+        assert_eq!(speakers(&renamed), vec!["Alice", "Speaker 2"]);
+    }
+
+    #[test]
+    fn test_convert_utc_timestamps() {
+        let converted = convert_utc_timestamps(
+            vec![
+                timed("1700000000", "1700000004.5", "first"),
+                timed("1700000065.25", "1700003700", "second"),
+                // Already relative, and before the recording started
+                timed("00:00:10", "1699999990", "third"),
+            ],
+            1_700_000_000.0,
+        );
+        let times: Vec<(&str, &str)> =
+            converted.iter().map(|s| (s.start.as_str(), s.end.as_str())).collect();
+        assert_eq!(
+            times,
+            vec![
+                ("00:00:00.000", "00:00:04.500"),
+                ("00:01:05.250", "01:01:40.000"),
+                ("00:00:10", "00:00:00.000"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shift_transcript_timestamps() {
+        let segments = vec![
+            timed("00:00:02.500", "00:00:04", "first"),
+            timed("00:01:59.750", "00:02:01.250", "second"),
+        ];
+
+        let shifted = shift_transcript_timestamps(segments.clone(), 1.5).unwrap();
+        let times: Vec<(&str, &str)> =
+            shifted.iter().map(|s| (s.start.as_str(), s.end.as_str())).collect();
+        assert_eq!(
+            times,
+            vec![("00:00:04.000", "00:00:05.500"), ("00:02:01.250", "00:02:02.750")]
+        );
+
+        let shifted = shift_transcript_timestamps(segments.clone(), -2.5).unwrap();
+        assert_eq!(shifted[0].start, "00:00:00.000");
+        assert_eq!(shifted[1].end, "00:01:58.750");
+
+        let err = shift_transcript_timestamps(segments, -3.0).unwrap_err();
+        assert!(err.contains("Segment 1"), "{}", err);
+        assert!(shift_transcript_timestamps(vec![timed("x", "00:01", "bad")], 1.0).is_err());
+    }
+
+    #[test]
+    fn test_auto_detect_sync_offset() {
+        let reference = vec![
+            timed("00:00:10", "00:00:14", "Welcome back to the show everybody"),
+            timed("00:00:14", "00:00:18", "today we are talking about editing podcasts"),
+            timed("00:00:18", "00:00:22", "and why silence removal matters so much"),
+        ];
+        // The same captions, 2.5 seconds late
+        let late: Vec<TranscriptSegment> = reference
+            .iter()
+            .map(|s| {
+                let shift = |ts: &str| {
+                    seconds_to_timestamp(parse_timestamp_to_seconds_raw(ts).unwrap() + 2.5)
+                };
+                timed(&shift(&s.start), &shift(&s.end), &s.text)
+            })
+            .collect();
+
+        let offset = auto_detect_sync_offset(late, reference.clone()).unwrap();
+        assert!((offset + 2.5).abs() < 1e-9, "{}", offset);
+
+        let err =
+            auto_detect_sync_offset(vec![timed("00:00", "00:02", "nothing alike")], reference);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_auto_normalize_zero_based() {
+        let normalized =
+            auto_normalize_speaker_labels(vec![seg("SPEAKER_00"), seg("SPEAKER_01")]);
+        assert_eq!(speakers(&normalized), vec!["Speaker 1", "Speaker 2"]);
+
+        let normalized = auto_normalize_speaker_labels(vec![seg("spk_0"), seg("spk_1")]);
+        assert_eq!(speakers(&normalized), vec!["Speaker 1", "Speaker 2"]);
+    }
+
+    #[test]
+    fn test_auto_normalize_one_based_and_letters() {
+        let normalized = auto_normalize_speaker_labels(vec![seg("speaker 1"), seg("Speaker-2")]);
+        assert_eq!(speakers(&normalized), vec!["Speaker 1", "Speaker 2"]);
+
+        let normalized = auto_normalize_speaker_labels(vec![seg("Speaker A"), seg("Speaker B")]);
+        assert_eq!(speakers(&normalized), vec!["Speaker 1", "Speaker 2"]);
+
+        let normalized = auto_normalize_speaker_labels(vec![seg("S1"), seg("S2")]);
+        assert_eq!(speakers(&normalized), vec!["Speaker 1", "Speaker 2"]);
+    }
+
+    #[test]
+    fn test_auto_normalize_keeps_names() {
+        let normalized = auto_normalize_speaker_labels(vec![seg("Alice"), seg("Sam")]);
+        assert_eq!(speakers(&normalized), vec!["Alice", "Sam"]);
+    }
+
+    #[test]
+    fn test_hallucination_out_of_range() {
+        let (kept, reports) = detect_hallucinated_timestamps(
+            vec![
+                timed("00:10", "00:20", "ok"),
+                timed("00:50", "01:05", "too long"),
+                timed("02:00", "02:10", "made up"),
+            ],
+            60.0,
+        );
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[1].end, "00:01:00.000");
+        assert_eq!(
+            reports.iter().map(|r| (r.index, r.field.as_str(), r.value.as_str())).collect::<Vec<_>>(),
+            vec![(1, "end", "01:05"), (2, "start", "02:00")]
+        );
+    }
+
+    #[test]
+    fn test_hallucination_reversed_and_negative() {
+        let (kept, reports) = detect_hallucinated_timestamps(
+            vec![
+                timed("00:30", "00:20", "reversed"),
+                timed("-00:05", "00:02", "negative"),
+                timed("00:01", "xx", "invalid"),
+                timed("00:40", "00:45", "ok"),
+            ],
+            60.0,
+        );
+        assert_eq!(kept.iter().map(|s| s.text.as_str()).collect::<Vec<_>>(), vec!["ok"]);
+        assert_eq!(reports[0].reason, "start is after end 00:20");
+        assert_eq!((reports[1].index, reports[1].reason.as_str()), (1, "negative timestamp"));
+        assert_eq!((reports[2].index, reports[2].field.as_str()), (2, "end"));
+    }
+
+    #[test]
+    fn test_hallucination_repeated_timestamps() {
+        let (kept, reports) = detect_hallucinated_timestamps(
+            vec![
+                timed("00:00", "00:05", "a"),
+                timed("00:10", "00:15", "b"),
+                timed("00:00", "00:06", "c"),
+                timed("00:00", "00:07", "d"),
+            ],
+            60.0,
+        );
+        assert_eq!(kept.iter().map(|s| s.text.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(reports.iter().map(|r| r.index).collect::<Vec<_>>(), vec![2, 3]);
+
+        // Two segments sharing a start is normal
+        let (kept, reports) = detect_hallucinated_timestamps(
+            vec![timed("00:00", "00:05", "a"), timed("00:00", "00:03", "b")],
+            60.0,
+        );
+        assert_eq!(kept.len(), 2);
+        assert!(reports.is_empty());
+    }
+
+    #[test]
+    fn test_hallucination_bilingual_segments() {
+        let bilingual = |start: &str, end: &str| BilingualSegment {
+            start: start.to_string(),
+            end: end.to_string(),
+            speaker: "Speaker 1".to_string(),
+            text_primary: "Hello".to_string(),
+            text_secondary: "Hallo".to_string(),
+        };
+        let kept = remove_hallucinated_segments(
+            vec![bilingual("00:10", "01:05"), bilingual("02:00", "02:10")],
+            60.0,
+        );
+        assert_eq!(kept, vec![bilingual("00:10", "00:01:00.000")]);
+    }
+
+    #[test]
+    fn test_remove_hallucinated_timestamps_response() {
+        let response = r#"Here you go: [{"start": "00:01", "end": "00:02", "speaker": "A", "text": "hi"},
+            {"start": "05:00", "end": "05:02", "speaker": "A", "text": "bye"}]"#;
+        let cleaned = remove_hallucinated_timestamps(response, 60.0);
+        assert!(cleaned.starts_with('['), "{}", cleaned);
+        assert!(cleaned.contains("hi"));
+        assert!(!cleaned.contains("bye"));
+
+        // Trailing comma and a `]` in the prose after the array
+        let response = "```json\n[{\"start\": \"00:01\", \"end\": \"00:02\", \"speaker\": \"A\", \
+                        \"text\": \"hi\"},\n{\"start\": \"05:00\", \"end\": \"05:02\", \
+                        \"speaker\": \"A\", \"text\": \"bye\"},]\n```\nSee [1] for details.";
+        let cleaned: Vec<TranscriptSegment> =
+            serde_json::from_str(&remove_hallucinated_timestamps(response, 60.0)).unwrap();
+        assert_eq!(cleaned.len(), 1);
+        assert_eq!(cleaned[0].text, "hi");
+
+        assert_eq!(remove_hallucinated_timestamps("no json", 60.0), "no json");
+    }
+
+    #[test]
+    fn test_validate_transcript_segments() {
+        let segments = validate_transcript_segments(
+            r#"[{"start": "00:00", "end": "00:04.5", "speaker": "Speaker 1", "text": "Hi"}]"#,
+        )
+        .unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].end, "00:04.5");
+
+        let error = |json: &str| validate_transcript_segments(json).unwrap_err();
+        let field_error = |index, field| ValidationError::Field {
+            index,
+            field,
+            message: String::new(),
+        };
+        let without_message = |e: ValidationError| match e {
+            ValidationError::Field { index, field, .. } => field_error(index, field),
+            other => other,
+        };
+
+        let missing = error(
+            r#"[{"start": "00:00", "end": "00:01", "speaker": "A", "text": "ok"},
+                {"start": "00:01", "end": "00:02", "text": "no speaker"}]"#,
+        );
+        assert_eq!(missing.to_string(), "Segment 2: 'speaker' is missing");
+        assert_eq!(without_message(missing), field_error(1, "speaker"));
+
+        let not_string = error(r#"[{"start": 5, "end": "00:01", "speaker": "A", "text": "t"}]"#);
+        assert_eq!(without_message(not_string), field_error(0, "start"));
+
+        let timestamp =
+            error(r#"[{"start": "00:00", "end": "soon", "speaker": "A", "text": "t"}]"#);
+        assert_eq!(without_message(timestamp), field_error(0, "end"));
+
+        let empty = error(r#"[{"start": "00:00", "end": "00:01", "speaker": "A", "text": "  "}]"#);
+        assert_eq!(empty.to_string(), "Segment 1: 'text' is empty");
+
+        assert!(matches!(error(r#"{"segments": []}"#), ValidationError::Json(_)));
+        assert!(matches!(error("[1]"), ValidationError::Json(_)));
+    }
+}
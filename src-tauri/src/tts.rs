@@ -0,0 +1,295 @@
+use crate::gemini::build_api_url;
+use crate::time_utils::parse_timestamp_to_seconds_raw;
+use crate::video::TranscriptSegment;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use log::{debug, info, warn};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::io::Cursor;
+use std::path::Path;
+use symphonia::core::audio::AudioBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+const OPENAI_TTS_MODEL: &str = "tts-1";
+const GOOGLE_TTS_HOST: &str = "texttospeech.googleapis.com";
+const GOOGLE_TTS_URL: &str = "https://texttospeech.googleapis.com/v1/text:synthesize";
+/// Sample rate requested from Google; OpenAI always returns 24 kHz.
+const TTS_SAMPLE_RATE: u32 = 24000;
+
+/// Checks that every segment has text and a valid, positive time range, and
+/// returns the ranges in seconds.
+pub fn validate_segment_timing(segments: &[TranscriptSegment]) -> Result<Vec<(f64, f64)>> {
+    segments
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let start = parse_timestamp_to_seconds_raw(&s.start)
+                .map_err(|e| anyhow!("Segment {}: invalid start {:?}: {}", i + 1, s.start, e))?;
+            let end = parse_timestamp_to_seconds_raw(&s.end)
+                .map_err(|e| anyhow!("Segment {}: invalid end {:?}: {}", i + 1, s.end, e))?;
+            if end <= start {
+                return Err(anyhow!(
+                    "Segment {}: end {} is not after start {}",
+                    i + 1,
+                    s.end,
+                    s.start
+                ));
+            }
+            if s.text.trim().is_empty() {
+                return Err(anyhow!("Segment {}: no text to synthesize", i + 1));
+            }
+            Ok((start, end))
+        })
+        .collect()
+}
+
+/// Synthesizes every segment with the OpenAI (or Google Cloud, see
+/// `google_tts_url`) TTS API and writes a mono WAV file in which each segment
+/// starts at its transcript time. Speech that runs longer than its slot pushes
+/// the following segments back instead of overlapping them.
+pub async fn synthesize_speech_to_wav(
+    api_key: &str,
+    base_url: &str,
+    segments: &[TranscriptSegment],
+    voice: &str,
+    output_path: &Path,
+) -> Result<()> {
+    if segments.is_empty() {
+        return Err(anyhow!("No segments to synthesize"));
+    }
+    let timing = validate_segment_timing(segments)?;
+    let client = Client::new();
+
+    let mut mix: Vec<f32> = Vec::new();
+    let mut sample_rate = 0;
+    for (segment, (start, _)) in segments.iter().zip(timing) {
+        let wav = request_speech(&client, api_key, base_url, &segment.text, voice).await?;
+        let (samples, rate) = decode_wav(wav)?;
+        if sample_rate == 0 {
+            sample_rate = rate;
+        } else if rate != sample_rate {
+            return Err(anyhow!(
+                "TTS returned mixed sample rates ({} and {})",
+                sample_rate,
+                rate
+            ));
+        }
+
+        let mut offset = (start * sample_rate as f64).round() as usize;
+        if offset < mix.len() {
+            warn!(
+                "Speech for segment at {} runs {:.2}s into the next slot",
+                segment.start,
+                (mix.len() - offset) as f64 / sample_rate as f64
+            );
+            offset = mix.len();
+        }
+        mix.resize(offset, 0.0);
+        mix.extend(samples);
+    }
+
+    info!(
+        "Writing {:.1}s of synthesized speech to {:?}",
+        mix.len() as f64 / sample_rate as f64,
+        output_path
+    );
+    std::fs::write(output_path, encode_wav(&mix, sample_rate))?;
+    Ok(())
+}
+
+/// Google Cloud TTS endpoint for `base_url`, None for OpenAI-compatible APIs.
+/// Other googleapis.com hosts (e.g. the Gemini API the key was entered for)
+/// use the public Cloud TTS endpoint; proxies and other hosts are Google if
+/// their URL contains `texttospeech`, and get `v1/text:synthesize` appended.
+fn google_tts_url(base_url: &str) -> Option<String> {
+    if base_url.contains("googleapis.com") && !base_url.contains(GOOGLE_TTS_HOST) {
+        return Some(GOOGLE_TTS_URL.to_string());
+    }
+    base_url
+        .contains("texttospeech")
+        .then(|| build_api_url(base_url, "v1/text:synthesize"))
+}
+
+async fn request_speech(
+    client: &Client,
+    api_key: &str,
+    base_url: &str,
+    text: &str,
+    voice: &str,
+) -> Result<Vec<u8>> {
+    if let Some(url) = google_tts_url(base_url) {
+        // Voice names look like "en-US-Neural2-A"
+        let language_code = voice.splitn(3, '-').take(2).collect::<Vec<_>>().join("-");
+        let payload = json!({
+            "input": { "text": text },
+            "voice": { "languageCode": language_code, "name": voice },
+            "audioConfig": { "audioEncoding": "LINEAR16", "sampleRateHertz": TTS_SAMPLE_RATE }
+        });
+        let response = client
+            .post(format!("{}?key={}", url, api_key))
+            .json(&payload)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("TTS failed: {}", response.text().await?));
+        }
+        let res_json: Value = response.json().await?;
+        let content = res_json["audioContent"]
+            .as_str()
+            .ok_or_else(|| anyhow!("TTS response has no audioContent"))?;
+        Ok(general_purpose::STANDARD.decode(content)?)
+    } else {
+        let payload = json!({
+            "model": OPENAI_TTS_MODEL,
+            "input": text,
+            "voice": voice,
+            "response_format": "wav"
+        });
+        let response = client
+            .post(build_api_url(base_url, "v1/audio/speech"))
+            .header("Authorization", format!("Bearer {}", api_key))
+            .json(&payload)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("TTS failed: {}", response.text().await?));
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+/// Decodes a WAV file to mono samples.
+fn decode_wav(bytes: Vec<u8>) -> Result<(Vec<f32>, u32)> {
+    let mss = MediaSourceStream::new(Box::new(Cursor::new(bytes)), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension("wav");
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| anyhow!("no supported audio tracks"))?;
+    let track_id = track.id;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut samples = Vec::new();
+    let mut sample_rate = track.codec_params.sample_rate.unwrap_or(0);
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = decoder.decode(&packet)?;
+        sample_rate = decoded.spec().rate;
+        let mut buf = AudioBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        decoded.convert(&mut buf);
+        let planes = buf.planes();
+        let channels = planes.planes().len();
+        for i in 0..planes.planes()[0].len() {
+            let sum: f32 = planes.planes().iter().map(|p| p[i]).sum();
+            samples.push(sum / channels as f32);
+        }
+    }
+    debug!(
+        "Decoded {} TTS samples at {} Hz",
+        samples.len(),
+        sample_rate
+    );
+
+    if sample_rate == 0 {
+        return Err(anyhow!("TTS audio has no sample rate"));
+    }
+    Ok((samples, sample_rate))
+}
+
+/// Encodes mono samples as a 16-bit PCM WAV file.
+pub fn encode_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let mut out = Vec::with_capacity(44 + data_len as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&1u16.to_le_bytes()); // mono
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // byte rate
+    out.extend_from_slice(&2u16.to_le_bytes()); // block align
+    out.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    for &s in samples {
+        let v = (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seg(start: &str, end: &str, text: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            start: start.to_string(),
+            end: end.to_string(),
+            speaker: "Speaker 1".to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_google_tts_url() {
+        assert_eq!(
+            google_tts_url("https://generativelanguage.googleapis.com").as_deref(),
+            Some(GOOGLE_TTS_URL)
+        );
+        assert_eq!(
+            google_tts_url("https://texttospeech.googleapis.com/").as_deref(),
+            Some(GOOGLE_TTS_URL)
+        );
+        assert_eq!(
+            google_tts_url("http://localhost:8080/texttospeech").as_deref(),
+            Some("http://localhost:8080/texttospeech/v1/text:synthesize")
+        );
+        assert_eq!(google_tts_url("https://api.openai.com"), None);
+    }
+
+    #[test]
+    fn test_validate_segment_timing() {
+        let timing = validate_segment_timing(&[
+            seg("00:01", "00:02.500", "Hallo"),
+            seg("00:03", "00:04", "Welt"),
+        ])
+        .unwrap();
+        assert_eq!(timing, vec![(1.0, 2.5), (3.0, 4.0)]);
+
+        assert!(validate_segment_timing(&[seg("00:05", "00:04", "Hallo")]).is_err());
+        assert!(validate_segment_timing(&[seg("00:01", "00:02", "  ")]).is_err());
+        assert!(validate_segment_timing(&[seg("xx", "00:02", "Hallo")]).is_err());
+    }
+
+    #[test]
+    fn test_wav_roundtrip() {
+        let samples: Vec<f32> = (0..1000).map(|i| (i as f32 / 50.0).sin() * 0.5).collect();
+        let (decoded, rate) = decode_wav(encode_wav(&samples, 16000)).unwrap();
+        assert_eq!(rate, 16000);
+        assert_eq!(decoded.len(), samples.len());
+        assert!(decoded
+            .iter()
+            .zip(&samples)
+            .all(|(a, b)| (a - b).abs() < 1e-3));
+    }
+}
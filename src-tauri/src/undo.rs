@@ -0,0 +1,152 @@
+use crate::state::AppState;
+use crate::video::TranscriptSegment;
+
+/// Snapshots kept of the transcript while it is edited.
+pub const TRANSCRIPT_UNDO_DEPTH: usize = 50;
+
+/// Linear undo history. The last entry of `past` is the current state, so the
+/// first pushed state can't be undone.
+#[derive(Debug, Clone)]
+pub struct UndoStack<T> {
+    past: Vec<T>,
+    future: Vec<T>,
+    max_depth: usize,
+}
+
+impl<T: Clone> UndoStack<T> {
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            past: Vec::new(),
+            future: Vec::new(),
+            max_depth: max_depth.max(1),
+        }
+    }
+
+    /// Records `state` as the current state. The oldest state is dropped once
+    /// more than `max_depth` are kept, and the redo history is discarded.
+    pub fn push(&mut self, state: T) {
+        self.past.push(state);
+        if self.past.len() > self.max_depth {
+            self.past.remove(0);
+        }
+        self.future.clear();
+    }
+
+    /// Steps back and returns the state before the current one.
+    pub fn undo(&mut self) -> Option<T> {
+        if self.past.len() < 2 {
+            return None;
+        }
+        self.future.push(self.past.pop()?);
+        self.past.last().cloned()
+    }
+
+    /// Reapplies the last undone state and returns it.
+    pub fn redo(&mut self) -> Option<T> {
+        let state = self.future.pop()?;
+        self.past.push(state.clone());
+        Some(state)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.past.len() > 1
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.future.is_empty()
+    }
+}
+
+impl<T: Clone> Default for UndoStack<T> {
+    fn default() -> Self {
+        Self::new(TRANSCRIPT_UNDO_DEPTH)
+    }
+}
+
+/// Records the transcript after an edit. The frontend also pushes the loaded
+/// transcript once, so the first edit can be undone.
+#[tauri::command]
+pub fn push_transcript_edit(
+    state: tauri::State<'_, AppState>,
+    segments: Vec<TranscriptSegment>,
+) -> Result<(), String> {
+    state.transcript_history.lock().unwrap().push(segments);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn undo_transcript(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<TranscriptSegment>, String> {
+    state
+        .transcript_history
+        .lock()
+        .unwrap()
+        .undo()
+        .ok_or_else(|| "Nothing to undo".to_string())
+}
+
+#[tauri::command]
+pub fn redo_transcript(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<TranscriptSegment>, String> {
+    state
+        .transcript_history
+        .lock()
+        .unwrap()
+        .redo()
+        .ok_or_else(|| "Nothing to redo".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut stack = UndoStack::new(10);
+        assert_eq!(stack.undo(), None);
+        stack.push(1);
+        // The initial state can't be undone
+        assert_eq!(stack.undo(), None);
+        stack.push(2);
+        stack.push(3);
+
+        assert_eq!(stack.undo(), Some(2));
+        assert_eq!(stack.undo(), Some(1));
+        assert_eq!(stack.undo(), None);
+        assert!(stack.can_redo());
+        assert_eq!(stack.redo(), Some(2));
+        assert_eq!(stack.redo(), Some(3));
+        assert_eq!(stack.redo(), None);
+        assert_eq!(stack.undo(), Some(2));
+    }
+
+    #[test]
+    fn test_push_clears_future() {
+        let mut stack = UndoStack::new(10);
+        stack.push(1);
+        stack.push(2);
+        assert_eq!(stack.undo(), Some(1));
+
+        stack.push(4);
+        assert!(!stack.can_redo());
+        assert_eq!(stack.redo(), None);
+        assert_eq!(stack.undo(), Some(1));
+    }
+
+    #[test]
+    fn test_max_depth_evicts_oldest() {
+        let mut stack = UndoStack::default();
+        for i in 0..TRANSCRIPT_UNDO_DEPTH + 5 {
+            stack.push(i);
+        }
+        let mut undone = Vec::new();
+        while let Some(state) = stack.undo() {
+            undone.push(state);
+        }
+        assert_eq!(undone.len(), TRANSCRIPT_UNDO_DEPTH - 1);
+        // States 0-4 were evicted
+        assert_eq!(undone.last(), Some(&5));
+    }
+}
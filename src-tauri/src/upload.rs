@@ -1,3 +1,5 @@
+use crate::audio::AudioFmt;
+use crate::gemini::supports_files_api;
 use anyhow::Result;
 use reqwest::Client;
 use serde::Deserialize;
@@ -16,26 +18,46 @@ struct UploadResponseCorrect {
     file: FileResource,
 }
 
+/// MIME type the Files API expects for `path`, from its extension. Files that
+/// weren't passed through `prepare_audio_for_ai` (which keeps the format of
+/// audio files) may be videos as well. Unknown extensions are sent as OGG.
+pub(crate) fn detect_upload_mime(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "ogg" => "audio/ogg",
+        "mp4" => "video/mp4",
+        "m4a" => "audio/mp4",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "webm" => "video/webm",
+        other => AudioFmt::from_extension(other)
+            .unwrap_or(AudioFmt::Vorbis)
+            .mime_type(),
+    }
+}
+
 pub async fn upload_file_and_wait(
     api_key: &str,
     base_url: &str,
     path: &Path,
 ) -> Result<Option<String>> {
     // Only upload to Google Files API if using Google endpoint
-    let is_google_api = base_url.contains("generativelanguage.googleapis.com");
-
-    if !is_google_api {
+    if !supports_files_api(base_url) {
         // For non-Google APIs, return None to indicate no upload was performed
         return Ok(None);
     }
 
     let client = Client::new();
     let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
+    let mime_type = detect_upload_mime(path);
 
     let content = tokio::fs::read(path).await?;
     let part = reqwest::multipart::Part::bytes(content)
         .file_name(file_name)
-        .mime_str("audio/ogg")?;
+        .mime_str(mime_type)?;
 
     let form = reqwest::multipart::Form::new()
         .part("file", part)
@@ -90,3 +112,23 @@ pub async fn upload_file_and_wait(
 
     Ok(Some(uri))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_upload_mime() {
+        let mime = |name: &str| detect_upload_mime(Path::new(name));
+        assert_eq!(mime("audio.ogg"), "audio/ogg");
+        assert_eq!(mime("recording.mp4"), "video/mp4");
+        assert_eq!(mime("podcast.m4a"), "audio/mp4");
+        assert_eq!(mime("episode.mp3"), "audio/mpeg");
+        assert_eq!(mime("interview.wav"), "audio/wav");
+        assert_eq!(mime("screen.webm"), "video/webm");
+        // Case doesn't matter, other formats we can encode keep their type
+        assert_eq!(mime("/tmp/CLIP.MP4"), "video/mp4");
+        assert_eq!(mime("audio.ai.flac"), "audio/flac");
+        assert_eq!(mime("no_extension"), "audio/ogg");
+    }
+}
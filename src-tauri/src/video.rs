@@ -1,12 +1,24 @@
+use crate::ffmpeg_version::require_ffmpeg_version;
+use crate::fingerprint::warn_duplicate_clips;
+use crate::gemini::RankedClip;
+use crate::chapters::group_chapters_into_parts;
+use crate::probe::{
+    probe_chapters, probe_colorspace, probe_frame_rate, probe_media, probe_rotation,
+    ColorspaceInfo, MediaInfo,
+};
+use crate::progress::for_each_buffered;
+use crate::subtitles::{clip_captions, export_clip_srt, extract_clip_subtitles};
+use crate::time_utils::{parse_timestamp_to_seconds_raw, seconds_to_timestamp};
 use anyhow::Result;
 use ffmpeg_sidecar::command::FfmpegCommand;
 use ffmpeg_sidecar::event::FfmpegEvent;
-use std::path::Path;
-use log::{info, error, debug};
+use std::path::{Path, PathBuf};
+use log::{info, error, debug, warn};
+use regex::Regex;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Segment {
     pub start: String,
     pub end: String,
@@ -20,22 +32,551 @@ pub struct TranscriptSegment {
     pub text: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ClipSegment {
     pub segments: Vec<Segment>,
     pub label: Option<String>,
     pub reason: Option<String>,
+    /// File name template without extension, see `render_template`.
+    /// Falls back to `DEFAULT_CLIP_NAME_TEMPLATE`.
+    #[serde(default)]
+    pub name_template: Option<String>,
+    /// How engaging the model rated the clip (1-10), if it did.
+    #[serde(default)]
+    pub score: Option<f64>,
+    /// Volume change of the clip, e.g. to even out a highlight reel. Must be
+    /// within `AUDIO_GAIN_RANGE_DB`.
+    #[serde(default)]
+    pub audio_gain_db: Option<f64>,
+}
+
+/// Allowed `ClipSegment::audio_gain_db`.
+pub const AUDIO_GAIN_RANGE_DB: std::ops::RangeInclusive<f64> = -40.0..=20.0;
+
+/// Audio filters for the clip's `audio_gain_db`.
+fn build_clip_audio_filters(clip: &ClipSegment) -> Result<Vec<String>> {
+    match clip.audio_gain_db {
+        Some(gain) if !AUDIO_GAIN_RANGE_DB.contains(&gain) => Err(anyhow::anyhow!(
+            "Audio gain of {} dB is outside {} to {} dB",
+            gain,
+            AUDIO_GAIN_RANGE_DB.start(),
+            AUDIO_GAIN_RANGE_DB.end()
+        )),
+        Some(gain) if gain != 0.0 => Ok(vec![format!("volume={:.1}dB", gain)]),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Editable sequence of clips. The order of `clips` is the playback order.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Timeline {
+    pub clips: Vec<ClipEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClipEntry {
+    pub id: String,
+    pub segment: Segment,
+    /// Lane in the timeline view; lower tracks are played first.
+    pub track: u8,
+}
+
+pub const DEFAULT_CLIP_NAME_TEMPLATE: &str = "clip_{index:03}_{title}";
+
+/// Values available to clip name templates.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateContext {
+    /// 1-based clip number.
+    pub index: usize,
+    pub title: String,
+    pub start: String,
+    pub end: String,
+    /// Export date as YYYY-MM-DD.
+    pub date: String,
+}
+
+/// Orientation correction applied to exported clips.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum VideoTransform {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipH,
+    FlipV,
+    /// Read the display matrix rotation via ffprobe and undo it.
+    AutoFromMetadata,
+}
+
+impl VideoTransform {
+    /// Maps a display matrix rotation (as reported by ffprobe) to the transform
+    /// that shows the video upright. Returns None for unrotated streams.
+    fn from_rotation(rotation: f64) -> Option<Self> {
+        match ((-rotation).round() as i64).rem_euclid(360) {
+            90 => Some(Self::Rotate90),
+            180 => Some(Self::Rotate180),
+            270 => Some(Self::Rotate270),
+            _ => None,
+        }
+    }
+
+    fn filter(&self) -> Option<&'static str> {
+        match self {
+            Self::Rotate90 => Some("transpose=1"),
+            Self::Rotate180 => Some("hflip,vflip"),
+            Self::Rotate270 => Some("transpose=2"),
+            Self::FlipH => Some("hflip"),
+            Self::FlipV => Some("vflip"),
+            Self::AutoFromMetadata => None,
+        }
+    }
+}
+
+/// Controls the speed/accuracy tradeoff of stream-copy ("fast mode") exports.
+///
+/// A stream copy can only start on a keyframe. Seeking on the input (`-ss` before
+/// `-i`) makes ffmpeg jump to the keyframe before the requested start, so the clip
+/// may begin up to one GOP early but never contains undecodable frames (seeking
+/// on the output instead would keep the packets before the next keyframe and
+/// produce a corrupted prefix).
+///
+/// With `frame_accurate`, the input is still seeked quickly but the video is
+/// re-encoded from the exact start frame, which is forced to be a keyframe. Audio
+/// is copied in both modes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct FastModeAccuracy {
+    pub frame_accurate: bool,
+}
+
+/// How `minterpolate` creates the frames in between the source frames.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum InterpolationMethod {
+    /// Motion compensated with bidirectional motion estimation. Smoothest
+    /// result, but by far the slowest.
+    BiDir,
+    /// Blends neighbouring frames.
+    Blend,
+    /// Repeats frames, only changes the frame rate.
+    Dup,
+}
+
+/// Raises the frame rate of the clips, e.g. to slow down 30fps footage
+/// without it looking choppy.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct FrameInterpolation {
+    pub target_fps: u32,
+    pub method: InterpolationMethod,
+}
+
+/// Highest `FrameInterpolation::target_fps` as a multiple of the source frame
+/// rate. Beyond that the interpolated frames are mostly artifacts.
+pub const MAX_INTERPOLATION_FACTOR: f64 = 4.0;
+
+/// Encoding options shared by all clips of an `export_clips` run.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct OutputProfile {
+    pub rotation: Option<VideoTransform>,
+    /// Export single-segment clips without a full re-encode.
+    /// Clips made of several segments are always re-encoded.
+    pub fast_mode: Option<FastModeAccuracy>,
+    /// Mux the transcript lines overlapping each clip as a subtitle track
+    /// (also written next to the clip as `.srt`).
+    pub embed_captions: bool,
+    /// Write the transcript lines overlapping each clip as `.srt` next to it,
+    /// without muxing them. Implied by `embed_captions`.
+    pub export_srt: bool,
+    /// Language tag of the caption track, e.g. "eng" or "deu".
+    pub caption_language: Option<String>,
+    /// Color grading LUT applied to every clip: a `.cube` file or a `.png`
+    /// HALD CLUT image.
+    pub lut_path: Option<String>,
+    /// Subdirectory of the output directory for each clip, see
+    /// `render_folder_template`. Missing directories are created.
+    pub folder_template: Option<String>,
+    /// Convert every clip to BT.709 primaries, transfer and matrix, so footage
+    /// from different cameras matches when the clips are put together. HDR
+    /// footage is tone mapped.
+    pub normalize_colorspace: bool,
+    /// Interpolate frames up to a higher frame rate. Needs a re-encode.
+    pub frame_interpolation: Option<FrameInterpolation>,
+    /// Retry failed clips (re-encoded instead of fast mode, then stream copied)
+    /// and carry on with the next clip if they keep failing.
+    pub retry_on_error: bool,
+    /// Retries per clip with `retry_on_error`.
+    pub max_retries: u32,
+    /// What to do if a clip file already exists.
+    pub conflict_strategy: ConflictStrategy,
+    /// Largest size of each clip in MB (10^6 bytes), e.g. 8 for Discord. The
+    /// clips are encoded in two passes at the video bitrate that fits. Ignores
+    /// `fast_mode`, and failed clips aren't retried as stream copy.
+    pub size_limit_mb: Option<f64>,
+}
+
+/// What `export_clips` does with a clip whose output file already exists.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ConflictStrategy {
+    #[default]
+    Overwrite,
+    /// Keep the existing file and carry on with the next clip.
+    Skip,
+    /// Append `_2`, `_3`, ... to the file name until it is unique.
+    Rename,
+}
+
+/// Outcome of one clip of `export_clips`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClipExportResult {
+    pub index: usize,
+    pub output_path: String,
+    pub attempts: u32,
+    /// `None` if the clip was exported.
+    pub error: Option<String>,
+    /// The file already existed and was kept, see `ConflictStrategy::Skip`.
+    pub skipped: bool,
+}
+
+/// Where a watermark is placed. Offsets are in pixels, measured inwards from the
+/// anchoring edges (or from the centre for `Center`).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum WatermarkPosition {
+    TopLeft { offset_x: i32, offset_y: i32 },
+    TopRight { offset_x: i32, offset_y: i32 },
+    BottomLeft { offset_x: i32, offset_y: i32 },
+    BottomRight { offset_x: i32, offset_y: i32 },
+    Center { offset_x: i32, offset_y: i32 },
+}
+
+impl WatermarkPosition {
+    /// `overlay` filter x/y expressions (W/H: video size, w/h: watermark size).
+    fn overlay_coordinates(&self) -> (String, String) {
+        match *self {
+            Self::TopLeft { offset_x, offset_y } => (offset_x.to_string(), offset_y.to_string()),
+            Self::TopRight { offset_x, offset_y } => {
+                (format!("W-w-{}", offset_x), offset_y.to_string())
+            }
+            Self::BottomLeft { offset_x, offset_y } => {
+                (offset_x.to_string(), format!("H-h-{}", offset_y))
+            }
+            Self::BottomRight { offset_x, offset_y } => {
+                (format!("W-w-{}", offset_x), format!("H-h-{}", offset_y))
+            }
+            Self::Center { offset_x, offset_y } => (
+                format!("(W-w)/2+({})", offset_x),
+                format!("(H-h)/2+({})", offset_y),
+            ),
+        }
+    }
+}
+
+/// Resolves `AutoFromMetadata` into a concrete transform by probing the input.
+fn resolve_transform(
+    input_path: &Path,
+    transform: Option<VideoTransform>,
+) -> Result<Option<VideoTransform>> {
+    match transform {
+        Some(VideoTransform::AutoFromMetadata) => {
+            let rotation = probe_rotation(input_path)?;
+            info!("Detected rotation metadata: {} degrees", rotation);
+            Ok(VideoTransform::from_rotation(rotation))
+        }
+        other => Ok(other),
+    }
+}
+
+/// Filter converting a video with the probed colors to BT.709, see
+/// `OutputProfile::normalize_colorspace`. `None` if it already is BT.709.
+///
+/// HDR (PQ or HLG) is tone mapped with zscale, which `colorspace` can't do.
+/// For SDR, properties the file doesn't tag are assumed to be BT.709, as
+/// `colorspace` refuses to convert from unspecified input.
+fn build_colorspace_filter(info: &ColorspaceInfo) -> Option<String> {
+    if info.is_hdr {
+        let transfer = info.color_transfer.as_deref().unwrap_or("smpte2084");
+        let primaries = info.color_primaries.as_deref().unwrap_or("bt2020");
+        return Some(format!(
+            "zscale=tin={}:pin={}:t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,\
+             tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p",
+            transfer, primaries
+        ));
+    }
+
+    let is_bt709 = |value: &Option<String>| value.as_deref() == Some("bt709");
+    if is_bt709(&info.color_primaries)
+        && is_bt709(&info.color_transfer)
+        && is_bt709(&info.color_space)
+    {
+        return None;
+    }
+    let mut filter = "colorspace=all=bt709".to_string();
+    for (option, value) in [
+        ("iprimaries", &info.color_primaries),
+        ("itrc", &info.color_transfer),
+        ("ispace", &info.color_space),
+    ] {
+        if value.is_none() {
+            filter.push_str(&format!(":{}=bt709", option));
+        }
+    }
+    Some(filter)
+}
+
+/// Builds the video filter chain for a clip. Orientation fixes always come first
+/// so that any later filters operate on upright frames.
+fn build_video_filters(transform: Option<VideoTransform>) -> Vec<String> {
+    let mut filters = Vec::new();
+    if let Some(f) = transform.and_then(|t| t.filter()) {
+        filters.push(f.to_string());
+    }
+    filters
+}
+
+/// `minterpolate` filter for `interpolation` of a video with `source_fps`.
+fn build_interpolation_filter(interpolation: &FrameInterpolation, source_fps: f64) -> Result<String> {
+    let target_fps = interpolation.target_fps;
+    if target_fps == 0 {
+        return Err(anyhow::anyhow!("Target frame rate must be positive"));
+    }
+    if target_fps as f64 > source_fps * MAX_INTERPOLATION_FACTOR {
+        return Err(anyhow::anyhow!(
+            "Target frame rate {} is more than {}x the source frame rate {:.3}",
+            target_fps,
+            MAX_INTERPOLATION_FACTOR,
+            source_fps
+        ));
+    }
+    let mode = match interpolation.method {
+        InterpolationMethod::BiDir => "mci:me_mode=bidir",
+        InterpolationMethod::Blend => "blend",
+        InterpolationMethod::Dup => "dup",
+    };
+    Ok(format!("minterpolate=fps={}:mi_mode={}", target_fps, mode))
+}
+
+/// Builds the filter that applies a color grading LUT. `.cube` files are
+/// handled by `lut3d`; `.png` HALD CLUTs need `haldclut`, which takes the CLUT
+/// as a second stream, so the image is loaded with `movie` inside the chain.
+fn build_lut_filter(lut_path: &Path) -> Result<String> {
+    if !lut_path.is_file() {
+        return Err(anyhow::anyhow!("LUT file not found: {:?}", lut_path));
+    }
+    let extension = lut_path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let path = escape_filter_path(lut_path);
+    match extension.as_str() {
+        "cube" => Ok(format!("lut3d=file={}", path)),
+        "png" => Ok(format!(
+            "null[lut_in];movie={}[clut];[lut_in][clut]haldclut",
+            path
+        )),
+        _ => Err(anyhow::anyhow!(
+            "Unsupported LUT format {:?}, expected .cube or .png",
+            lut_path
+        )),
+    }
+}
+
+/// Quotes a path for use as a filter option inside a filter graph. The quotes
+/// protect it from the graph parser, the escaped colons from the option parser.
+/// Backslashes are replaced so that Windows paths survive both.
+pub(crate) fn escape_filter_path(path: &Path) -> String {
+    let path = path
+        .to_string_lossy()
+        .replace('\\', "/")
+        .replace(':', "\\:")
+        // Quotes need escaping for both the graph and the option parser
+        .replace('\'', "'\\\\\\''");
+    format!("'{}'", path)
+}
+
+/// Shifts segments taken from an absolute stream clock (e.g. a broadcast
+/// recording starting at PTS 1.4s) onto the file timeline that the cut filters
+/// use. Times that would fall before the start are clamped to zero.
+pub fn subtract_pts_offset(segments: &[Segment], offset: f64) -> Result<Vec<Segment>> {
+    if offset <= 0.0 {
+        return Ok(segments.to_vec());
+    }
+    segments
+        .iter()
+        .map(|s| {
+            let start = parse_timestamp_to_seconds_raw(&s.start)?;
+            let end = parse_timestamp_to_seconds_raw(&s.end)?;
+            Ok(Segment {
+                start: seconds_to_timestamp(start - offset),
+                end: seconds_to_timestamp(end - offset),
+            })
+        })
+        .collect()
+}
+
+/// Moves the clip with `clip_id` to `new_index` (clamped to the end of the
+/// timeline). Unknown ids leave the timeline unchanged.
+pub fn reorder_timeline(mut timeline: Timeline, clip_id: &str, new_index: usize) -> Timeline {
+    if let Some(index) = timeline.clips.iter().position(|c| c.id == clip_id) {
+        let clip = timeline.clips.remove(index);
+        let new_index = new_index.min(timeline.clips.len());
+        timeline.clips.insert(new_index, clip);
+    }
+    timeline
+}
+
+/// Sets new in/out points for the clip with `clip_id`.
+pub fn trim_clip(
+    mut timeline: Timeline,
+    clip_id: &str,
+    new_start: &str,
+    new_end: &str,
+) -> Result<Timeline> {
+    let start = parse_timestamp_to_seconds_raw(new_start)?;
+    let end = parse_timestamp_to_seconds_raw(new_end)?;
+    if start < 0.0 || end <= start {
+        return Err(anyhow::anyhow!(
+            "Clip end {} must be after start {}",
+            new_end,
+            new_start
+        ));
+    }
+
+    let clip = timeline
+        .clips
+        .iter_mut()
+        .find(|c| c.id == clip_id)
+        .ok_or_else(|| anyhow::anyhow!("Clip {} not found in timeline", clip_id))?;
+    clip.segment = Segment {
+        start: new_start.to_string(),
+        end: new_end.to_string(),
+    };
+    Ok(timeline)
+}
+
+/// Segments in playback order: track by track, keeping the timeline order
+/// within each track.
+pub fn flatten_timeline(timeline: &Timeline) -> Vec<Segment> {
+    let mut clips: Vec<&ClipEntry> = timeline.clips.iter().collect();
+    clips.sort_by_key(|c| c.track);
+    clips.into_iter().map(|c| c.segment.clone()).collect()
+}
+
+/// Drops clips that share more than `min_overlap_secs` of source time with a
+/// better clip. Clips are compared by score; unscored clips rank below scored
+/// ones and keep the model's order among each other. The result keeps the
+/// original order.
+pub fn deduplicate_clips(clips: Vec<ClipSegment>, min_overlap_secs: f64) -> Vec<ClipSegment> {
+    let ranges: Vec<Vec<(f64, f64)>> = clips.iter().map(clip_time_ranges).collect();
+
+    let mut by_score: Vec<usize> = (0..clips.len()).collect();
+    by_score.sort_by(|&a, &b| {
+        let score = |i: usize| clips[i].score.unwrap_or(f64::NEG_INFINITY);
+        score(b).total_cmp(&score(a))
+    });
+
+    let mut kept: Vec<usize> = Vec::new();
+    for i in by_score {
+        match kept
+            .iter()
+            .find(|&&k| ranges_overlap(&ranges[i], &ranges[k]) > min_overlap_secs)
+        {
+            Some(&k) => debug!(
+                "Dropping clip {} ({:?}), overlaps clip {} ({:?})",
+                i, clips[i].label, k, clips[k].label
+            ),
+            None => kept.push(i),
+        }
+    }
+
+    kept.sort_unstable();
+    let mut clips: Vec<Option<ClipSegment>> = clips.into_iter().map(Some).collect();
+    kept.into_iter().filter_map(|i| clips[i].take()).collect()
+}
+
+/// Sorted, merged source time ranges of a clip's segments. Segments with
+/// unparsable timestamps are ignored.
+fn clip_time_ranges(clip: &ClipSegment) -> Vec<(f64, f64)> {
+    let mut ranges: Vec<(f64, f64)> = clip
+        .segments
+        .iter()
+        .filter_map(|s| {
+            let start = parse_timestamp_to_seconds_raw(&s.start).ok()?;
+            let end = parse_timestamp_to_seconds_raw(&s.end).ok()?;
+            (end > start).then_some((start, end))
+        })
+        .collect();
+    ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut merged: Vec<(f64, f64)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Total seconds covered by both sets of merged ranges.
+fn ranges_overlap(a: &[(f64, f64)], b: &[(f64, f64)]) -> f64 {
+    a.iter()
+        .flat_map(|x| b.iter().map(move |y| (x.1.min(y.1) - x.0.max(y.0)).max(0.0)))
+        .sum()
+}
+
+/// Picks clips by descending virality score until the next one would exceed
+/// `max_duration_secs`, and returns their segments in chronological order.
+pub fn select_highlight_segments(clips: &[RankedClip], max_duration_secs: f64) -> Result<Vec<Segment>> {
+    let mut by_score: Vec<&RankedClip> = clips.iter().collect();
+    by_score.sort_by(|a, b| b.virality_score.total_cmp(&a.virality_score));
+
+    let mut selected: Vec<(f64, &ClipSegment)> = Vec::new();
+    let mut total = 0.0;
+    for ranked in by_score {
+        let duration = segments_duration(&ranked.clip.segments)?;
+        if total + duration > max_duration_secs {
+            break;
+        }
+        total += duration;
+        let start = match ranked.clip.segments.first() {
+            Some(s) => parse_timestamp_to_seconds_raw(&s.start)?,
+            None => continue,
+        };
+        selected.push((start, &ranked.clip));
+    }
+    info!("Selected {} clips ({:.1}s) for the highlight reel", selected.len(), total);
+
+    selected.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(selected
+        .into_iter()
+        .flat_map(|(_, clip)| clip.segments.iter().cloned())
+        .collect())
 }
 
+/// Cuts `segments` out of the input and joins them. `normalize_segments`
+/// re-encodes every segment separately first, see `cut_video_normalized`.
 pub fn cut_video<F>(
     input_path: &Path,
     segments: &[Segment],
     output_path: &Path,
+    normalize_segments: bool,
+    silence_between_segments_ms: Option<u32>,
     on_progress: F,
 ) -> Result<()>
 where
     F: Fn(String) + Send + 'static,
 {
+    match output_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => validate_output_writable(dir)?,
+        _ => validate_output_writable(Path::new("."))?,
+    }
+    let gap_ms = silence_between_segments_ms.filter(|&ms| ms > 0);
+    if normalize_segments {
+        if gap_ms.is_some() {
+            return Err(anyhow::anyhow!(
+                "Gaps between segments are not supported together with segment normalization"
+            ));
+        }
+        return cut_video_normalized(input_path, segments, output_path, on_progress);
+    }
+
     // Optimization: Use filter_complex to cut and concat in a single pass.
     // Example:
     // ffmpeg -i input.mp4 -filter_complex
@@ -46,13 +587,133 @@ where
     //  [v0][a0][v1][a1]concat=n=2:v=1:a=1[v][a]"
     // -map "[v]" -map "[a]" output.mp4
 
-    info!("Starting cut_video: input={:?}, output={:?}, segments={}", input_path, output_path, segments.len());
+    let gap = match gap_ms {
+        Some(ms) if segments.len() > 1 => {
+            Some(GapSource::matching(&probe_media(input_path)?, ms as f64 / 1000.0))
+        }
+        _ => None,
+    };
+    cut_video_filtered(input_path, &[], segments, &[], &[], gap.as_ref(), output_path, on_progress)
+}
 
-    let (filter_complex, _inputs) = build_filter_complex(segments);
+/// Cuts `input_path` into parts at its embedded chapters, see
+/// `group_chapters_into_parts`. The parts are written to `output_dir` as
+/// `{stem}_part01.{ext}`, ... and their paths returned. `on_progress` receives
+/// the position within the whole video.
+pub fn split_by_chapters<F>(
+    input_path: &Path,
+    output_dir: &Path,
+    max_part_secs: Option<f64>,
+    on_progress: F,
+) -> Result<Vec<PathBuf>>
+where
+    F: Fn(String) + Send + Sync + 'static + Clone,
+{
+    if max_part_secs.is_some_and(|max| max.is_nan() || max <= 0.0) {
+        return Err(anyhow::anyhow!("Invalid maximum part length: {:?}", max_part_secs));
+    }
+    let chapters = probe_chapters(input_path)?;
+    if chapters.is_empty() {
+        return Err(anyhow::anyhow!("{:?} has no chapters", input_path));
+    }
+    let parts = group_chapters_into_parts(&chapters, max_part_secs);
+    info!("Splitting {:?} into {} parts at chapter boundaries", input_path, parts.len());
 
-    let mut last_error = None;
+    std::fs::create_dir_all(output_dir)
+        .map_err(|e| anyhow::anyhow!("Failed to create output directory {:?}: {}", output_dir, e))?;
+    let stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = input_path.extension().map_or("mp4".into(), |e| e.to_string_lossy());
 
-    FfmpegCommand::new()
+    let mut outputs = Vec::with_capacity(parts.len());
+    for (i, (start, end)) in parts.into_iter().enumerate() {
+        let output_path = output_dir.join(format!("{}_part{:02}.{}", stem, i + 1, extension));
+        let segment = Segment {
+            start: seconds_to_timestamp(start),
+            end: seconds_to_timestamp(end),
+        };
+        let on_part_progress = {
+            let on_progress = on_progress.clone();
+            move |time: String| on_progress(offset_progress_time(&time, start))
+        };
+        cut_video(input_path, &[segment], &output_path, false, None, on_part_progress)?;
+        outputs.push(output_path);
+    }
+    Ok(outputs)
+}
+
+/// Black, silent filler inserted between segments. Its format has to match the
+/// input streams for the concat filter.
+#[derive(Debug, Clone, PartialEq)]
+struct GapSource {
+    duration_secs: f64,
+    width: u32,
+    height: u32,
+    frame_rate: String,
+    sample_rate: u32,
+    channel_layout: String,
+}
+
+impl GapSource {
+    fn matching(info: &MediaInfo, duration_secs: f64) -> Self {
+        let video = info.video_streams().next();
+        let audio = info.audio_streams().next();
+        let channel_layout = match audio.and_then(|a| a.channels).unwrap_or(2) {
+            1 => "mono".to_string(),
+            2 => "stereo".to_string(),
+            n => format!("{}c", n),
+        };
+        Self {
+            duration_secs,
+            width: video.and_then(|v| v.width).unwrap_or(1920),
+            height: video.and_then(|v| v.height).unwrap_or(1080),
+            frame_rate: video
+                .and_then(|v| v.frame_rate.clone())
+                .unwrap_or_else(|| "30".to_string()),
+            sample_rate: audio.and_then(|a| a.sample_rate).unwrap_or(48000),
+            channel_layout,
+        }
+    }
+
+    /// Source filters producing the gap as `[gv{i}]` and `[ga{i}]`.
+    fn filters(&self, i: usize) -> String {
+        format!(
+            "color=c=black:s={}x{}:r={}:d={:.3},setsar=1[gv{i}];aevalsrc=0:d={:.3}:s={}:c={}[ga{i}];",
+            self.width,
+            self.height,
+            self.frame_rate,
+            self.duration_secs,
+            self.duration_secs,
+            self.sample_rate,
+            self.channel_layout,
+        )
+    }
+}
+
+/// Same as `cut_video`, but applies `video_filters` to the concatenated video stream,
+/// `audio_filters` to the audio of each segment and puts `gap` between the
+/// segments. `input_args` are placed before `-i` (e.g. `-noautorotate`).
+#[allow(clippy::too_many_arguments)]
+fn cut_video_filtered<F>(
+    input_path: &Path,
+    input_args: &[&str],
+    segments: &[Segment],
+    video_filters: &[String],
+    audio_filters: &[String],
+    gap: Option<&GapSource>,
+    output_path: &Path,
+    on_progress: F,
+) -> Result<()>
+where
+    F: Fn(String) + Send + 'static,
+{
+    info!("Starting cut_video: input={:?}, output={:?}, segments={}", input_path, output_path, segments.len());
+
+    let (filter_complex, _inputs) =
+        build_filter_complex(segments, video_filters, audio_filters, gap);
+
+    let mut command = base_ffmpeg_command();
+    command
+        .args(input_args)
         .input(input_path.to_str().unwrap())
         .args(&[
             "-y",
@@ -63,22 +724,219 @@ where
             "-map",
             "[a]",
         ])
-        .output(output_path.to_str().unwrap())
-        .spawn()
-        .map_err(|e| anyhow::anyhow!("Failed to spawn ffmpeg: {}", e))?
-        .iter()
-        .map_err(|e| anyhow::anyhow!("Failed to iterate ffmpeg events: {}", e))?
-        .for_each(|event| match event {
-            FfmpegEvent::Progress(p) => on_progress(p.time),
-            FfmpegEvent::Log(_level, msg) => {
-                debug!("[FFmpeg Log] {}", msg);
+        .output(output_path.to_str().unwrap());
+
+    run_ffmpeg(&mut command, output_path, on_progress)
+}
+
+/// Frame rate of the intermediate files written by `cut_video_normalized`.
+const NORMALIZED_FRAME_RATE: &str = "30";
+
+/// Two-phase cut for footage whose codec parameters change mid-file (e.g.
+/// variable frame rate phone recordings), which breaks the single filter graph
+/// of `cut_video`. Every segment is first re-encoded on its own to a temp file
+/// with constant frame rate and identical codec settings; the temp files are
+/// then joined losslessly with the concat demuxer.
+fn cut_video_normalized<F>(
+    input_path: &Path,
+    segments: &[Segment],
+    output_path: &Path,
+    on_progress: F,
+) -> Result<()>
+where
+    F: Fn(String) + Send + 'static,
+{
+    // -fps_mode replaced -vsync in FFmpeg 5.1
+    require_ffmpeg_version(5, 1, "Segment normalization")?;
+    info!("Starting normalized cut_video: input={:?}, output={:?}, segments={}", input_path, output_path, segments.len());
+
+    // Each run gets its own directory, removed with everything in it on drop
+    let temp_dir = tempfile::tempdir()?;
+    let segment_paths: Vec<PathBuf> = (0..segments.len())
+        .map(|i| temp_dir.path().join(format!("segment_{:03}.mp4", i)))
+        .collect();
+    let list_path = temp_dir.path().join("segments.txt");
+
+    let mut cut_secs = 0.0;
+    for (segment, segment_path) in segments.iter().zip(&segment_paths) {
+        let mut command = base_ffmpeg_command();
+        command.args(build_normalized_segment_args(input_path, segment, segment_path)?);
+        let offset = cut_secs;
+        run_ffmpeg(&mut command, segment_path, |time| {
+            on_progress(offset_progress_time(&time, offset))
+        })?;
+        cut_secs += segments_duration(std::slice::from_ref(segment))?;
+    }
+
+    // Stream copy, quick compared to the segments and not worth reporting
+    std::fs::write(&list_path, build_concat_list(&segment_paths))?;
+    let mut command = base_ffmpeg_command();
+    command.args(build_normalized_concat_args(&list_path, output_path));
+    run_ffmpeg(&mut command, output_path, |_| {})
+}
+
+/// Phase 1 of `cut_video_normalized`: re-encodes one segment to the common
+/// intermediate format.
+fn build_normalized_segment_args(
+    input_path: &Path,
+    segment: &Segment,
+    output_path: &Path,
+) -> Result<Vec<String>> {
+    let start = parse_timestamp_to_seconds_raw(&segment.start)?;
+    let end = parse_timestamp_to_seconds_raw(&segment.end)?;
+    if end <= start {
+        return Err(anyhow::anyhow!(
+            "Segment end {} must be after start {}",
+            segment.end,
+            segment.start
+        ));
+    }
+
+    let mut args = vec![
+        "-y".to_string(),
+        "-ss".to_string(),
+        format!("{:.3}", start),
+        "-i".to_string(),
+        input_path.to_string_lossy().to_string(),
+        "-t".to_string(),
+        format!("{:.3}", end - start),
+    ];
+    args.extend(
+        [
+            "-r", NORMALIZED_FRAME_RATE, "-fps_mode", "cfr",
+            "-c:v", "libx264", "-pix_fmt", "yuv420p",
+            "-c:a", "aac", "-ar", "48000", "-ac", "2",
+        ]
+        .map(String::from),
+    );
+    args.push(output_path.to_string_lossy().to_string());
+    Ok(args)
+}
+
+/// Phase 2 of `cut_video_normalized`: joins the intermediate files without
+/// re-encoding.
+fn build_normalized_concat_args(list_path: &Path, output_path: &Path) -> Vec<String> {
+    vec![
+        "-f".to_string(),
+        "concat".to_string(),
+        "-safe".to_string(),
+        "0".to_string(),
+        "-i".to_string(),
+        list_path.to_string_lossy().to_string(),
+        "-y".to_string(),
+        "-c".to_string(),
+        "copy".to_string(),
+        output_path.to_string_lossy().to_string(),
+    ]
+}
+
+/// Total duration of the segments in seconds.
+pub fn segments_duration(segments: &[Segment]) -> Result<f64> {
+    segments.iter().try_fold(0.0, |total, s| {
+        let start = parse_timestamp_to_seconds_raw(&s.start)?;
+        let end = parse_timestamp_to_seconds_raw(&s.end)?;
+        Ok(total + (end - start).max(0.0))
+    })
+}
+
+/// Splits clips into `(valid, rejected)` by their total duration, e.g. to drop
+/// the few-second clips a model sometimes returns. Clips with unparsable
+/// timestamps are rejected as well.
+pub fn filter_clips_by_duration(
+    clips: Vec<ClipSegment>,
+    min_secs: f64,
+    max_secs: f64,
+) -> (Vec<ClipSegment>, Vec<ClipSegment>) {
+    clips
+        .into_iter()
+        .partition(|clip| match segments_duration(&clip.segments) {
+            Ok(duration) if (min_secs..=max_secs).contains(&duration) => true,
+            Ok(duration) => {
+                warn!(
+                    "Rejecting clip {:?}: {:.1}s is outside {}-{}s",
+                    clip.label, duration, min_secs, max_secs
+                );
+                false
             }
-            FfmpegEvent::Error(e) => {
-                error!("[FFmpeg Error] {}", e);
-                last_error = Some(e);
+            Err(e) => {
+                warn!("Rejecting clip {:?}: {}", clip.label, e);
+                false
             }
-            _ => {}
+        })
+}
+
+/// Shifts an FFmpeg progress time by `offset_secs`, so that several FFmpeg runs
+/// report one continuous position. Unparsable times are passed through.
+fn offset_progress_time(time: &str, offset_secs: f64) -> String {
+    match parse_timestamp_to_seconds_raw(time) {
+        Ok(secs) => seconds_to_timestamp(offset_secs + secs),
+        Err(_) => time.to_string(),
+    }
+}
+
+/// Log level for FFmpeg runs where we only look at errors and progress. The
+/// `level+` prefix must stay, ffmpeg-sidecar tells errors from other output by it.
+const FFMPEG_LOG_LEVEL: &str = "level+warning";
+
+/// `FfmpegCommand` without the banner, stdin and the informational output,
+/// which only clutters our logs. Progress is still reported, `-stats` keeps the
+/// progress lines below `level+info`.
+pub fn base_ffmpeg_command() -> FfmpegCommand {
+    base_ffmpeg_command_with_log_level(FFMPEG_LOG_LEVEL)
+}
+
+/// For analysis filters like `silencedetect`, `astats` or `volumedetect`,
+/// which report their results at `level+info`.
+pub fn base_ffmpeg_command_with_log_level(level: &str) -> FfmpegCommand {
+    let mut command = FfmpegCommand::new();
+    command.args(["-nostdin", "-hide_banner", "-loglevel", level, "-stats"]);
+    command
+}
+
+/// Writes and deletes a small file in `dir`, so a missing permission or a full
+/// disk is reported before minutes of encoding instead of after. The file gets
+/// a unique name, concurrent exports to the same directory don't collide.
+pub fn validate_output_writable(dir: &Path) -> Result<()> {
+    let result = tempfile::Builder::new()
+        .prefix(".write-test-")
+        .tempfile_in(dir)
+        .and_then(|mut file| {
+            std::io::Write::write_all(&mut file, b"0")?;
+            file.close()
         });
+    result.map_err(|e| {
+        anyhow::anyhow!(
+            "Output directory {:?} is not writable ({}). Check its permissions or pick another one.",
+            dir,
+            e
+        )
+    })
+}
+
+/// Spawns the prepared command, forwards progress and logs, and verifies that
+/// `output_path` was actually written.
+pub(crate) fn run_ffmpeg<F>(command: &mut FfmpegCommand, output_path: &Path, on_progress: F) -> Result<()>
+where
+    F: Fn(String),
+{
+    let mut last_error = None;
+
+    let events = command
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to spawn ffmpeg: {}", e))?
+        .iter()
+        .map_err(|e| anyhow::anyhow!("Failed to iterate ffmpeg events: {}", e))?;
+    for_each_buffered(events, |event| match event {
+        FfmpegEvent::Progress(p) => on_progress(p.time),
+        FfmpegEvent::Log(_level, msg) => {
+            debug!("[FFmpeg Log] {}", msg);
+        }
+        FfmpegEvent::Error(e) => {
+            error!("[FFmpeg Error] {}", e);
+            last_error = Some(e);
+        }
+        _ => {}
+    });
 
     if !output_path.exists() {
         let msg = last_error.unwrap_or_else(|| "Unknown error".to_string());
@@ -92,11 +950,26 @@ where
     Ok(())
 }
 
-fn build_filter_complex(segments: &[Segment]) -> (String, String) {
+fn build_filter_complex(
+    segments: &[Segment],
+    video_filters: &[String],
+    audio_filters: &[String],
+    gap: Option<&GapSource>,
+) -> (String, String) {
+    // Applied per segment, so the gap audio stays silent
+    let audio_chain: String = audio_filters.iter().map(|f| format!(",{}", f)).collect();
+
     let mut filter_complex = String::new();
     let mut inputs = String::new();
+    let mut count = segments.len();
 
     for (i, segment) in segments.iter().enumerate() {
+        if let (Some(gap), true) = (gap, i > 0) {
+            filter_complex.push_str(&gap.filters(i));
+            inputs.push_str(&format!("[gv{}][ga{}]", i, i));
+            count += 1;
+        }
+
         // Video trim
         filter_complex.push_str(&format!(
             "[0:v]trim=start={}:end={},setpts=PTS-STARTPTS[v{}];",
@@ -105,31 +978,62 @@ fn build_filter_complex(segments: &[Segment]) -> (String, String) {
 
         // Audio trim
         filter_complex.push_str(&format!(
-            "[0:a]atrim=start={}:end={},asetpts=PTS-STARTPTS[a{}];",
-            segment.start, segment.end, i
+            "[0:a]atrim=start={}:end={},asetpts=PTS-STARTPTS{}[a{}];",
+            segment.start, segment.end, audio_chain, i
         ));
 
         inputs.push_str(&format!("[v{}][a{}]", i, i));
     }
 
-    filter_complex.push_str(&format!(
-        "{}concat=n={}:v=1:a=1[v][a]",
-        inputs,
-        segments.len()
-    ));
+    if video_filters.is_empty() {
+        filter_complex.push_str(&format!(
+            "{}concat=n={}:v=1:a=1[v][a]",
+            inputs,
+            count
+        ));
+    } else {
+        filter_complex.push_str(&format!(
+            "{}concat=n={}:v=1:a=1[vcat][a];[vcat]{}[v]",
+            inputs,
+            count,
+            video_filters.join(",")
+        ));
+    }
 
     (filter_complex, inputs)
 }
 
+/// `on_progress` receives the position within all clips together, i.e. it
+/// reaches the sum of the clip durations at the end. With `retry_on_error` a
+/// failed clip is retried and, if it still fails, reported in its result
+/// instead of aborting the export.
 pub fn export_clips<F>(
     input_path: &Path,
     segments: &[ClipSegment],
+    transcript: &[TranscriptSegment],
     output_dir: &Path,
+    profile: &OutputProfile,
     on_progress: F,
-) -> Result<()>
+) -> Result<Vec<ClipExportResult>>
 where
     F: Fn(String) + Send + Sync + 'static + Clone,
 {
+    let lut_filter = profile
+        .lut_path
+        .as_deref()
+        .map(|p| build_lut_filter(Path::new(p)))
+        .transpose()?;
+    let interpolation_filter = profile
+        .frame_interpolation
+        .as_ref()
+        .map(|fi| probe_frame_rate(input_path).and_then(|fps| build_interpolation_filter(fi, fps)))
+        .transpose()?;
+    let colorspace_filter = if profile.normalize_colorspace {
+        build_colorspace_filter(&probe_colorspace(input_path)?)
+    } else {
+        None
+    };
+
     if output_dir.exists() {
         if !output_dir.is_dir() {
             return Err(anyhow::anyhow!(
@@ -142,12 +1046,85 @@ where
             anyhow::anyhow!("Failed to create output directory {:?}: {}", output_dir, e)
         })?;
     }
+    validate_output_writable(output_dir)?;
 
     info!("Starting export_clips: input={:?}, output_dir={:?}, segments={}", input_path, output_dir, segments.len());
 
+    // Rendered up front so an invalid template fails before anything is exported
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let clip_dirs = segments
+        .iter()
+        .enumerate()
+        .map(|(i, segment)| match &profile.folder_template {
+            Some(template) => Ok(output_dir.join(render_folder_template(template, i, segment, &date)?)),
+            None => Ok(output_dir.to_path_buf()),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let clip_audio_filters = segments
+        .iter()
+        .map(build_clip_audio_filters)
+        .collect::<Result<Vec<_>>>()?;
+
+    // Also up front, so a limit too small for one of the clips fails early
+    let clip_video_bitrates = segments
+        .iter()
+        .map(|segment| match profile.size_limit_mb {
+            Some(mb) => target_video_bitrate(
+                mb * BYTES_PER_MB,
+                segments_duration(&segment.segments)?,
+                SIZE_LIMITED_AUDIO_BITRATE_BPS,
+            )
+            .map(Some),
+            None => Ok(None),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    warn_duplicate_clips(input_path, segments);
+
+    let transform = resolve_transform(input_path, profile.rotation)?;
+    let mut video_filters = build_video_filters(transform);
+    // Before the LUT, grading LUTs usually expect BT.709 input
+    video_filters.extend(colorspace_filter);
+    video_filters.extend(lut_filter);
+    video_filters.extend(interpolation_filter);
+    // ffmpeg auto-rotates by default, which would double up with our own transpose
+    let input_args: &[&str] = if profile.rotation == Some(VideoTransform::AutoFromMetadata) {
+        &["-noautorotate"]
+    } else {
+        &[]
+    };
+
+    // Progress is reported as the position within all clips together
+    let mut exported_secs = 0.0;
+    let mut results = Vec::with_capacity(segments.len());
     for (i, segment) in segments.iter().enumerate() {
+        let audio_filters = &clip_audio_filters[i];
+        let clip_offset = exported_secs;
+        exported_secs += segments_duration(&segment.segments)?;
+        let on_clip_progress = {
+            let on_progress = on_progress.clone();
+            move |time: String| on_progress(offset_progress_time(&time, clip_offset))
+        };
+
+        let clip_dir = &clip_dirs[i];
+        std::fs::create_dir_all(clip_dir).map_err(|e| {
+            anyhow::anyhow!("Failed to create clip directory {:?}: {}", clip_dir, e)
+        })?;
         let output_filename = build_clip_output_filename(i, segment);
-        let output_path = output_dir.join(&output_filename);
+        let default_path = clip_dir.join(&output_filename);
+        let Some(output_path) = resolve_output_conflict(&default_path, profile.conflict_strategy)
+        else {
+            info!("Skipping clip {}, {:?} already exists", i + 1, default_path);
+            results.push(ClipExportResult {
+                index: i,
+                output_path: default_path.to_string_lossy().to_string(),
+                attempts: 0,
+                error: None,
+                skipped: true,
+            });
+            continue;
+        };
 
         // 1. Save Metadata
         let metadata_filename = output_path.with_extension("json");
@@ -160,72 +1137,2184 @@ where
             let _ = std::fs::write(&metadata_filename, content);
         }
 
-        // 2. Cut Video
-        // If single segment, use simple cut. If multiple, use cut_video logic (concat).
-        if segment.segments.len() == 1 {
-            let s = &segment.segments[0];
-            let mut last_error = None;
-            FfmpegCommand::new()
-                .input(input_path.to_str().unwrap())
-                .args(&[
-                    "-y", "-ss", &s.start, "-to", &s.end, "-c:v", "libx264", "-c:a", "aac",
-                ])
-                .output(output_path.to_str().unwrap())
-                .spawn()
-                .map_err(|e| anyhow::anyhow!("Failed to spawn ffmpeg: {}", e))?
-                .iter()
-                .map_err(|e| anyhow::anyhow!("Failed to iterate ffmpeg events: {}", e))?
-                .for_each(|event| match event {
-                    FfmpegEvent::Progress(p) => on_progress(p.time),
-                    FfmpegEvent::Log(_level, msg) => {
-                        debug!("[FFmpeg Log] {}", msg);
-                    }
-                    FfmpegEvent::Error(e) => {
-                        error!("[FFmpeg Error] {}", e);
-                        last_error = Some(e);
-                    }
-                    _ => {}
-                });
-
-            if !output_path.exists() {
-                let msg = last_error.unwrap_or_else(|| "Unknown error".to_string());
-                return Err(anyhow::anyhow!(
-                    "FFmpeg failed to create output file: {:?}. Error: {}",
-                    output_path,
-                    msg
-                ));
+        // 2. Cut Video, 3. Captions
+        let max_retries = if profile.retry_on_error { profile.max_retries } else { 0 };
+        let video_bitrate = clip_video_bitrates[i];
+        let fast_mode = profile
+            .fast_mode
+            .filter(|_| segment.segments.len() == 1 && video_bitrate.is_none());
+        let size_limited = video_bitrate.is_some();
+        let (attempts, result) =
+            export_with_retries(max_retries, fast_mode.is_some(), size_limited, |mode| {
+                export_clip_video(
+                    input_path,
+                    input_args,
+                    &segment.segments,
+                    &video_filters,
+                    audio_filters,
+                    fast_mode,
+                    video_bitrate,
+                    mode,
+                    &output_path,
+                    on_clip_progress.clone(),
+                )
+            });
+        let result =
+            result.and_then(|()| export_clip_captions(profile, transcript, segment, &output_path));
+
+        let error = match result {
+            Ok(()) => None,
+            Err(e) if profile.retry_on_error => {
+                error!("Failed to export clip {} after {} attempts: {:#}", i + 1, attempts, e);
+                Some(format!("{:#}", e))
             }
-        } else {
-            // Use existing cut_video logic which handles concat
-            let cb = on_progress.clone();
-            cut_video(input_path, &segment.segments, &output_path, move |time| {
-                cb(time);
-            })?;
+            Err(e) => return Err(e),
+        };
+        results.push(ClipExportResult {
+            index: i,
+            output_path: output_path.to_string_lossy().to_string(),
+            attempts,
+            error,
+            skipped: false,
+        });
+    }
+    Ok(results)
+}
+
+/// The path to write to instead of `path` according to `strategy`, or `None`
+/// if the clip is to be skipped.
+fn resolve_output_conflict(path: &Path, strategy: ConflictStrategy) -> Option<PathBuf> {
+    if !path.exists() {
+        return Some(path.to_path_buf());
+    }
+    match strategy {
+        ConflictStrategy::Overwrite => Some(path.to_path_buf()),
+        ConflictStrategy::Skip => None,
+        ConflictStrategy::Rename => {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = path.extension().map(|e| e.to_string_lossy());
+            (2..)
+                .map(|n| {
+                    let name = match &extension {
+                        Some(extension) => format!("{}_{}.{}", stem, n, extension),
+                        None => format!("{}_{}", stem, n),
+                    };
+                    path.with_file_name(name)
+                })
+                .find(|candidate| !candidate.exists())
         }
     }
-    Ok(())
 }
 
-fn build_clip_output_filename(i: usize, segment: &ClipSegment) -> String {
-    let suffix = segment
-        .label
-        .as_ref()
-        .map(|l| l.replace(|c: char| !c.is_alphanumeric() && c != '-' && c != '_', ""))
-        .unwrap_or_else(|| "".to_string());
+/// How one attempt of `export_clips` encodes a clip.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClipExportMode {
+    /// As configured in the `OutputProfile`.
+    Configured,
+    /// Full re-encode, ignoring `fast_mode`.
+    ReEncode,
+    /// Stream copy without any filters, the last resort.
+    StreamCopy,
+}
 
-    if suffix.is_empty() {
-        format!("clip_{:03}.mp4", i + 1)
-    } else {
-        format!("clip_{:03}_{}.mp4", i + 1, suffix)
+/// Mode of the `attempt`th try (0 is the first). A failed fast mode export is
+/// re-encoded first, everything after that is stream copied.
+fn retry_mode(attempt: u32, fast_mode: bool) -> ClipExportMode {
+    match attempt {
+        0 => ClipExportMode::Configured,
+        1 if fast_mode => ClipExportMode::ReEncode,
+        _ => ClipExportMode::StreamCopy,
     }
 }
 
-#[cfg(test)]
-mod tests {
+/// Calls `export` until it succeeds, at most `max_retries` times more than
+/// once. Returns the number of attempts and the result of the last one.
+/// A `size_limited` clip isn't retried as stream copy, which can't keep the
+/// size limit, the export fails instead.
+fn export_with_retries<E>(
+    max_retries: u32,
+    fast_mode: bool,
+    size_limited: bool,
+    mut export: E,
+) -> (u32, Result<()>)
+where
+    E: FnMut(ClipExportMode) -> Result<()>,
+{
+    let mut attempt = 0;
+    loop {
+        let mode = retry_mode(attempt, fast_mode);
+        let result = export(mode);
+        attempt += 1;
+        let next = retry_mode(attempt, fast_mode);
+        let copy_breaks_limit = size_limited && next == ClipExportMode::StreamCopy;
+        match result {
+            Err(e) if attempt <= max_retries && copy_breaks_limit => {
+                let e = e.context("Not retrying as stream copy, it can't keep the size limit");
+                return (attempt, Err(e));
+            }
+            Err(e) if attempt <= max_retries => {
+                warn!("Clip export ({:?}) failed, retrying as {:?}: {:#}", mode, next, e);
+            }
+            result => return (attempt, result),
+        }
+    }
+}
+
+/// Cuts one clip of `export_clips`. `fast_mode` is only set for clips made of
+/// a single segment. With a `video_bitrate` (bits/s) the clip is encoded in
+/// two passes, see `OutputProfile::size_limit_mb`.
+#[allow(clippy::too_many_arguments)]
+fn export_clip_video<F>(
+    input_path: &Path,
+    input_args: &[&str],
+    segments: &[Segment],
+    video_filters: &[String],
+    audio_filters: &[String],
+    fast_mode: Option<FastModeAccuracy>,
+    video_bitrate: Option<u64>,
+    mode: ClipExportMode,
+    output_path: &Path,
+    on_progress: F,
+) -> Result<()>
+where
+    F: Fn(String) + Send + 'static,
+{
+    // Left over by a failed attempt, it must not count as success
+    let _ = std::fs::remove_file(output_path);
+
+    if mode == ClipExportMode::StreamCopy {
+        warn!("Exporting {:?} as stream copy, without filters", output_path);
+        return copy_clip_segments(input_path, input_args, segments, output_path, on_progress);
+    }
+    if let Some(bitrate) = video_bitrate {
+        let args = match segments {
+            [segment] => single_clip_filter_args(segment, video_filters, audio_filters),
+            _ => {
+                let (filter_complex, _) =
+                    build_filter_complex(segments, video_filters, audio_filters, None);
+                ["-y", "-filter_complex", &filter_complex, "-map", "[v]", "-map", "[a]"]
+                    .map(String::from)
+                    .to_vec()
+            }
+        };
+        return encode_two_pass(input_path, input_args, &args, bitrate, output_path, on_progress);
+    }
+    if segments.len() != 1 {
+        // Use existing cut_video logic which handles concat
+        return cut_video_filtered(
+            input_path,
+            input_args,
+            segments,
+            video_filters,
+            audio_filters,
+            None,
+            output_path,
+            on_progress,
+        );
+    }
+
+    let s = &segments[0];
+    let mut command = base_ffmpeg_command();
+    command.args(input_args);
+    match fast_mode.filter(|_| mode == ClipExportMode::Configured) {
+        Some(accuracy) => {
+            command.args(build_fast_clip_args(
+                input_path,
+                s,
+                video_filters,
+                audio_filters,
+                accuracy,
+            )?);
+        }
+        None => {
+            command
+                .input(input_path.to_str().unwrap())
+                .args(build_single_clip_args(s, video_filters, audio_filters));
+        }
+    }
+    command.output(output_path.to_str().unwrap());
+    run_ffmpeg(&mut command, output_path, on_progress)
+}
+
+/// Bytes per MB of `OutputProfile::size_limit_mb`.
+const BYTES_PER_MB: f64 = 1_000_000.0;
+
+/// Audio bitrate of clips with a size limit.
+const SIZE_LIMITED_AUDIO_BITRATE_BPS: u64 = 128_000;
+
+/// Share of the size limit the bitrate is computed for. Two-pass encodes can
+/// overshoot their target by a bit, and the container adds its own overhead.
+const SIZE_LIMIT_SAFETY_FACTOR: f64 = 0.97;
+
+/// Video bitrate (bits/s) at which a clip of `duration_secs` with audio at
+/// `audio_bitrate_bps` fills 97% of `size_limit_bytes`, leaving the rest for
+/// the container and encoder overshoot.
+pub fn target_video_bitrate(
+    size_limit_bytes: f64,
+    duration_secs: f64,
+    audio_bitrate_bps: u64,
+) -> Result<u64> {
+    if !(size_limit_bytes.is_finite() && size_limit_bytes > 0.0) {
+        return Err(anyhow::anyhow!("Invalid size limit: {} bytes", size_limit_bytes));
+    }
+    if !(duration_secs.is_finite() && duration_secs > 0.0) {
+        return Err(anyhow::anyhow!("Invalid clip duration: {}s", duration_secs));
+    }
+    let bitrate = size_limit_bytes * SIZE_LIMIT_SAFETY_FACTOR * 8.0 / duration_secs
+        - audio_bitrate_bps as f64;
+    if bitrate < 1.0 {
+        return Err(anyhow::anyhow!(
+            "{} bytes are too little for {:.1}s of video",
+            size_limit_bytes,
+            duration_secs
+        ));
+    }
+    Ok(bitrate as u64)
+}
+
+/// Encoder arguments of `pass` (1 or 2) of a two-pass encode. The first pass
+/// only analyses the video and writes its statistics to `passlog`, so it has
+/// no audio and its output is discarded.
+fn build_two_pass_args(pass: u8, video_bitrate: u64, passlog: &Path) -> Vec<String> {
+    let mut args = vec![
+        "-c:v".to_string(),
+        "libx264".to_string(),
+        "-b:v".to_string(),
+        video_bitrate.to_string(),
+        "-pass".to_string(),
+        pass.to_string(),
+        "-passlogfile".to_string(),
+        passlog.to_string_lossy().to_string(),
+    ];
+    if pass == 1 {
+        args.extend(["-an", "-f", "null"].map(String::from));
+    } else {
+        let audio_bitrate = SIZE_LIMITED_AUDIO_BITRATE_BPS.to_string();
+        args.extend(["-c:a", "aac", "-b:a", &audio_bitrate].map(String::from));
+    }
+    args
+}
+
+/// Encodes `input_path` with the cut and filter `args` in two passes at
+/// `video_bitrate`. Only the second pass reports progress.
+fn encode_two_pass<F>(
+    input_path: &Path,
+    input_args: &[&str],
+    args: &[String],
+    video_bitrate: u64,
+    output_path: &Path,
+    on_progress: F,
+) -> Result<()>
+where
+    F: Fn(String),
+{
+    info!("Two-pass encode of {:?} at {} bit/s", output_path, video_bitrate);
+    let passlog = output_path.with_extension("passlog");
+    // x264 appends the stream index
+    let stats_path = PathBuf::from(format!("{}-0.log", passlog.to_string_lossy()));
+
+    let mut first_pass = base_ffmpeg_command();
+    first_pass
+        .args(input_args)
+        .input(input_path.to_str().unwrap())
+        .args(args)
+        .args(build_two_pass_args(1, video_bitrate, &passlog))
+        .output("-");
+    let result = run_ffmpeg(&mut first_pass, &stats_path, |_| {}).and_then(|()| {
+        let mut second_pass = base_ffmpeg_command();
+        second_pass
+            .args(input_args)
+            .input(input_path.to_str().unwrap())
+            .args(args)
+            .args(build_two_pass_args(2, video_bitrate, &passlog))
+            .output(output_path.to_str().unwrap());
+        run_ffmpeg(&mut second_pass, output_path, on_progress)
+    });
+
+    let _ = std::fs::remove_file(&stats_path);
+    let _ = std::fs::remove_file(format!("{}.mbtree", stats_path.to_string_lossy()));
+    result
+}
+
+/// Stream copies every segment (cut at keyframes) and joins the parts with the
+/// concat demuxer.
+fn copy_clip_segments<F>(
+    input_path: &Path,
+    input_args: &[&str],
+    segments: &[Segment],
+    output_path: &Path,
+    on_progress: F,
+) -> Result<()>
+where
+    F: Fn(String) + Send + 'static,
+{
+    let copy = |segment: &Segment, path: &Path| {
+        let mut command = base_ffmpeg_command();
+        command
+            .args(input_args)
+            .args(build_fast_clip_args(
+                input_path,
+                segment,
+                &[],
+                &[],
+                FastModeAccuracy::default(),
+            )?)
+            .output(path.to_str().unwrap());
+        run_ffmpeg(&mut command, path, |_| {})
+    };
+
+    if let [segment] = segments {
+        return copy(segment, output_path);
+    }
+    let parts: Vec<PathBuf> = (0..segments.len())
+        .map(|i| output_path.with_extension(format!("part{}.mp4", i)))
+        .collect();
+    let result = segments
+        .iter()
+        .zip(&parts)
+        .try_for_each(|(segment, part)| copy(segment, part))
+        .and_then(|()| concat_videos(&parts, output_path, on_progress));
+    for part in &parts {
+        let _ = std::fs::remove_file(part);
+    }
+    result
+}
+
+fn export_clip_captions(
+    profile: &OutputProfile,
+    transcript: &[TranscriptSegment],
+    segment: &ClipSegment,
+    output_path: &Path,
+) -> Result<()> {
+    if profile.embed_captions {
+        embed_clip_captions(
+            output_path,
+            transcript,
+            &segment.segments,
+            profile.caption_language.as_deref(),
+        )?;
+    } else if profile.export_srt {
+        let subtitles = extract_clip_subtitles(transcript, segment)?;
+        if subtitles.is_empty() {
+            info!("No transcript lines overlap {:?}, skipping subtitles", output_path);
+        } else {
+            export_clip_srt(&subtitles, &output_path.with_extension("srt"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the clip's captions as `.srt` next to it and muxes them into the clip
+/// as a subtitle track. The streams of the clip are copied, not re-encoded.
+fn embed_clip_captions(
+    clip_path: &Path,
+    transcript: &[TranscriptSegment],
+    clip_segments: &[Segment],
+    language: Option<&str>,
+) -> Result<()> {
+    let captions = clip_captions(transcript, clip_segments)?;
+    if captions.is_empty() {
+        info!("No transcript lines overlap {:?}, skipping captions", clip_path);
+        return Ok(());
+    }
+
+    let srt_path = clip_path.with_extension("srt");
+    export_clip_srt(&captions, &srt_path)?;
+
+    let extension = clip_path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let muxed_path = clip_path.with_extension(format!("captioned.{}", extension));
+    let mut command = base_ffmpeg_command();
+    command.args(build_caption_mux_args(clip_path, &srt_path, &muxed_path, language));
+    run_ffmpeg(&mut command, &muxed_path, |_| {})?;
+
+    std::fs::rename(&muxed_path, clip_path)?;
+    Ok(())
+}
+
+/// Arguments that add `srt_path` as subtitle track to `clip_path`. MP4 only
+/// supports `mov_text` subtitles, Matroska keeps them as SRT.
+fn build_caption_mux_args(
+    clip_path: &Path,
+    srt_path: &Path,
+    output_path: &Path,
+    language: Option<&str>,
+) -> Vec<String> {
+    let is_mkv = output_path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("mkv"));
+    let mut args = vec![
+        "-y".to_string(),
+        "-i".to_string(),
+        clip_path.to_string_lossy().to_string(),
+        "-i".to_string(),
+        srt_path.to_string_lossy().to_string(),
+        "-map".to_string(),
+        "0".to_string(),
+        "-map".to_string(),
+        "1".to_string(),
+        "-c".to_string(),
+        "copy".to_string(),
+        "-c:s".to_string(),
+        if is_mkv { "srt" } else { "mov_text" }.to_string(),
+    ];
+    if let Some(language) = language.filter(|l| !l.trim().is_empty()) {
+        args.push("-metadata:s:s:0".to_string());
+        args.push(format!("language={}", language.trim()));
+    }
+    args.push(output_path.to_string_lossy().to_string());
+    args
+}
+
+/// Burns an image watermark into the video. The watermark (second input) is
+/// scaled relative to its own size and made translucent before the overlay.
+pub fn add_watermark<F>(
+    input_path: &Path,
+    watermark_path: &Path,
+    output_path: &Path,
+    position: WatermarkPosition,
+    opacity: f64,
+    scale: f64,
+    on_progress: F,
+) -> Result<()>
+where
+    F: Fn(String),
+{
+    if !watermark_path.exists() {
+        return Err(anyhow::anyhow!("Watermark file does not exist: {:?}", watermark_path));
+    }
+    let filter = build_watermark_filter(position, opacity, scale)?;
+
+    info!("Adding watermark {:?} to {:?}", watermark_path, input_path);
+
+    let mut command = base_ffmpeg_command();
+    command
+        .input(input_path.to_str().unwrap())
+        .input(watermark_path.to_str().unwrap())
+        .args([
+            "-y",
+            "-filter_complex",
+            &filter,
+            "-map",
+            "[v]",
+            "-map",
+            "0:a?",
+            "-c:v",
+            "libx264",
+            "-c:a",
+            "copy",
+        ])
+        .output(output_path.to_str().unwrap());
+    run_ffmpeg(&mut command, output_path, on_progress)
+}
+
+fn build_watermark_filter(position: WatermarkPosition, opacity: f64, scale: f64) -> Result<String> {
+    if !(opacity > 0.0 && opacity <= 1.0) {
+        return Err(anyhow::anyhow!("Opacity must be in (0.0, 1.0], got {}", opacity));
+    }
+    if !(scale > 0.0 && scale <= 2.0) {
+        return Err(anyhow::anyhow!("Scale must be in (0.0, 2.0], got {}", scale));
+    }
+
+    let (x, y) = position.overlay_coordinates();
+    Ok(format!(
+        "[1:v]scale=iw*{scale}:-1,format=rgba,colorchannelmixer=aa={opacity}[wm];[0:v][wm]overlay={x}:{y}[v]"
+    ))
+}
+
+/// Most inputs `create_video_grid` tiles (3x3).
+pub const MAX_GRID_INPUTS: usize = 9;
+
+/// Tiles the inputs (e.g. several camera angles of one recording) into a grid
+/// with `columns` columns, row by row. Every cell has the size of the first
+/// input; other inputs are scaled to fit and letterboxed. `sync_offsets` skips
+/// the given number of seconds at the start of each input, so the cameras line
+/// up. The audio of the first input is kept.
+pub fn create_video_grid<F>(
+    input_paths: &[PathBuf],
+    output_path: &Path,
+    columns: u32,
+    sync_offsets: &[f64],
+    on_progress: F,
+) -> Result<()>
+where
+    F: Fn(String),
+{
+    let first = input_paths
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No inputs for the video grid"))?;
+    let info = probe_media(first)?;
+    let (cell_width, cell_height) = info
+        .video_streams()
+        .next()
+        .and_then(|s| Some((s.width?, s.height?)))
+        .ok_or_else(|| anyhow::anyhow!("No video stream found in {:?}", first))?;
+
+    let args = build_video_grid_args(
+        input_paths,
+        sync_offsets,
+        columns,
+        (cell_width, cell_height),
+        output_path,
+    )?;
+    info!(
+        "Creating a {}-column video grid of {} inputs ({}x{} cells)",
+        columns,
+        input_paths.len(),
+        cell_width,
+        cell_height
+    );
+    let mut command = base_ffmpeg_command();
+    command.args(args);
+    run_ffmpeg(&mut command, output_path, on_progress)
+}
+
+/// `xstack` layout placing `count` cells of `cell_width`x`cell_height` row by
+/// row, e.g. `0_0|640_0|0_360|640_360` for a 2x2 grid of 640x360 cells.
+pub fn build_xstack_layout(count: usize, columns: u32, cell_width: u32, cell_height: u32) -> String {
+    (0..count as u32)
+        .map(|i| {
+            let x = (i % columns) * cell_width;
+            let y = (i / columns) * cell_height;
+            format!("{}_{}", x, y)
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn build_video_grid_args(
+    input_paths: &[PathBuf],
+    sync_offsets: &[f64],
+    columns: u32,
+    (cell_width, cell_height): (u32, u32),
+    output_path: &Path,
+) -> Result<Vec<String>> {
+    let count = input_paths.len();
+    if !(2..=MAX_GRID_INPUTS).contains(&count) {
+        return Err(anyhow::anyhow!(
+            "A video grid needs 2 to {} inputs, got {}",
+            MAX_GRID_INPUTS,
+            count
+        ));
+    }
+    if columns == 0 || columns as usize > count {
+        return Err(anyhow::anyhow!("Columns must be between 1 and {}, got {}", count, columns));
+    }
+    if !sync_offsets.is_empty() && sync_offsets.len() != count {
+        return Err(anyhow::anyhow!(
+            "Expected {} sync offsets, got {}",
+            count,
+            sync_offsets.len()
+        ));
+    }
+    if let Some(offset) = sync_offsets.iter().find(|o| !(o.is_finite() && **o >= 0.0)) {
+        return Err(anyhow::anyhow!("Sync offsets must not be negative, got {}", offset));
+    }
+
+    let mut args = vec!["-y".to_string()];
+    for (i, path) in input_paths.iter().enumerate() {
+        let offset = sync_offsets.get(i).copied().unwrap_or(0.0);
+        if offset > 0.0 {
+            args.extend(["-ss".to_string(), format!("{:.3}", offset)]);
+        }
+        args.extend(["-i".to_string(), path.to_string_lossy().to_string()]);
+    }
+
+    let mut filter = String::new();
+    for i in 0..count {
+        filter.push_str(&format!(
+            "[{i}:v]scale={w}:{h}:force_original_aspect_ratio=decrease,\
+             pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1[cell{i}];",
+            i = i,
+            w = cell_width,
+            h = cell_height
+        ));
+    }
+    for i in 0..count {
+        filter.push_str(&format!("[cell{}]", i));
+    }
+    // Cells of an incomplete last row stay black
+    filter.push_str(&format!(
+        "xstack=inputs={}:layout={}:fill=black[v]",
+        count,
+        build_xstack_layout(count, columns, cell_width, cell_height)
+    ));
+
+    args.extend([
+        "-filter_complex".to_string(),
+        filter,
+        "-map".to_string(),
+        "[v]".to_string(),
+        "-map".to_string(),
+        "0:a?".to_string(),
+        "-c:v".to_string(),
+        "libx264".to_string(),
+        "-c:a".to_string(),
+        "aac".to_string(),
+        output_path.to_string_lossy().to_string(),
+    ]);
+    Ok(args)
+}
+
+/// How `export_comparison_video` arranges the before and after versions.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ComparisonLayout {
+    SideBySide,
+    TopBottom,
+    /// The after version blended over the before one with the given opacity.
+    Overlay(f64),
+}
+
+/// How far (seconds) the durations of the compared videos may differ, to allow
+/// for the container rounding up to the last audio or video packet.
+const COMPARISON_DURATION_TOLERANCE_SECS: f64 = 0.1;
+
+/// Renders `before_path` and `after_path` into one video, e.g. to check color
+/// grading or stabilization. The after version is scaled to the size of the
+/// before one, whose audio is kept.
+pub fn export_comparison_video<F>(
+    before_path: &Path,
+    after_path: &Path,
+    output_path: &Path,
+    layout: ComparisonLayout,
+    on_progress: F,
+) -> Result<()>
+where
+    F: Fn(String),
+{
+    let before = probe_media(before_path)?;
+    let after = probe_media(after_path)?;
+    if (before.duration - after.duration).abs() > COMPARISON_DURATION_TOLERANCE_SECS {
+        return Err(anyhow::anyhow!(
+            "Compared videos differ in duration: {:.3}s vs {:.3}s",
+            before.duration,
+            after.duration
+        ));
+    }
+    let size = before
+        .video_streams()
+        .next()
+        .and_then(|s| Some((s.width?, s.height?)))
+        .ok_or_else(|| anyhow::anyhow!("No video stream found in {:?}", before_path))?;
+    let filter = build_comparison_filter(layout, size)?;
+
+    info!("Exporting {:?} comparison of {:?} and {:?}", layout, before_path, after_path);
+
+    let mut command = base_ffmpeg_command();
+    command
+        .input(before_path.to_str().unwrap())
+        .input(after_path.to_str().unwrap())
+        .args([
+            "-y",
+            "-filter_complex",
+            &filter,
+            "-map",
+            "[v]",
+            "-map",
+            "0:a?",
+            "-c:v",
+            "libx264",
+            "-c:a",
+            "copy",
+        ])
+        .output(output_path.to_str().unwrap());
+    run_ffmpeg(&mut command, output_path, on_progress)
+}
+
+/// Both inputs are brought to the same size and pixel format first, `hstack`,
+/// `vstack` and `blend` reject anything else.
+fn build_comparison_filter(layout: ComparisonLayout, (width, height): (u32, u32)) -> Result<String> {
+    let combine = match layout {
+        ComparisonLayout::SideBySide => "hstack=inputs=2".to_string(),
+        ComparisonLayout::TopBottom => "vstack=inputs=2".to_string(),
+        ComparisonLayout::Overlay(opacity) => {
+            if !(opacity > 0.0 && opacity <= 1.0) {
+                return Err(anyhow::anyhow!("Opacity must be in (0.0, 1.0], got {}", opacity));
+            }
+            format!("blend=all_mode=overlay:all_opacity={}", opacity)
+        }
+    };
+    Ok(format!(
+        "[0:v]setsar=1,format=yuv420p[before];\
+         [1:v]scale={width}:{height},setsar=1,format=yuv420p[after];\
+         [before][after]{combine}[v]"
+    ))
+}
+
+/// Minimum scene change score for a frame to be considered as poster frame.
+const POSTER_SCENE_THRESHOLD: f64 = 0.1;
+
+/// Picks the frame with the biggest scene change between `start` and `end` as
+/// clip thumbnail and returns its absolute timestamp in seconds. Falls back to
+/// the middle of the segment if nothing changes enough.
+pub fn select_poster_frame(input_path: &Path, start: &str, end: &str) -> Result<f64> {
+    let start = parse_timestamp_to_seconds_raw(start)?;
+    let end = parse_timestamp_to_seconds_raw(end)?;
+    if end <= start {
+        return Err(anyhow::anyhow!("Segment end {} must be after start {}", end, start));
+    }
+
+    // metadata=print writes to stdout, which the sidecar iterator would try to
+    // parse as frames, so run the binary directly.
+    let output = std::process::Command::new(ffmpeg_sidecar::paths::ffmpeg_path())
+        .args(build_poster_frame_args(input_path, start, end))
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run ffmpeg: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Scene detection failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    let best = parse_scene_scores(&String::from_utf8_lossy(&output.stdout))
+        .into_iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+    Ok(match best {
+        // Timestamps restart at zero after the input seek
+        Some((time, score)) => {
+            debug!("Poster frame at +{:.3}s (scene score {:.3})", time, score);
+            (start + time).min(end)
+        }
+        None => (start + end) / 2.0,
+    })
+}
+
+fn build_poster_frame_args(input_path: &Path, start: f64, end: f64) -> Vec<String> {
+    vec![
+        "-hide_banner".to_string(),
+        "-ss".to_string(),
+        format!("{:.3}", start),
+        "-t".to_string(),
+        format!("{:.3}", end - start),
+        "-i".to_string(),
+        input_path.to_string_lossy().to_string(),
+        "-vf".to_string(),
+        format!(
+            "select='gt(scene,{})',metadata=print:file=-",
+            POSTER_SCENE_THRESHOLD
+        ),
+        "-an".to_string(),
+        "-f".to_string(),
+        "null".to_string(),
+        "-".to_string(),
+    ]
+}
+
+/// Parses `metadata=print` output into (pts_time, scene score) pairs.
+fn parse_scene_scores(output: &str) -> Vec<(f64, f64)> {
+    let mut scores = Vec::new();
+    let mut pts_time = None;
+    for line in output.lines() {
+        let line = line.trim();
+        if line.starts_with("frame:") {
+            pts_time = line
+                .split_whitespace()
+                .find_map(|field| field.strip_prefix("pts_time:"))
+                .and_then(|t| t.parse::<f64>().ok());
+        } else if let Some(score) = line.strip_prefix("lavfi.scene_score=") {
+            if let (Some(time), Ok(score)) = (pts_time, score.parse::<f64>()) {
+                scores.push((time, score));
+            }
+        }
+    }
+    scores
+}
+
+/// Concatenates separate files into one. Inputs sharing the same codecs and
+/// stream parameters are joined losslessly with the concat demuxer; otherwise
+/// we fall back to the (re-encoding) concat filter.
+pub fn concat_videos<F>(input_paths: &[PathBuf], output_path: &Path, on_progress: F) -> Result<()>
+where
+    F: Fn(String) + Send + 'static,
+{
+    if input_paths.is_empty() {
+        return Err(anyhow::anyhow!("No input files given"));
+    }
+
+    let infos = input_paths
+        .iter()
+        .map(|p| probe_media(p))
+        .collect::<Result<Vec<_>>>()?;
+
+    info!("Starting concat_videos: inputs={}, output={:?}", input_paths.len(), output_path);
+
+    let mut command = base_ffmpeg_command();
+
+    if streams_are_concat_compatible(&infos) {
+        info!("Inputs share codec parameters, using concat demuxer");
+        // Uniquely named, so concurrent exports don't share a list. Deleted on drop.
+        let list = tempfile::Builder::new().prefix("concat_").suffix(".txt").tempfile()?;
+        std::fs::write(list.path(), build_concat_list(input_paths))?;
+
+        command
+            .args(["-f", "concat", "-safe", "0"])
+            .input(list.path().to_str().unwrap())
+            .args(["-y", "-c", "copy"])
+            .output(output_path.to_str().unwrap());
+        run_ffmpeg(&mut command, output_path, on_progress)
+    } else {
+        info!("Inputs have mismatched codecs, re-encoding with concat filter");
+        let target_size = infos[0]
+            .video_streams()
+            .next()
+            .and_then(|v| v.width.zip(v.height));
+
+        for path in input_paths {
+            command.input(path.to_str().unwrap());
+        }
+        command
+            .args([
+                "-y",
+                "-filter_complex",
+                &build_concat_filter(input_paths.len(), target_size),
+                "-map",
+                "[v]",
+                "-map",
+                "[a]",
+                "-c:v",
+                "libx264",
+                "-c:a",
+                "aac",
+            ])
+            .output(output_path.to_str().unwrap());
+        run_ffmpeg(&mut command, output_path, on_progress)
+    }
+}
+
+/// The concat demuxer only works if every input has identical stream layouts.
+fn streams_are_concat_compatible(infos: &[MediaInfo]) -> bool {
+    let signature = |info: &MediaInfo| {
+        info.streams
+            .iter()
+            .map(|s| {
+                (
+                    s.codec_type.clone(),
+                    s.codec_name.clone(),
+                    s.width,
+                    s.height,
+                    s.frame_rate.clone(),
+                    s.sample_rate,
+                    s.channels,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    match infos.first() {
+        Some(first) => {
+            let expected = signature(first);
+            infos.iter().all(|i| signature(i) == expected)
+        }
+        None => true,
+    }
+}
+
+/// Builds the list file for `-f concat`. Single quotes in paths must be escaped
+/// as `'\''` because the whole path is single-quoted.
+fn build_concat_list(input_paths: &[PathBuf]) -> String {
+    input_paths
+        .iter()
+        .map(|p| format!("file '{}'\n", p.to_string_lossy().replace('\'', "'\\''")))
+        .collect()
+}
+
+/// Builds the filter graph for concatenating `count` inputs with the concat filter.
+/// The concat filter requires equal frame sizes, so every input is scaled and
+/// padded to `target_size` (usually the first input's resolution).
+fn build_concat_filter(count: usize, target_size: Option<(u32, u32)>) -> String {
+    let mut filter = String::new();
+    let mut inputs = String::new();
+
+    for i in 0..count {
+        match target_size {
+            Some((w, h)) => {
+                filter.push_str(&format!(
+                    "[{i}:v]scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1[v{i}];"
+                ));
+                inputs.push_str(&format!("[v{i}][{i}:a]"));
+            }
+            None => inputs.push_str(&format!("[{i}:v][{i}:a]")),
+        }
+    }
+
+    filter.push_str(&format!("{}concat=n={}:v=1:a=1[v][a]", inputs, count));
+    filter
+}
+
+fn build_single_clip_args(
+    segment: &Segment,
+    video_filters: &[String],
+    audio_filters: &[String],
+) -> Vec<String> {
+    let mut args = single_clip_filter_args(segment, video_filters, audio_filters);
+    args.extend(["-c:v", "libx264", "-c:a", "aac"].map(String::from));
+    args
+}
+
+/// `build_single_clip_args` without the encoders.
+fn single_clip_filter_args(
+    segment: &Segment,
+    video_filters: &[String],
+    audio_filters: &[String],
+) -> Vec<String> {
+    let mut args = vec![
+        "-y".to_string(),
+        "-ss".to_string(),
+        segment.start.clone(),
+        "-to".to_string(),
+        segment.end.clone(),
+    ];
+    if !video_filters.is_empty() {
+        args.push("-vf".to_string());
+        args.push(video_filters.join(","));
+    }
+    if !audio_filters.is_empty() {
+        args.push("-af".to_string());
+        args.push(audio_filters.join(","));
+    }
+    args
+}
+
+/// Full argument list (including `-i`) for a stream-copy export of one segment.
+/// See `FastModeAccuracy` for the difference between both modes. With
+/// `audio_filters` the audio is re-encoded instead of copied.
+fn build_fast_clip_args(
+    input_path: &Path,
+    segment: &Segment,
+    video_filters: &[String],
+    audio_filters: &[String],
+    accuracy: FastModeAccuracy,
+) -> Result<Vec<String>> {
+    let start = parse_timestamp_to_seconds_raw(&segment.start)?;
+    let end = parse_timestamp_to_seconds_raw(&segment.end)?;
+    if end <= start {
+        return Err(anyhow::anyhow!(
+            "Segment end {} must be after start {}",
+            segment.end,
+            segment.start
+        ));
+    }
+    if !accuracy.frame_accurate && !video_filters.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Video filters need a re-encode, enable frame-accurate fast mode or disable fast mode"
+        ));
+    }
+
+    // -ss before -i: fast (keyframe) seek on the input
+    let mut args = vec![
+        "-y".to_string(),
+        "-ss".to_string(),
+        format!("{:.3}", start),
+        "-i".to_string(),
+        input_path.to_string_lossy().to_string(),
+        "-t".to_string(),
+        format!("{:.3}", end - start),
+    ];
+
+    if accuracy.frame_accurate {
+        if !video_filters.is_empty() {
+            args.push("-vf".to_string());
+            args.push(video_filters.join(","));
+        }
+        args.extend(["-c:v", "libx264", "-force_key_frames", "0"].map(String::from));
+    } else {
+        args.extend(["-c:v", "copy", "-avoid_negative_ts", "make_zero"].map(String::from));
+    }
+    if audio_filters.is_empty() {
+        args.extend(["-c:a", "copy"].map(String::from));
+    } else {
+        args.extend(["-af".to_string(), audio_filters.join(",")]);
+        args.extend(["-c:a", "aac"].map(String::from));
+    }
+    Ok(args)
+}
+
+fn build_clip_output_filename(i: usize, segment: &ClipSegment) -> String {
+    let context = TemplateContext {
+        index: i + 1,
+        title: segment.label.clone().unwrap_or_default(),
+        start: segment.segments.first().map(|s| s.start.clone()).unwrap_or_default(),
+        end: segment.segments.last().map(|s| s.end.clone()).unwrap_or_default(),
+        date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+    };
+    let template = segment
+        .name_template
+        .as_deref()
+        .unwrap_or(DEFAULT_CLIP_NAME_TEMPLATE);
+
+    let mut name = render_template(template, &context);
+    if name.is_empty() {
+        name = format!("clip_{:03}", i + 1);
+    }
+    format!("{}.mp4", name)
+}
+
+/// Renders a clip file name template. Supported variables are `{index}`, `{title}`,
+/// `{start}`, `{end}` and `{date}`; `{index:03}` zero-pads the index to the given
+/// width. Unknown variables are kept as-is. The result is sanitized so that it is
+/// always a single file name, and trailing separators left behind by empty
+/// variables (e.g. a missing title) are trimmed.
+pub fn render_template(template: &str, context: &TemplateContext) -> String {
+    let re = Regex::new(r"\{(\w+)(?::0?(\d+))?\}").unwrap();
+
+    let rendered = re.replace_all(template, |caps: &regex::Captures| {
+        let width: usize = caps.get(2).and_then(|w| w.as_str().parse().ok()).unwrap_or(0);
+        match &caps[1] {
+            "index" => format!("{:0width$}", context.index, width = width),
+            "title" => context
+                .title
+                .replace(|c: char| !c.is_alphanumeric() && c != '-' && c != '_', ""),
+            // Colons are not allowed in Windows file names
+            "start" => context.start.replace(':', "-"),
+            "end" => context.end.replace(':', "-"),
+            "date" => context.date.clone(),
+            _ => caps[0].to_string(),
+        }
+    });
+
+    rendered
+        .replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "")
+        .trim_matches(|c: char| c == '_' || c == '-' || c == '.' || c.is_whitespace())
+        .to_string()
+}
+
+/// Clips per `{index_range}` folder.
+const FOLDER_INDEX_RANGE_SIZE: usize = 10;
+
+/// Renders a clip folder template into a path relative to the output directory.
+/// Supported variables are `{label}` (the clip label, `unlabeled` if missing),
+/// `{index_range}` (the block of ten clip numbers the clip belongs to, e.g.
+/// `011-020`) and `{date}`. Variables are sanitized into single path components,
+/// while `/` in the template itself creates nested folders. Absolute paths and
+/// `..` components are rejected so clips can't be written outside the output
+/// directory.
+pub fn render_folder_template(
+    template: &str,
+    i: usize,
+    segment: &ClipSegment,
+    date: &str,
+) -> Result<PathBuf> {
+    let re = Regex::new(r"\{(\w+)\}").unwrap();
+    let label = segment
+        .label
+        .as_deref()
+        .unwrap_or_default()
+        .replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "")
+        .trim_matches(|c: char| c == '.' || c.is_whitespace())
+        .to_string();
+    let range_start = i / FOLDER_INDEX_RANGE_SIZE * FOLDER_INDEX_RANGE_SIZE + 1;
+
+    let rendered = re.replace_all(template, |caps: &regex::Captures| match &caps[1] {
+        "label" if label.is_empty() => "unlabeled".to_string(),
+        "label" => label.clone(),
+        "index_range" => format!(
+            "{:03}-{:03}",
+            range_start,
+            range_start + FOLDER_INDEX_RANGE_SIZE - 1
+        ),
+        "date" => date.to_string(),
+        _ => caps[0].to_string(),
+    });
+
+    let path = PathBuf::from(rendered.replace('\\', "/"));
+    let escapes = path.components().any(|c| {
+        matches!(
+            c,
+            std::path::Component::ParentDir
+                | std::path::Component::RootDir
+                | std::path::Component::Prefix(_)
+        )
+    });
+    if escapes {
+        return Err(anyhow::anyhow!(
+            "Folder template {:?} leads outside the output directory ({:?})",
+            template,
+            rendered
+        ));
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
 
     #[test]
-    fn test_build_filter_complex() {
+    fn test_filter_clips_by_duration() {
+        let clip = |label: &str, start: &str, end: &str| ClipSegment {
+            segments: vec![Segment {
+                start: start.to_string(),
+                end: end.to_string(),
+            }],
+            label: Some(label.to_string()),
+            reason: None,
+            name_template: None,
+            score: None,
+            audio_gain_db: None,
+        };
+        let clips = vec![
+            clip("short", "00:10", "00:11"),
+            clip("ok", "01:00", "01:30"),
+            clip("long", "02:00", "04:00"),
+            clip("broken", "xx", "00:20"),
+        ];
+
+        let (valid, rejected) = filter_clips_by_duration(clips, 5.0, 60.0);
+        let labels = |clips: &[ClipSegment]| {
+            clips
+                .iter()
+                .map(|c| c.label.clone().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(labels(&valid), vec!["ok"]);
+        assert_eq!(labels(&rejected), vec!["short", "long", "broken"]);
+    }
+
+    #[test]
+    fn test_build_filter_complex() {
+        let segments = vec![
+            Segment {
+                start: "00:00".to_string(),
+                end: "00:10".to_string(),
+            },
+            Segment {
+                start: "00:20".to_string(),
+                end: "00:30".to_string(),
+            },
+        ];
+
+        let (filter, inputs) = build_filter_complex(&segments, &[], &[], None);
+
+        assert!(filter.contains("[0:v]trim=start=00:00:end=00:10,setpts=PTS-STARTPTS[v0];"));
+        assert!(filter.contains("[0:a]atrim=start=00:00:end=00:10,asetpts=PTS-STARTPTS[a0];"));
+        assert!(filter.contains("[0:v]trim=start=00:20:end=00:30,setpts=PTS-STARTPTS[v1];"));
+        assert!(filter.contains("[0:a]atrim=start=00:20:end=00:30,asetpts=PTS-STARTPTS[a1];"));
+        assert!(filter.contains("concat=n=2:v=1:a=1[v][a]"));
+        assert_eq!(inputs, "[v0][a0][v1][a1]");
+    }
+
+    #[test]
+    fn test_clip_audio_gain() {
+        let segments = vec![
+            Segment { start: "00:00".into(), end: "00:10".into() },
+            Segment { start: "00:20".into(), end: "00:30".into() },
+        ];
+        let mut clip = ClipSegment {
+            segments: segments.clone(),
+            label: None,
+            reason: None,
+            name_template: None,
+            score: None,
+            audio_gain_db: Some(-6.0),
+        };
+
+        let audio_filters = build_clip_audio_filters(&clip).unwrap();
+        assert_eq!(audio_filters, ["volume=-6.0dB"]);
+        let (filter, _) = build_filter_complex(&segments, &[], &audio_filters, None);
+        assert!(filter.contains(
+            "[0:a]atrim=start=00:00:end=00:10,asetpts=PTS-STARTPTS,volume=-6.0dB[a0];"
+        ));
+        assert!(filter.contains(
+            "[0:a]atrim=start=00:20:end=00:30,asetpts=PTS-STARTPTS,volume=-6.0dB[a1];"
+        ));
+
+        let args = build_single_clip_args(&segments[0], &[], &audio_filters);
+        assert_eq!(args[arg_position(&args, "-af") + 1], "volume=-6.0dB");
+        // The audio can't be copied with a gain
+        let args = build_fast_clip_args(
+            Path::new("in.mp4"),
+            &segments[0],
+            &[],
+            &audio_filters,
+            FastModeAccuracy { frame_accurate: false },
+        )
+        .unwrap();
+        assert_eq!(args[arg_position(&args, "-c:a") + 1], "aac");
+
+        clip.audio_gain_db = None;
+        let audio_filters = build_clip_audio_filters(&clip).unwrap();
+        assert!(audio_filters.is_empty());
+        let (filter, _) = build_filter_complex(&segments, &[], &audio_filters, None);
+        assert!(!filter.contains("volume="));
+
+        clip.audio_gain_db = Some(-40.5);
+        assert!(build_clip_audio_filters(&clip).is_err());
+        clip.audio_gain_db = Some(20.0);
+        assert!(build_clip_audio_filters(&clip).is_ok());
+    }
+
+    #[test]
+    fn test_progress_time_offsets() {
+        let segments = vec![
+            Segment { start: "00:10".into(), end: "00:20".into() },
+            Segment { start: "01:00".into(), end: "01:02.500".into() },
+        ];
+        assert_eq!(segments_duration(&segments).unwrap(), 12.5);
+        assert_eq!(offset_progress_time("00:00:05.25", 12.5), "00:00:17.750");
+        assert_eq!(offset_progress_time("N/A", 12.5), "N/A");
+    }
+
+    #[test]
+    fn test_build_clip_output_filename() {
+        let s1 = ClipSegment {
+            segments: vec![Segment {
+                start: "0".into(),
+                end: "10".into(),
+            }],
+            label: None,
+            reason: None,
+            name_template: None,
+            score: None,
+            audio_gain_db: None,
+        };
+        assert_eq!(build_clip_output_filename(0, &s1), "clip_001.mp4");
+
+        let s2 = ClipSegment {
+            segments: vec![Segment {
+                start: "0".into(),
+                end: "10".into(),
+            }],
+            label: Some("My Clip".into()),
+            reason: None,
+            name_template: None,
+            score: None,
+            audio_gain_db: None,
+        };
+        assert_eq!(build_clip_output_filename(1, &s2), "clip_002_MyClip.mp4");
+
+        let s3 = ClipSegment {
+            segments: vec![Segment {
+                start: "0".into(),
+                end: "10".into(),
+            }],
+            label: Some("Clip/With\\BadChars!".into()),
+            reason: None,
+            name_template: None,
+            score: None,
+            audio_gain_db: None,
+        };
+        assert_eq!(
+            build_clip_output_filename(2, &s3),
+            "clip_003_ClipWithBadChars.mp4"
+        );
+    }
+
+    #[test]
+    fn test_base_ffmpeg_command_flags() {
+        let args = |mut command: FfmpegCommand| -> Vec<String> {
+            command
+                .as_inner()
+                .get_args()
+                .map(|a| a.to_string_lossy().to_string())
+                .collect()
+        };
+        let loglevel = |args: &[String]| {
+            // The last one wins over the sidecar's default
+            let position = args.iter().rposition(|a| a == "-loglevel").unwrap();
+            args[position + 1].clone()
+        };
+
+        let base = args(base_ffmpeg_command());
+        assert!(base.iter().any(|a| a == "-nostdin"));
+        assert!(base.iter().any(|a| a == "-hide_banner"));
+        assert_eq!(loglevel(&base), "level+warning");
+        // Without it there are no progress events at this log level
+        assert!(base.iter().any(|a| a == "-stats"));
+
+        let analysis = args(base_ffmpeg_command_with_log_level("level+info"));
+        assert!(analysis.iter().any(|a| a == "-nostdin"));
+        assert!(analysis.iter().any(|a| a == "-hide_banner"));
+        assert_eq!(loglevel(&analysis), "level+info");
+    }
+
+    #[test]
+    fn test_xstack_layout() {
+        assert_eq!(build_xstack_layout(4, 2, 640, 360), "0_0|640_0|0_360|640_360");
+        assert_eq!(
+            build_xstack_layout(6, 3, 640, 360),
+            "0_0|640_0|1280_0|0_360|640_360|1280_360"
+        );
+        // Incomplete last row
+        assert_eq!(build_xstack_layout(5, 3, 100, 50), "0_0|100_0|200_0|0_50|100_50");
+    }
+
+    #[test]
+    fn test_video_grid_args() {
+        let inputs: Vec<PathBuf> = ["a.mp4", "b.mp4", "c.mp4", "d.mp4"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let args = build_video_grid_args(
+            &inputs,
+            &[0.0, 1.5, 0.0, 0.25],
+            2,
+            (640, 360),
+            Path::new("grid.mp4"),
+        )
+        .unwrap();
+        assert_eq!(
+            args[..11],
+            ["-y", "-i", "a.mp4", "-ss", "1.500", "-i", "b.mp4", "-i", "c.mp4", "-ss", "0.250"]
+        );
+        let filter = &args[arg_position(&args, "-filter_complex") + 1];
+        assert!(filter.starts_with(
+            "[0:v]scale=640:360:force_original_aspect_ratio=decrease,pad=640:360:(ow-iw)/2:(oh-ih)/2,setsar=1[cell0];"
+        ));
+        assert!(filter.ends_with(
+            "[cell0][cell1][cell2][cell3]xstack=inputs=4:layout=0_0|640_0|0_360|640_360:fill=black[v]"
+        ));
+
+        let grid = |count: usize, columns: u32, offsets: &[f64]| {
+            let inputs = vec![PathBuf::from("a.mp4"); count];
+            build_video_grid_args(&inputs, offsets, columns, (640, 360), Path::new("grid.mp4"))
+        };
+        assert!(grid(6, 3, &[]).is_ok());
+        assert!(grid(1, 1, &[]).is_err());
+        assert!(grid(10, 3, &[]).is_err());
+        assert!(grid(4, 0, &[]).is_err());
+        assert!(grid(4, 2, &[0.0, 1.0]).is_err());
+        assert!(grid(2, 2, &[0.0, -1.0]).is_err());
+    }
+
+    #[test]
+    fn test_comparison_filter() {
+        let inputs = "[0:v]setsar=1,format=yuv420p[before];\
+                      [1:v]scale=1920:1080,setsar=1,format=yuv420p[after];";
+        let cases = [
+            (ComparisonLayout::SideBySide, "[before][after]hstack=inputs=2[v]"),
+            (ComparisonLayout::TopBottom, "[before][after]vstack=inputs=2[v]"),
+            (
+                ComparisonLayout::Overlay(0.5),
+                "[before][after]blend=all_mode=overlay:all_opacity=0.5[v]",
+            ),
+        ];
+        for (layout, expected) in cases {
+            let filter = build_comparison_filter(layout, (1920, 1080)).unwrap();
+            assert_eq!(filter, format!("{}{}", inputs, expected));
+        }
+
+        assert!(build_comparison_filter(ComparisonLayout::Overlay(1.0), (640, 360)).is_ok());
+        assert!(build_comparison_filter(ComparisonLayout::Overlay(0.0), (640, 360)).is_err());
+        assert!(build_comparison_filter(ComparisonLayout::Overlay(1.5), (640, 360)).is_err());
+        assert!(build_comparison_filter(ComparisonLayout::Overlay(f64::NAN), (640, 360)).is_err());
+    }
+
+    #[test]
+    fn test_watermark_overlay_coordinates() {
+        let cases = [
+            (WatermarkPosition::TopLeft { offset_x: 10, offset_y: 20 }, "overlay=10:20"),
+            (WatermarkPosition::TopRight { offset_x: 10, offset_y: 20 }, "overlay=W-w-10:20"),
+            (WatermarkPosition::BottomLeft { offset_x: 10, offset_y: 20 }, "overlay=10:H-h-20"),
+            (
+                WatermarkPosition::BottomRight { offset_x: 10, offset_y: 20 },
+                "overlay=W-w-10:H-h-20",
+            ),
+            (
+                WatermarkPosition::Center { offset_x: 0, offset_y: -15 },
+                "overlay=(W-w)/2+(0):(H-h)/2+(-15)",
+            ),
+        ];
+
+        for (position, expected) in cases {
+            let filter = build_watermark_filter(position, 0.5, 1.0).unwrap();
+            assert!(filter.ends_with(&format!("[0:v][wm]{}[v]", expected)), "{}", filter);
+        }
+    }
+
+    #[test]
+    fn test_watermark_filter_validation() {
+        let position = WatermarkPosition::TopLeft { offset_x: 0, offset_y: 0 };
+        let filter = build_watermark_filter(position, 0.3, 0.5).unwrap();
+        assert!(filter.starts_with("[1:v]scale=iw*0.5:-1,format=rgba,colorchannelmixer=aa=0.3[wm];"));
+
+        assert!(build_watermark_filter(position, 1.0, 2.0).is_ok());
+        assert!(build_watermark_filter(position, 0.0, 1.0).is_err());
+        assert!(build_watermark_filter(position, 1.1, 1.0).is_err());
+        assert!(build_watermark_filter(position, 0.5, 0.0).is_err());
+        assert!(build_watermark_filter(position, 0.5, 2.5).is_err());
+        assert!(build_watermark_filter(position, f64::NAN, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_subtract_pts_offset() {
+        let segments = vec![
+            Segment { start: "00:00:01".into(), end: "00:00:05.500".into() },
+            Segment { start: "01:10".into(), end: "01:20".into() },
+        ];
+
+        let corrected = subtract_pts_offset(&segments, 1.4).unwrap();
+        assert_eq!(corrected[0].start, "00:00:00.000");
+        assert_eq!(corrected[0].end, "00:00:04.100");
+        assert_eq!(corrected[1].start, "00:01:08.600");
+        assert_eq!(corrected[1].end, "00:01:18.600");
+
+        // No offset leaves the original strings untouched
+        let unchanged = subtract_pts_offset(&segments, 0.0).unwrap();
+        assert_eq!(unchanged[1].start, "01:10");
+
+        let invalid = vec![Segment { start: "abc".into(), end: "00:10".into() }];
+        assert!(subtract_pts_offset(&invalid, 2.0).is_err());
+    }
+
+    fn timeline() -> Timeline {
+        let clip = |id: &str, start: &str, end: &str, track: u8| ClipEntry {
+            id: id.to_string(),
+            segment: Segment { start: start.to_string(), end: end.to_string() },
+            track,
+        };
+        Timeline {
+            clips: vec![
+                clip("a", "00:00", "00:10", 0),
+                clip("b", "00:20", "00:30", 1),
+                clip("c", "00:40", "00:50", 0),
+            ],
+        }
+    }
+
+    fn clip_ids(timeline: &Timeline) -> Vec<&str> {
+        timeline.clips.iter().map(|c| c.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_reorder_timeline() {
+        assert_eq!(clip_ids(&reorder_timeline(timeline(), "c", 0)), ["c", "a", "b"]);
+        assert_eq!(clip_ids(&reorder_timeline(timeline(), "a", 1)), ["b", "a", "c"]);
+        assert_eq!(clip_ids(&reorder_timeline(timeline(), "a", 99)), ["b", "c", "a"]);
+        assert_eq!(reorder_timeline(timeline(), "missing", 0), timeline());
+    }
+
+    #[test]
+    fn test_trim_clip() {
+        let trimmed = trim_clip(timeline(), "b", "00:22", "00:25.500").unwrap();
+        assert_eq!(trimmed.clips[1].segment.start, "00:22");
+        assert_eq!(trimmed.clips[1].segment.end, "00:25.500");
+        assert_eq!(trimmed.clips[0], timeline().clips[0]);
+
+        assert!(trim_clip(timeline(), "b", "00:25", "00:25").is_err());
+        assert!(trim_clip(timeline(), "b", "00:26", "00:25").is_err());
+        assert!(trim_clip(timeline(), "b", "abc", "00:25").is_err());
+        assert!(trim_clip(timeline(), "missing", "00:01", "00:02").is_err());
+    }
+
+    #[test]
+    fn test_flatten_timeline() {
+        let starts: Vec<String> = flatten_timeline(&timeline())
+            .into_iter()
+            .map(|s| s.start)
+            .collect();
+        assert_eq!(starts, ["00:00", "00:40", "00:20"]);
+
+        let reordered = reorder_timeline(timeline(), "c", 0);
+        let starts: Vec<String> = flatten_timeline(&reordered)
+            .into_iter()
+            .map(|s| s.start)
+            .collect();
+        assert_eq!(starts, ["00:40", "00:00", "00:20"]);
+    }
+
+    fn scored_clip(label: &str, ranges: &[(&str, &str)], score: Option<f64>) -> ClipSegment {
+        ClipSegment {
+            segments: ranges
+                .iter()
+                .map(|(start, end)| Segment {
+                    start: start.to_string(),
+                    end: end.to_string(),
+                })
+                .collect(),
+            label: Some(label.to_string()),
+            reason: None,
+            name_template: None,
+            score,
+            audio_gain_db: None,
+        }
+    }
+
+    fn labels(clips: &[ClipSegment]) -> Vec<&str> {
+        clips.iter().filter_map(|c| c.label.as_deref()).collect()
+    }
+
+    #[test]
+    fn test_select_highlight_segments_stops_at_limit() {
+        let ranked = |label: &str, ranges: &[(&str, &str)], score: f64| RankedClip {
+            clip: scored_clip(label, ranges, None),
+            virality_score: score,
+            reasoning: String::new(),
+        };
+        let clips = vec![
+            ranked("late", &[("05:00", "05:20")], 0.9),
+            ranked("early", &[("00:30", "00:40"), ("00:50", "00:55")], 0.8),
+            ranked("too long", &[("02:00", "02:30")], 0.7),
+            ranked("short", &[("03:00", "03:05")], 0.6),
+        ];
+
+        // 20s + 15s fit into 40s, the 30s clip doesn't and ends the selection
+        let segments = select_highlight_segments(&clips, 40.0).unwrap();
+        let starts: Vec<&str> = segments.iter().map(|s| s.start.as_str()).collect();
+        assert_eq!(starts, ["00:30", "00:50", "05:00"]);
+
+        assert!(select_highlight_segments(&clips, 10.0).unwrap().is_empty());
+        assert_eq!(select_highlight_segments(&clips, 1000.0).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_deduplicate_clips_nested() {
+        let clips = vec![
+            scored_clip("inner", &[("00:20", "00:30")], Some(6.0)),
+            scored_clip("outer", &[("00:10", "00:50")], Some(9.0)),
+            scored_clip("other", &[("01:00", "01:30")], Some(5.0)),
+        ];
+        assert_eq!(labels(&deduplicate_clips(clips, 2.0)), ["outer", "other"]);
+    }
+
+    #[test]
+    fn test_deduplicate_clips_partial_overlap() {
+        // Spliced clip overlaps "b" by 3s in its second sub-segment only
+        let clips = vec![
+            scored_clip("spliced", &[("00:00", "00:10"), ("00:40", "00:45")], Some(8.0)),
+            scored_clip("b", &[("00:42", "01:00")], Some(7.0)),
+            scored_clip("c", &[("00:08", "00:20")], None),
+        ];
+        assert_eq!(
+            labels(&deduplicate_clips(clips.clone(), 5.0)),
+            ["spliced", "b", "c"]
+        );
+        assert_eq!(labels(&deduplicate_clips(clips.clone(), 2.5)), ["spliced", "c"]);
+        assert_eq!(labels(&deduplicate_clips(clips, 1.0)), ["spliced"]);
+
+        // Without scores the earlier clip wins
+        let clips = vec![
+            scored_clip("first", &[("00:00", "00:10")], None),
+            scored_clip("second", &[("00:05", "00:15")], None),
+        ];
+        assert_eq!(labels(&deduplicate_clips(clips, 1.0)), ["first"]);
+    }
+
+    fn arg_position(args: &[String], flag: &str) -> usize {
+        args.iter().position(|a| a == flag).unwrap()
+    }
+
+    #[test]
+    fn test_build_fast_clip_args_keyframe() {
+        let segment = Segment {
+            start: "01:00".to_string(),
+            end: "01:30.500".to_string(),
+        };
+        let args = build_fast_clip_args(
+            Path::new("in.mp4"),
+            &segment,
+            &[],
+            &[],
+            FastModeAccuracy { frame_accurate: false },
+        )
+        .unwrap();
+
+        assert!(arg_position(&args, "-ss") < arg_position(&args, "-i"));
+        assert!(arg_position(&args, "-t") > arg_position(&args, "-i"));
+        assert_eq!(args[arg_position(&args, "-ss") + 1], "60.000");
+        assert_eq!(args[arg_position(&args, "-t") + 1], "30.500");
+        assert_eq!(args[arg_position(&args, "-c:v") + 1], "copy");
+        assert_eq!(args[arg_position(&args, "-c:a") + 1], "copy");
+        assert!(!args.contains(&"-force_key_frames".to_string()));
+
+        // Filters can't be applied to a stream copy
+        assert!(build_fast_clip_args(
+            Path::new("in.mp4"),
+            &segment,
+            &["hflip".to_string()],
+            &[],
+            FastModeAccuracy { frame_accurate: false },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_build_fast_clip_args_frame_accurate() {
+        let segment = Segment {
+            start: "00:10".to_string(),
+            end: "00:20".to_string(),
+        };
+        let args = build_fast_clip_args(
+            Path::new("in.mp4"),
+            &segment,
+            &["transpose=1".to_string()],
+            &[],
+            FastModeAccuracy { frame_accurate: true },
+        )
+        .unwrap();
+
+        let input = arg_position(&args, "-i");
+        assert!(arg_position(&args, "-ss") < input);
+        assert!(arg_position(&args, "-c:v") > input);
+        assert_eq!(args[arg_position(&args, "-c:v") + 1], "libx264");
+        assert_eq!(args[arg_position(&args, "-force_key_frames") + 1], "0");
+        assert_eq!(args[arg_position(&args, "-c:a") + 1], "copy");
+        assert_eq!(args[arg_position(&args, "-vf") + 1], "transpose=1");
+        assert!(!args.contains(&"-c".to_string()));
+    }
+
+    #[test]
+    fn test_build_filter_complex_gaps() {
+        let segments = vec![
+            Segment { start: "00:00".to_string(), end: "00:10".to_string() },
+            Segment { start: "00:20".to_string(), end: "00:30".to_string() },
+        ];
+        let gap = GapSource {
+            duration_secs: 0.5,
+            width: 1280,
+            height: 720,
+            frame_rate: "25/1".to_string(),
+            sample_rate: 44100,
+            channel_layout: "stereo".to_string(),
+        };
+
+        let (filter, inputs) = build_filter_complex(&segments, &[], &[], Some(&gap));
+        assert!(filter.contains("color=c=black:s=1280x720:r=25/1:d=0.500,setsar=1[gv1];"));
+        assert!(filter.contains("aevalsrc=0:d=0.500:s=44100:c=stereo[ga1];"));
+        assert!(!filter.contains("[gv0]"));
+        assert_eq!(inputs, "[v0][a0][gv1][ga1][v1][a1]");
+        assert!(filter.ends_with("concat=n=3:v=1:a=1[v][a]"));
+    }
+
+    #[test]
+    fn test_gap_source_matches_input() {
+        let info = MediaInfo {
+            duration: 10.0,
+            streams: vec![
+                crate::probe::StreamInfo {
+                    codec_type: "video".to_string(),
+                    width: Some(640),
+                    height: Some(360),
+                    frame_rate: Some("30000/1001".to_string()),
+                    ..Default::default()
+                },
+                crate::probe::StreamInfo {
+                    codec_type: "audio".to_string(),
+                    sample_rate: Some(48000),
+                    channels: Some(1),
+                    ..Default::default()
+                },
+            ],
+        };
+        let gap = GapSource::matching(&info, 0.25);
+        assert_eq!((gap.width, gap.height), (640, 360));
+        assert_eq!(gap.frame_rate, "30000/1001");
+        assert_eq!(gap.channel_layout, "mono");
+    }
+
+    #[test]
+    fn test_build_normalized_cut_args() {
+        let segment = Segment {
+            start: "00:05".to_string(),
+            end: "00:07.250".to_string(),
+        };
+        let args =
+            build_normalized_segment_args(Path::new("in.mov"), &segment, Path::new("seg_000.mp4"))
+                .unwrap();
+        assert!(arg_position(&args, "-ss") < arg_position(&args, "-i"));
+        assert_eq!(args[arg_position(&args, "-ss") + 1], "5.000");
+        assert_eq!(args[arg_position(&args, "-t") + 1], "2.250");
+        assert_eq!(args[arg_position(&args, "-r") + 1], NORMALIZED_FRAME_RATE);
+        assert_eq!(args[arg_position(&args, "-fps_mode") + 1], "cfr");
+        assert_eq!(args[arg_position(&args, "-c:v") + 1], "libx264");
+        assert_eq!(args[arg_position(&args, "-c:a") + 1], "aac");
+        assert_eq!(args.last().unwrap(), "seg_000.mp4");
+
+        let invalid = Segment {
+            start: "00:07".to_string(),
+            end: "00:05".to_string(),
+        };
+        assert!(build_normalized_segment_args(Path::new("in.mov"), &invalid, Path::new("x.mp4")).is_err());
+
+        let args = build_normalized_concat_args(Path::new("list.txt"), Path::new("out.mp4"));
+        assert_eq!(
+            args,
+            ["-f", "concat", "-safe", "0", "-i", "list.txt", "-y", "-c", "copy", "out.mp4"]
+        );
+    }
+
+    #[test]
+    fn test_build_caption_mux_args() {
+        let args = build_caption_mux_args(
+            Path::new("clip.mp4"),
+            Path::new("clip.srt"),
+            Path::new("clip.captioned.mp4"),
+            Some("deu"),
+        );
+        let inputs: Vec<&String> = args
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| *a == "-i")
+            .map(|(i, _)| &args[i + 1])
+            .collect();
+        assert_eq!(inputs, vec!["clip.mp4", "clip.srt"]);
+        assert_eq!(args[arg_position(&args, "-c") + 1], "copy");
+        assert_eq!(args[arg_position(&args, "-c:s") + 1], "mov_text");
+        assert_eq!(args[arg_position(&args, "-metadata:s:s:0") + 1], "language=deu");
+        assert_eq!(args.last().unwrap(), "clip.captioned.mp4");
+
+        let args = build_caption_mux_args(
+            Path::new("clip.mkv"),
+            Path::new("clip.srt"),
+            Path::new("clip.captioned.mkv"),
+            None,
+        );
+        assert_eq!(args[arg_position(&args, "-c:s") + 1], "srt");
+        assert!(!args.contains(&"-metadata:s:s:0".to_string()));
+    }
+
+    fn template_context() -> TemplateContext {
+        TemplateContext {
+            index: 7,
+            title: "Big Reveal".to_string(),
+            start: "01:05".to_string(),
+            end: "01:35.500".to_string(),
+            date: "2024-03-01".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_template_variables() {
+        let ctx = template_context();
+        assert_eq!(render_template("{index}", &ctx), "7");
+        assert_eq!(render_template("{index:03}", &ctx), "007");
+        assert_eq!(render_template("{title}", &ctx), "BigReveal");
+        assert_eq!(render_template("{start}", &ctx), "01-05");
+        assert_eq!(render_template("{end}", &ctx), "01-35.500");
+        assert_eq!(render_template("{date}", &ctx), "2024-03-01");
+        assert_eq!(
+            render_template(DEFAULT_CLIP_NAME_TEMPLATE, &ctx),
+            "clip_007_BigReveal"
+        );
+        assert_eq!(render_template("{date}_{unknown}", &ctx), "2024-03-01_{unknown}");
+    }
+
+    #[test]
+    fn test_render_template_sanitizes_paths() {
+        let ctx = TemplateContext {
+            title: "../../etc/passwd".to_string(),
+            ..template_context()
+        };
+        assert_eq!(render_template("{title}", &ctx), "etcpasswd");
+        assert_eq!(render_template("../{index}/{title}", &ctx), "7etcpasswd");
+
+        let ctx = TemplateContext {
+            title: "..\\..\\Windows".to_string(),
+            ..template_context()
+        };
+        assert_eq!(render_template("clip_{title}", &ctx), "clip_Windows");
+    }
+
+    #[test]
+    fn test_render_folder_template() {
+        let clip = ClipSegment {
+            segments: vec![Segment { start: "00:10".into(), end: "00:20".into() }],
+            label: Some("Product/Reviews".into()),
+            reason: None,
+            name_template: None,
+            score: None,
+            audio_gain_db: None,
+        };
+        assert_eq!(
+            render_folder_template("{label}/{index_range}", 10, &clip, "2024-03-01").unwrap(),
+            PathBuf::from("ProductReviews/011-020")
+        );
+        assert_eq!(
+            render_folder_template("{date}_{label}", 0, &clip, "2024-03-01").unwrap(),
+            PathBuf::from("2024-03-01_ProductReviews")
+        );
+
+        let unlabeled = ClipSegment { label: None, ..clip.clone() };
+        assert_eq!(
+            render_folder_template("{label}", 3, &unlabeled, "2024-03-01").unwrap(),
+            PathBuf::from("unlabeled")
+        );
+    }
+
+    #[test]
+    fn test_render_folder_template_rejects_traversal() {
+        let clip = ClipSegment {
+            segments: vec![],
+            label: Some("../..".into()),
+            reason: None,
+            name_template: None,
+            score: None,
+            audio_gain_db: None,
+        };
+        // Labels can't introduce path components
+        assert_eq!(
+            render_folder_template("{label}", 0, &clip, "").unwrap(),
+            PathBuf::from("unlabeled")
+        );
+        for template in ["../{label}", "clips/../../{date}", "/tmp/{label}", "..\\{label}"] {
+            assert!(
+                render_folder_template(template, 0, &clip, "2024-03-01").is_err(),
+                "{} was accepted",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_clip_output_filename_custom_template() {
+        let clip = ClipSegment {
+            segments: vec![
+                Segment { start: "00:10".into(), end: "00:20".into() },
+                Segment { start: "00:40".into(), end: "00:50".into() },
+            ],
+            label: Some("Intro".into()),
+            reason: None,
+            name_template: Some("{title}_{start}_{end}".into()),
+            score: None,
+            audio_gain_db: None,
+        };
+        assert_eq!(build_clip_output_filename(0, &clip), "Intro_00-10_00-50.mp4");
+    }
+
+    #[test]
+    fn test_video_transform_filters() {
+        assert_eq!(
+            build_video_filters(Some(VideoTransform::Rotate90)),
+            vec!["transpose=1"]
+        );
+        assert_eq!(
+            build_video_filters(Some(VideoTransform::FlipH)),
+            vec!["hflip"]
+        );
+        assert!(build_video_filters(None).is_empty());
+
+        // Phones store portrait video as landscape with a -90 display matrix
+        assert_eq!(
+            VideoTransform::from_rotation(-90.0),
+            Some(VideoTransform::Rotate90)
+        );
+        assert_eq!(
+            VideoTransform::from_rotation(90.0),
+            Some(VideoTransform::Rotate270)
+        );
+        assert_eq!(VideoTransform::from_rotation(0.0), None);
+    }
+
+    #[test]
+    fn test_parse_scene_scores() {
+        let output = "frame:0    pts:12012   pts_time:0.4004
+lavfi.scene_score=0.134521
+frame:1    pts:96096   pts_time:3.2032
+lavfi.scene_score=0.612300
+frame:2    pts:150150  pts_time:5.005
+lavfi.scene_score=0.254000
+";
+        let scores = parse_scene_scores(output);
+        assert_eq!(scores, vec![(0.4004, 0.134521), (3.2032, 0.6123), (5.005, 0.254)]);
+        let best = scores.into_iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+        assert_eq!(best.0, 3.2032);
+
+        assert!(parse_scene_scores("").is_empty());
+    }
+
+    #[test]
+    fn test_build_poster_frame_args() {
+        let args = build_poster_frame_args(Path::new("in.mp4"), 10.0, 25.5);
+        assert!(arg_position(&args, "-ss") < arg_position(&args, "-i"));
+        assert_eq!(args[arg_position(&args, "-t") + 1], "15.500");
+        assert_eq!(
+            args[arg_position(&args, "-vf") + 1],
+            "select='gt(scene,0.1)',metadata=print:file=-"
+        );
+    }
+
+    #[test]
+    fn test_build_interpolation_filter() {
+        let interpolation = |target_fps, method| FrameInterpolation { target_fps, method };
+        assert_eq!(
+            build_interpolation_filter(&interpolation(120, InterpolationMethod::BiDir), 30.0)
+                .unwrap(),
+            "minterpolate=fps=120:mi_mode=mci:me_mode=bidir"
+        );
+        assert_eq!(
+            build_interpolation_filter(&interpolation(60, InterpolationMethod::Blend), 29.97)
+                .unwrap(),
+            "minterpolate=fps=60:mi_mode=blend"
+        );
+        assert_eq!(
+            build_interpolation_filter(&interpolation(50, InterpolationMethod::Dup), 25.0).unwrap(),
+            "minterpolate=fps=50:mi_mode=dup"
+        );
+
+        // 4x 29.97 is 119.88
+        assert!(build_interpolation_filter(&interpolation(120, InterpolationMethod::Blend), 29.97)
+            .is_err());
+        assert!(build_interpolation_filter(&interpolation(0, InterpolationMethod::Dup), 25.0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_build_colorspace_filter() {
+        let probed = |stream: serde_json::Value| {
+            crate::probe::parse_colorspace(&serde_json::json!({ "streams": [stream] })).unwrap()
+        };
+
+        // Untagged phone footage: ffprobe reports "unknown" or nothing at all
+        let untagged = probed(serde_json::json!({ "color_primaries": "unknown" }));
+        assert_eq!(
+            build_colorspace_filter(&untagged).unwrap(),
+            "colorspace=all=bt709:iprimaries=bt709:itrc=bt709:ispace=bt709"
+        );
+
+        let bt601 = probed(serde_json::json!({
+            "color_primaries": "smpte170m",
+            "color_transfer": "smpte170m",
+            "color_space": "smpte170m"
+        }));
+        assert_eq!(build_colorspace_filter(&bt601).unwrap(), "colorspace=all=bt709");
+
+        let bt709 = probed(serde_json::json!({
+            "color_primaries": "bt709",
+            "color_transfer": "bt709",
+            "color_space": "bt709"
+        }));
+        assert_eq!(build_colorspace_filter(&bt709), None);
+
+        let hlg = probed(serde_json::json!({
+            "color_primaries": "bt2020",
+            "color_transfer": "arib-std-b67",
+            "color_space": "bt2020nc"
+        }));
+        let filter = build_colorspace_filter(&hlg).unwrap();
+        assert!(filter.starts_with("zscale=tin=arib-std-b67:pin=bt2020:t=linear"), "{}", filter);
+        assert!(filter.contains("tonemap=hable"), "{}", filter);
+        assert!(!filter.contains("colorspace="), "{}", filter);
+    }
+
+    #[test]
+    fn test_build_lut_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let cube = dir.path().join("grade.cube");
+        let hald = dir.path().join("grade.PNG");
+        let other = dir.path().join("grade.txt");
+        for p in [&cube, &hald, &other] {
+            std::fs::write(p, "").unwrap();
+        }
+
+        let filter = build_lut_filter(&cube).unwrap();
+        assert!(filter.starts_with("lut3d=file='"));
+        assert!(filter.ends_with("grade.cube'"));
+
+        let filter = build_lut_filter(&hald).unwrap();
+        assert!(filter.starts_with("null[lut_in];movie='"));
+        assert!(filter.ends_with("grade.PNG'[clut];[lut_in][clut]haldclut"));
+
+        assert!(build_lut_filter(&other).is_err());
+        assert!(build_lut_filter(&dir.path().join("missing.cube")).is_err());
+
+        assert_eq!(
+            escape_filter_path(Path::new("C:\\Luts\\it's.cube")),
+            "'C\\:/Luts/it'\\\\\\''s.cube'"
+        );
+    }
+
+    #[test]
+    fn test_validate_output_writable() {
+        let dir = tempfile::tempdir().unwrap();
+        validate_output_writable(dir.path()).unwrap();
+        // The test file is cleaned up
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let err = validate_output_writable(&dir.path().join("missing")).unwrap_err();
+        assert!(err.to_string().contains("is not writable"), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_validate_output_writable_read_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let read_only = dir.path().join("read-only");
+        std::fs::create_dir(&read_only).unwrap();
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        // Root ignores the permissions
+        if std::fs::write(read_only.join("root-check"), "").is_ok() {
+            return;
+        }
+        let result = validate_output_writable(&read_only);
+        let _ = std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o755));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_retry_falls_back_to_reencode_and_copy() {
+        // Codec error in fast mode, the re-encode works
+        let mut modes = Vec::new();
+        let (attempts, result) = export_with_retries(3, true, false, |mode| {
+            modes.push(mode);
+            match mode {
+                ClipExportMode::Configured => Err(anyhow::anyhow!("Unknown encoder")),
+                _ => Ok(()),
+            }
+        });
+        assert!(result.is_ok());
+        assert_eq!(attempts, 2);
+        assert_eq!(modes, [ClipExportMode::Configured, ClipExportMode::ReEncode]);
+
+        // A failing re-encode falls back to stream copy
+        let mut modes = Vec::new();
+        let (attempts, result) = export_with_retries(3, false, false, |mode| {
+            modes.push(mode);
+            match mode {
+                ClipExportMode::StreamCopy => Ok(()),
+                _ => Err(anyhow::anyhow!("Unknown encoder")),
+            }
+        });
+        assert!(result.is_ok());
+        assert_eq!(attempts, 2);
+        assert_eq!(modes, [ClipExportMode::Configured, ClipExportMode::StreamCopy]);
+
+        let mut modes = Vec::new();
+        let (attempts, result) = export_with_retries(2, true, false, |mode| {
+            modes.push(mode);
+            Err(anyhow::anyhow!("Unknown encoder"))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+        assert_eq!(
+            modes,
+            [ClipExportMode::Configured, ClipExportMode::ReEncode, ClipExportMode::StreamCopy]
+        );
+
+        // Without retries the first error is final
+        let (attempts, result) =
+            export_with_retries(0, true, false, |_| Err(anyhow::anyhow!("failed")));
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        // Stream copy would ignore the size limit
+        let mut modes = Vec::new();
+        let (attempts, result) = export_with_retries(3, false, true, |mode| {
+            modes.push(mode);
+            Err(anyhow::anyhow!("Unknown encoder"))
+        });
+        assert!(format!("{:#}", result.unwrap_err()).contains("size limit"));
+        assert_eq!(attempts, 1);
+        assert_eq!(modes, [ClipExportMode::Configured]);
+    }
+
+    #[test]
+    fn test_target_video_bitrate() {
+        // 97% of 8 MB in 10 seconds are 6.208 Mbit/s, minus the audio
+        assert_eq!(target_video_bitrate(8.0 * BYTES_PER_MB, 10.0, 128_000).unwrap(), 6_080_000);
+        assert!(target_video_bitrate(8.0 * BYTES_PER_MB, 0.0, 128_000).is_err());
+        assert!(target_video_bitrate(0.0, 10.0, 128_000).is_err());
+        // Not even the audio fits
+        assert!(target_video_bitrate(100_000.0, 10.0, 128_000).is_err());
+    }
+
+    #[test]
+    fn test_two_pass_args() {
+        let passlog = Path::new("/out/clip_001.passlog");
+        assert_eq!(
+            build_two_pass_args(1, 6_272_000, passlog),
+            [
+                "-c:v", "libx264", "-b:v", "6272000", "-pass", "1",
+                "-passlogfile", "/out/clip_001.passlog", "-an", "-f", "null",
+            ]
+        );
+        assert_eq!(
+            build_two_pass_args(2, 6_272_000, passlog),
+            [
+                "-c:v", "libx264", "-b:v", "6272000", "-pass", "2",
+                "-passlogfile", "/out/clip_001.passlog", "-c:a", "aac", "-b:a", "128000",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_output_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clip_001.mp4");
+        // Nothing to resolve yet
+        assert_eq!(resolve_output_conflict(&path, ConflictStrategy::Skip), Some(path.clone()));
+
+        std::fs::write(&path, "existing").unwrap();
+        assert_eq!(
+            resolve_output_conflict(&path, ConflictStrategy::Overwrite),
+            Some(path.clone())
+        );
+        assert_eq!(resolve_output_conflict(&path, ConflictStrategy::Skip), None);
+        let renamed = dir.path().join("clip_001_2.mp4");
+        assert_eq!(
+            resolve_output_conflict(&path, ConflictStrategy::Rename),
+            Some(renamed.clone())
+        );
+
+        std::fs::write(&renamed, "existing").unwrap();
+        assert_eq!(
+            resolve_output_conflict(&path, ConflictStrategy::Rename),
+            Some(dir.path().join("clip_001_3.mp4"))
+        );
+        // Existing files are left alone
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "existing");
+    }
+
+    #[test]
+    fn test_export_clips_skips_existing_clip() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("clip_001.mp4"), "existing").unwrap();
+        let profile = OutputProfile {
+            conflict_strategy: ConflictStrategy::Skip,
+            ..Default::default()
+        };
+        let clips = vec![ClipSegment {
+            segments: vec![Segment { start: "00:00".into(), end: "00:01".into() }],
+            label: None,
+            reason: None,
+            name_template: None,
+            score: None,
+            audio_gain_db: None,
+        }];
+
+        // The input is never touched, so it doesn't have to exist
+        let results =
+            export_clips(Path::new("in.mp4"), &clips, &[], dir.path(), &profile, |_| {}).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].skipped);
+        assert_eq!(results[0].attempts, 0);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("clip_001.mp4")).unwrap(),
+            "existing"
+        );
+        assert!(!dir.path().join("clip_001.json").exists());
+    }
+
+    #[test]
+    fn test_export_clips_rejects_missing_lut() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = dir.path().join("clips");
+        let profile = OutputProfile {
+            lut_path: Some(dir.path().join("missing.cube").to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let clips = vec![ClipSegment {
+            segments: vec![Segment { start: "00:00".into(), end: "00:01".into() }],
+            label: None,
+            reason: None,
+            name_template: None,
+            score: None,
+            audio_gain_db: None,
+        }];
+
+        let err = export_clips(Path::new("in.mp4"), &clips, &[], &output_dir, &profile, |_| {})
+            .unwrap_err();
+        assert!(err.to_string().contains("LUT file not found"));
+        // Nothing was written before the check
+        assert!(!output_dir.exists());
+    }
+
+    #[test]
+    fn test_video_filters_applied_to_commands() {
         let segments = vec![
             Segment {
                 start: "00:00".to_string(),
@@ -236,50 +3325,56 @@ mod tests {
                 end: "00:30".to_string(),
             },
         ];
+        let filters = build_video_filters(Some(VideoTransform::Rotate90));
 
-        let (filter, inputs) = build_filter_complex(&segments);
+        let (filter, _) = build_filter_complex(&segments, &filters, &[], None);
+        assert!(filter.ends_with("concat=n=2:v=1:a=1[vcat][a];[vcat]transpose=1[v]"));
 
-        assert!(filter.contains("[0:v]trim=start=00:00:end=00:10,setpts=PTS-STARTPTS[v0];"));
-        assert!(filter.contains("[0:a]atrim=start=00:00:end=00:10,asetpts=PTS-STARTPTS[a0];"));
-        assert!(filter.contains("[0:v]trim=start=00:20:end=00:30,setpts=PTS-STARTPTS[v1];"));
-        assert!(filter.contains("[0:a]atrim=start=00:20:end=00:30,asetpts=PTS-STARTPTS[a1];"));
-        assert!(filter.contains("concat=n=2:v=1:a=1[v][a]"));
-        assert_eq!(inputs, "[v0][a0][v1][a1]");
+        let args = build_single_clip_args(&segments[0], &filters, &[]);
+        let vf = args.iter().position(|a| a == "-vf").unwrap();
+        assert_eq!(args[vf + 1], "transpose=1");
     }
 
     #[test]
-    fn test_build_clip_output_filename() {
-        let s1 = ClipSegment {
-            segments: vec![Segment {
-                start: "0".into(),
-                end: "10".into(),
-            }],
-            label: None,
-            reason: None,
-        };
-        assert_eq!(build_clip_output_filename(0, &s1), "clip_001.mp4");
+    fn test_build_concat_list() {
+        let paths = vec![
+            PathBuf::from("/videos/part1.mp4"),
+            PathBuf::from("/videos/it's here.mp4"),
+        ];
+        assert_eq!(
+            build_concat_list(&paths),
+            "file '/videos/part1.mp4'\nfile '/videos/it'\\''s here.mp4'\n"
+        );
+    }
 
-        let s2 = ClipSegment {
-            segments: vec![Segment {
-                start: "0".into(),
-                end: "10".into(),
-            }],
-            label: Some("My Clip".into()),
-            reason: None,
-        };
-        assert_eq!(build_clip_output_filename(1, &s2), "clip_002_MyClip.mp4");
+    #[test]
+    fn test_build_concat_filter() {
+        assert_eq!(
+            build_concat_filter(2, None),
+            "[0:v][0:a][1:v][1:a]concat=n=2:v=1:a=1[v][a]"
+        );
 
-        let s3 = ClipSegment {
-            segments: vec![Segment {
-                start: "0".into(),
-                end: "10".into(),
+        let filter = build_concat_filter(2, Some((1280, 720)));
+        assert!(filter.contains("[1:v]scale=1280:720:force_original_aspect_ratio=decrease,pad=1280:720:(ow-iw)/2:(oh-ih)/2,setsar=1[v1];"));
+        assert!(filter.ends_with("[v0][0:a][v1][1:a]concat=n=2:v=1:a=1[v][a]"));
+    }
+
+    #[test]
+    fn test_streams_are_concat_compatible() {
+        use crate::probe::StreamInfo;
+
+        let media = |codec: &str| MediaInfo {
+            duration: 10.0,
+            streams: vec![StreamInfo {
+                codec_type: "video".into(),
+                codec_name: codec.into(),
+                width: Some(1920),
+                height: Some(1080),
+                ..Default::default()
             }],
-            label: Some("Clip/With\\BadChars!".into()),
-            reason: None,
         };
-        assert_eq!(
-            build_clip_output_filename(2, &s3),
-            "clip_003_ClipWithBadChars.mp4"
-        );
+
+        assert!(streams_are_concat_compatible(&[media("h264"), media("h264")]));
+        assert!(!streams_are_concat_compatible(&[media("h264"), media("hevc")]));
     }
 }
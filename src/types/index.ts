@@ -5,6 +5,15 @@ export interface TranscriptSegment {
   speaker: string;
 }
 
+/** Returned by `analyze_audio` when more than one output language is requested. */
+export interface BilingualSegment {
+  start: string;
+  end: string;
+  speaker: string;
+  text_primary: string;
+  text_secondary: string;
+}
+
 export interface Clip {
   segments: { start: string; end: string }[];
   title: string;
@@ -16,6 +25,7 @@ export interface Clip {
 export interface AudioInfo {
   path: string;
   size: number;
+  source_type: "Audio" | "Video"; // Type of the input
 }
 
 export interface SilenceInterval {
@@ -24,6 +34,11 @@ export interface SilenceInterval {
   duration: number;
 }
 
+export interface AudioLevel {
+  time_secs: number;
+  level_db: number; // RMS level
+}
+
 export interface SegmentOffset {
   min_time: number;
   offset: number;
@@ -34,3 +49,82 @@ export interface ProcessedAudio {
   silence_intervals: SilenceInterval[];
   offsets: SegmentOffset[];
 }
+
+export interface SilenceRemovalJob {
+  input_path: string;
+  output_path: string | null; // Next to the input if not set
+}
+
+export interface BatchSilenceResult {
+  input_path: string;
+  output: ProcessedAudio;
+}
+
+export interface BatchSilenceProgress {
+  job_index: number;
+  total_jobs: number;
+  file_name: string;
+  stage: "detecting" | "removing";
+}
+
+export interface SubtitleStyle {
+  font_name: string | null;
+  font_size: number;
+  primary_color: string; // #RRGGBB
+  outline_color: string; // #RRGGBB
+  outline_width: number;
+  margin_v: number;
+}
+
+export interface ProgressEvent {
+  job_id: string;
+  percentage: number;
+  message: string | null;
+  current_time: string | null;
+}
+
+export interface ModelInfo {
+  id: string;
+  display_name: string;
+  supports_audio: boolean;
+  context_window_tokens: number | null;
+}
+
+export interface AudioPeak {
+  timestamp_secs: number;
+  amplitude: number; // RMS, 0-1
+}
+
+export interface GapInfo {
+  start_secs: number;
+  end_secs: number;
+  duration_secs: number;
+}
+
+export interface OverlapInfo {
+  first_index: number;
+  second_index: number;
+  start_secs: number;
+  end_secs: number;
+  duration_secs: number;
+}
+
+export interface TranscriptCoverageReport {
+  coverage_fraction: number; // 0-1
+  gaps: GapInfo[];
+  overlaps: OverlapInfo[];
+}
+
+export interface SeekThumbnailManifest {
+  vtt_path: string;
+  sprite_path: string;
+  frame_count: number;
+}
+
+export interface ClipExportResult {
+  index: number;
+  output_path: string;
+  attempts: number;
+  error: string | null; // null if the clip was exported
+  skipped: boolean; // The clip file already existed and was kept
+}